@@ -58,6 +58,8 @@ async fn protected(jar: CookieJar) -> impl IntoResponse {
 
     let verify_token_body = serde_json::json!({
         "token": &jwt_cookie.value(),
+        // reject tokens minted for other services
+        "audience": "app-service",
     });
 
     let auth_hostname = env::var("AUTH_SERVICE_HOST_NAME").unwrap_or("0.0.0.0".to_owned());
@@ -1,10 +1,13 @@
 const loginSection = document.getElementById("login-section");
 const twoFASection = document.getElementById("2fa-section");
 const signupSection = document.getElementById("signup-section");
+const resetSection = document.getElementById("reset-section");
 
 const signupLink = document.getElementById("signup-link");
 const twoFALoginLink = document.getElementById("2fa-login-link");
 const signupLoginLink = document.getElementById("signup-login-link");
+const resetLink = document.getElementById("reset-link");
+const resetLoginLink = document.getElementById("reset-login-link");
 
 signupLink.addEventListener("click", (e) => {
     e.preventDefault();
@@ -30,8 +33,37 @@ signupLoginLink.addEventListener("click", (e) => {
     signupSection.style.display = "none";
 });
 
+resetLink.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    loginSection.style.display = "none";
+    twoFASection.style.display = "none";
+    signupSection.style.display = "none";
+    resetSection.style.display = "block";
+});
+
+resetLoginLink.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    loginSection.style.display = "block";
+    twoFASection.style.display = "none";
+    signupSection.style.display = "none";
+    resetSection.style.display = "none";
+});
+
 // -----------------------------------------------------
 
+// /oauth/authorize and the device page send users here to log in, then expect them back to finish
+// authorizing the client. Only our own pages are allowed, so the parameter can't send users to another site
+function returnAfterLogin() {
+    const returnTo = new URLSearchParams(window.location.search).get("return_to");
+    if (returnTo !== null && (returnTo.startsWith("/oauth/") || returnTo.startsWith("/device/"))) {
+        window.location.assign(returnTo);
+        return true;
+    }
+    return false;
+}
+
 const loginForm = document.getElementById("login-form");
 const loginButton = document.getElementById("login-form-submit");
 const loginErrAlter = document.getElementById("login-err-alert");
@@ -66,7 +98,9 @@ loginButton.addEventListener("click", (e) => {
             loginForm.email.value = "";
             loginForm.password.value = "";
             loginErrAlter.style.display = "none";
-            alert("You have successfully logged in.");
+            if (!returnAfterLogin()) {
+                alert("You have successfully logged in.");
+            }
         } else {
             response.json().then(data => {
                 let error_msg = data.error;
@@ -104,7 +138,13 @@ signupButton.addEventListener("click", (e) => {
             signupForm.password.value = "";
             signupForm.twoFA.checked = false;
             signupErrAlter.style.display = "none";
-            alert("You have successfully created a user.");
+            response.json().then(data => {
+                let message = "You have successfully created a user. Check your email to verify your account.";
+                if (data.recoveryCodes) {
+                    message += "\n\nSave these recovery codes, each one can be used once if you can't get a 2FA code:\n" + data.recoveryCodes.join("\n");
+                }
+                alert(message);
+            });
             loginSection.style.display = "block";
             twoFASection.style.display = "none";
             signupSection.style.display = "none";
@@ -145,6 +185,9 @@ TwoFAButton.addEventListener("click", (e) => {
             TwoFAForm.email_code.value = "";
             TwoFAForm.login_attempt_id.value = "";
             TwoFAErrAlter.style.display = "none";
+            if (returnAfterLogin()) {
+                return;
+            }
             alert("You have successfully logged in.");
             loginSection.style.display = "block";
             twoFASection.style.display = "none";
@@ -161,4 +204,76 @@ TwoFAButton.addEventListener("click", (e) => {
             });
         }
     });
-});
\ No newline at end of file
+});
+
+const resetRequestForm = document.getElementById("reset-request-form");
+const resetRequestButton = document.getElementById("reset-request-form-submit");
+const resetConfirmForm = document.getElementById("reset-confirm-form");
+const resetConfirmButton = document.getElementById("reset-confirm-form-submit");
+const resetErrAlter = document.getElementById("reset-err-alert");
+
+function showResetError(response) {
+    response.json().then(data => {
+        let error_msg = data.error;
+        if (error_msg !== undefined && error_msg !== null && error_msg !== "") {
+            resetErrAlter.innerHTML = `<span><strong>Error: </strong>${error_msg}</span>`;
+            resetErrAlter.style.display = "block";
+        } else {
+            resetErrAlter.style.display = "none";
+        }
+    });
+}
+
+resetRequestButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const email = resetRequestForm.email.value;
+
+    fetch('/password-reset/request', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ email }),
+    }).then(response => {
+        if (response.ok) {
+            resetConfirmForm.email.value = email;
+            resetRequestForm.email.value = "";
+            resetErrAlter.style.display = "none";
+            resetRequestForm.style.display = "none";
+            resetConfirmForm.style.display = "block";
+        } else {
+            showResetError(response);
+        }
+    });
+});
+
+resetConfirmButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const email = resetConfirmForm.email.value;
+    const token = resetConfirmForm.token.value;
+    const newPassword = resetConfirmForm.password.value;
+
+    fetch('/password-reset/confirm', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ email, token, newPassword }),
+    }).then(response => {
+        if (response.ok) {
+            resetConfirmForm.email.value = "";
+            resetConfirmForm.token.value = "";
+            resetConfirmForm.password.value = "";
+            resetErrAlter.style.display = "none";
+            alert("Your password has been reset.");
+            resetRequestForm.style.display = "block";
+            resetConfirmForm.style.display = "none";
+            loginSection.style.display = "block";
+            resetSection.style.display = "none";
+        } else {
+            showResetError(response);
+        }
+    });
+});
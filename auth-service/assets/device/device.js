@@ -0,0 +1,81 @@
+const codeSection = document.getElementById("code-section");
+const confirmSection = document.getElementById("confirm-section");
+const doneSection = document.getElementById("done-section");
+
+const codeForm = document.getElementById("code-form");
+const codeButton = document.getElementById("code-form-submit");
+const codeErrAlert = document.getElementById("code-err-alert");
+const confirmErrAlert = document.getElementById("confirm-err-alert");
+
+// The login page brings the user back here, with the code they already typed in
+function logInFirst(userCode) {
+    const returnTo = `/device/?user_code=${encodeURIComponent(userCode)}`;
+    window.location.assign(`/?return_to=${encodeURIComponent(returnTo)}`);
+}
+
+function showError(alert, response) {
+    response.json().then(data => {
+        alert.innerHTML = `<span><strong>Error: </strong>${data.error}</span>`;
+        alert.style.display = "block";
+    });
+}
+
+function lookUp(userCode) {
+    fetch(`/oauth/device?user_code=${encodeURIComponent(userCode)}`).then(response => {
+        if (response.status === 200) {
+            response.json().then(data => {
+                document.getElementById("confirm-client-name").textContent = data.clientName;
+                document.getElementById("confirm-device").textContent = data.device;
+                document.getElementById("confirm-ip").textContent = data.ip;
+                document.getElementById("confirm-user-code").textContent = data.userCode;
+                document.getElementById("confirm-scopes").textContent = data.scopes.join(", ");
+                codeSection.style.display = "none";
+                confirmSection.style.display = "block";
+            });
+        } else if (response.status === 404) {
+            showError(codeErrAlert, response);
+        } else {
+            // not logged in, or the session expired
+            logInFirst(userCode);
+        }
+    });
+}
+
+function decide(approve) {
+    const userCode = codeForm.user_code.value;
+
+    fetch('/oauth/device', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ userCode, approve }),
+    }).then(response => {
+        if (response.status === 200) {
+            document.getElementById("done-message").textContent = approve ? "Device connected" : "Device denied";
+            confirmSection.style.display = "none";
+            doneSection.style.display = "block";
+        } else if (response.status === 404) {
+            showError(confirmErrAlert, response);
+        } else {
+            logInFirst(userCode);
+        }
+    });
+}
+
+codeButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    codeErrAlert.style.display = "none";
+    lookUp(codeForm.user_code.value);
+});
+
+document.getElementById("approve-button").addEventListener("click", () => decide(true));
+document.getElementById("deny-button").addEventListener("click", () => decide(false));
+
+// verification_uri_complete links fill the code in
+const userCode = new URLSearchParams(window.location.search).get("user_code");
+if (userCode !== null) {
+    codeForm.user_code.value = userCode;
+    lookUp(userCode);
+}
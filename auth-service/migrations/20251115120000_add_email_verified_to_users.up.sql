@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- Accounts created before verification existed are treated as verified,
+-- every new account starts out unverified
+ALTER TABLE users ADD COLUMN email_verified BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE users ALTER COLUMN email_verified SET DEFAULT FALSE;
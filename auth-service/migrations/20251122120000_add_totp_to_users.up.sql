@@ -0,0 +1,6 @@
+-- Add up migration script here
+-- 2FA codes are emailed unless the user has enrolled an authenticator app.
+-- TOTP secrets are stored encrypted with TOTP_ENCRYPTION_KEY
+ALTER TABLE users ADD COLUMN two_fa_method TEXT NOT NULL DEFAULT 'email';
+ALTER TABLE users ADD COLUMN totp_secret TEXT;
+ALTER TABLE users ADD COLUMN pending_totp_secret TEXT;
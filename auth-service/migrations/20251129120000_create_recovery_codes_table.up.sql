@@ -0,0 +1,8 @@
+-- Add up migration script here
+-- Single-use 2FA backup codes, hashed with argon2 like passwords
+CREATE TABLE IF NOT EXISTS recovery_codes(
+       id BIGSERIAL PRIMARY KEY,
+       email TEXT NOT NULL REFERENCES users(email) ON DELETE CASCADE,
+       code_hash TEXT NOT NULL
+    );
+CREATE INDEX IF NOT EXISTS recovery_codes_email_idx ON recovery_codes(email);
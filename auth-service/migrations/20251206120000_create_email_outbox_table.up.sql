@@ -0,0 +1,16 @@
+-- Add up migration script here
+-- Emails waiting to be delivered, or already delivered/dead-lettered, by the outbox worker
+CREATE TABLE IF NOT EXISTS email_outbox(
+       id UUID PRIMARY KEY,
+       recipient TEXT NOT NULL,
+       subject TEXT NOT NULL,
+       html_body TEXT NOT NULL,
+       text_body TEXT NOT NULL,
+       status TEXT NOT NULL DEFAULT 'pending',
+       attempts INTEGER NOT NULL DEFAULT 0,
+       last_error TEXT,
+       next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+       created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+    );
+CREATE INDEX IF NOT EXISTS email_outbox_due_idx ON email_outbox(next_attempt_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS email_outbox_recipient_idx ON email_outbox(recipient);
@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- Every JWT carries the epoch it was issued in, bumping it invalidates all of the user's JWTs
+ALTER TABLE users ADD COLUMN token_epoch INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+-- Add up migration script here
+-- Applications allowed to get tokens for users through /oauth/authorize
+CREATE TABLE IF NOT EXISTS oauth_clients(
+       client_id TEXT PRIMARY KEY,
+       name TEXT NOT NULL,
+       -- argon2 hash like passwords, NULL for public clients (SPAs, mobile apps) that can't keep a secret
+       client_secret_hash TEXT,
+       redirect_uris TEXT[] NOT NULL,
+       scopes TEXT[] NOT NULL DEFAULT '{}',
+       created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+    );
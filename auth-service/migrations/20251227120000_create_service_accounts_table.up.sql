@@ -0,0 +1,10 @@
+-- Add up migration script here
+-- Machine principals that get tokens in their own name with the client credentials grant
+CREATE TABLE IF NOT EXISTS service_accounts(
+       id TEXT PRIMARY KEY,
+       name TEXT NOT NULL,
+       -- argon2 hash like passwords
+       client_secret_hash TEXT NOT NULL,
+       scopes TEXT[] NOT NULL DEFAULT '{}',
+       created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+    );
@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- Time step of the last TOTP code the user logged in with, so a code can't be used twice
+ALTER TABLE users ADD COLUMN totp_last_step BIGINT;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- Stable opaque identifier for the user, used as the OpenID Connect subject instead of the email
+ALTER TABLE users ADD COLUMN id UUID NOT NULL UNIQUE DEFAULT gen_random_uuid();
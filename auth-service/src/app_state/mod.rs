@@ -3,32 +3,91 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 
 use crate::domain::{
-    data_store::{BannedTokenStore, TwoFACodeStore, UserStore},
+    data_store::{AuthorizationCodeStore, BannedTokenStore, DeviceAuthorizationStore, EmailOutbox, EmailVerificationTokenStore, FailedLoginStore, OAuthClientStore, PasswordResetTokenStore, RateLimitStore, RefreshTokenStore, ServiceAccountStore, SessionStore, TwoFACodeStore, UserStore},
     email_client::EmailClient,
+    rate_limit::RateLimits,
 };
 
-// pub type UserStoreType = Arc<RwLock<HashmapUserStore>>;
+// Handlers only see the store traits, so any implementation
+// (Postgres, Redis or the in-memory ones) can be wired into the app
+pub type UserStoreType = Arc<RwLock<dyn UserStore + Send + Sync>>;
+pub type BannedTokenStoreType = Arc<RwLock<dyn BannedTokenStore + Send + Sync>>;
+pub type TwoFACodeStoreType = Arc<RwLock<dyn TwoFACodeStore + Send + Sync>>;
+pub type EmailClientType = Arc<RwLock<dyn EmailClient + Send + Sync>>;
+pub type PasswordResetTokenStoreType = Arc<RwLock<dyn PasswordResetTokenStore + Send + Sync>>;
+pub type EmailVerificationTokenStoreType = Arc<RwLock<dyn EmailVerificationTokenStore + Send + Sync>>;
+pub type RefreshTokenStoreType = Arc<RwLock<dyn RefreshTokenStore + Send + Sync>>;
+pub type SessionStoreType = Arc<RwLock<dyn SessionStore + Send + Sync>>;
+pub type OAuthClientStoreType = Arc<RwLock<dyn OAuthClientStore + Send + Sync>>;
+pub type ServiceAccountStoreType = Arc<RwLock<dyn ServiceAccountStore + Send + Sync>>;
+pub type AuthorizationCodeStoreType = Arc<RwLock<dyn AuthorizationCodeStore + Send + Sync>>;
+pub type DeviceAuthorizationStoreType = Arc<RwLock<dyn DeviceAuthorizationStore + Send + Sync>>;
+pub type EmailOutboxType = Arc<RwLock<dyn EmailOutbox + Send + Sync>>;
+pub type FailedLoginStoreType = Arc<RwLock<dyn FailedLoginStore + Send + Sync>>;
+pub type RateLimitStoreType = Arc<RwLock<dyn RateLimitStore + Send + Sync>>;
 
 #[derive(Clone)]
-pub struct AppState<T: UserStore, U: BannedTokenStore, V: TwoFACodeStore, X: EmailClient> {
-    pub user_store: Arc<RwLock<T>>,
-    pub banned_token_store: Arc<RwLock<U>>,
-    pub two_fa_code_store: Arc<RwLock<V>>,
-    pub email_client: Arc<RwLock<X>>,
+pub struct AppState {
+    pub user_store: UserStoreType,
+    pub banned_token_store: BannedTokenStoreType,
+    pub two_fa_code_store: TwoFACodeStoreType,
+    // Only used by the outbox worker. Routes queue emails in `email_outbox` instead of sending them
+    pub email_client: EmailClientType,
+    pub email_outbox: EmailOutboxType,
+    pub password_reset_token_store: PasswordResetTokenStoreType,
+    pub email_verification_token_store: EmailVerificationTokenStoreType,
+    pub refresh_token_store: RefreshTokenStoreType,
+    pub session_store: SessionStoreType,
+    pub oauth_client_store: OAuthClientStoreType,
+    pub service_account_store: ServiceAccountStoreType,
+    pub authorization_code_store: AuthorizationCodeStoreType,
+    pub device_authorization_store: DeviceAuthorizationStoreType,
+    pub failed_login_store: FailedLoginStoreType,
+    pub rate_limit_store: RateLimitStoreType,
+    pub rate_limits: Arc<RateLimits>,
+    // when true users can log in before verifying their email
+    pub allow_unverified_login: bool,
 }
 
-impl<T: UserStore, U: BannedTokenStore, V: TwoFACodeStore, X: EmailClient> AppState<T, U, V, X> {
+impl AppState {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        user_store: Arc<RwLock<T>>,
-        banned_token_store: Arc<RwLock<U>>,
-        two_fa_code_store: Arc<RwLock<V>>,
-        email_client: Arc<RwLock<X>>,
+        user_store: UserStoreType,
+        banned_token_store: BannedTokenStoreType,
+        two_fa_code_store: TwoFACodeStoreType,
+        email_client: EmailClientType,
+        email_outbox: EmailOutboxType,
+        password_reset_token_store: PasswordResetTokenStoreType,
+        email_verification_token_store: EmailVerificationTokenStoreType,
+        refresh_token_store: RefreshTokenStoreType,
+        session_store: SessionStoreType,
+        oauth_client_store: OAuthClientStoreType,
+        service_account_store: ServiceAccountStoreType,
+        authorization_code_store: AuthorizationCodeStoreType,
+        device_authorization_store: DeviceAuthorizationStoreType,
+        failed_login_store: FailedLoginStoreType,
+        rate_limit_store: RateLimitStoreType,
+        rate_limits: RateLimits,
+        allow_unverified_login: bool,
     ) -> Self {
         Self {
             user_store,
             banned_token_store,
             two_fa_code_store,
             email_client,
+            email_outbox,
+            password_reset_token_store,
+            email_verification_token_store,
+            refresh_token_store,
+            session_store,
+            oauth_client_store,
+            service_account_store,
+            authorization_code_store,
+            device_authorization_store,
+            failed_login_store,
+            rate_limit_store,
+            rate_limits: Arc::new(rate_limits),
+            allow_unverified_login,
         }
     }
 }
@@ -1,7 +1,11 @@
 
-use rand::Rng;
+use rand::{distr::Alphanumeric, Rng};
+use ring::digest::{digest, SHA256};
 
-use crate::domain::{email::Email, user::User};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::domain::{email::Email, email_message::EmailMessage, oauth::{AuthorizationGrant, DeviceAuthorization, DeviceAuthorizationStatus, OAuthClient, ServiceAccount}, password::Password, rate_limit::{RateLimit, RateLimitDecision}, session::Session, totp::TotpSecret, user::User};
 
 
 
@@ -12,12 +16,45 @@ pub trait UserStore  {
     async fn get_user(&self, email: &str) -> Result<User, UserStoreError > ;
     
     async fn validate_user(&self, email: &str, password: &str) -> Result<(), UserStoreError > ;
+
+    async fn update_password(&mut self, email: &Email, password: Password) -> Result<(), UserStoreError>;
+
+    async fn verify_email(&mut self, email: &Email) -> Result<(), UserStoreError>;
+
+    // A new TOTP secret is kept aside until the user proves their authenticator app
+    // works by sending a first code, and only then replaces the active one
+    async fn set_pending_totp_secret(&mut self, email: &Email, secret: TotpSecret) -> Result<(), UserStoreError>;
+
+    async fn get_pending_totp_secret(&self, email: &Email) -> Result<TotpSecret, UserStoreError>;
+
+    // Activate the pending secret and switch the user to TOTP based 2FA
+    async fn enable_totp(&mut self, email: &Email) -> Result<(), UserStoreError>;
+
+    async fn get_totp_secret(&self, email: &Email) -> Result<TotpSecret, UserStoreError>;
+
+    // Remember the time step of an accepted TOTP code. Codes from that step or an earlier one
+    // are refused from then on, so a code can't be replayed while it is still valid
+    async fn use_totp_step(&mut self, email: &Email, step: u64) -> Result<(), UserStoreError>;
+
+    // Replace all of the user's recovery codes. They are stored hashed, like passwords
+    async fn set_recovery_codes(&mut self, email: &Email, codes: &[RecoveryCode]) -> Result<(), UserStoreError>;
+
+    // Check `code` against the user's unused recovery codes and burn it if it matches
+    async fn use_recovery_code(&mut self, email: &Email, code: &RecoveryCode) -> Result<(), UserStoreError>;
+
+    // JWTs carry the epoch they were issued in and are only valid while it is the user's current one
+    async fn get_token_epoch(&self, email: &Email) -> Result<u32, UserStoreError>;
+
+    // Invalidate every JWT issued to the user so far, returning the new epoch
+    async fn bump_token_epoch(&mut self, email: &Email) -> Result<u32, UserStoreError>;
 }
 #[async_trait::async_trait]
 pub trait BannedTokenStore {
-    async fn store_token(&mut self,token: String)-> Result<(), BannedTokenStoreError>; 
-    
-    async fn is_token_banned(&self, token: String) -> Result<bool, BannedTokenStoreError>;
+    // Revoke the token with this `jti`. The entry is kept until `expires_at`, after which the
+    // token is rejected as expired anyway
+    async fn revoke_token(&mut self, jti: &str, expires_at: usize) -> Result<(), BannedTokenStoreError>;
+
+    async fn is_token_revoked(&self, jti: &str) -> Result<bool, BannedTokenStoreError>;
 }
 
 #[derive(Debug, PartialEq)]
@@ -25,6 +62,9 @@ pub enum UserStoreError {
     UserAlreadyExists,
     UserNotFound,
     InvalidCredentials,
+    TotpSecretNotFound,
+    TotpCodeAlreadyUsed,
+    InvalidRecoveryCode,
     UnexpectedError,
 }
 
@@ -47,11 +87,38 @@ pub trait TwoFACodeStore {
         &self,
         email: &Email,
     ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError>;
+    // Consume the login attempt if `code` is its code, in a single step so a code can't be used twice.
+    // Wrong codes count against the attempt, which is dropped after `MAX_TWO_FA_ATTEMPTS` of them
+    async fn verify_code(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        code: &TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError>;
+    // Same as `verify_code` for codes checked outside the store, like authenticator app or recovery codes
+    async fn settle_attempt(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        is_valid: bool,
+    ) -> Result<(), TwoFACodeStoreError>;
+    // Swap the code of a pending login attempt for a new one to send again. Refused for
+    // `TWO_FA_RESEND_COOLDOWN_SECONDS` after a code was issued and after `MAX_TWO_FA_RESENDS` resends
+    async fn replace_code(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        code: TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError>;
 }
 
 #[derive(Debug, PartialEq)]
 pub enum TwoFACodeStoreError {
     LoginAttemptIdNotFound,
+    IncorrectCode,
+    // holds the seconds until a code can be resent
+    ResendCooldown(u64),
+    TooManyResends,
     UnexpectedError,
 }
 
@@ -117,3 +184,516 @@ impl AsRef<str> for TwoFACode {
         self.0.as_ref()
     }
 }
+
+// Single-use backup code that can be used instead of a 2FA code, formatted as two groups
+// of 5 lowercase letters and digits (ex: 7gk2p-xm4qa)
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecoveryCode(String);
+
+impl RecoveryCode {
+    pub fn parse(code: String) -> Result<Self, String> {
+        // Users type these in by hand, so accept them without the dash and in any case
+        let code: String = code.chars().filter(|c| *c != '-').collect::<String>().to_ascii_lowercase();
+        if code.len() != RECOVERY_CODE_LENGTH || !code.chars().all(|c| RECOVERY_CODE_CHARSET.contains(&(c as u8))) {
+            return Err("Invalid recovery code".into());
+        }
+        let (first, second) = code.split_at(RECOVERY_CODE_LENGTH / 2);
+        Ok(Self(format!("{}-{}", first, second)))
+    }
+}
+
+impl Default for RecoveryCode {
+    fn default() -> Self {
+        let mut rng = rand::rng();
+        let code: String = (0..RECOVERY_CODE_LENGTH)
+            .map(|_| RECOVERY_CODE_CHARSET[rng.random_range(0..RECOVERY_CODE_CHARSET.len())] as char)
+            .collect();
+        Self::parse(code).expect("generated recovery codes are valid")
+    }
+}
+
+impl AsRef<str> for RecoveryCode {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+// Number of recovery codes a user gets every time they are generated
+pub const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+const RECOVERY_CODE_CHARSET: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+
+// This trait represents the interface all concrete password reset token stores should implement
+#[async_trait::async_trait]
+pub trait PasswordResetTokenStore {
+    async fn add_token(
+        &mut self,
+        email: Email,
+        token: PasswordResetToken,
+    ) -> Result<(), PasswordResetTokenStoreError>;
+    async fn remove_token(&mut self, email: &Email) -> Result<(), PasswordResetTokenStoreError>;
+    async fn get_token(&self, email: &Email) -> Result<PasswordResetToken, PasswordResetTokenStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PasswordResetTokenStoreError {
+    TokenNotFound,
+    UnexpectedError,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PasswordResetToken(String);
+
+impl PasswordResetToken {
+    pub fn parse(token: String) -> Result<Self, String> {
+        if is_valid_random_token(&token, EMAILED_TOKEN_LENGTH) {
+            Ok(Self(token))
+        } else {
+            Err("Invalid password reset token".into())
+        }
+    }
+}
+
+impl PasswordResetToken {
+    pub fn matches(&self, other: &Self) -> bool {
+        tokens_match(&self.0, &other.0)
+    }
+}
+
+impl Default for PasswordResetToken {
+    fn default() -> Self {
+        Self(generate_random_token(EMAILED_TOKEN_LENGTH))
+    }
+}
+
+impl AsRef<str> for PasswordResetToken {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+// This trait represents the interface all concrete email verification token stores should implement
+#[async_trait::async_trait]
+pub trait EmailVerificationTokenStore {
+    async fn add_token(
+        &mut self,
+        email: Email,
+        token: EmailVerificationToken,
+    ) -> Result<(), EmailVerificationTokenStoreError>;
+    async fn remove_token(&mut self, email: &Email) -> Result<(), EmailVerificationTokenStoreError>;
+    async fn get_token(&self, email: &Email) -> Result<EmailVerificationToken, EmailVerificationTokenStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EmailVerificationTokenStoreError {
+    TokenNotFound,
+    UnexpectedError,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmailVerificationToken(String);
+
+impl EmailVerificationToken {
+    pub fn parse(token: String) -> Result<Self, String> {
+        if is_valid_random_token(&token, EMAILED_TOKEN_LENGTH) {
+            Ok(Self(token))
+        } else {
+            Err("Invalid email verification token".into())
+        }
+    }
+}
+
+impl EmailVerificationToken {
+    pub fn matches(&self, other: &Self) -> bool {
+        tokens_match(&self.0, &other.0)
+    }
+}
+
+impl Default for EmailVerificationToken {
+    fn default() -> Self {
+        Self(generate_random_token(EMAILED_TOKEN_LENGTH))
+    }
+}
+
+impl AsRef<str> for EmailVerificationToken {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+// This trait represents the interface all concrete refresh token stores should implement.
+// Every login starts a new token family, and only the newest token of a family can be used.
+#[async_trait::async_trait]
+pub trait RefreshTokenStore {
+    // Store `token` and make it the current token of `family`
+    async fn add_token(
+        &mut self,
+        email: Email,
+        family: RefreshTokenFamily,
+        token: RefreshToken,
+    ) -> Result<(), RefreshTokenStoreError>;
+    async fn get_token(&self, token: &RefreshToken) -> Result<(Email, RefreshTokenFamily), RefreshTokenStoreError>;
+    async fn get_current_token(&self, family: &RefreshTokenFamily) -> Result<RefreshToken, RefreshTokenStoreError>;
+    async fn revoke_family(&mut self, family: &RefreshTokenFamily) -> Result<(), RefreshTokenStoreError>;
+    async fn revoke_user_families(&mut self, email: &Email) -> Result<(), RefreshTokenStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RefreshTokenStoreError {
+    TokenNotFound,
+    FamilyNotFound,
+    UnexpectedError,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RefreshToken(String);
+
+impl RefreshToken {
+    pub fn parse(token: String) -> Result<Self, String> {
+        if is_valid_random_token(&token, REFRESH_TOKEN_LENGTH) {
+            Ok(Self(token))
+        } else {
+            Err("Invalid refresh token".into())
+        }
+    }
+}
+
+impl Default for RefreshToken {
+    fn default() -> Self {
+        Self(generate_random_token(REFRESH_TOKEN_LENGTH))
+    }
+}
+
+impl AsRef<str> for RefreshToken {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RefreshTokenFamily(String);
+
+impl RefreshTokenFamily {
+    pub fn parse(id: String) -> Result<Self, String> {
+        match uuid::Uuid::parse_str(&id) {
+            Ok(uuid) => Ok(Self(uuid.into())),
+            Err(_) => Err("Failed to parse UUID".into()),
+        }
+    }
+}
+
+impl Default for RefreshTokenFamily {
+    fn default() -> Self {
+        Self(uuid::Uuid::new_v4().into())
+    }
+}
+
+impl AsRef<str> for RefreshTokenFamily {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+// This trait represents the interface all concrete session stores should implement.
+// A session lives as long as its refresh token family, so it expires along with it
+#[async_trait::async_trait]
+pub trait SessionStore {
+    async fn add_session(&mut self, session: Session) -> Result<(), SessionStoreError>;
+    async fn get_session(&self, id: &RefreshTokenFamily) -> Result<Session, SessionStoreError>;
+    // The user's sessions, most recently seen first
+    async fn get_user_sessions(&self, email: &Email) -> Result<Vec<Session>, SessionStoreError>;
+    // Record that a new JWT was issued for the session
+    async fn touch_session(&mut self, id: &RefreshTokenFamily, last_seen_at: DateTime<Utc>) -> Result<(), SessionStoreError>;
+    async fn remove_session(&mut self, id: &RefreshTokenFamily) -> Result<(), SessionStoreError>;
+    async fn remove_user_sessions(&mut self, email: &Email) -> Result<(), SessionStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SessionStoreError {
+    SessionNotFound,
+    UnexpectedError,
+}
+
+// Registered OAuth clients. Secrets are only ever compared, never handed back
+#[async_trait::async_trait]
+pub trait OAuthClientStore {
+    // `secret` is required for confidential clients and must be None for public ones
+    async fn add_client(&mut self, client: OAuthClient, secret: Option<String>) -> Result<(), OAuthClientStoreError>;
+    async fn get_client(&self, client_id: &str) -> Result<OAuthClient, OAuthClientStoreError>;
+    async fn validate_client_secret(&self, client_id: &str, secret: &str) -> Result<(), OAuthClientStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum OAuthClientStoreError {
+    ClientAlreadyExists,
+    ClientNotFound,
+    InvalidClientSecret,
+    UnexpectedError,
+}
+
+// Service accounts and their client secrets, for the client credentials grant at /oauth/token
+#[async_trait::async_trait]
+pub trait ServiceAccountStore {
+    async fn add_service_account(&mut self, account: ServiceAccount, secret: String) -> Result<(), ServiceAccountStoreError>;
+    async fn get_service_account(&self, id: &str) -> Result<ServiceAccount, ServiceAccountStoreError>;
+    async fn validate_secret(&self, id: &str, secret: &str) -> Result<(), ServiceAccountStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ServiceAccountStoreError {
+    AccountAlreadyExists,
+    AccountNotFound,
+    InvalidSecret,
+    UnexpectedError,
+}
+
+// Authorization codes waiting to be exchanged at /oauth/token. Codes are single use
+// and expire `AUTHORIZATION_CODE_TTL_SECONDS` after they are issued
+#[async_trait::async_trait]
+pub trait AuthorizationCodeStore {
+    async fn add_code(&mut self, code: AuthorizationCode, grant: AuthorizationGrant) -> Result<(), AuthorizationCodeStoreError>;
+    // Returns the grant and forgets the code, so it can't be exchanged twice
+    async fn take_code(&mut self, code: &AuthorizationCode) -> Result<AuthorizationGrant, AuthorizationCodeStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AuthorizationCodeStoreError {
+    CodeNotFound,
+    UnexpectedError,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AuthorizationCode(String);
+
+impl AuthorizationCode {
+    pub fn parse(code: String) -> Result<Self, String> {
+        if is_valid_random_token(&code, AUTHORIZATION_CODE_LENGTH) {
+            Ok(Self(code))
+        } else {
+            Err("Invalid authorization code".into())
+        }
+    }
+}
+
+impl Default for AuthorizationCode {
+    fn default() -> Self {
+        Self(generate_random_token(AUTHORIZATION_CODE_LENGTH))
+    }
+}
+
+impl AsRef<str> for AuthorizationCode {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+// Devices waiting for the user to approve them at /device (RFC 8628), by their device code and
+// by the user code typed in. Both expire `DEVICE_CODE_TTL_SECONDS` after they are issued
+#[async_trait::async_trait]
+pub trait DeviceAuthorizationStore {
+    async fn add_authorization(&mut self, device_code: DeviceCode, user_code: UserCode, authorization: DeviceAuthorization) -> Result<(), DeviceAuthorizationStoreError>;
+    async fn get_authorization(&self, user_code: &UserCode) -> Result<DeviceAuthorization, DeviceAuthorizationStoreError>;
+    // Record whether the user approved the device. The user code can only be used once
+    async fn decide(&mut self, user_code: &UserCode, status: DeviceAuthorizationStatus) -> Result<(), DeviceAuthorizationStoreError>;
+    // The device asking whether the user decided yet. A decided authorization is forgotten, so the
+    // device gets its tokens once. Polls closer together than `DEVICE_POLL_INTERVAL_SECONDS` fail.
+    // Polls by a client the code wasn't issued to fail before they count as a poll or use anything up
+    async fn poll(&mut self, device_code: &DeviceCode, client_id: &str) -> Result<(DeviceAuthorization, DeviceAuthorizationStatus), DeviceAuthorizationStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DeviceAuthorizationStoreError {
+    AuthorizationNotFound,
+    WrongClient,
+    PolledTooSoon,
+    UnexpectedError,
+}
+
+// What the device polls /oauth/token with, kept secret on the device
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DeviceCode(String);
+
+impl DeviceCode {
+    pub fn parse(code: String) -> Result<Self, String> {
+        if is_valid_random_token(&code, AUTHORIZATION_CODE_LENGTH) {
+            Ok(Self(code))
+        } else {
+            Err("Invalid device code".into())
+        }
+    }
+}
+
+impl Default for DeviceCode {
+    fn default() -> Self {
+        Self(generate_random_token(AUTHORIZATION_CODE_LENGTH))
+    }
+}
+
+impl AsRef<str> for DeviceCode {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+// What the user types in on another screen, e.g. "BCDF-GHJK". Consonants only, so codes
+// don't spell words and can't be mistaken for digits (RFC 8628 section 6.1)
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UserCode(String);
+
+impl UserCode {
+    // Accepts codes in any case, with or without the dash or spaces
+    pub fn parse(code: &str) -> Result<Self, String> {
+        let code: String = code.chars().filter(|c| *c != '-' && !c.is_whitespace()).map(|c| c.to_ascii_uppercase()).collect();
+        if code.len() == USER_CODE_LENGTH && code.chars().all(|c| USER_CODE_CHARACTERS.contains(c)) {
+            Ok(Self(code))
+        } else {
+            Err("Invalid user code".into())
+        }
+    }
+
+    // The code as shown to the user, in two halves
+    pub fn display(&self) -> String {
+        let (first, second) = self.0.split_at(USER_CODE_LENGTH / 2);
+        format!("{}-{}", first, second)
+    }
+}
+
+impl Default for UserCode {
+    fn default() -> Self {
+        let characters = USER_CODE_CHARACTERS.as_bytes();
+        let mut rng = rand::rng();
+        Self((0..USER_CODE_LENGTH).map(|_| char::from(characters[rng.random_range(0..characters.len())])).collect())
+    }
+}
+
+impl AsRef<str> for UserCode {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+// 20^8 codes, far too many to guess one of the few that are live at any time
+const USER_CODE_CHARACTERS: &str = "BCDFGHJKLMNPQRSTVWXZ";
+const USER_CODE_LENGTH: usize = 8;
+
+// Tokens that are sent to users by email are 32 random alphanumeric characters,
+// refresh tokens are longer since they live for much longer. Authorization codes
+// only live for a minute but pass through browsers, so they get a few more characters
+const EMAILED_TOKEN_LENGTH: usize = 32;
+const REFRESH_TOKEN_LENGTH: usize = 64;
+const AUTHORIZATION_CODE_LENGTH: usize = 43;
+
+fn generate_random_token(length: usize) -> String {
+    rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+fn is_valid_random_token(token: &str, length: usize) -> bool {
+    token.len() == length && token.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+// Compares the SHA-256 hashes of two secret tokens rather than the tokens themselves, so the time
+// a comparison takes says nothing about how much of a guessed token was right
+fn tokens_match(a: &str, b: &str) -> bool {
+    digest(&SHA256, a.as_bytes()).as_ref() == digest(&SHA256, b.as_bytes()).as_ref()
+}
+
+// Emails waiting to be delivered by the outbox worker, so a slow or failing mail server
+// never fails the request that triggered the email
+#[async_trait::async_trait]
+pub trait EmailOutbox {
+    async fn enqueue(&mut self, recipient: Email, message: EmailMessage) -> Result<Uuid, EmailOutboxError>;
+    // Hand out up to `limit` pending emails that are due at `now`. They are hidden from
+    // other workers until `lease_until`, after which they are retried if still pending
+    async fn claim_due(
+        &mut self,
+        now: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+        limit: u32,
+    ) -> Result<Vec<OutboxEmail>, EmailOutboxError>;
+    async fn mark_sent(&mut self, id: Uuid) -> Result<(), EmailOutboxError>;
+    // Record a failed delivery attempt. The email is retried at `retry_at`, or dead-lettered if it is None
+    async fn mark_failed(&mut self, id: Uuid, error: &str, retry_at: Option<DateTime<Utc>>) -> Result<(), EmailOutboxError>;
+    // Every email queued for `recipient`, newest first, to check on their delivery
+    async fn get_emails(&self, recipient: &Email) -> Result<Vec<OutboxEmail>, EmailOutboxError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EmailOutboxError {
+    EmailNotFound,
+    UnexpectedError,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutboxEmail {
+    pub id: Uuid,
+    pub recipient: Email,
+    pub message: EmailMessage,
+    pub status: EmailStatus,
+    // Failed delivery attempts so far
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EmailStatus {
+    Pending,
+    Sent,
+    // Gave up after too many failed attempts
+    Dead,
+}
+
+impl EmailStatus {
+    pub fn parse(status: &str) -> Result<Self, String> {
+        match status {
+            "pending" => Ok(Self::Pending),
+            "sent" => Ok(Self::Sent),
+            "dead" => Ok(Self::Dead),
+            other => Err(format!("Unknown email status: {}", other)),
+        }
+    }
+}
+
+impl AsRef<str> for EmailStatus {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Pending => "pending",
+            Self::Sent => "sent",
+            Self::Dead => "dead",
+        }
+    }
+}
+
+// Tracks failed logins per account so passwords can't be brute forced
+#[async_trait::async_trait]
+pub trait FailedLoginStore {
+    // Count a failed login for `email`, returning how many failures there were in the tracking window
+    async fn record_failure(&mut self, email: &Email) -> Result<u32, FailedLoginStoreError>;
+    async fn clear_failures(&mut self, email: &Email) -> Result<(), FailedLoginStoreError>;
+    // Refuse logins for `email` for the next `seconds`
+    async fn lock(&mut self, email: &Email, seconds: u64) -> Result<(), FailedLoginStoreError>;
+    // Seconds left before `email` can log in again, if it is locked
+    async fn get_lock(&self, email: &Email) -> Result<Option<u64>, FailedLoginStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FailedLoginStoreError {
+    UnexpectedError,
+}
+
+// Token buckets for the rate limiting middleware, one per route and client
+#[async_trait::async_trait]
+pub trait RateLimitStore {
+    // Take a token from the bucket under `key`, creating a full one if there is none
+    async fn take_token(&mut self, key: &str, limit: &RateLimit) -> Result<RateLimitDecision, RateLimitStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RateLimitStoreError {
+    UnexpectedError,
+}
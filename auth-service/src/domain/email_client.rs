@@ -1,7 +1,7 @@
-use crate::domain::email::Email;
+use crate::domain::{email::Email, email_message::EmailMessage};
 
 
 #[async_trait::async_trait]
 pub trait EmailClient {
-    async fn send_email(&self, recipient: &Email, subject: &str, content: &str) -> Result<(), String>;
+    async fn send_email(&self, recipient: &Email, message: &EmailMessage) -> Result<(), String>;
 }
@@ -0,0 +1,128 @@
+use askama::Template;
+
+use crate::domain::data_store::{PasswordResetToken, TwoFACode};
+
+// An email ready to be sent, with an HTML body and a plain text fallback
+// for clients that do not render HTML
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmailMessage {
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+impl EmailMessage {
+    pub fn two_fa_code(code: &TwoFACode) -> Result<Self, String> {
+        let code = code.as_ref();
+        Self::render("Your login code", TwoFACodeHtml { code }, TwoFACodeText { code })
+    }
+
+    pub fn email_verification(link: &str) -> Result<Self, String> {
+        Self::render("Verify your email", EmailVerificationHtml { link }, EmailVerificationText { link })
+    }
+
+    pub fn password_reset(token: &PasswordResetToken) -> Result<Self, String> {
+        let token = token.as_ref();
+        Self::render("Password reset", PasswordResetHtml { token }, PasswordResetText { token })
+    }
+
+    pub fn account_locked(minutes: u64) -> Result<Self, String> {
+        Self::render("Your account has been locked", AccountLockedHtml { minutes }, AccountLockedText { minutes })
+    }
+
+    fn render(subject: &str, html: impl Template, text: impl Template) -> Result<Self, String> {
+        Ok(Self {
+            subject: subject.to_owned(),
+            html_body: html.render().map_err(|e| e.to_string())?,
+            text_body: text.render().map_err(|e| e.to_string())?,
+        })
+    }
+}
+
+// Templates live in `templates/emails`. The HTML ones are escaped, the text ones are not
+#[derive(Template)]
+#[template(path = "emails/two_fa_code.html")]
+struct TwoFACodeHtml<'a> {
+    code: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/two_fa_code.txt")]
+struct TwoFACodeText<'a> {
+    code: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/email_verification.html")]
+struct EmailVerificationHtml<'a> {
+    link: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/email_verification.txt")]
+struct EmailVerificationText<'a> {
+    link: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/password_reset.html")]
+struct PasswordResetHtml<'a> {
+    token: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/password_reset.txt")]
+struct PasswordResetText<'a> {
+    token: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/account_locked.html")]
+struct AccountLockedHtml {
+    minutes: u64,
+}
+
+#[derive(Template)]
+#[template(path = "emails/account_locked.txt")]
+struct AccountLockedText {
+    minutes: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_two_fa_code_message() {
+        let code = TwoFACode::parse("123456".to_owned()).unwrap();
+        let message = EmailMessage::two_fa_code(&code).unwrap();
+        assert_eq!(message.subject, "Your login code");
+        assert!(message.html_body.contains("123456"));
+        assert!(message.text_body.contains("123456"));
+    }
+
+    #[test]
+    fn test_email_verification_message_escapes_html_only() {
+        let link = "http://localhost:3000/verify-email?email=a%40b.com&token=abc";
+        let message = EmailMessage::email_verification(link).unwrap();
+        assert!(message.html_body.contains("email=a%40b.com&amp;token=abc"));
+        assert!(message.text_body.contains(link));
+    }
+
+    #[test]
+    fn test_password_reset_message() {
+        let token = PasswordResetToken::default();
+        let message = EmailMessage::password_reset(&token).unwrap();
+        assert_eq!(message.subject, "Password reset");
+        assert!(message.html_body.contains(token.as_ref()));
+        assert!(message.text_body.contains(token.as_ref()));
+    }
+
+    #[test]
+    fn test_account_locked_message() {
+        let message = EmailMessage::account_locked(15).unwrap();
+        assert_eq!(message.subject, "Your account has been locked");
+        assert!(message.html_body.contains("15 minutes"));
+        assert!(message.text_body.contains("15 minutes"));
+    }
+}
@@ -5,4 +5,70 @@ pub enum AuthAPIError {
     UserAlreadyExists,
     MissingToken,
     InvalidToken,
+    EmailNotVerified,
+    Incorrect2FACode,
+    // Too many failed logins, holds the seconds until the account can be used again
+    AccountLocked(u64),
+    // Rate limited, holds the seconds until the client may try again
+    TooManyRequests(u64),
+    // The 2FA code was resent as often as allowed, the user has to log in again
+    TooManyResends,
+    // The session doesn't exist, or belongs to another user
+    SessionNotFound,
+    // No device is waiting for the code the user typed in, or it expired
+    UserCodeNotFound,
+    // A store the route can't safely do without is down
+    ServiceUnavailable,
+}
+
+// Errors of the /oauth endpoints, named by their RFC 6749 error codes. Clients get these
+// back as JSON, or as query parameters on their redirect URI from /oauth/authorize
+#[derive(Debug, PartialEq)]
+pub enum OAuthError {
+    InvalidRequest(String),
+    // The client is unknown or failed to authenticate
+    InvalidClient,
+    // The authorization code or refresh token is invalid, expired, used or was issued to another client
+    InvalidGrant(String),
+    UnsupportedGrantType,
+    UnsupportedResponseType,
+    InvalidScope(String),
+    // The bearer token is missing, invalid, expired or revoked (RFC 6750)
+    InvalidToken,
+    // The bearer token wasn't granted the scope the request needs (RFC 6750)
+    InsufficientScope,
+    // Answers to devices polling for tokens (RFC 8628 section 3.5): the user hasn't decided yet,
+    // the device polls too often, the user denied it, or the device code expired
+    AuthorizationPending,
+    SlowDown,
+    AccessDenied,
+    ExpiredToken,
+    ServerError,
+}
+
+impl OAuthError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            OAuthError::InvalidRequest(_) => "invalid_request",
+            OAuthError::InvalidClient => "invalid_client",
+            OAuthError::InvalidGrant(_) => "invalid_grant",
+            OAuthError::UnsupportedGrantType => "unsupported_grant_type",
+            OAuthError::UnsupportedResponseType => "unsupported_response_type",
+            OAuthError::InvalidScope(_) => "invalid_scope",
+            OAuthError::InvalidToken => "invalid_token",
+            OAuthError::InsufficientScope => "insufficient_scope",
+            OAuthError::AuthorizationPending => "authorization_pending",
+            OAuthError::SlowDown => "slow_down",
+            OAuthError::AccessDenied => "access_denied",
+            OAuthError::ExpiredToken => "expired_token",
+            OAuthError::ServerError => "server_error",
+        }
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        match self {
+            OAuthError::InvalidRequest(description) | OAuthError::InvalidGrant(description) | OAuthError::InvalidScope(description) => Some(description),
+            _ => None,
+        }
+    }
 }
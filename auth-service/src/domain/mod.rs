@@ -1,6 +1,11 @@
 pub mod data_store;
 pub mod email;
 pub mod email_client;
+pub mod email_message;
 pub mod error;
+pub mod oauth;
 pub mod password;
+pub mod rate_limit;
+pub mod session;
+pub mod totp;
 pub mod user;
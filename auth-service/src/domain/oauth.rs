@@ -0,0 +1,318 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::digest::{digest, SHA256};
+use url::Url;
+
+use crate::domain::{email::Email, user::User};
+
+// Clients get ID tokens and may call /userinfo with this scope (OpenID Connect)
+pub const OPENID_SCOPE: &str = "openid";
+// Lets clients see the user's email address and whether it is verified
+pub const EMAIL_SCOPE: &str = "email";
+
+// An application allowed to get tokens for users through /oauth/authorize
+#[derive(Clone, Debug, PartialEq)]
+pub struct OAuthClient {
+    pub client_id: String,
+    pub name: String,
+    // users are only ever sent back to one of these, compared exactly. Empty for device only clients
+    pub redirect_uris: Vec<String>,
+    // the scopes the client may ask for, and gets when it asks for none
+    pub scopes: Vec<String>,
+    // Confidential clients (servers) authenticate with a secret at /oauth/token,
+    // public ones (SPAs, mobile apps) can't keep a secret and rely on PKCE alone
+    pub confidential: bool,
+}
+
+impl OAuthClient {
+    pub fn new(
+        client_id: String,
+        name: String,
+        redirect_uris: Vec<String>,
+        scopes: Vec<String>,
+        confidential: bool,
+    ) -> Result<Self, String> {
+        if !is_client_id(&client_id) {
+            return Err(format!("Invalid client id '{}'", client_id));
+        }
+        // clients without redirect URIs, such as CLIs, can only use the device flow
+        for redirect_uri in &redirect_uris {
+            // custom schemes such as com.example.app:/callback are fine for mobile apps
+            let url = Url::parse(redirect_uri).map_err(|_| format!("Redirect URI '{}' is not an absolute URI", redirect_uri))?;
+            if url.fragment().is_some() {
+                return Err(format!("Redirect URI '{}' must not have a fragment", redirect_uri));
+            }
+        }
+        for scope in &scopes {
+            if !is_scope_token(scope) {
+                return Err(format!("Invalid scope '{}'", scope));
+            }
+        }
+        Ok(Self { client_id, name, redirect_uris, scopes, confidential })
+    }
+
+    // Where to send the user back to: the requested URI if it is registered, or the only
+    // registered URI when none was requested
+    pub fn redirect_uri(&self, requested: Option<&str>) -> Option<String> {
+        match (requested, self.redirect_uris.as_slice()) {
+            (Some(requested), registered) => registered.iter().find(|uri| *uri == requested).cloned(),
+            (None, [only]) => Some(only.clone()),
+            (None, _) => None,
+        }
+    }
+
+    // The scopes to grant for a space separated `scope` parameter, which may only
+    // name scopes the client is registered for
+    pub fn grant_scopes(&self, requested: Option<&str>) -> Result<Vec<String>, String> {
+        grant_scopes(&self.scopes, requested)
+    }
+}
+
+// A machine principal, such as a backend job, that gets tokens in its own name with the
+// client credentials grant. Its tokens carry its id as `sub` instead of a user's email
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServiceAccount {
+    pub id: String,
+    pub name: String,
+    // the scopes the account may ask for, and gets when it asks for none
+    pub scopes: Vec<String>,
+}
+
+impl ServiceAccount {
+    pub fn new(id: String, name: String, scopes: Vec<String>) -> Result<Self, String> {
+        // ids share the client_id parameter with OAuth clients, and must never pass for an email
+        if !is_client_id(&id) || id.contains('@') {
+            return Err(format!("Invalid service account id '{}'", id));
+        }
+        for scope in &scopes {
+            if !is_scope_token(scope) {
+                return Err(format!("Invalid scope '{}'", scope));
+            }
+        }
+        Ok(Self { id, name, scopes })
+    }
+
+    // The scopes to grant for a space separated `scope` parameter, see `OAuthClient::grant_scopes`
+    pub fn grant_scopes(&self, requested: Option<&str>) -> Result<Vec<String>, String> {
+        grant_scopes(&self.scopes, requested)
+    }
+}
+
+fn grant_scopes(allowed: &[String], requested: Option<&str>) -> Result<Vec<String>, String> {
+    let requested = match requested {
+        Some(requested) => parse_scope(requested)?,
+        None => return Ok(allowed.to_vec()),
+    };
+    match requested.iter().find(|scope| !allowed.contains(scope)) {
+        Some(scope) => Err(format!("The client may not ask for the '{}' scope", scope)),
+        None => Ok(requested),
+    }
+}
+
+fn is_client_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_graphic())
+}
+
+// Splits a space separated scope parameter (RFC 6749 section 3.3), dropping duplicates
+pub fn parse_scope(scope: &str) -> Result<Vec<String>, String> {
+    let mut scopes: Vec<String> = Vec::new();
+    for token in scope.split(' ').filter(|token| !token.is_empty()) {
+        if !is_scope_token(token) {
+            return Err(format!("Invalid scope '{}'", token));
+        }
+        if !scopes.iter().any(|scope| scope == token) {
+            scopes.push(token.to_owned());
+        }
+    }
+    Ok(scopes)
+}
+
+fn is_scope_token(token: &str) -> bool {
+    !token.is_empty() && token.chars().all(|c| c == '!' || ('#'..='[').contains(&c) || (']'..='~').contains(&c))
+}
+
+// PKCE (RFC 7636) code challenge. Only S256 is supported, "plain" would let anyone who
+// sees the authorization request redeem the code
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodeChallenge(String);
+
+impl CodeChallenge {
+    pub fn parse(challenge: String, method: Option<&str>) -> Result<Self, String> {
+        if method != Some("S256") {
+            return Err("code_challenge_method must be S256".to_owned());
+        }
+        // base64url without padding of a SHA-256 hash
+        match URL_SAFE_NO_PAD.decode(&challenge) {
+            Ok(hash) if hash.len() == 32 => Ok(Self(challenge)),
+            _ => Err("Invalid code_challenge".to_owned()),
+        }
+    }
+
+    // Whether `verifier` is the secret the challenge was derived from
+    pub fn verify(&self, verifier: &str) -> bool {
+        let is_unreserved = |c: char| c.is_ascii_alphanumeric() || "-._~".contains(c);
+        (43..=128).contains(&verifier.len())
+            && verifier.chars().all(is_unreserved)
+            && URL_SAFE_NO_PAD.encode(digest(&SHA256, verifier.as_bytes())) == self.0
+    }
+}
+
+impl AsRef<str> for CodeChallenge {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// What an authorization code stands for until the client exchanges it for tokens
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuthorizationGrant {
+    pub client_id: String,
+    pub redirect_uri: String,
+    // whether the client sent the redirect URI or left it to the single registered one.
+    // If it sent it, it has to send it again with the code (RFC 6749 section 4.1.3)
+    pub redirect_uri_supplied: bool,
+    pub email: Email,
+    pub scopes: Vec<String>,
+    pub code_challenge: CodeChallenge,
+    // the browser the user authorized the client in, recorded in the session the tokens are issued in
+    pub device: String,
+    pub ip: String,
+    // passed on to the ID token, so the client can tell it answers its own request
+    pub nonce: Option<String>,
+    // how the user logged in, see `Session`
+    pub amr: Vec<String>,
+}
+
+// A device, such as a CLI, waiting for a user to approve it on another screen (RFC 8628)
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceAuthorization {
+    pub client_id: String,
+    pub scopes: Vec<String>,
+    // the device asking, recorded in the session the tokens are issued in
+    pub device: String,
+    pub ip: String,
+}
+
+// Where a device authorization stands, as the device finds out by polling /oauth/token
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeviceAuthorizationStatus {
+    Pending,
+    // the user approved the device, logged in as described by `amr` (see `Session`)
+    Approved { email: Email, amr: Vec<String> },
+    Denied,
+}
+
+// The claims about a user an OpenID Connect client gets in ID tokens and from /userinfo,
+// depending on the scopes it was granted. `sub` is the user's id, which stays the same if their email changes
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserInfo {
+    pub sub: String,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+}
+
+impl UserInfo {
+    pub fn new(user: &User, scopes: &[String]) -> Self {
+        let email_scope = scopes.iter().any(|scope| scope == EMAIL_SCOPE);
+        Self {
+            sub: user.id.to_string(),
+            email: email_scope.then(|| user.email.as_ref().to_owned()),
+            email_verified: email_scope.then_some(user.email_verified),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::password::Password;
+
+    fn client(redirect_uris: &[&str]) -> OAuthClient {
+        OAuthClient::new(
+            "dashboard".to_owned(),
+            "Dashboard".to_owned(),
+            redirect_uris.iter().map(|uri| uri.to_string()).collect(),
+            vec!["read".to_owned(), "write".to_owned()],
+            false,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_redirect_uris_must_match_exactly() {
+        let single = client(&["https://app.example.com/callback"]);
+        assert_eq!(single.redirect_uri(None).as_deref(), Some("https://app.example.com/callback"));
+        assert_eq!(single.redirect_uri(Some("https://app.example.com/callback")).as_deref(), Some("https://app.example.com/callback"));
+        assert_eq!(single.redirect_uri(Some("https://app.example.com/callback/")), None);
+        assert_eq!(single.redirect_uri(Some("https://app.example.com/callback?next=/admin")), None);
+        assert_eq!(single.redirect_uri(Some("https://evil.example.com/callback")), None);
+
+        // with several URIs the client has to say which one it wants
+        let several = client(&["https://app.example.com/callback", "com.example.app:/callback"]);
+        assert_eq!(several.redirect_uri(None), None);
+        assert_eq!(several.redirect_uri(Some("com.example.app:/callback")).as_deref(), Some("com.example.app:/callback"));
+    }
+
+    #[test]
+    fn test_clients_are_validated() {
+        let new = |client_id: &str, redirect_uri: &str| {
+            OAuthClient::new(client_id.to_owned(), "App".to_owned(), vec![redirect_uri.to_owned()], vec![], true)
+        };
+        assert!(new("app", "https://app.example.com/callback").is_ok());
+        assert!(new("", "https://app.example.com/callback").is_err());
+        assert!(new("my app", "https://app.example.com/callback").is_err());
+        assert!(new("app", "/callback").is_err());
+        assert!(new("app", "https://app.example.com/callback#token").is_err());
+        // device only clients don't need any
+        assert!(OAuthClient::new("cli".to_owned(), "CLI".to_owned(), vec![], vec![], false).is_ok());
+    }
+
+    #[test]
+    fn test_grant_scopes() {
+        let client = client(&["https://app.example.com/callback"]);
+        assert_eq!(client.grant_scopes(None), Ok(vec!["read".to_owned(), "write".to_owned()]));
+        assert_eq!(client.grant_scopes(Some("read read")), Ok(vec!["read".to_owned()]));
+        assert!(client.grant_scopes(Some("read admin")).is_err());
+        assert!(client.grant_scopes(Some("re\"ad")).is_err());
+    }
+
+    #[test]
+    fn test_service_accounts() {
+        let new = |id: &str| ServiceAccount::new(id.to_owned(), "Nightly export".to_owned(), vec!["read".to_owned()]);
+        assert!(new("nightly-export").is_ok());
+        assert!(new("").is_err());
+        assert!(new("nightly export").is_err());
+        assert!(new("jobs@example.com").is_err());
+
+        let account = new("nightly-export").unwrap();
+        assert_eq!(account.grant_scopes(None), Ok(vec!["read".to_owned()]));
+        assert!(account.grant_scopes(Some("read write")).is_err());
+    }
+
+    #[test]
+    fn test_user_info_is_filtered_by_scope() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let user = User::new(email, Password::parse("Password123".to_owned()).unwrap(), false);
+
+        let user_info = UserInfo::new(&user, &["openid".to_owned()]);
+        assert_eq!(user_info.sub, user.id.to_string());
+        assert_eq!(user_info.email, None);
+        assert_eq!(user_info.email_verified, None);
+
+        let user_info = UserInfo::new(&user, &["openid".to_owned(), "email".to_owned()]);
+        assert_eq!(user_info.email.as_deref(), Some("test@example.com"));
+        assert_eq!(user_info.email_verified, Some(false));
+    }
+
+    #[test]
+    fn test_pkce() {
+        // the example from RFC 7636 appendix B
+        let challenge = CodeChallenge::parse("E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM".to_owned(), Some("S256")).unwrap();
+        assert!(challenge.verify("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"));
+        assert!(!challenge.verify("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXj"));
+        assert!(!challenge.verify("too-short"));
+
+        assert!(CodeChallenge::parse("E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM".to_owned(), Some("plain")).is_err());
+        assert!(CodeChallenge::parse("E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM".to_owned(), None).is_err());
+        assert!(CodeChallenge::parse("not a challenge".to_owned(), Some("S256")).is_err());
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+// Token bucket allowing bursts of up to `capacity` requests, refilled continuously
+// so that `capacity` requests are allowed per `period_seconds` on average
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub capacity: u32,
+    pub period_seconds: u64,
+}
+
+impl RateLimit {
+    // Parses `<requests>/<seconds>`, e.g. "10/60"
+    pub fn parse(limit: &str) -> Result<Self, String> {
+        let (capacity, period) = limit
+            .trim()
+            .split_once('/')
+            .ok_or(format!("Rate limit '{}' should look like <requests>/<seconds>", limit))?;
+        let capacity: u32 = capacity.trim().parse().map_err(|_| format!("Invalid request count in rate limit '{}'", limit))?;
+        let period_seconds: u64 = period.trim().parse().map_err(|_| format!("Invalid period in rate limit '{}'", limit))?;
+        if capacity == 0 || period_seconds == 0 {
+            return Err(format!("Rate limit '{}' must allow at least one request over a non-zero period", limit));
+        }
+        Ok(Self { capacity, period_seconds })
+    }
+
+    pub fn period_millis(&self) -> u64 {
+        self.period_seconds * 1000
+    }
+
+    // Tokens added back to the bucket per millisecond
+    fn refill_rate(&self) -> f64 {
+        f64::from(self.capacity) / self.period_millis() as f64
+    }
+
+    // Refill a bucket holding `tokens` last updated `elapsed_millis` ago and try to take a token from it.
+    // Returns the tokens left in the bucket along with the decision
+    pub fn take(&self, tokens: f64, elapsed_millis: u64) -> (f64, RateLimitDecision) {
+        let tokens = (tokens + elapsed_millis as f64 * self.refill_rate()).min(f64::from(self.capacity));
+        let allowed = tokens >= 1.0;
+        let tokens = if allowed { tokens - 1.0 } else { tokens };
+        (tokens, self.decision(allowed, tokens))
+    }
+
+    // What to tell the client given whether the request was allowed and the tokens left afterwards
+    pub fn decision(&self, allowed: bool, tokens: f64) -> RateLimitDecision {
+        let seconds_until = |target: f64| ((target - tokens).max(0.0) / self.refill_rate() / 1000.0).ceil() as u64;
+        RateLimitDecision {
+            allowed,
+            limit: self.capacity,
+            remaining: tokens.floor() as u32,
+            reset_seconds: seconds_until(f64::from(self.capacity)),
+            retry_after_seconds: if allowed { 0 } else { seconds_until(1.0).max(1) },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    // Seconds until the bucket is full again
+    pub reset_seconds: u64,
+    // Seconds until the next request would be allowed, 0 if this one was
+    pub retry_after_seconds: u64,
+}
+
+// Limits per route, with a default for the routes that aren't listed
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimits {
+    pub default: RateLimit,
+    pub routes: HashMap<String, RateLimit>,
+}
+
+impl RateLimits {
+    // Parses a comma separated list of `<route>=<requests>/<seconds>`, e.g. "/login=10/60,default=60/60".
+    // Routes that aren't listed keep their default limit
+    pub fn parse(limits: &str) -> Result<Self, String> {
+        let mut rate_limits = Self::default();
+        for entry in limits.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (route, limit) = entry
+                .split_once('=')
+                .ok_or(format!("Rate limit '{}' should look like <route>=<requests>/<seconds>", entry))?;
+            let limit = RateLimit::parse(limit)?;
+            match route.trim() {
+                "default" => rate_limits.default = limit,
+                route if route.starts_with('/') => {
+                    rate_limits.routes.insert(route.to_owned(), limit);
+                }
+                route => return Err(format!("Unknown route '{}' in rate limits, routes start with '/'", route)),
+            }
+        }
+        Ok(rate_limits)
+    }
+
+    pub fn for_route(&self, route: &str) -> RateLimit {
+        self.routes.get(route).copied().unwrap_or(self.default)
+    }
+}
+
+impl Default for RateLimits {
+    // Routes that check credentials, including client secrets, or send emails get tighter limits
+    fn default() -> Self {
+        let limit = |capacity| RateLimit { capacity, period_seconds: 60 };
+        Self {
+            default: limit(60),
+            routes: HashMap::from([
+                ("/signup".to_owned(), limit(5)),
+                ("/login".to_owned(), limit(10)),
+                ("/verify-2fa".to_owned(), limit(10)),
+                ("/verify-2fa/resend".to_owned(), limit(5)),
+                ("/password-reset/request".to_owned(), limit(5)),
+                ("/password-reset/confirm".to_owned(), limit(10)),
+                ("/verify-email/resend".to_owned(), limit(5)),
+                // leaves room for a device polling every 5 seconds
+                ("/oauth/token".to_owned(), limit(20)),
+                ("/oauth/introspect".to_owned(), limit(20)),
+                ("/oauth/revoke".to_owned(), limit(20)),
+                ("/oauth/device_authorization".to_owned(), limit(20)),
+            ]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate_limits() {
+        let limits = RateLimits::parse("/login=3/10, default=100/60").unwrap();
+        assert_eq!(limits.for_route("/login"), RateLimit { capacity: 3, period_seconds: 10 });
+        assert_eq!(limits.for_route("/logout"), RateLimit { capacity: 100, period_seconds: 60 });
+        // unlisted routes keep their defaults
+        assert_eq!(limits.for_route("/signup"), RateLimit { capacity: 5, period_seconds: 60 });
+
+        assert_eq!(RateLimits::parse(""), Ok(RateLimits::default()));
+        assert!(RateLimits::parse("/login").is_err());
+        assert!(RateLimits::parse("login=3/10").is_err());
+        assert!(RateLimits::parse("/login=3").is_err());
+        assert!(RateLimits::parse("/login=0/10").is_err());
+    }
+
+    #[test]
+    fn test_take_until_empty() {
+        let limit = RateLimit { capacity: 2, period_seconds: 10 };
+
+        let (tokens, decision) = limit.take(2.0, 0);
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 1);
+        assert_eq!(decision.reset_seconds, 5);
+
+        let (tokens, decision) = limit.take(tokens, 0);
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 0);
+
+        let (tokens, decision) = limit.take(tokens, 0);
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after_seconds, 5);
+        assert_eq!(decision.reset_seconds, 10);
+
+        // a token is back after period / capacity
+        let (_, decision) = limit.take(tokens, 5_000);
+        assert!(decision.allowed);
+    }
+
+    #[test]
+    fn test_take_never_overfills() {
+        let limit = RateLimit { capacity: 2, period_seconds: 10 };
+        let (tokens, decision) = limit.take(0.0, 1_000_000);
+        assert!(decision.allowed);
+        assert_eq!(tokens, 1.0);
+    }
+}
@@ -0,0 +1,90 @@
+use chrono::{DateTime, Utc};
+
+use crate::domain::{data_store::RefreshTokenFamily, email::Email};
+
+// Longest device label kept, User-Agent headers can be arbitrarily long
+const MAX_DEVICE_LENGTH: usize = 100;
+
+// A device the user is logged in on. Every login starts a session, identified by the refresh
+// token family it starts, and the JWTs issued for it carry its id in their `sid` claim
+#[derive(Clone, Debug, PartialEq)]
+pub struct Session {
+    pub id: RefreshTokenFamily,
+    pub email: Email,
+    pub device: String,
+    pub ip: String,
+    pub created_at: DateTime<Utc>,
+    // the last time a JWT was issued for the session, at login or when refreshing
+    pub last_seen_at: DateTime<Utc>,
+    // the OAuth client the session was started for, None for logins through our own UI
+    pub client_id: Option<String>,
+    // the scopes granted to the OAuth client
+    pub scopes: Vec<String>,
+    // how the user proved who they are when the session started, as RFC 8176 `amr` values
+    pub amr: Vec<String>,
+}
+
+impl Session {
+    pub fn new(id: RefreshTokenFamily, email: Email, device: String, ip: String) -> Self {
+        let now = Utc::now();
+        Self { id, email, device, ip, created_at: now, last_seen_at: now, client_id: None, scopes: Vec::new(), amr: Vec::new() }
+    }
+}
+
+// Authentication methods of a login: a password, plus a one-time code (emailed, from an
+// authenticator app or a recovery code) for users with 2FA
+pub fn authentication_methods(two_factor: bool) -> Vec<String> {
+    let methods: &[&str] = if two_factor { &["pwd", "otp", "mfa"] } else { &["pwd"] };
+    methods.iter().map(|method| method.to_string()).collect()
+}
+
+// Label a device by its browser and OS, e.g. "Firefox on Linux", falling back to the
+// (shortened) User-Agent itself when neither is recognised
+pub fn device_label(user_agent: Option<&str>) -> String {
+    let user_agent = match user_agent.map(str::trim) {
+        Some(user_agent) if !user_agent.is_empty() => user_agent,
+        _ => return "Unknown device".to_owned(),
+    };
+
+    // Order matters: Edge and Opera also claim to be Chrome, and Chrome claims to be Safari
+    let browser = [("Edg/", "Edge"), ("OPR/", "Opera"), ("Firefox/", "Firefox"), ("Chrome/", "Chrome"), ("Safari/", "Safari")]
+        .into_iter()
+        .find(|(token, _)| user_agent.contains(token))
+        .map(|(_, name)| name);
+    // Android and iOS user agents also mention Linux and Mac OS X
+    let os = [("Android", "Android"), ("iPhone", "iOS"), ("iPad", "iOS"), ("Windows", "Windows"), ("Mac OS X", "macOS"), ("Linux", "Linux")]
+        .into_iter()
+        .find(|(token, _)| user_agent.contains(token))
+        .map(|(_, name)| name);
+
+    match (browser, os) {
+        (Some(browser), Some(os)) => format!("{} on {}", browser, os),
+        (Some(name), None) | (None, Some(name)) => name.to_owned(),
+        (None, None) => user_agent.chars().take(MAX_DEVICE_LENGTH).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_label() {
+        assert_eq!(
+            device_label(Some("Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0")),
+            "Firefox on Linux"
+        );
+        assert_eq!(
+            device_label(Some("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Safari/537.36 Edg/126.0.0.0")),
+            "Edge on Windows"
+        );
+        assert_eq!(
+            device_label(Some("Mozilla/5.0 (iPhone; CPU iPhone OS 17_5 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.5 Mobile/15E148 Safari/604.1")),
+            "Safari on iOS"
+        );
+        assert_eq!(device_label(Some("curl/8.5.0")), "curl/8.5.0");
+        assert_eq!(device_label(Some(&"x".repeat(500))).len(), MAX_DEVICE_LENGTH);
+        assert_eq!(device_label(Some("  ")), "Unknown device");
+        assert_eq!(device_label(None), "Unknown device");
+    }
+}
@@ -0,0 +1,139 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::{domain::{data_store::TwoFACode, email::Email}, utils::constants::TOTP_ISSUER};
+
+// Shared secret between the auth service and the user's authenticator app (RFC 6238),
+// kept base32 encoded as that is how authenticator apps expect it
+#[derive(Clone, Debug, PartialEq)]
+pub struct TotpSecret(String);
+
+impl TotpSecret {
+    pub fn parse(secret: String) -> Result<Self, String> {
+        let bytes = Secret::Encoded(secret.clone())
+            .to_bytes()
+            .map_err(|_| "TOTP secret is not valid base32".to_owned())?;
+        // RFC 4226 requires at least 128 bits of shared secret
+        if bytes.len() < 16 {
+            return Err("TOTP secret is too short".into());
+        }
+        Ok(Self(secret))
+    }
+
+    // otpauth:// URI that authenticator apps import, usually by scanning it as a QR code
+    pub fn provisioning_uri(&self, email: &Email) -> Result<String, String> {
+        Ok(self.totp(email)?.get_url())
+    }
+
+    // Returns the 30 second time step the code belongs to, or None if it is wrong, so callers
+    // can refuse a code that was already used. Codes for the current step are accepted, as well as
+    // the steps right before and after it to allow for some clock drift between the server and the user's device
+    pub fn verify(&self, email: &Email, code: &TwoFACode) -> Result<Option<u64>, String> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| e.to_string())?
+            .as_secs();
+        self.verify_at(email, code, now)
+    }
+
+    fn verify_at(&self, email: &Email, code: &TwoFACode, time: u64) -> Result<Option<u64>, String> {
+        let mut totp = self.totp(email)?;
+        // check the steps one by one to know which of them matched
+        totp.skew = 0;
+        let current_step = time / totp.step;
+        Ok([current_step - 1, current_step, current_step + 1]
+            .into_iter()
+            .find(|step| totp.check(code.as_ref(), step * totp.step)))
+    }
+
+    fn totp(&self, email: &Email) -> Result<TOTP, String> {
+        let bytes = Secret::Encoded(self.0.clone()).to_bytes().map_err(|e| format!("{:?}", e))?;
+        TOTP::new(
+            Algorithm::SHA1,
+            6,
+            1,
+            30,
+            bytes,
+            Some(TOTP_ISSUER.to_owned()),
+            email.as_ref().to_owned(),
+        )
+        .map_err(|e| e.to_string())
+    }
+}
+
+impl Default for TotpSecret {
+    fn default() -> Self {
+        // 160 bit secret, as recommended by RFC 4226
+        match Secret::generate_secret().to_encoded() {
+            Secret::Encoded(secret) => Self(secret),
+            Secret::Raw(_) => unreachable!("to_encoded always returns an encoded secret"),
+        }
+    }
+}
+
+impl AsRef<str> for TotpSecret {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn current_code(secret: &TotpSecret, email: &Email) -> TwoFACode {
+        let code = secret.totp(email).unwrap().generate_current().unwrap();
+        TwoFACode::parse(code).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let secret = TotpSecret::default();
+        assert_eq!(TotpSecret::parse(secret.as_ref().to_owned()), Ok(secret));
+        assert!(TotpSecret::parse("not base32!".to_owned()).is_err());
+        assert!(TotpSecret::parse("JBSWY3DPEHPK3PXP".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_provisioning_uri() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let secret = TotpSecret::default();
+        let uri = secret.provisioning_uri(&email).unwrap();
+        assert!(uri.starts_with("otpauth://totp/"));
+        assert!(uri.contains(&format!("secret={}", secret.as_ref())));
+        assert!(uri.contains("test%40example.com"));
+    }
+
+    #[test]
+    fn test_verify() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let secret = TotpSecret::default();
+        let code = current_code(&secret, &email);
+        assert!(secret.verify(&email, &code).unwrap().is_some());
+
+        // a code generated from a different secret is rejected
+        let other_code = current_code(&TotpSecret::default(), &email);
+        if other_code != code {
+            assert_eq!(secret.verify(&email, &other_code), Ok(None));
+        }
+    }
+
+    #[test]
+    fn test_verify_returns_matching_step() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let secret = TotpSecret::default();
+        let totp = secret.totp(&email).unwrap();
+        let time = 1_700_000_000;
+        let code_at = |time| TwoFACode::parse(totp.generate(time)).unwrap();
+
+        assert_eq!(secret.verify_at(&email, &code_at(time), time), Ok(Some(time / 30)));
+        // one step of drift either way is allowed
+        assert_eq!(secret.verify_at(&email, &code_at(time - 30), time), Ok(Some(time / 30 - 1)));
+        assert_eq!(secret.verify_at(&email, &code_at(time + 30), time), Ok(Some(time / 30 + 1)));
+        let old_code = code_at(time - 90);
+        if old_code != code_at(time - 30) && old_code != code_at(time) && old_code != code_at(time + 30) {
+            assert_eq!(secret.verify_at(&email, &old_code, time), Ok(None));
+        }
+    }
+}
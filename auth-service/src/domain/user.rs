@@ -1,20 +1,59 @@
+use uuid::Uuid;
+
 use crate::domain::{email::Email, password::Password};
 
-// The User struct shoudl contain 3 fields.  email, which is a String;
-// pssword, also a String; and requires_2fa, whih is a boolean
+// The User struct shoudl contain 4 fields.  email, which is a String;
+// pssword, also a String; requires_2fa, whih is a boolean
+// and email_verified, which is false until the user confirms they own the email
+// two_fa_method says how users that require 2FA receive their codes
+// id never changes, unlike the email, so it is what other services know the user by
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub struct User {
+    pub id: Uuid,
     pub email: Email,
     pub password: Password,
     pub requires_2fa: bool,
+    pub email_verified: bool,
+    pub two_fa_method: TwoFAMethod,
 }
 
 impl User {
+    // New users always start with an unverified email
     pub fn new(email: Email, password: Password, requires_2fa: bool) -> Self {
         User {
+            id: Uuid::new_v4(),
             email,
             password,
             requires_2fa,
+            email_verified: false,
+            two_fa_method: TwoFAMethod::Email,
+        }
+    }
+}
+
+// Codes are either emailed to the user, or generated by an authenticator app
+// once the user has enrolled one
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum TwoFAMethod {
+    Email,
+    Totp,
+}
+
+impl TwoFAMethod {
+    pub fn parse(method: &str) -> Result<Self, String> {
+        match method {
+            "email" => Ok(Self::Email),
+            "totp" => Ok(Self::Totp),
+            _ => Err(format!("Unknown 2FA method: {}", method)),
+        }
+    }
+}
+
+impl AsRef<str> for TwoFAMethod {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Email => "email",
+            Self::Totp => "totp",
         }
     }
 }
@@ -1,13 +1,13 @@
 //This struct encapsulates our application-related logic
 
-use std::error::Error;
+use std::{error::Error, net::SocketAddr};
 
-use axum::{http::{Method, StatusCode}, response::IntoResponse, routing::post, serve::Serve, Json, Router};
+use axum::{extract::{connect_info::IntoMakeServiceWithConnectInfo, ConnectInfo}, http::{header::{CACHE_CONTROL, RETRY_AFTER, WWW_AUTHENTICATE}, HeaderValue, Method, StatusCode}, middleware::{self, AddExtension}, response::IntoResponse, routing::{delete, get, post}, serve::Serve, Json, Router};
 use redis::{Client, RedisResult};
 use serde::{Deserialize, Serialize};
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use tower_http::{cors::CorsLayer, services::ServeDir};
-use crate::{app_state::AppState, domain::error::AuthAPIError, routes::{login, logout, signup, verify2fa, verify_token }, services::{data_store::PostgresUserStore, mock_email_client::MockEmailClient, redis_banned_token_store::RedisBannedTokenStore, redis_two_fa_code_store::RedisTwoFACodeStore}};
+use crate::{app_state::AppState, domain::error::{AuthAPIError, OAuthError}, services::{email_outbox_worker::EmailOutboxWorker, jwt_keyring_reloader::JwtKeyringReloader}, utils::constants::{JWT_KEYRING, JWT_KEYRING_FILE}, routes::{authorize, confirm_password_reset, confirm_totp, decide_device_authorization, delete_session, device_authorization, enroll_totp, get_device_authorization, get_sessions, introspect, jwks, login, logout, logout_all, oauth_token, openid_configuration, rate_limit, refresh_token, regenerate_recovery_codes, request_password_reset, resend_2fa_code, resend_verification_email, revoke, signup, userinfo, verify2fa, verify_email, verify_token }};
 
 
 pub mod routes;
@@ -17,16 +17,17 @@ pub mod app_state;
 pub mod utils;
 
 pub struct Application {
-    server: Serve<Router, Router>,
+    server: Serve<IntoMakeServiceWithConnectInfo<Router, SocketAddr>, AddExtension<Router, ConnectInfo<SocketAddr>>>,
     // adress is exposed as a public field
     // so we have access to it in tests.
     pub address: String,
+    email_outbox_worker: EmailOutboxWorker,
+    // Only runs when the keys come from a keyring file
+    jwt_keyring_reloader: Option<JwtKeyringReloader>,
 }
 
 impl Application {
-    pub async fn build(app_state: AppState<PostgresUserStore,
-        RedisBannedTokenStore, RedisTwoFACodeStore, MockEmailClient>,
-        address: &str ) -> Result<Self, Box<dyn Error>> {
+    pub async fn build(app_state: AppState, address: &str ) -> Result<Self, Box<dyn Error>> {
         // Allow the app service (running on our local machine & in production) to call the auth service
         let allowed_origins = [
             "http://localhost:8000".parse()?,
@@ -37,27 +38,61 @@ impl Application {
             .allow_methods([Method::GET, Method::POST])
             .allow_credentials(true)
             .allow_origin(allowed_origins);
+        let email_outbox_worker = EmailOutboxWorker::new(app_state.email_outbox.clone(), app_state.email_client.clone());
+        let jwt_keyring_reloader = JWT_KEYRING_FILE.as_ref().map(|path| JwtKeyringReloader::new(path.into(), &JWT_KEYRING));
         let router = Router::new()
-            .nest_service("/", ServeDir::new("assets"))
             .route("/signup", post(signup))
             .route("/login", post(login))
             .route("/logout", post(logout))
+            .route("/logout/all", post(logout_all))
             .route("/verify-2fa", post(verify2fa))
+            .route("/verify-2fa/resend", post(resend_2fa_code))
             .route("/verify-token", post(verify_token))
+            .route("/password-reset/request", post(request_password_reset))
+            .route("/password-reset/confirm", post(confirm_password_reset))
+            .route("/verify-email", get(verify_email))
+            .route("/verify-email/resend", post(resend_verification_email))
+            .route("/token/refresh", post(refresh_token))
+            .route("/totp/enroll", post(enroll_totp))
+            .route("/totp/confirm", post(confirm_totp))
+            .route("/recovery-codes", post(regenerate_recovery_codes))
+            .route("/sessions", get(get_sessions))
+            .route("/sessions/:id", delete(delete_session))
+            .route("/oauth/authorize", get(authorize))
+            .route("/oauth/token", post(oauth_token))
+            .route("/oauth/device_authorization", post(device_authorization))
+            .route("/oauth/device", get(get_device_authorization).post(decide_device_authorization))
+            .route("/oauth/introspect", post(introspect))
+            .route("/oauth/revoke", post(revoke))
+            .route("/userinfo", get(userinfo).post(userinfo))
+            .route("/.well-known/jwks.json", get(jwks))
+            .route("/.well-known/openid-configuration", get(openid_configuration))
+            // only applies to the routes above, not to the static assets
+            .route_layer(middleware::from_fn_with_state(app_state.clone(), rate_limit))
+            .nest_service("/", ServeDir::new("assets"))
             .with_state(app_state)
             .layer(cors);
 
         let listener = tokio::net::TcpListener::bind(address).await?;
         let address = listener.local_addr()?.to_string();
-        let server = axum::serve(listener, router);
+        // the rate limiter needs to know which address each request comes from
+        let server = axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>());
 
         // Create a new Application instance & return it
-        Ok(Self { server, address })
+        Ok(Self { server, address, email_outbox_worker, jwt_keyring_reloader })
     }
 
     pub async fn run(self) -> Result<(), std::io::Error> {
         println!("listening on {}", &self.address);
-        self.server.await
+        // Deliver queued emails in the background for as long as the server runs
+        let email_outbox_worker = tokio::spawn(self.email_outbox_worker.run());
+        let jwt_keyring_reloader = self.jwt_keyring_reloader.map(|reloader| tokio::spawn(reloader.run()));
+        let result = self.server.await;
+        email_outbox_worker.abort();
+        if let Some(jwt_keyring_reloader) = jwt_keyring_reloader {
+            jwt_keyring_reloader.abort();
+        }
+        result
     }
 }
 
@@ -69,20 +104,73 @@ pub struct ErrorResponse {
 
 impl IntoResponse for AuthAPIError {
     fn into_response(self) -> axum::response::Response {
+        let retry_after = match self {
+            AuthAPIError::AccountLocked(seconds) | AuthAPIError::TooManyRequests(seconds) => Some(seconds),
+            _ => None,
+        };
         let (status, error_message) = match self {
             AuthAPIError::UserAlreadyExists => (StatusCode::CONFLICT, "User already exists"),
             AuthAPIError::InvalidCredentials=> (StatusCode::BAD_REQUEST, "Invalid credentials"),
             AuthAPIError::UnexpectedError => (StatusCode::INTERNAL_SERVER_ERROR, "Unexpected error"),
             AuthAPIError::IncorrectCredentials => (StatusCode::UNAUTHORIZED, "User does not exist"),
             AuthAPIError::MissingToken => (StatusCode::BAD_REQUEST, "Missing token"),
-            AuthAPIError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid token")
+            AuthAPIError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid token"),
+            AuthAPIError::EmailNotVerified => (StatusCode::FORBIDDEN, "Email not verified"),
+            AuthAPIError::Incorrect2FACode => (StatusCode::UNAUTHORIZED, "Incorrect 2FA code"),
+            AuthAPIError::AccountLocked(_) => (StatusCode::TOO_MANY_REQUESTS, "Account temporarily locked"),
+            AuthAPIError::TooManyRequests(_) => (StatusCode::TOO_MANY_REQUESTS, "Too many requests"),
+            AuthAPIError::TooManyResends => (StatusCode::TOO_MANY_REQUESTS, "Too many 2FA code resends, log in again"),
+            AuthAPIError::SessionNotFound => (StatusCode::NOT_FOUND, "Session not found"),
+            AuthAPIError::UserCodeNotFound => (StatusCode::NOT_FOUND, "Unknown or expired code"),
+            AuthAPIError::ServiceUnavailable => (StatusCode::SERVICE_UNAVAILABLE, "Service temporarily unavailable"),
         };
 
         let body = Json(ErrorResponse {
             error: error_message.to_string(),
         });
 
-        (status, body).into_response()
+        let mut response = (status, body).into_response();
+        if let Some(seconds) = retry_after {
+            response.headers_mut().insert(RETRY_AFTER, seconds.into());
+        }
+        response
+    }
+}
+
+// RFC 6749 section 5.2 error body
+#[derive(Serialize, Deserialize)]
+pub struct OAuthErrorResponse {
+    pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_description: Option<String>,
+}
+
+impl IntoResponse for OAuthError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            OAuthError::InvalidClient | OAuthError::InvalidToken => StatusCode::UNAUTHORIZED,
+            OAuthError::InsufficientScope => StatusCode::FORBIDDEN,
+            OAuthError::ServerError => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        // clients authenticate with Basic, bearer token users are told what was wrong with the token
+        let authenticate = match self {
+            OAuthError::InvalidClient => Some(HeaderValue::from_static("Basic realm=\"oauth\"")),
+            OAuthError::InvalidToken => Some(HeaderValue::from_static("Bearer error=\"invalid_token\"")),
+            OAuthError::InsufficientScope => Some(HeaderValue::from_static("Bearer error=\"insufficient_scope\", scope=\"openid\"")),
+            _ => None,
+        };
+        let body = Json(OAuthErrorResponse {
+            error: self.code().to_owned(),
+            error_description: self.description().map(str::to_owned),
+        });
+
+        let mut response = (status, body).into_response();
+        response.headers_mut().insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        if let Some(authenticate) = authenticate {
+            response.headers_mut().insert(WWW_AUTHENTICATE, authenticate);
+        }
+        response
     }
 }
 
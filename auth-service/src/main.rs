@@ -1,29 +1,111 @@
 use std::sync::Arc;
 
-use auth_service::{app_state::AppState, get_postgres_pool, get_redis_client, services::{data_store::PostgresUserStore, mock_email_client::MockEmailClient, redis_banned_token_store::RedisBannedTokenStore, redis_two_fa_code_store::RedisTwoFACodeStore}, utils::constants::{prod, DATABASE_URL, REDIS_HOST_NAME}, Application};
+use auth_service::{
+    app_state::{AppState, AuthorizationCodeStoreType, BannedTokenStoreType, DeviceAuthorizationStoreType, EmailClientType, EmailOutboxType, EmailVerificationTokenStoreType, FailedLoginStoreType, OAuthClientStoreType, PasswordResetTokenStoreType, RateLimitStoreType, RefreshTokenStoreType, ServiceAccountStoreType, SessionStoreType, TwoFACodeStoreType, UserStoreType},
+    get_postgres_pool, get_redis_client,
+    services::{data_store::{PostgresEmailOutbox, PostgresOAuthClientStore, PostgresServiceAccountStore, PostgresUserStore}, hashmap_authorization_code_store::HashmapAuthorizationCodeStore, hashmap_device_authorization_store::HashmapDeviceAuthorizationStore, hashmap_email_outbox::HashmapEmailOutbox, hashmap_email_verification_token_store::HashmapEmailVerificationTokenStore, hashmap_failed_login_store::HashmapFailedLoginStore, hashmap_oauth_client_store::HashmapOAuthClientStore, hashmap_password_reset_token_store::HashmapPasswordResetTokenStore, hashmap_rate_limit_store::HashmapRateLimitStore, hashmap_refresh_token_store::HashmapRefreshTokenStore, hashmap_service_account_store::HashmapServiceAccountStore, hashmap_session_store::HashmapSessionStore, hashmap_two_fa_code_store::HashmapTwoFACodeStore, hashmap_user_store::HashmapUserStore, hashset_banned_token_store::HashsetBannedTokenStore, mock_email_client::MockEmailClient, redis_authorization_code_store::RedisAuthorizationCodeStore, redis_banned_token_store::RedisBannedTokenStore, redis_device_authorization_store::RedisDeviceAuthorizationStore, redis_email_verification_token_store::RedisEmailVerificationTokenStore, redis_failed_login_store::RedisFailedLoginStore, redis_password_reset_token_store::RedisPasswordResetTokenStore, redis_rate_limit_store::RedisRateLimitStore, redis_refresh_token_store::RedisRefreshTokenStore, redis_session_store::RedisSessionStore, redis_two_fa_code_store::RedisTwoFACodeStore, smtp_email_client::SmtpEmailClient},
+    utils::{config::{EmailClientBackend, SmtpTls, TokenStoreBackend, UserStoreBackend}, constants::{prod, ALLOW_UNVERIFIED_LOGIN, BANNED_TOKEN_STORE_BACKEND, DATABASE_URL, EMAIL_CLIENT_BACKEND, EMAIL_SENDER, RATE_LIMITS, RATE_LIMIT_STORE_BACKEND, REDIS_HOST_NAME, SMTP_HOST, SMTP_PASSWORD, SMTP_PORT, SMTP_TLS, SMTP_USERNAME, TOKEN_STORE_BACKEND, TWO_FA_CODE_STORE_BACKEND, USER_STORE_BACKEND}},
+    Application,
+};
+use lettre::{transport::smtp::authentication::Credentials, AsyncSmtpTransport, Tokio1Executor};
 use sqlx::PgPool;
 use tokio::sync::RwLock;
 
 #[tokio::main]
 async fn main() {
-    let pg_pool = configure_postgres().await ;
-    let database_store = PostgresUserStore::new(pg_pool);
-    // let _user_store: HashMap<Email, User> = HashMap::new();
-    
-    // Use helper fn to get the redis connection
-    let conn = configure_redis();
-    let conn = Arc::new(RwLock::new(conn));
-    // The conn field in the BannedRedisStore has a Rwlock
-    let banned_token_store = RedisBannedTokenStore::new(conn.clone()); 
-    // let banned_token_store: HashSet<String> = HashSet::new();
-    let two_fa_code_store = RedisTwoFACodeStore::new(conn);
-    let email_client = MockEmailClient;
+    // The email outbox, the OAuth clients and the service accounts live next to the users, so they are as durable as the user store
+    let (user_store, email_outbox, oauth_client_store, service_account_store): (UserStoreType, EmailOutboxType, OAuthClientStoreType, ServiceAccountStoreType) = match *USER_STORE_BACKEND {
+        UserStoreBackend::Postgres => {
+            let pg_pool = configure_postgres().await;
+            (
+                Arc::new(RwLock::new(PostgresUserStore::new(pg_pool.clone()))),
+                Arc::new(RwLock::new(PostgresEmailOutbox::new(pg_pool.clone()))),
+                Arc::new(RwLock::new(PostgresOAuthClientStore::new(pg_pool.clone()))),
+                Arc::new(RwLock::new(PostgresServiceAccountStore::new(pg_pool))),
+            )
+        }
+        UserStoreBackend::Memory => (
+            Arc::new(RwLock::new(HashmapUserStore::default())),
+            Arc::new(RwLock::new(HashmapEmailOutbox::default())),
+            Arc::new(RwLock::new(HashmapOAuthClientStore::default())),
+            Arc::new(RwLock::new(HashmapServiceAccountStore::default())),
+        ),
+    };
+
+    // Only connect to Redis if one of the stores actually lives there
+    let token_backends = [*BANNED_TOKEN_STORE_BACKEND, *TWO_FA_CODE_STORE_BACKEND, *TOKEN_STORE_BACKEND, *RATE_LIMIT_STORE_BACKEND];
+    let conn = token_backends
+        .contains(&TokenStoreBackend::Redis)
+        .then(|| Arc::new(RwLock::new(configure_redis())));
+    let redis_conn = || conn.clone().expect("a Redis connection is opened when a store uses Redis");
+
+    let banned_token_store: BannedTokenStoreType = match *BANNED_TOKEN_STORE_BACKEND {
+        TokenStoreBackend::Redis => Arc::new(RwLock::new(RedisBannedTokenStore::new(redis_conn()))),
+        TokenStoreBackend::Memory => Arc::new(RwLock::new(HashsetBannedTokenStore::default())),
+    };
+    let two_fa_code_store: TwoFACodeStoreType = match *TWO_FA_CODE_STORE_BACKEND {
+        TokenStoreBackend::Redis => Arc::new(RwLock::new(RedisTwoFACodeStore::new(redis_conn()))),
+        TokenStoreBackend::Memory => Arc::new(RwLock::new(HashmapTwoFACodeStore::default())),
+    };
+    let (password_reset_token_store, email_verification_token_store, refresh_token_store, session_store, authorization_code_store, device_authorization_store, failed_login_store): (
+        PasswordResetTokenStoreType,
+        EmailVerificationTokenStoreType,
+        RefreshTokenStoreType,
+        SessionStoreType,
+        AuthorizationCodeStoreType,
+        DeviceAuthorizationStoreType,
+        FailedLoginStoreType,
+    ) = match *TOKEN_STORE_BACKEND {
+        TokenStoreBackend::Redis => (
+            Arc::new(RwLock::new(RedisPasswordResetTokenStore::new(redis_conn()))),
+            Arc::new(RwLock::new(RedisEmailVerificationTokenStore::new(redis_conn()))),
+            Arc::new(RwLock::new(RedisRefreshTokenStore::new(redis_conn()))),
+            Arc::new(RwLock::new(RedisSessionStore::new(redis_conn()))),
+            Arc::new(RwLock::new(RedisAuthorizationCodeStore::new(redis_conn()))),
+            Arc::new(RwLock::new(RedisDeviceAuthorizationStore::new(redis_conn()))),
+            Arc::new(RwLock::new(RedisFailedLoginStore::new(redis_conn()))),
+        ),
+        TokenStoreBackend::Memory => (
+            Arc::new(RwLock::new(HashmapPasswordResetTokenStore::default())),
+            Arc::new(RwLock::new(HashmapEmailVerificationTokenStore::default())),
+            Arc::new(RwLock::new(HashmapRefreshTokenStore::default())),
+            Arc::new(RwLock::new(HashmapSessionStore::default())),
+            Arc::new(RwLock::new(HashmapAuthorizationCodeStore::default())),
+            Arc::new(RwLock::new(HashmapDeviceAuthorizationStore::default())),
+            Arc::new(RwLock::new(HashmapFailedLoginStore::default())),
+        ),
+    };
+
+    // Buckets in Redis are shared by every instance of the service, in memory ones are per instance
+    let rate_limit_store: RateLimitStoreType = match *RATE_LIMIT_STORE_BACKEND {
+        TokenStoreBackend::Redis => Arc::new(RwLock::new(RedisRateLimitStore::new(redis_conn()))),
+        TokenStoreBackend::Memory => Arc::new(RwLock::new(HashmapRateLimitStore::default())),
+    };
+
+    let email_client: EmailClientType = match *EMAIL_CLIENT_BACKEND {
+        EmailClientBackend::Mock => Arc::new(RwLock::new(MockEmailClient)),
+        EmailClientBackend::Smtp => Arc::new(RwLock::new(configure_smtp())),
+    };
+
     let app_state  = AppState::new(
-        Arc::new(RwLock::new(database_store)),
-        Arc::new(RwLock::new(banned_token_store)),
-        Arc::new(RwLock::new(two_fa_code_store)),
-        Arc::new(RwLock::new(email_client)));
-           
+        user_store,
+        banned_token_store,
+        two_fa_code_store,
+        email_client,
+        email_outbox,
+        password_reset_token_store,
+        email_verification_token_store,
+        refresh_token_store,
+        session_store,
+        oauth_client_store,
+        service_account_store,
+        authorization_code_store,
+        device_authorization_store,
+        failed_login_store,
+        rate_limit_store,
+        RATE_LIMITS.clone(),
+        *ALLOW_UNVERIFIED_LOGIN);
+
     let app = Application::build(app_state,prod::APP_ADDRESS).await.expect("Failed to build app");
 
     app.run().await.expect("Failed to run app")
@@ -47,3 +129,20 @@ fn configure_redis() -> redis::Connection {
         .get_connection()
         .expect("Failed to get Redis Connection")
 }
+
+fn configure_smtp() -> SmtpEmailClient {
+    let mut builder = match *SMTP_TLS {
+        SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(SMTP_HOST.as_str()),
+        SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&SMTP_HOST).expect("Failed to configure SMTP relay"),
+        SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&SMTP_HOST).expect("Failed to configure SMTP relay"),
+    };
+    if let Some(port) = *SMTP_PORT {
+        builder = builder.port(port);
+    }
+    if let (Some(username), Some(password)) = (SMTP_USERNAME.clone(), SMTP_PASSWORD.clone()) {
+        builder = builder.credentials(Credentials::new(username, password));
+    }
+    let sender = EMAIL_SENDER.parse().expect("EMAIL_SENDER must be a valid mailbox");
+
+    SmtpEmailClient::new(builder.build(), sender)
+}
@@ -0,0 +1,163 @@
+use axum::{extract::{Query, State}, http::{header::CACHE_CONTROL, HeaderMap, StatusCode}, response::IntoResponse, Form, Json};
+use axum_extra::extract::CookieJar;
+use serde::{Deserialize, Serialize};
+
+use crate::{app_state::AppState, routes::{authenticate_client, authenticated_session, check_openid_scope, record_session, ClientInfo, OAuthTokenRequest}, domain::{data_store::{DeviceAuthorizationStoreError, DeviceCode, OAuthClientStoreError, RefreshToken, RefreshTokenFamily, UserCode}, error::{AuthAPIError, OAuthError}, oauth::{DeviceAuthorization, DeviceAuthorizationStatus, OAuthClient}, session::Session}, utils::{auth::{DEVICE_CODE_TTL_SECONDS, DEVICE_POLL_INTERVAL_SECONDS}, constants::AUTH_SERVICE_URL}};
+
+// Device authorization endpoint (RFC 8628 section 3.1), for devices that can't receive a redirect,
+// such as CLIs. The device shows the user code and the verification URI, where the user logs in
+// (and passes 2FA) to approve it, while the device polls /oauth/token with the device code
+pub async fn device_authorization(State(state): State<AppState>,
+    headers: HeaderMap,
+    client_info: ClientInfo,
+    Form(request): Form<DeviceAuthorizationRequest>) -> Result<impl IntoResponse, OAuthError> {
+    let client = authenticate_client(&state, &headers, request.client_id.as_deref(), request.client_secret.as_deref()).await?;
+    let scopes = client.grant_scopes(request.scope.as_deref()).map_err(OAuthError::InvalidScope)?;
+    check_openid_scope(&scopes)?;
+
+    let (device_code, user_code) = (DeviceCode::default(), UserCode::default());
+    let authorization = DeviceAuthorization {
+        client_id: client.client_id,
+        scopes,
+        device: client_info.device,
+        ip: client_info.ip,
+    };
+    state
+        .device_authorization_store
+        .write()
+        .await
+        .add_authorization(device_code.clone(), user_code.clone(), authorization)
+        .await
+        .map_err(|_| OAuthError::ServerError)?;
+
+    // the page is served by this service, like the links in our emails
+    let verification_uri = format!("{}/device/", AUTH_SERVICE_URL.trim_end_matches('/'));
+    let response = DeviceAuthorizationResponse {
+        device_code: device_code.as_ref().to_owned(),
+        user_code: user_code.display(),
+        verification_uri_complete: format!("{}?user_code={}", verification_uri, user_code.display()),
+        verification_uri,
+        expires_in: DEVICE_CODE_TTL_SECONDS,
+        interval: DEVICE_POLL_INTERVAL_SECONDS,
+    };
+    Ok(([(CACHE_CONTROL, "no-store")], Json(response)))
+}
+
+// What the verification page shows the logged in user before they approve a device
+pub async fn get_device_authorization(State(state): State<AppState>,
+    jar: CookieJar,
+    Query(request): Query<UserCodeQuery>) -> Result<impl IntoResponse, AuthAPIError> {
+    authenticated_session(&jar, &state).await?;
+    let user_code = UserCode::parse(&request.user_code).map_err(|_| AuthAPIError::UserCodeNotFound)?;
+
+    let authorization = match state.device_authorization_store.read().await.get_authorization(&user_code).await {
+        Ok(authorization) => authorization,
+        Err(DeviceAuthorizationStoreError::AuthorizationNotFound) => return Err(AuthAPIError::UserCodeNotFound),
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    };
+    let client = match state.oauth_client_store.read().await.get_client(&authorization.client_id).await {
+        Ok(client) => client,
+        // the client was removed since
+        Err(OAuthClientStoreError::ClientNotFound) => return Err(AuthAPIError::UserCodeNotFound),
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    };
+    Ok(Json(DeviceAuthorizationDetails {
+        user_code: user_code.display(),
+        client_name: client.name,
+        scopes: authorization.scopes,
+        device: authorization.device,
+        ip: authorization.ip,
+    }))
+}
+
+// The logged in user approving or denying a device. Approved devices get tokens in the
+// user's name, from a session that remembers how the user logged in here
+pub async fn decide_device_authorization(State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<DeviceDecisionRequest>) -> Result<impl IntoResponse, AuthAPIError> {
+    let session = authenticated_session(&jar, &state).await?;
+    let user_code = UserCode::parse(&request.user_code).map_err(|_| AuthAPIError::UserCodeNotFound)?;
+
+    let status = if request.approve {
+        DeviceAuthorizationStatus::Approved { email: session.email, amr: session.amr }
+    } else {
+        DeviceAuthorizationStatus::Denied
+    };
+    match state.device_authorization_store.write().await.decide(&user_code, status).await {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(DeviceAuthorizationStoreError::AuthorizationNotFound) => Err(AuthAPIError::UserCodeNotFound),
+        Err(_) => Err(AuthAPIError::UnexpectedError),
+    }
+}
+
+// Device access token request (RFC 8628 section 3.4): once the user approved the device, start
+// a session for the client in their name. Until then the device is told to keep polling
+pub(crate) async fn exchange_device_code(state: &AppState, client: &OAuthClient, request: &OAuthTokenRequest) -> Result<(Session, RefreshToken), OAuthError> {
+    let device_code = request.device_code.clone().ok_or_else(|| OAuthError::InvalidRequest("device_code is required".to_owned()))?;
+    let device_code = DeviceCode::parse(device_code).map_err(|_| OAuthError::InvalidGrant("Invalid device code".to_owned()))?;
+
+    let (authorization, status) = match state.device_authorization_store.write().await.poll(&device_code, &client.client_id).await {
+        Ok(polled) => polled,
+        Err(DeviceAuthorizationStoreError::PolledTooSoon) => return Err(OAuthError::SlowDown),
+        // unknown codes may well have expired, either way the device has to start over
+        Err(DeviceAuthorizationStoreError::AuthorizationNotFound) => return Err(OAuthError::ExpiredToken),
+        Err(DeviceAuthorizationStoreError::WrongClient) => return Err(OAuthError::InvalidGrant("Invalid device code".to_owned())),
+        Err(_) => return Err(OAuthError::ServerError),
+    };
+    let (email, amr) = match status {
+        DeviceAuthorizationStatus::Pending => return Err(OAuthError::AuthorizationPending),
+        DeviceAuthorizationStatus::Denied => return Err(OAuthError::AccessDenied),
+        DeviceAuthorizationStatus::Approved { email, amr } => (email, amr),
+    };
+
+    let session = Session {
+        client_id: Some(client.client_id.clone()),
+        scopes: authorization.scopes,
+        amr,
+        ..Session::new(RefreshTokenFamily::default(), email, format!("{} ({})", authorization.device, client.name), authorization.ip)
+    };
+    let refresh_token = record_session(state, &session).await.map_err(|_| OAuthError::ServerError)?;
+    Ok((session, refresh_token))
+}
+
+#[derive(Deserialize)]
+pub struct DeviceAuthorizationRequest {
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub scope: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct DeviceAuthorizationResponse {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    // the verification URI with the user code filled in, e.g. for a QR code
+    pub verification_uri_complete: String,
+    pub expires_in: i64,
+    pub interval: i64,
+}
+
+#[derive(Deserialize)]
+pub struct UserCodeQuery {
+    pub user_code: String,
+}
+
+#[derive(Serialize)]
+pub struct DeviceAuthorizationDetails {
+    #[serde(rename = "userCode")]
+    pub user_code: String,
+    #[serde(rename = "clientName")]
+    pub client_name: String,
+    pub scopes: Vec<String>,
+    // where the device asked from, so users can tell it's their own
+    pub device: String,
+    pub ip: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeviceDecisionRequest {
+    #[serde(rename = "userCode")]
+    pub user_code: String,
+    pub approve: bool,
+}
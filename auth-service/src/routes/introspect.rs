@@ -0,0 +1,137 @@
+use axum::{extract::State, http::{header::CACHE_CONTROL, HeaderMap}, response::IntoResponse, Form, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{app_state::AppState, routes::{authenticate_client, unrevoked_service_account, unrevoked_session}, domain::{data_store::{RefreshToken, SessionStoreError}, error::{AuthAPIError, OAuthError}, session::Session}, utils::auth::{validate_token, Claims, PrincipalType}};
+
+// Token introspection (RFC 7662), so resource servers can find out who a token belongs to and
+// what it may be used for. Only confidential clients may introspect tokens. Tokens that are
+// invalid, expired or revoked are reported as inactive, with no other details
+pub async fn introspect(State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(request): Form<TokenActionRequest>) -> Result<impl IntoResponse, OAuthError> {
+    let client = authenticate_client(&state, &headers, request.client_id.as_deref(), request.client_secret.as_deref()).await?;
+    if !client.confidential {
+        return Err(OAuthError::InvalidClient);
+    }
+    let token = request.token.ok_or_else(|| OAuthError::InvalidRequest("token is required".to_owned()))?;
+
+    let response = match find_active_token(&state, &token).await? {
+        Some(ActiveToken::Access(claims, session)) => IntrospectionResponse {
+            active: true,
+            sub: Some(claims.sub),
+            exp: Some(claims.exp),
+            iat: Some(claims.iat),
+            scope: claims.scope,
+            client_id: claims.client_id,
+            token_type: Some("access_token"),
+            principal_type: Some(PrincipalType::User),
+            sid: Some(session.id.as_ref().to_owned()),
+        },
+        Some(ActiveToken::Service(claims)) => IntrospectionResponse {
+            active: true,
+            sub: Some(claims.sub),
+            exp: Some(claims.exp),
+            iat: Some(claims.iat),
+            scope: claims.scope,
+            client_id: claims.client_id,
+            token_type: Some("access_token"),
+            principal_type: Some(PrincipalType::ServiceAccount),
+            sid: None,
+        },
+        Some(ActiveToken::Refresh(session)) => IntrospectionResponse {
+            active: true,
+            sub: Some(session.email.as_ref().to_owned()),
+            // refresh tokens last until they are used, their session is ended or it goes unused for too long
+            exp: None,
+            iat: None,
+            scope: (!session.scopes.is_empty()).then(|| session.scopes.join(" ")),
+            client_id: session.client_id,
+            token_type: Some("refresh_token"),
+            principal_type: Some(PrincipalType::User),
+            sid: Some(session.id.as_ref().to_owned()),
+        },
+        None => IntrospectionResponse::inactive(),
+    };
+    Ok(([(CACHE_CONTROL, "no-store")], Json(response)))
+}
+
+// A token that can still be used, along with the session it belongs to
+pub(crate) enum ActiveToken {
+    Access(Claims, Session),
+    Refresh(Session),
+    // an access token issued to a service account, which has no session
+    Service(Claims),
+}
+
+// Look a token up as a refresh token or a JWT. Refresh tokens are only active while they
+// are the newest of their family, JWTs while they haven't been revoked (see `unrevoked_session`
+// and `unrevoked_service_account`)
+pub(crate) async fn find_active_token(state: &AppState, token: &str) -> Result<Option<ActiveToken>, OAuthError> {
+    if let Ok(token) = RefreshToken::parse(token.to_owned()) {
+        let refresh_token_store = state.refresh_token_store.read().await;
+        let (email, family) = match refresh_token_store.get_token(&token).await {
+            Ok(record) => record,
+            Err(_) => return Ok(None),
+        };
+        if refresh_token_store.get_current_token(&family).await.ok() != Some(token) {
+            return Ok(None);
+        }
+        return match state.session_store.read().await.get_session(&family).await {
+            Ok(session) if session.email == email => Ok(Some(ActiveToken::Refresh(session))),
+            Ok(_) | Err(SessionStoreError::SessionNotFound) => Ok(None),
+            Err(_) => Err(OAuthError::ServerError),
+        };
+    }
+
+    let claims = match validate_token(token).await {
+        Ok(claims) => claims,
+        Err(_) => return Ok(None),
+    };
+    let active = match claims.principal_type {
+        PrincipalType::User => unrevoked_session(state, &claims).await.map(|session| ActiveToken::Access(claims, session)),
+        PrincipalType::ServiceAccount => unrevoked_service_account(state, &claims).await.map(|_| ActiveToken::Service(claims)),
+    };
+    match active {
+        Ok(active) => Ok(Some(active)),
+        Err(AuthAPIError::InvalidToken) => Ok(None),
+        Err(_) => Err(OAuthError::ServerError),
+    }
+}
+
+// Body of /oauth/introspect and /oauth/revoke requests
+#[derive(Deserialize)]
+pub struct TokenActionRequest {
+    pub token: Option<String>,
+    // "access_token" or "refresh_token". Tokens are told apart by their shape, so it isn't needed
+    pub token_type_hint: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<&'static str>,
+    // whether `sub` is a user or a service account
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub principal_type: Option<PrincipalType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+}
+
+impl IntrospectionResponse {
+    fn inactive() -> Self {
+        Self { active: false, sub: None, exp: None, iat: None, scope: None, client_id: None, token_type: None, principal_type: None, sid: None }
+    }
+}
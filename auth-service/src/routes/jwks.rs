@@ -0,0 +1,13 @@
+use std::sync::PoisonError;
+
+use axum::{http::header::CACHE_CONTROL, response::IntoResponse, Json};
+use chrono::Utc;
+
+use crate::utils::constants::JWT_KEYRING;
+
+// Public keys consumers can verify JWTs with, matched to tokens by the `kid` in their header.
+// Empty when tokens are signed with shared secrets
+pub async fn jwks() -> impl IntoResponse {
+    let jwks = JWT_KEYRING.read().unwrap_or_else(PoisonError::into_inner).jwks(Utc::now());
+    ([(CACHE_CONTROL, "public, max-age=300")], Json(jwks))
+}
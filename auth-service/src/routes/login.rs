@@ -2,12 +2,13 @@ use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use axum_extra::extract::CookieJar;
 use serde::{Deserialize, Serialize};
 
-use crate::{app_state::AppState, domain::{data_store::{LoginAttemptId, TwoFACode, TwoFACodeStore, UserStore}, email::Email, email_client::EmailClient, error::AuthAPIError, password::Password}, services::{data_store::PostgresUserStore, mock_email_client::MockEmailClient, redis_banned_token_store::RedisBannedTokenStore, redis_two_fa_code_store::RedisTwoFACodeStore}, utils::auth::generate_auth_cookie};
+use crate::{app_state::AppState, routes::{start_session, ClientInfo}, domain::{data_store::{LoginAttemptId, TwoFACode}, email::Email, email_message::EmailMessage, error::AuthAPIError, password::Password, user::TwoFAMethod}, utils::{lockout::{lock_seconds, LOCKOUT_SECONDS, MAX_FAILED_LOGINS}}};
 
 
 
-pub async fn login(State(state):State<AppState<PostgresUserStore, RedisBannedTokenStore, RedisTwoFACodeStore, MockEmailClient>>,
+pub async fn login(State(state):State<AppState>,
     jar: CookieJar,
+    client: ClientInfo,
     Json(request): Json<LoginRequest>) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>)
 {
     // Return 400 bad request for invalid email / password
@@ -28,12 +29,27 @@ pub async fn login(State(state):State<AppState<PostgresUserStore, RedisBannedTok
         Err(e) => return (jar, Err(e))
     };
     
+    // Locked accounts are turned away before the password is even checked
+    match state.failed_login_store.read().await.get_lock(&email).await {
+        Ok(None) => {},
+        Ok(Some(seconds)) => return (jar, Err(AuthAPIError::AccountLocked(seconds))),
+        Err(_e) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    }
+
     // if user is not validated return incorrectCredentials
     let user_validation = user_store.validate_user(email.as_ref(),password.as_ref()).await;
 
     if user_validation.is_err() {
+        let user_exists = user_store.get_user(email.as_ref()).await.is_ok();
+        drop(user_store);
+        if let Err(e) = record_failed_login(&state, &email, user_exists).await {
+            return (jar, Err(e))
+        }
         return (jar, Err(AuthAPIError::IncorrectCredentials))
     };
+    if state.failed_login_store.write().await.clear_failures(&email).await.is_err() {
+        return (jar, Err(AuthAPIError::UnexpectedError))
+    }
     // Call `user_store.get_user`.
     // Return AuthAPIError::IncorrectCredentials if the operation fails.
     let user = user_store.get_user(email.as_ref()).await.map_err(|_| AuthAPIError::IncorrectCredentials);
@@ -43,15 +59,47 @@ pub async fn login(State(state):State<AppState<PostgresUserStore, RedisBannedTok
         Err(e) => return (jar, Err(e))
     };
 
+    // Users have to confirm they own the email before they can log in
+    if !user.email_verified && !state.allow_unverified_login {
+        return (jar, Err(AuthAPIError::EmailNotVerified))
+    }
+
     // handle request based on user's 2FA configuration
     match user.requires_2fa {
-        true => handle_2fa(jar, user.email, &state).await,
-        false => handle_no_2fa(user.email, jar).await
+        true => handle_2fa(jar, user.email, user.two_fa_method, &state).await,
+        false => handle_no_2fa(user.email, jar, &state, client).await
     }
     
     
 }
 
+// Count a failed login and slow down or lock the account once there are too many.
+// Unknown emails are tracked too, so lockouts don't reveal which accounts exist
+async fn record_failed_login(state: &AppState,
+    email: &Email,
+    user_exists: bool) -> Result<(), AuthAPIError> {
+    let mut failed_login_store = state.failed_login_store.write().await;
+    let failures = failed_login_store.record_failure(email).await.map_err(|_| AuthAPIError::UnexpectedError)?;
+    let Some(seconds) = lock_seconds(failures) else {
+        return Ok(());
+    };
+    failed_login_store.lock(email, seconds).await.map_err(|_| AuthAPIError::UnexpectedError)?;
+    drop(failed_login_store);
+
+    // Let the owner know someone may be guessing their password
+    if failures >= MAX_FAILED_LOGINS && user_exists {
+        let message = EmailMessage::account_locked(LOCKOUT_SECONDS / 60).map_err(|_| AuthAPIError::UnexpectedError)?;
+        state
+            .email_outbox
+            .write()
+            .await
+            .enqueue(email.clone(), message)
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)?;
+    }
+    Ok(())
+}
+
 #[derive(Deserialize)]
 pub struct LoginRequest {
     pub email: String,
@@ -73,19 +121,39 @@ pub struct TwoFactorAuthResponse {
     pub message: String,
     #[serde(rename = "loginAttemptId")]
     pub login_attempt_id: String,
+    // "email" or "totp", tells the client where the user gets their code from
+    #[serde(rename = "twoFAMethod")]
+    pub two_fa_method: String,
 }
 
 async fn handle_2fa(jar: CookieJar,
     email: Email,
-    state: &AppState<PostgresUserStore, RedisBannedTokenStore, RedisTwoFACodeStore, MockEmailClient>) -> (CookieJar, Result<(StatusCode, Json<LoginResponse>), AuthAPIError>) {
-    //Create the cookie using email
-    let auth_cookie = generate_auth_cookie(email.clone()).map_err(|_|AuthAPIError::UnexpectedError);
+    two_fa_method: TwoFAMethod,
+    state: &AppState) -> (CookieJar, Result<(StatusCode, Json<LoginResponse>), AuthAPIError>) {
+    // No JWT is issued until the second factor is verified, the login attempt ID
+    // is all the client gets to carry on with the login
 
     // Generate random login attempt ID & 2FA Code
     let login_attempt_id = LoginAttemptId::default();
 
     let code = TwoFACode::default();
 
+    // Send 2FA code via email client. return AuthApiError if operation fails.
+    // Users with an authenticator app read the code from the app instead
+    if two_fa_method == TwoFAMethod::Email {
+        let message = match EmailMessage::two_fa_code(&code) {
+            Ok(message) => message,
+            Err(_e) => return (jar, Err(AuthAPIError::UnexpectedError)),
+        };
+        // Queued rather than sent right away, so a mail server hiccup doesn't fail the login.
+        // The code is only stored once its email is queued, so a failing outbox leaves nothing behind
+        let email_send_result = state.email_outbox.write().await.enqueue(email.clone(), message).await;
+        match email_send_result {
+            Ok(_id)=> {},
+            Err(_e) => return (jar, Err(AuthAPIError::UnexpectedError))
+        }
+    }
+
     // get the codes store
     let mut two_fa_codes_store = state.two_fa_code_store.write().await;
 
@@ -94,36 +162,23 @@ async fn handle_2fa(jar: CookieJar,
         Ok(()) => { },
         Err(_e) => return (jar, Err(AuthAPIError::UnexpectedError)),
     };
+    drop(two_fa_codes_store);
 
-    // Send 2FA code via email client. return AuthApiError if operation fails
-    let email_send_result = MockEmailClient.send_email(&email, login_attempt_id.as_ref(), code.as_ref()).await;
-    match email_send_result {
-        Ok(())=> {},
-        Err(_e) => return (jar, Err(AuthAPIError::UnexpectedError))
-    }    
-    // if cookie has issue generating return an error with the empty jar
-    let auth_cookie= match auth_cookie{
-        Ok(cookie) => cookie,
-        Err(e) => return (jar, Err(e))
-    };
-    // If no error set the cookie in the jar
-    let updated_jar = jar.add(auth_cookie);
-    let two_fa_auth_response = TwoFactorAuthResponse {message: "2FA required".into(), login_attempt_id: login_attempt_id.as_ref().into()};
-    (updated_jar, Ok((StatusCode::PARTIAL_CONTENT,Json(LoginResponse::TwoFactorAuth(two_fa_auth_response)))))
+    let two_fa_auth_response = TwoFactorAuthResponse {message: "2FA required".into(), login_attempt_id: login_attempt_id.as_ref().into(), two_fa_method: two_fa_method.as_ref().into()};
+    (jar, Ok((StatusCode::PARTIAL_CONTENT,Json(LoginResponse::TwoFactorAuth(two_fa_auth_response)))))
 }
 
-async fn handle_no_2fa(email: Email, jar: CookieJar)->(CookieJar, Result<(StatusCode, Json<LoginResponse>), AuthAPIError>)  {
+async fn handle_no_2fa(email: Email, jar: CookieJar,
+    state: &AppState, client: ClientInfo)->(CookieJar, Result<(StatusCode, Json<LoginResponse>), AuthAPIError>)  {
     
-    //Create the cookie using email
-    let auth_cookie = generate_auth_cookie(email).map_err(|_|AuthAPIError::UnexpectedError);
-
-    // if cookie has issue generating return an error with the empty jar
-    let auth_cookie= match auth_cookie{
-        Ok(cookie) => cookie,
+    // Start a session for this device, with a JWT and a refresh token
+    // so the user can get new JWTs without logging in again
+    let (auth_cookie, refresh_cookie) = match start_session(state, email, client, false).await {
+        Ok(cookies) => cookies,
         Err(e) => return (jar, Err(e))
     };
-    // If no error set the cookie in the jar
-    let updated_jar = jar.add(auth_cookie);
+    // If no error set the cookies in the jar
+    let updated_jar = jar.add(auth_cookie).add(refresh_cookie);
 
     // return jar and regular auth variant of `LoginResponse`
     (updated_jar,Ok((StatusCode::OK, Json(LoginResponse::RegularAuth))))
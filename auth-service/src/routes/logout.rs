@@ -1,9 +1,9 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse};
 use axum_extra::extract::{cookie::Cookie, CookieJar};
 
-use crate::{app_state::AppState, domain::{data_store::BannedTokenStore, error::AuthAPIError}, services::{data_store::PostgresUserStore, mock_email_client::MockEmailClient, redis_banned_token_store::RedisBannedTokenStore, redis_two_fa_code_store::RedisTwoFACodeStore}, utils::{auth::validate_token, constants::JWT_COOKIE_NAME}};
+use crate::{app_state::AppState, routes::{authenticated_email, end_session}, domain::{data_store::RefreshTokenFamily, email::Email, error::AuthAPIError}, utils::{auth::validate_token, constants::{JWT_COOKIE_NAME, JWT_LEEWAY_SECONDS, REFRESH_TOKEN_COOKIE_NAME}}};
 
-pub async fn logout(State(state): State<AppState<PostgresUserStore, RedisBannedTokenStore, RedisTwoFACodeStore, MockEmailClient>> ,jar: CookieJar) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>)  {
+pub async fn logout(State(state): State<AppState> ,jar: CookieJar) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>)  {
      // Retrieve JWT cookie from the `CookieJar`
     // Return AuthAPIError::MissingToken is the cookie is not found
     let cookie = jar.get(JWT_COOKIE_NAME).ok_or(AuthAPIError::MissingToken);
@@ -12,21 +12,27 @@ pub async fn logout(State(state): State<AppState<PostgresUserStore, RedisBannedT
         Ok(c)=> {
             let token = c.value().to_owned();
             let val_result= validate_token(&token).await;
-            if val_result.is_err() {
-                // return Invalid token 401, if cookie is not validated
-                (jar, Err(AuthAPIError::InvalidToken))
-       
-            } else {
-                // add the token to the banned token store
+            if let Ok(claims) = val_result {
+                // revoke the token until it would be rejected as expired anyway
+                let revoked_until = claims.exp + *JWT_LEEWAY_SECONDS as usize;
                 let mut banned_store = state.banned_token_store.write().await;
-                let res = banned_store.store_token(token).await;
+                let res = banned_store.revoke_token(&claims.jti, revoked_until).await;
                 if res.is_err() {
-                    (jar, Err(AuthAPIError::UnexpectedError))
-                }else {
-                    let jar = jar.remove(Cookie::from(JWT_COOKIE_NAME));
-                   (jar, Ok(StatusCode::OK))
-                    
+                    return (jar, Err(AuthAPIError::UnexpectedError))
+                }
+                // also end the session so its refresh token can't be used to log back in
+                if let Some(Ok(session)) = claims.sid.map(RefreshTokenFamily::parse) {
+                    if let Err(e) = end_session(&state, &session).await {
+                        return (jar, Err(e))
+                    }
                 }
+                let jar = jar
+                    .remove(Cookie::from(JWT_COOKIE_NAME))
+                    .remove(Cookie::from(REFRESH_TOKEN_COOKIE_NAME));
+                (jar, Ok(StatusCode::OK))
+            } else {
+                // return Invalid token 401, if cookie is not validated
+                (jar, Err(AuthAPIError::InvalidToken))
             }
             
         } ,
@@ -37,3 +43,45 @@ pub async fn logout(State(state): State<AppState<PostgresUserStore, RedisBannedT
 
 
 }
+
+// Log the user out of every session, e.g. after a suspected compromise
+pub async fn logout_all(State(state): State<AppState>, jar: CookieJar) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let email = match authenticated_email(&jar, &state).await {
+        Ok(email) => email,
+        Err(e) => return (jar, Err(e)),
+    };
+    if let Err(e) = revoke_all_sessions(&state, &email).await {
+        return (jar, Err(e));
+    }
+
+    let jar = jar
+        .remove(Cookie::from(JWT_COOKIE_NAME))
+        .remove(Cookie::from(REFRESH_TOKEN_COOKIE_NAME));
+    (jar, Ok(StatusCode::OK))
+}
+
+// Invalidate every JWT issued to the user by bumping their token epoch, and end every
+// session so no new JWTs can be minted with their refresh tokens either
+pub(crate) async fn revoke_all_sessions(state: &AppState, email: &Email) -> Result<(), AuthAPIError> {
+    state
+        .user_store
+        .write()
+        .await
+        .bump_token_epoch(email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+    state
+        .refresh_token_store
+        .write()
+        .await
+        .revoke_user_families(email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+    state
+        .session_store
+        .write()
+        .await
+        .remove_user_sessions(email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)
+}
@@ -1,11 +1,37 @@
+mod device;
+mod introspect;
+mod jwks;
 mod login;
 mod logout;
+mod oauth;
+mod oidc;
+mod password_reset;
+mod rate_limit;
+mod recovery_codes;
+mod refresh_token;
+mod revoke;
+mod sessions;
 mod signup;
+mod totp;
 mod verify_2fa;
+mod verify_email;
 mod verify_token;
 
+pub use device::*;
+pub use introspect::*;
+pub use jwks::*;
 pub use login::*;
 pub use logout::*;
+pub use oauth::*;
+pub use oidc::*;
+pub use password_reset::*;
+pub use rate_limit::*;
+pub use recovery_codes::*;
+pub use refresh_token::*;
+pub use revoke::*;
+pub use sessions::*;
 pub use signup::*;
+pub use totp::*;
 pub use verify_2fa::*;
+pub use verify_email::*;
 pub use verify_token::*;
@@ -0,0 +1,332 @@
+use axum::{extract::{Query, RawQuery, State}, http::{header::{AUTHORIZATION, CACHE_CONTROL, PRAGMA}, HeaderMap}, response::{IntoResponse, Redirect, Response}, Form, Json};
+use axum_extra::extract::CookieJar;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{app_state::AppState, routes::{authenticated_session, check_openid_scope, exchange_device_code, issue_auth_token, issue_id_token, record_session, rotate_refresh_token, ClientInfo}, domain::{data_store::{AuthorizationCode, AuthorizationCodeStoreError, OAuthClientStoreError, RefreshToken, RefreshTokenFamily, ServiceAccountStoreError}, error::{AuthAPIError, OAuthError}, oauth::{AuthorizationGrant, CodeChallenge, OAuthClient, ServiceAccount}, session::Session}, utils::auth::{generate_service_token, TOKEN_TTL_SECDONDS}};
+
+// Authorization endpoint (RFC 6749 section 4.1.1). Users that aren't logged in are sent to
+// the login page first, which brings them back here once they have logged in (and passed 2FA).
+// Only the authorization code flow with PKCE is supported
+pub async fn authorize(State(state): State<AppState>,
+    jar: CookieJar,
+    client_info: ClientInfo,
+    RawQuery(query): RawQuery,
+    Query(request): Query<AuthorizeRequest>) -> Response {
+    // Without a registered redirect URI there is nowhere safe to send the user, so these
+    // errors are shown to them instead of being passed on to the client
+    let (client, redirect_uri) = match authorizing_client(&state, &request).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    let (scopes, code_challenge) = match check_authorization_request(&client, &request) {
+        Ok(checked) => checked,
+        Err(e) => return redirect_to_client(&redirect_uri, error_params(&e), request.state.as_deref()),
+    };
+
+    let session = match authenticated_session(&jar, &state).await {
+        Ok(session) => session,
+        Err(AuthAPIError::MissingToken | AuthAPIError::InvalidToken) => {
+            let return_to = format!("/oauth/authorize?{}", query.unwrap_or_default());
+            return Redirect::to(&format!("/?return_to={}", urlencoding::encode(&return_to))).into_response();
+        }
+        Err(_) => return redirect_to_client(&redirect_uri, error_params(&OAuthError::ServerError), request.state.as_deref()),
+    };
+
+    let code = AuthorizationCode::default();
+    let grant = AuthorizationGrant {
+        client_id: client.client_id,
+        redirect_uri: redirect_uri.clone(),
+        redirect_uri_supplied: request.redirect_uri.is_some(),
+        email: session.email,
+        scopes,
+        code_challenge,
+        device: client_info.device,
+        ip: client_info.ip,
+        nonce: request.nonce.clone(),
+        amr: session.amr,
+    };
+    if state.authorization_code_store.write().await.add_code(code.clone(), grant).await.is_err() {
+        return redirect_to_client(&redirect_uri, error_params(&OAuthError::ServerError), request.state.as_deref());
+    }
+    redirect_to_client(&redirect_uri, vec![("code", code.as_ref().to_owned())], request.state.as_deref())
+}
+
+// Devices poll the token endpoint with this grant type until the user approves them (RFC 8628)
+pub const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+// Token endpoint (RFC 6749 section 3.2), exchanging authorization codes, device codes and refresh
+// tokens for JWTs, and client credentials of service accounts
+pub async fn oauth_token(State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(request): Form<OAuthTokenRequest>) -> Result<Response, OAuthError> {
+    // service accounts aren't OAuth clients, they get tokens in their own name
+    if request.grant_type.as_deref() == Some("client_credentials") {
+        return client_credentials_grant(&state, &headers, &request).await;
+    }
+    let client = authenticate_client(&state, &headers, request.client_id.as_deref(), request.client_secret.as_deref()).await?;
+
+    let (session, refresh_token, nonce) = match request.grant_type.as_deref() {
+        Some("authorization_code") => exchange_code(&state, &client, &request).await?,
+        Some("refresh_token") => {
+            let token = request.refresh_token.clone().ok_or_else(|| OAuthError::InvalidRequest("refresh_token is required".to_owned()))?;
+            let token = RefreshToken::parse(token).map_err(|_| OAuthError::InvalidGrant("Invalid refresh token".to_owned()))?;
+            let (session, refresh_token) = rotate_refresh_token(&state, &token, Some(&client.client_id)).await.map_err(|e| match e {
+                AuthAPIError::InvalidToken => OAuthError::InvalidGrant("Invalid refresh token".to_owned()),
+                _ => OAuthError::ServerError,
+            })?;
+            (session, refresh_token, None)
+        }
+        Some(DEVICE_CODE_GRANT_TYPE) => {
+            let (session, refresh_token) = exchange_device_code(&state, &client, &request).await?;
+            (session, refresh_token, None)
+        }
+        Some(_) => return Err(OAuthError::UnsupportedGrantType),
+        None => return Err(OAuthError::InvalidRequest("grant_type is required".to_owned())),
+    };
+
+    let access_token = issue_auth_token(&state, &session).await.map_err(|_| OAuthError::ServerError)?;
+    let id_token = issue_id_token(&state, &session, nonce).await?;
+    let response = OAuthTokenResponse {
+        access_token,
+        id_token,
+        token_type: "Bearer",
+        expires_in: TOKEN_TTL_SECDONDS,
+        refresh_token: Some(refresh_token.as_ref().to_owned()),
+        scope: session.scopes.join(" "),
+    };
+    Ok(token_response(response))
+}
+
+// Client credentials grant (RFC 6749 section 4.4), for service accounts authenticating with their
+// secret like confidential clients do. There is no refresh token, the account just asks again
+async fn client_credentials_grant(state: &AppState, headers: &HeaderMap, request: &OAuthTokenRequest) -> Result<Response, OAuthError> {
+    let account = authenticate_service_account(state, headers, request.client_id.as_deref(), request.client_secret.as_deref()).await?;
+    let scopes = account.grant_scopes(request.scope.as_deref()).map_err(OAuthError::InvalidScope)?;
+    let access_token = generate_service_token(&account, &scopes).map_err(|_| OAuthError::ServerError)?;
+    let response = OAuthTokenResponse {
+        access_token,
+        id_token: None,
+        token_type: "Bearer",
+        expires_in: TOKEN_TTL_SECDONDS,
+        refresh_token: None,
+        scope: scopes.join(" "),
+    };
+    Ok(token_response(response))
+}
+
+// tokens must never be cached (RFC 6749 section 5.1)
+fn token_response(response: OAuthTokenResponse) -> Response {
+    ([(CACHE_CONTROL, "no-store"), (PRAGMA, "no-cache")], Json(response)).into_response()
+}
+
+async fn authorizing_client(state: &AppState, request: &AuthorizeRequest) -> Result<(OAuthClient, String), OAuthError> {
+    let client_id = request.client_id.as_deref().ok_or_else(|| OAuthError::InvalidRequest("client_id is required".to_owned()))?;
+    let client = match state.oauth_client_store.read().await.get_client(client_id).await {
+        Ok(client) => client,
+        Err(OAuthClientStoreError::ClientNotFound) => return Err(OAuthError::InvalidRequest("Unknown client".to_owned())),
+        Err(_) => return Err(OAuthError::ServerError),
+    };
+    let redirect_uri = client
+        .redirect_uri(request.redirect_uri.as_deref())
+        .ok_or_else(|| OAuthError::InvalidRequest("redirect_uri is not registered for the client".to_owned()))?;
+    Ok((client, redirect_uri))
+}
+
+// Check the rest of the authorization request, returning the scopes to grant and the PKCE challenge
+fn check_authorization_request(client: &OAuthClient, request: &AuthorizeRequest) -> Result<(Vec<String>, CodeChallenge), OAuthError> {
+    if request.response_type.as_deref() != Some("code") {
+        return Err(OAuthError::UnsupportedResponseType);
+    }
+    let code_challenge = request
+        .code_challenge
+        .clone()
+        .ok_or_else(|| OAuthError::InvalidRequest("code_challenge is required".to_owned()))?;
+    let code_challenge = CodeChallenge::parse(code_challenge, request.code_challenge_method.as_deref()).map_err(OAuthError::InvalidRequest)?;
+    let scopes = client.grant_scopes(request.scope.as_deref()).map_err(OAuthError::InvalidScope)?;
+    check_openid_scope(&scopes)?;
+    Ok((scopes, code_challenge))
+}
+
+fn error_params(error: &OAuthError) -> Vec<(&'static str, String)> {
+    let mut params = vec![("error", error.code().to_owned())];
+    if let Some(description) = error.description() {
+        params.push(("error_description", description.to_owned()));
+    }
+    params
+}
+
+// Send the user back to the client, passing its `state` through untouched
+fn redirect_to_client(redirect_uri: &str, params: Vec<(&'static str, String)>, state: Option<&str>) -> Response {
+    // registered redirect URIs were checked to be absolute when the client was added
+    let mut url = match Url::parse(redirect_uri) {
+        Ok(url) => url,
+        Err(_) => return OAuthError::ServerError.into_response(),
+    };
+    {
+        let mut query = url.query_pairs_mut();
+        for (name, value) in &params {
+            query.append_pair(name, value);
+        }
+        if let Some(state) = state {
+            query.append_pair("state", state);
+        }
+    }
+    Redirect::to(url.as_str()).into_response()
+}
+
+// Clients authenticate with HTTP Basic or by posting their credentials (RFC 6749 section 2.3.1).
+// Confidential clients have to prove they know their secret, public ones can't have one
+pub(crate) async fn authenticate_client(state: &AppState,
+    headers: &HeaderMap,
+    client_id: Option<&str>,
+    client_secret: Option<&str>) -> Result<OAuthClient, OAuthError> {
+    let (client_id, secret) = client_credentials(headers, client_id, client_secret)?;
+
+    let oauth_client_store = state.oauth_client_store.read().await;
+    let client = match oauth_client_store.get_client(&client_id).await {
+        Ok(client) => client,
+        Err(OAuthClientStoreError::ClientNotFound) => return Err(OAuthError::InvalidClient),
+        Err(_) => return Err(OAuthError::ServerError),
+    };
+    match (client.confidential, secret) {
+        (true, Some(secret)) => match oauth_client_store.validate_client_secret(&client_id, &secret).await {
+            Ok(()) => Ok(client),
+            Err(OAuthClientStoreError::InvalidClientSecret | OAuthClientStoreError::ClientNotFound) => Err(OAuthError::InvalidClient),
+            Err(_) => Err(OAuthError::ServerError),
+        },
+        (false, None) => Ok(client),
+        (true, None) | (false, Some(_)) => Err(OAuthError::InvalidClient),
+    }
+}
+
+// Service accounts authenticate like confidential clients, with their id and secret
+pub(crate) async fn authenticate_service_account(state: &AppState,
+    headers: &HeaderMap,
+    client_id: Option<&str>,
+    client_secret: Option<&str>) -> Result<ServiceAccount, OAuthError> {
+    let (id, secret) = client_credentials(headers, client_id, client_secret)?;
+    let secret = secret.ok_or(OAuthError::InvalidClient)?;
+
+    let service_account_store = state.service_account_store.read().await;
+    let account = match service_account_store.get_service_account(&id).await {
+        Ok(account) => account,
+        Err(ServiceAccountStoreError::AccountNotFound) => return Err(OAuthError::InvalidClient),
+        Err(_) => return Err(OAuthError::ServerError),
+    };
+    match service_account_store.validate_secret(&id, &secret).await {
+        Ok(()) => Ok(account),
+        Err(ServiceAccountStoreError::InvalidSecret | ServiceAccountStoreError::AccountNotFound) => Err(OAuthError::InvalidClient),
+        Err(_) => Err(OAuthError::ServerError),
+    }
+}
+
+// The client id, and secret if any, from the Authorization header or the posted form
+fn client_credentials(headers: &HeaderMap,
+    client_id: Option<&str>,
+    client_secret: Option<&str>) -> Result<(String, Option<String>), OAuthError> {
+    match basic_credentials(headers)? {
+        Some((basic_client_id, secret)) => {
+            if client_secret.is_some() || client_id.is_some_and(|id| id != basic_client_id) {
+                return Err(OAuthError::InvalidRequest("Use only one way to authenticate the client".to_owned()));
+            }
+            Ok((basic_client_id, Some(secret)))
+        }
+        None => Ok((client_id.ok_or(OAuthError::InvalidClient)?.to_owned(), client_secret.map(str::to_owned))),
+    }
+}
+
+// Client id and secret from an `Authorization: Basic` header, both form-urlencoded
+fn basic_credentials(headers: &HeaderMap) -> Result<Option<(String, String)>, OAuthError> {
+    let Some(header) = headers.get(AUTHORIZATION) else {
+        return Ok(None);
+    };
+    let encoded = header
+        .to_str()
+        .ok()
+        .and_then(|value| value.strip_prefix("Basic "))
+        .ok_or(OAuthError::InvalidClient)?;
+    let decoded = STANDARD.decode(encoded.trim()).ok().and_then(|bytes| String::from_utf8(bytes).ok()).ok_or(OAuthError::InvalidClient)?;
+    let (client_id, secret) = decoded.split_once(':').ok_or(OAuthError::InvalidClient)?;
+    let decode = |value: &str| urlencoding::decode(&value.replace('+', " ")).map(|value| value.into_owned()).map_err(|_| OAuthError::InvalidClient);
+    Ok(Some((decode(client_id)?, decode(secret)?)))
+}
+
+// Redeem an authorization code, starting a session for the client in the user's name.
+// Also returns the nonce the client authorized with, for the ID token
+async fn exchange_code(state: &AppState, client: &OAuthClient, request: &OAuthTokenRequest) -> Result<(Session, RefreshToken, Option<String>), OAuthError> {
+    let code = request.code.clone().ok_or_else(|| OAuthError::InvalidRequest("code is required".to_owned()))?;
+    let code_verifier = request.code_verifier.as_deref().ok_or_else(|| OAuthError::InvalidRequest("code_verifier is required".to_owned()))?;
+    let invalid_code = || OAuthError::InvalidGrant("Invalid authorization code".to_owned());
+
+    let code = AuthorizationCode::parse(code).map_err(|_| invalid_code())?;
+    let grant = match state.authorization_code_store.write().await.take_code(&code).await {
+        Ok(grant) => grant,
+        Err(AuthorizationCodeStoreError::CodeNotFound) => return Err(invalid_code()),
+        Err(_) => return Err(OAuthError::ServerError),
+    };
+    // The code is gone either way, so a client guessing verifiers only gets one try
+    let redirect_uri_matches = match &request.redirect_uri {
+        Some(uri) => *uri == grant.redirect_uri,
+        None => !grant.redirect_uri_supplied,
+    };
+    if grant.client_id != client.client_id || !redirect_uri_matches {
+        return Err(invalid_code());
+    }
+    if !grant.code_challenge.verify(code_verifier) {
+        return Err(OAuthError::InvalidGrant("code_verifier doesn't match the code_challenge".to_owned()));
+    }
+
+    let session = Session {
+        client_id: Some(client.client_id.clone()),
+        scopes: grant.scopes,
+        amr: grant.amr,
+        ..Session::new(RefreshTokenFamily::default(), grant.email, format!("{} ({})", grant.device, client.name), grant.ip)
+    };
+    let refresh_token = record_session(state, &session).await.map_err(|_| OAuthError::ServerError)?;
+    Ok((session, refresh_token, grant.nonce))
+}
+
+#[derive(Deserialize)]
+pub struct AuthorizeRequest {
+    pub response_type: Option<String>,
+    pub client_id: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    // OpenID Connect clients can pass a value to find in the ID token
+    pub nonce: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct OAuthTokenRequest {
+    pub grant_type: Option<String>,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+    pub refresh_token: Option<String>,
+    // the device code a device polls with
+    pub device_code: Option<String>,
+    // the scopes a service account asks for with the client credentials grant
+    pub scope: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct OAuthTokenResponse {
+    pub access_token: String,
+    // only for clients granted the `openid` scope
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+    pub token_type: &'static str,
+    pub expires_in: i64,
+    // not issued to service accounts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    pub scope: String,
+}
@@ -0,0 +1,122 @@
+use std::sync::PoisonError;
+
+use axum::{extract::State, http::{header::{AUTHORIZATION, CACHE_CONTROL}, HeaderMap}, response::IntoResponse, Json};
+use chrono::Utc;
+use jsonwebtoken::Algorithm;
+use serde::Serialize;
+
+use crate::{app_state::AppState, routes::{unrevoked_session, DEVICE_CODE_GRANT_TYPE}, domain::{data_store::UserStoreError, error::OAuthError, oauth::{UserInfo, EMAIL_SCOPE, OPENID_SCOPE}, session::Session}, utils::{auth::{generate_id_token, validate_token}, constants::{JWT_ISSUER, JWT_KEYRING}}};
+
+// OpenID Connect discovery document, which OIDC client libraries configure themselves from.
+// The endpoints are relative to the issuer, so JWT_ISSUER has to be the URL the service is reached at.
+// The `openid` scope is only offered while an asymmetric key signs, see `check_openid_scope`
+pub async fn openid_configuration() -> impl IntoResponse {
+    let issuer = JWT_ISSUER.trim_end_matches('/');
+    let signing_algorithms: Vec<Algorithm> = id_token_signing_algorithm().into_iter().collect();
+    let scopes_supported = if signing_algorithms.is_empty() { vec![EMAIL_SCOPE] } else { vec![OPENID_SCOPE, EMAIL_SCOPE] };
+
+    let configuration = OpenIdConfiguration {
+        issuer: JWT_ISSUER.clone(),
+        authorization_endpoint: format!("{}/oauth/authorize", issuer),
+        token_endpoint: format!("{}/oauth/token", issuer),
+        userinfo_endpoint: format!("{}/userinfo", issuer),
+        introspection_endpoint: format!("{}/oauth/introspect", issuer),
+        revocation_endpoint: format!("{}/oauth/revoke", issuer),
+        device_authorization_endpoint: format!("{}/oauth/device_authorization", issuer),
+        jwks_uri: format!("{}/.well-known/jwks.json", issuer),
+        scopes_supported,
+        response_types_supported: vec!["code"],
+        grant_types_supported: vec!["authorization_code", "refresh_token", "client_credentials", DEVICE_CODE_GRANT_TYPE],
+        subject_types_supported: vec!["public"],
+        id_token_signing_alg_values_supported: signing_algorithms,
+        token_endpoint_auth_methods_supported: vec!["client_secret_basic", "client_secret_post", "none"],
+        code_challenge_methods_supported: vec!["S256"],
+        claims_supported: vec!["iss", "sub", "aud", "exp", "iat", "sid", "nonce", "amr", "email", "email_verified"],
+    };
+    ([(CACHE_CONTROL, "public, max-age=300")], Json(configuration))
+}
+
+// Claims about the user an access token was issued for (OpenID Connect Core section 5.3).
+// The token has to be granted the `openid` scope, and further scopes decide which claims are returned
+pub async fn userinfo(State(state): State<AppState>, headers: HeaderMap) -> Result<impl IntoResponse, OAuthError> {
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(OAuthError::InvalidToken)?;
+    let claims = validate_token(token).await.map_err(|_| OAuthError::InvalidToken)?;
+    let session = unrevoked_session(&state, &claims).await.map_err(|_| OAuthError::InvalidToken)?;
+    if !session.scopes.iter().any(|scope| scope == OPENID_SCOPE) {
+        return Err(OAuthError::InsufficientScope);
+    }
+
+    let user_info = user_info(&state, &session).await?;
+    Ok(Json(UserInfoResponse {
+        sub: user_info.sub,
+        email: user_info.email,
+        email_verified: user_info.email_verified,
+    }))
+}
+
+// Refuses the `openid` scope while the keyring signs with HS256: ID tokens signed with the shared
+// secret couldn't be checked by clients without it, and any service holding it could forge them
+pub(crate) fn check_openid_scope(scopes: &[String]) -> Result<(), OAuthError> {
+    if scopes.iter().any(|scope| scope == OPENID_SCOPE) && id_token_signing_algorithm().is_none() {
+        return Err(OAuthError::InvalidScope("The openid scope needs an RS256, ES256 or EdDSA signing key".to_owned()));
+    }
+    Ok(())
+}
+
+fn id_token_signing_algorithm() -> Option<Algorithm> {
+    JWT_KEYRING
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .id_token_signing_key(Utc::now())
+        .map(|key| key.algorithm())
+}
+
+// An ID token for a session started for an OAuth client, when the client was granted the `openid` scope
+pub(crate) async fn issue_id_token(state: &AppState, session: &Session, nonce: Option<String>) -> Result<Option<String>, OAuthError> {
+    if !session.scopes.iter().any(|scope| scope == OPENID_SCOPE) {
+        return Ok(None);
+    }
+    let user_info = user_info(state, session).await?;
+    generate_id_token(session, &user_info, nonce).map(Some).map_err(|_| OAuthError::ServerError)
+}
+
+async fn user_info(state: &AppState, session: &Session) -> Result<UserInfo, OAuthError> {
+    match state.user_store.read().await.get_user(session.email.as_ref()).await {
+        Ok(user) => Ok(UserInfo::new(&user, &session.scopes)),
+        Err(UserStoreError::UserNotFound) => Err(OAuthError::InvalidToken),
+        Err(_) => Err(OAuthError::ServerError),
+    }
+}
+
+#[derive(Serialize)]
+pub struct OpenIdConfiguration {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+    pub introspection_endpoint: String,
+    pub revocation_endpoint: String,
+    pub device_authorization_endpoint: String,
+    pub jwks_uri: String,
+    pub scopes_supported: Vec<&'static str>,
+    pub response_types_supported: Vec<&'static str>,
+    pub grant_types_supported: Vec<&'static str>,
+    pub subject_types_supported: Vec<&'static str>,
+    pub id_token_signing_alg_values_supported: Vec<Algorithm>,
+    pub token_endpoint_auth_methods_supported: Vec<&'static str>,
+    pub code_challenge_methods_supported: Vec<&'static str>,
+    pub claims_supported: Vec<&'static str>,
+}
+
+#[derive(Serialize)]
+pub struct UserInfoResponse {
+    pub sub: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email_verified: Option<bool>,
+}
@@ -0,0 +1,96 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{app_state::AppState, routes::revoke_all_sessions, domain::{data_store::PasswordResetToken, email::Email, email_message::EmailMessage, error::AuthAPIError, password::Password}};
+
+pub async fn request_password_reset(State(state): State<AppState>,
+    Json(request): Json<PasswordResetRequest>) -> Result<impl IntoResponse, AuthAPIError> {
+
+    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    // The same response is returned whether or not the account exists,
+    // so this route cannot be used to find out which emails are registered
+    let response = Json(PasswordResetResponse {
+        message: "If an account exists for this email, a password reset token has been sent".to_string(),
+    });
+
+    if state.user_store.read().await.get_user(email.as_ref()).await.is_err() {
+        return Ok((StatusCode::OK, response));
+    }
+
+    // Generate a new token, replacing any token previously issued for this email
+    let token = PasswordResetToken::default();
+    state
+        .password_reset_token_store
+        .write()
+        .await
+        .add_token(email.clone(), token.clone())
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    // Send the reset token via the email client. If it can't be queued the request fails
+    // and asking again replaces the token that was never sent
+    let message = EmailMessage::password_reset(&token).map_err(|_| AuthAPIError::UnexpectedError)?;
+    state
+        .email_outbox
+        .write()
+        .await
+        .enqueue(email, message)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok((StatusCode::OK, response))
+}
+
+pub async fn confirm_password_reset(State(state): State<AppState>,
+    Json(request): Json<PasswordResetConfirmRequest>) -> Result<impl IntoResponse, AuthAPIError> {
+
+    // Validate all the input before the token gets used up
+    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let token = PasswordResetToken::parse(request.token).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let password = Password::parse(request.new_password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let mut token_store = state.password_reset_token_store.write().await;
+    let stored_token = token_store.get_token(&email).await.map_err(|_| AuthAPIError::InvalidToken)?;
+    if !token.matches(&stored_token) {
+        return Err(AuthAPIError::InvalidToken);
+    }
+
+    // The token is single-use, remove it before changing the password
+    token_store.remove_token(&email).await.map_err(|_| AuthAPIError::UnexpectedError)?;
+    drop(token_store);
+
+    state
+        .user_store
+        .write()
+        .await
+        .update_password(&email, password)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    // Log the user out everywhere
+    revoke_all_sessions(&state, &email).await?;
+
+    let response = Json(PasswordResetResponse {
+        message: "Password reset successfully!".to_string(),
+    });
+    Ok((StatusCode::OK, response))
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetConfirmRequest {
+    pub email: String,
+    pub token: String,
+    #[serde(rename = "newPassword")]
+    pub new_password: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, PartialOrd)]
+pub struct PasswordResetResponse {
+    pub message: String,
+}
@@ -0,0 +1,70 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{HeaderMap, HeaderName},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{app_state::AppState, domain::{error::AuthAPIError, rate_limit::RateLimitDecision}};
+
+const RATE_LIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATE_LIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATE_LIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+// Routes that check passwords, codes or client secrets. While their limits can't be enforced
+// they are turned away rather than left open to brute forcing
+const FAIL_CLOSED_ROUTES: [&str; 8] = [
+    "/login",
+    "/verify-2fa",
+    "/password-reset/confirm",
+    "/totp/confirm",
+    "/oauth/token",
+    "/oauth/introspect",
+    "/oauth/revoke",
+    "/oauth/device_authorization",
+];
+
+// Every client IP gets its own token bucket for each route. Requests are let through
+// while the bucket has tokens and turned away with a 429 once it runs dry.
+// The IP is the one the connection comes from, so behind a proxy all clients share a bucket
+pub async fn rate_limit(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    route: MatchedPath,
+    request: Request,
+    next: Next,
+) -> Response {
+    let limit = state.rate_limits.for_route(route.as_str());
+    let key = format!("{}:{}", route.as_str(), client.ip());
+    let decision = state.rate_limit_store.write().await.take_token(&key, &limit).await;
+
+    match decision {
+        Ok(decision) => {
+            let mut response = if decision.allowed {
+                next.run(request).await
+            } else {
+                AuthAPIError::TooManyRequests(decision.retry_after_seconds).into_response()
+            };
+            add_rate_limit_headers(response.headers_mut(), &decision);
+            response
+        }
+        // An unavailable store shouldn't take the whole service down with it,
+        // only the routes that would be open to brute forcing without their limits
+        Err(e) if FAIL_CLOSED_ROUTES.contains(&route.as_str()) => {
+            eprintln!("Rate limiting {} failed, refusing the request: {:?}", key, e);
+            AuthAPIError::ServiceUnavailable.into_response()
+        }
+        Err(e) => {
+            eprintln!("Rate limiting {} failed, letting the request through: {:?}", key, e);
+            next.run(request).await
+        }
+    }
+}
+
+fn add_rate_limit_headers(headers: &mut HeaderMap, decision: &RateLimitDecision) {
+    headers.insert(RATE_LIMIT_LIMIT, decision.limit.into());
+    headers.insert(RATE_LIMIT_REMAINING, decision.remaining.into());
+    headers.insert(RATE_LIMIT_RESET, decision.reset_seconds.into());
+}
@@ -0,0 +1,37 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use serde::{Deserialize, Serialize};
+
+use crate::{app_state::AppState, routes::authenticated_email, domain::{data_store::{RecoveryCode, RECOVERY_CODE_COUNT}, email::Email, error::AuthAPIError}};
+
+// Replace the logged in user's recovery codes with a new set, invalidating the old ones
+pub async fn regenerate_recovery_codes(State(state): State<AppState>,
+    jar: CookieJar) -> Result<impl IntoResponse, AuthAPIError> {
+
+    let email = authenticated_email(&jar, &state).await?;
+    let recovery_codes = issue_recovery_codes(&state, &email).await?;
+
+    Ok((StatusCode::OK, Json(RecoveryCodesResponse { recovery_codes })))
+}
+
+// Generate and store a new set of recovery codes for the user. The codes are only ever
+// shown in this response since just their hashes are stored
+pub(crate) async fn issue_recovery_codes(state: &AppState,
+    email: &Email) -> Result<Vec<String>, AuthAPIError> {
+    let codes: Vec<RecoveryCode> = (0..RECOVERY_CODE_COUNT).map(|_| RecoveryCode::default()).collect();
+    state
+        .user_store
+        .write()
+        .await
+        .set_recovery_codes(email, &codes)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok(codes.iter().map(|code| code.as_ref().to_owned()).collect())
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RecoveryCodesResponse {
+    #[serde(rename = "recoveryCodes")]
+    pub recovery_codes: Vec<String>,
+}
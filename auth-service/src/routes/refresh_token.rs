@@ -0,0 +1,95 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use chrono::Utc;
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+
+use crate::{app_state::AppState, domain::{data_store::{RefreshToken, SessionStoreError}, error::AuthAPIError, session::Session}, utils::{auth::{create_refresh_cookie, generate_auth_cookie, generate_auth_token}, constants::REFRESH_TOKEN_COOKIE_NAME}};
+
+pub async fn refresh_token(State(state): State<AppState>,
+    jar: CookieJar) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    // Return AuthAPIError::MissingToken if the refresh cookie is not found
+    let token = match jar.get(REFRESH_TOKEN_COOKIE_NAME) {
+        Some(cookie) => cookie.value().to_owned(),
+        None => return (jar, Err(AuthAPIError::MissingToken)),
+    };
+    let token = match RefreshToken::parse(token) {
+        Ok(token) => token,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    // Only sessions started by logging in through our own UI are refreshed with cookies
+    let (session, new_token) = match rotate_refresh_token(&state, &token, None).await {
+        Ok(rotated) => rotated,
+        Err(AuthAPIError::InvalidToken) => return (jar.remove(Cookie::from(REFRESH_TOKEN_COOKIE_NAME)), Err(AuthAPIError::InvalidToken)),
+        Err(e) => return (jar, Err(e)),
+    };
+    let auth_cookie = match issue_auth_cookie(&state, &session).await {
+        Ok(cookie) => cookie,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    let updated_jar = jar.add(auth_cookie).add(create_refresh_cookie(&new_token));
+    (updated_jar, Ok(StatusCode::OK))
+}
+
+// Trade a refresh token for the next one of its family, returning the session it belongs to.
+// The session must have been started for `client_id`, None meaning our own UI
+pub(crate) async fn rotate_refresh_token(state: &AppState,
+    token: &RefreshToken,
+    client_id: Option<&str>) -> Result<(Session, RefreshToken), AuthAPIError> {
+    // Hold the write lock for the whole rotation so the same token cannot be used twice concurrently
+    let mut refresh_token_store = state.refresh_token_store.write().await;
+    let (email, family) = refresh_token_store.get_token(token).await.map_err(|_| AuthAPIError::InvalidToken)?;
+
+    // A family without a current token has been revoked or has expired
+    let current_token = refresh_token_store.get_current_token(&family).await.map_err(|_| AuthAPIError::InvalidToken)?;
+
+    // Only the newest token of a family may be used. Seeing an older one means it was
+    // stolen or replayed, so the whole family is revoked and the user has to log in again.
+    if current_token != *token {
+        refresh_token_store.revoke_family(&family).await.map_err(|_| AuthAPIError::UnexpectedError)?;
+        return Err(AuthAPIError::InvalidToken);
+    }
+
+    // The family is the session, which the user may have ended from another device
+    let mut session_store = state.session_store.write().await;
+    let session = match session_store.get_session(&family).await {
+        Ok(session) if session.client_id.as_deref() == client_id => session,
+        Ok(_) => return Err(AuthAPIError::InvalidToken),
+        Err(SessionStoreError::SessionNotFound) => {
+            refresh_token_store.revoke_family(&family).await.map_err(|_| AuthAPIError::UnexpectedError)?;
+            return Err(AuthAPIError::InvalidToken);
+        }
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    };
+    let last_seen_at = Utc::now();
+    session_store.touch_session(&family, last_seen_at).await.map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let new_token = RefreshToken::default();
+    refresh_token_store
+        .add_token(email, family, new_token.clone())
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+    Ok((Session { last_seen_at, ..session }, new_token))
+}
+
+// Create the JWT cookie for a user that just authenticated, in their current token epoch and session
+pub(crate) async fn issue_auth_cookie(state: &AppState, session: &Session) -> Result<Cookie<'static>, AuthAPIError> {
+    let epoch = token_epoch(state, session).await?;
+    generate_auth_cookie(session, epoch).map_err(|_| AuthAPIError::UnexpectedError)
+}
+
+// Same as `issue_auth_cookie`, for clients that send the JWT as a bearer token
+pub(crate) async fn issue_auth_token(state: &AppState, session: &Session) -> Result<String, AuthAPIError> {
+    let epoch = token_epoch(state, session).await?;
+    generate_auth_token(session, epoch).map_err(|_| AuthAPIError::UnexpectedError)
+}
+
+async fn token_epoch(state: &AppState, session: &Session) -> Result<u32, AuthAPIError> {
+    state
+        .user_store
+        .read()
+        .await
+        .get_token_epoch(&session.email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)
+}
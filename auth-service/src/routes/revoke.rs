@@ -0,0 +1,43 @@
+use axum::{extract::State, http::{HeaderMap, StatusCode}, response::IntoResponse, Form};
+
+use crate::{app_state::AppState, routes::{authenticate_client, authenticate_service_account, end_session, find_active_token, ActiveToken, TokenActionRequest}, domain::{data_store::BannedTokenStoreError, error::OAuthError}, utils::{auth::Claims, constants::JWT_LEEWAY_SECONDS}};
+
+// Token revocation (RFC 7009), for clients to log the user out. Clients may only revoke tokens
+// issued to them, and service accounts their own tokens; other tokens, including invalid ones,
+// are ignored and answered with 200 too
+pub async fn revoke(State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(request): Form<TokenActionRequest>) -> Result<impl IntoResponse, OAuthError> {
+    let (client_id, client_secret) = (request.client_id.as_deref(), request.client_secret.as_deref());
+    let (client_id, service_account_id) = match authenticate_client(&state, &headers, client_id, client_secret).await {
+        Ok(client) => (Some(client.client_id), None),
+        Err(OAuthError::InvalidClient) => (None, Some(authenticate_service_account(&state, &headers, client_id, client_secret).await?.id)),
+        Err(e) => return Err(e),
+    };
+    let token = request.token.ok_or_else(|| OAuthError::InvalidRequest("token is required".to_owned()))?;
+    let issued_to_client = |id: Option<&str>| id.is_some() && id == client_id.as_deref();
+
+    match find_active_token(&state, &token).await? {
+        // ending the session also revokes every access token issued in it
+        Some(ActiveToken::Refresh(session)) if issued_to_client(session.client_id.as_deref()) => {
+            end_session(&state, &session.id).await.map_err(|_| OAuthError::ServerError)?;
+        }
+        Some(ActiveToken::Access(claims, _)) if issued_to_client(claims.client_id.as_deref()) => {
+            revoke_access_token(&state, &claims).await?;
+        }
+        Some(ActiveToken::Service(claims)) if service_account_id.as_deref() == Some(claims.sub.as_str()) => {
+            revoke_access_token(&state, &claims).await?;
+        }
+        _ => {}
+    }
+    Ok(StatusCode::OK)
+}
+
+// Revoke the token until it would be rejected as expired anyway, like at logout
+async fn revoke_access_token(state: &AppState, claims: &Claims) -> Result<(), OAuthError> {
+    let revoked_until = claims.exp + *JWT_LEEWAY_SECONDS as usize;
+    match state.banned_token_store.write().await.revoke_token(&claims.jti, revoked_until).await {
+        Ok(()) | Err(BannedTokenStoreError::TokenAlreadyInStore) => Ok(()),
+        Err(_) => Err(OAuthError::ServerError),
+    }
+}
@@ -0,0 +1,154 @@
+use std::{convert::Infallible, net::SocketAddr};
+
+use axum::{async_trait, extract::{ConnectInfo, FromRequestParts, Path, State}, http::{header::USER_AGENT, request::Parts, StatusCode}, response::IntoResponse, Json};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use serde::Serialize;
+
+use crate::{app_state::AppState, routes::{authenticated_session, issue_auth_cookie}, domain::{data_store::{RefreshToken, RefreshTokenFamily, SessionStoreError}, email::Email, error::AuthAPIError, session::{authentication_methods, device_label, Session}}, utils::{auth::create_refresh_cookie, constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME}}};
+
+pub async fn get_sessions(State(state): State<AppState>, jar: CookieJar) -> Result<impl IntoResponse, AuthAPIError> {
+    let current = authenticated_session(&jar, &state).await?;
+    let sessions = state
+        .session_store
+        .read()
+        .await
+        .get_user_sessions(&current.email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let sessions = sessions
+        .into_iter()
+        .map(|session| SessionResponse {
+            current: session.id == current.id,
+            id: session.id.as_ref().to_owned(),
+            device: session.device,
+            ip: session.ip,
+            created_at: session.created_at.to_rfc3339(),
+            last_seen_at: session.last_seen_at.to_rfc3339(),
+        })
+        .collect();
+    Ok(Json(SessionsResponse { sessions }))
+}
+
+// Log one of the user's devices out. Ending the current session also clears its cookies
+pub async fn delete_session(State(state): State<AppState>,
+    jar: CookieJar,
+    Path(id): Path<String>) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let current = match authenticated_session(&jar, &state).await {
+        Ok(session) => session,
+        Err(e) => return (jar, Err(e)),
+    };
+    let id = match RefreshTokenFamily::parse(id) {
+        Ok(id) => id,
+        Err(_) => return (jar, Err(AuthAPIError::SessionNotFound)),
+    };
+
+    // Other users' sessions are reported as missing, not forbidden, so their ids can't be probed
+    match state.session_store.read().await.get_session(&id).await {
+        Ok(session) if session.email == current.email => {}
+        Ok(_) | Err(SessionStoreError::SessionNotFound) => return (jar, Err(AuthAPIError::SessionNotFound)),
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    }
+    if let Err(e) = end_session(&state, &id).await {
+        return (jar, Err(e));
+    }
+
+    // The cookies were set for "/", which has to be spelled out since this route lives under /sessions
+    let jar = if id == current.id {
+        jar.remove(Cookie::build(JWT_COOKIE_NAME).path("/")).remove(Cookie::build(REFRESH_TOKEN_COOKIE_NAME).path("/"))
+    } else {
+        jar
+    };
+    (jar, Ok(StatusCode::NO_CONTENT))
+}
+
+// Start a session for a user that just logged in: a new refresh token family and the
+// session recording the device, returning the JWT and refresh cookies for it
+pub(crate) async fn start_session(state: &AppState,
+    email: Email,
+    client: ClientInfo,
+    two_factor: bool) -> Result<(Cookie<'static>, Cookie<'static>), AuthAPIError> {
+    let session = Session {
+        amr: authentication_methods(two_factor),
+        ..Session::new(RefreshTokenFamily::default(), email, client.device, client.ip)
+    };
+    let token = record_session(state, &session).await?;
+
+    let auth_cookie = issue_auth_cookie(state, &session).await?;
+    Ok((auth_cookie, create_refresh_cookie(&token)))
+}
+
+// Store a new session along with the first refresh token of its family
+pub(crate) async fn record_session(state: &AppState, session: &Session) -> Result<RefreshToken, AuthAPIError> {
+    let token = RefreshToken::default();
+    state
+        .refresh_token_store
+        .write()
+        .await
+        .add_token(session.email.clone(), session.id.clone(), token.clone())
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+    state
+        .session_store
+        .write()
+        .await
+        .add_session(session.clone())
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+    Ok(token)
+}
+
+// End a session: its JWTs are rejected from now on and its refresh token can't mint new ones
+pub(crate) async fn end_session(state: &AppState, id: &RefreshTokenFamily) -> Result<(), AuthAPIError> {
+    match state.session_store.write().await.remove_session(id).await {
+        Ok(()) | Err(SessionStoreError::SessionNotFound) => {}
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    }
+    state
+        .refresh_token_store
+        .write()
+        .await
+        .revoke_family(id)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)
+}
+
+// The device and address a login comes from, recorded in the session it starts
+pub struct ClientInfo {
+    pub device: String,
+    pub ip: String,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let user_agent = parts.headers.get(USER_AGENT).and_then(|value| value.to_str().ok());
+        // Like the rate limiter this is the address the connection comes from, so behind a proxy it's the proxy's
+        let ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip().to_string())
+            .unwrap_or_else(|| "unknown".to_owned());
+        Ok(Self { device: device_label(user_agent), ip })
+    }
+}
+
+#[derive(Serialize)]
+pub struct SessionsResponse {
+    pub sessions: Vec<SessionResponse>,
+}
+
+#[derive(Serialize)]
+pub struct SessionResponse {
+    pub id: String,
+    pub device: String,
+    pub ip: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+    #[serde(rename = "lastSeenAt")]
+    pub last_seen_at: String,
+    // whether this is the session making the request
+    pub current: bool,
+}
@@ -1,17 +1,14 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use serde::{Deserialize, Serialize};
 
-use crate::{app_state::AppState, domain::{data_store::UserStore, email::Email, error::AuthAPIError, password::Password, user::User}, services::{data_store::PostgresUserStore, mock_email_client::MockEmailClient, redis_banned_token_store::RedisBannedTokenStore, redis_two_fa_code_store::RedisTwoFACodeStore}};
+use crate::{app_state::AppState, routes::issue_recovery_codes, domain::{data_store::EmailVerificationToken, email::Email, email_message::EmailMessage, error::AuthAPIError, password::Password, user::User}, utils::constants::AUTH_SERVICE_URL};
 // Order of parameters is important in the handler
-pub async fn signup(State(state): State<AppState<PostgresUserStore, RedisBannedTokenStore, RedisTwoFACodeStore, MockEmailClient>>,Json(request): Json<SignupRequest> ) -> Result<impl IntoResponse, AuthAPIError> {
+pub async fn signup(State(state): State<AppState>,Json(request): Json<SignupRequest> ) -> Result<impl IntoResponse, AuthAPIError> {
 
     let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
     let password = Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
-    let user = User {
-        email: email.clone(),
-        password,
-        requires_2fa : request.requires_2fa,
-    };
+    let requires_2fa = request.requires_2fa;
+    let user = User::new(email.clone(), password, requires_2fa);
 
     // lock the store first before writing data into it
     let mut user_store = state.user_store.write().await;
@@ -23,9 +20,22 @@ pub async fn signup(State(state): State<AppState<PostgresUserStore, RedisBannedT
     }
     match user_store.add_user(user).await {
         Ok(()) => {
-            
+            drop(user_store);
+
+            // Users that require 2FA get recovery codes in case they lose access to their mailbox
+            let recovery_codes = match requires_2fa {
+                true => Some(issue_recovery_codes(&state, &email).await?),
+                false => None,
+            };
+            // The user is already stored, so failing here would leave an account that can neither
+            // sign up again nor get its link. They can ask for a new one at /verify-email/resend instead
+            if send_verification_email(&state, email.clone()).await.is_err() {
+                eprintln!("Failed to queue the verification email for {}", email.as_ref());
+            }
+
             let response = Json( SignupResponse {
                 message: "User created successfully!".to_string(),
+                recovery_codes,
             });    
             Ok((StatusCode::CREATED, response))
         },
@@ -34,6 +44,35 @@ pub async fn signup(State(state): State<AppState<PostgresUserStore, RedisBannedT
 
 }
 
+// Store a new verification token for the user and email them a link to confirm their address
+pub(crate) async fn send_verification_email(state: &AppState,
+    email: Email) -> Result<(), AuthAPIError> {
+    let token = EmailVerificationToken::default();
+    state
+        .email_verification_token_store
+        .write()
+        .await
+        .add_token(email.clone(), token.clone())
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let link = format!(
+        "{}/verify-email?email={}&token={}",
+        AUTH_SERVICE_URL.as_str(),
+        urlencoding::encode(email.as_ref()),
+        token.as_ref()
+    );
+    let message = EmailMessage::email_verification(&link).map_err(|_| AuthAPIError::UnexpectedError)?;
+    state
+        .email_outbox
+        .write()
+        .await
+        .enqueue(email, message)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+    Ok(())
+}
+
 
 #[derive(Deserialize)]
 pub struct SignupRequest {
@@ -46,4 +85,6 @@ pub struct SignupRequest {
 #[derive(Serialize, Deserialize,Clone, Debug, PartialEq, PartialOrd)]
 pub struct SignupResponse {
     pub message: String,
+    #[serde(rename = "recoveryCodes", default, skip_serializing_if = "Option::is_none")]
+    pub recovery_codes: Option<Vec<String>>,
 }
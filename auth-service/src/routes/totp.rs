@@ -0,0 +1,83 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use serde::{Deserialize, Serialize};
+
+use crate::{app_state::AppState, routes::{authenticated_email, issue_recovery_codes}, domain::{data_store::{TwoFACode, UserStoreError}, error::AuthAPIError, totp::TotpSecret}};
+
+// Start enrolling an authenticator app for the logged in user.
+// The secret only becomes active once a first code is confirmed with `confirm_totp`
+pub async fn enroll_totp(State(state): State<AppState>,
+    jar: CookieJar) -> Result<impl IntoResponse, AuthAPIError> {
+
+    let email = authenticated_email(&jar, &state).await?;
+
+    let secret = TotpSecret::default();
+    let otpauth_uri = secret.provisioning_uri(&email).map_err(|_| AuthAPIError::UnexpectedError)?;
+    state
+        .user_store
+        .write()
+        .await
+        .set_pending_totp_secret(&email, secret.clone())
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let response = Json(TotpEnrollmentResponse {
+        secret: secret.as_ref().to_owned(),
+        otpauth_uri,
+    });
+    Ok((StatusCode::OK, response))
+}
+
+// Finish enrolling by checking a code from the authenticator app against the pending secret.
+// From then on the user's 2FA codes come from the app instead of email
+pub async fn confirm_totp(State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<ConfirmTotpRequest>) -> Result<impl IntoResponse, AuthAPIError> {
+
+    let email = authenticated_email(&jar, &state).await?;
+    let code = TwoFACode::parse(request.code).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let mut user_store = state.user_store.write().await;
+    // Nothing to confirm if enrollment was never started
+    let secret = user_store.get_pending_totp_secret(&email).await.map_err(|_| AuthAPIError::Incorrect2FACode)?;
+    let step = secret
+        .verify(&email, &code)
+        .map_err(|_| AuthAPIError::UnexpectedError)?
+        .ok_or(AuthAPIError::Incorrect2FACode)?;
+    // the code used to confirm can't be used again to log in
+    user_store.use_totp_step(&email, step).await.map_err(|e| match e {
+        UserStoreError::TotpCodeAlreadyUsed => AuthAPIError::Incorrect2FACode,
+        _ => AuthAPIError::UnexpectedError,
+    })?;
+
+    user_store.enable_totp(&email).await.map_err(|_| AuthAPIError::UnexpectedError)?;
+    drop(user_store);
+
+    // Enrolling a second factor comes with a fresh set of recovery codes
+    let recovery_codes = issue_recovery_codes(&state, &email).await?;
+
+    let response = Json(ConfirmTotpResponse {
+        message: "TOTP enabled successfully!".to_string(),
+        recovery_codes,
+    });
+    Ok((StatusCode::OK, response))
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TotpEnrollmentResponse {
+    pub secret: String,
+    #[serde(rename = "otpauthUri")]
+    pub otpauth_uri: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmTotpRequest {
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, PartialOrd)]
+pub struct ConfirmTotpResponse {
+    pub message: String,
+    #[serde(rename = "recoveryCodes")]
+    pub recovery_codes: Vec<String>,
+}
@@ -2,10 +2,11 @@ use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use axum_extra::extract::CookieJar;
 use serde::Deserialize;
 
-use crate::{app_state::AppState, domain::{data_store::{LoginAttemptId, TwoFACode, TwoFACodeStore}, email::Email, error::AuthAPIError}, services::{data_store::PostgresUserStore, mock_email_client::MockEmailClient, redis_banned_token_store::RedisBannedTokenStore, redis_two_fa_code_store::RedisTwoFACodeStore}, utils::auth::generate_auth_cookie};
+use crate::{app_state::AppState, routes::{start_session, ClientInfo}, domain::{data_store::{LoginAttemptId, RecoveryCode, TwoFACode, TwoFACodeStoreError, UserStoreError}, email::Email, email_message::EmailMessage, error::AuthAPIError, user::TwoFAMethod}};
 
-pub async fn verify2fa(State(state): State<AppState<PostgresUserStore, RedisBannedTokenStore, RedisTwoFACodeStore, MockEmailClient>>,
+pub async fn verify2fa(State(state): State<AppState>,
     jar: CookieJar,
+    client: ClientInfo,
     Json(request): Json<VerifyRequest>) -> (CookieJar, impl IntoResponse) {
     // Because the function accepts a VerifyRequest Deserialized Json it will return
     // 422 if the Json sent with the request is malformed
@@ -21,42 +22,131 @@ pub async fn verify2fa(State(state): State<AppState<PostgresUserStore, RedisBann
         Err(_) =>  return (jar,StatusCode::BAD_REQUEST.into_response())
     };
 
-    let two_fa_code = match TwoFACode::parse(request.two_fa_code) {
-        Ok(code) => code,
-        Err(_) => return (jar, StatusCode::BAD_REQUEST.into_response()),
+    // the 2FACode field holds either a 2FA code or one of the user's recovery codes
+    let second_factor = match TwoFACode::parse(request.two_fa_code.clone()) {
+        Ok(code) => SecondFactor::Code(code),
+        Err(_) => match RecoveryCode::parse(request.two_fa_code) {
+            Ok(code) => SecondFactor::RecoveryCode(code),
+            Err(_) => return (jar, StatusCode::BAD_REQUEST.into_response()),
+        }
     };
 
-    // read the login id and 2FAcode stored when client posts to /login successfully
+    // make sure the login attempt is still pending before spending a recovery code on it
     let mut two_fa_code_store = state.two_fa_code_store.write().await;
-    let (store_login_attempt_id, store_two_fa_code) = match two_fa_code_store.get_code(&email).await {
+    let (store_login_attempt_id, _) = match two_fa_code_store.get_code(&email).await {
         Ok(tuple) => tuple,
         Err(_e) => return (jar, AuthAPIError::IncorrectCredentials.into_response())
     };
 
-    // if they id and 2FA do not match what is in the store send back a 401
-    if login_attempt_id != store_login_attempt_id || two_fa_code!= store_two_fa_code {
+    // if the login attempt id does not match what is in the store send back a 401
+    if login_attempt_id != store_login_attempt_id {
         return (jar, AuthAPIError::IncorrectCredentials.into_response())
     };
 
-    // remove the 2FACode from the store
-    let removal_result = two_fa_code_store.remove_code(&email).await;
-    match removal_result {
+    // the code has to match the emailed one, or the user's authenticator app if they enrolled one.
+    // Either way the login attempt is consumed when the code is valid, and dropped after too many wrong ones
+    let user = match state.user_store.read().await.get_user(email.as_ref()).await {
+        Ok(user) => user,
+        Err(_) => return (jar, AuthAPIError::IncorrectCredentials.into_response())
+    };
+    let verification = match (second_factor, user.two_fa_method) {
+        (SecondFactor::Code(two_fa_code), TwoFAMethod::Email) => {
+            two_fa_code_store.verify_code(&email, &login_attempt_id, &two_fa_code).await
+        }
+        (SecondFactor::Code(two_fa_code), TwoFAMethod::Totp) => {
+            let secret = match state.user_store.read().await.get_totp_secret(&email).await {
+                Ok(secret) => secret,
+                Err(_) => return (jar, AuthAPIError::UnexpectedError.into_response())
+            };
+            let step = match secret.verify(&email, &two_fa_code) {
+                Ok(step) => step,
+                Err(_) => return (jar, AuthAPIError::UnexpectedError.into_response())
+            };
+            // a code only works once, even though it stays valid for its whole time step
+            let is_valid = match step {
+                Some(step) => match state.user_store.write().await.use_totp_step(&email, step).await {
+                    Ok(()) => true,
+                    Err(UserStoreError::TotpCodeAlreadyUsed) => false,
+                    Err(_) => return (jar, AuthAPIError::UnexpectedError.into_response())
+                },
+                None => false,
+            };
+            two_fa_code_store.settle_attempt(&email, &login_attempt_id, is_valid).await
+        }
+        // a recovery code works whatever the user's 2FA method, and is burnt once used
+        (SecondFactor::RecoveryCode(recovery_code), _) => {
+            let is_valid = match state.user_store.write().await.use_recovery_code(&email, &recovery_code).await {
+                Ok(()) => true,
+                Err(UserStoreError::InvalidRecoveryCode) => false,
+                Err(_) => return (jar, AuthAPIError::UnexpectedError.into_response())
+            };
+            two_fa_code_store.settle_attempt(&email, &login_attempt_id, is_valid).await
+        }
+    };
+    match verification {
         Ok(()) => {},
+        Err(TwoFACodeStoreError::IncorrectCode | TwoFACodeStoreError::LoginAttemptIdNotFound) => {
+            return (jar, AuthAPIError::IncorrectCredentials.into_response())
+        }
         Err(_) => return (jar, AuthAPIError::UnexpectedError.into_response())
     };
-    // create a cookie
-    let auth_cookie = generate_auth_cookie(email).map_err(|_|AuthAPIError::UnexpectedError);
-    // if cookie has issue generating return an error with the empty jar
-    let auth_cookie= match auth_cookie{
-        Ok(cookie) => cookie,
+    drop(two_fa_code_store);
+
+    // Start a session for this device, with a JWT and a refresh token
+    let (auth_cookie, refresh_cookie) = match start_session(&state, email, client, true).await {
+        Ok(cookies) => cookies,
         Err(e) => return (jar, e.into_response())
     };
-    // If no error set the cookie in the jar
-    let updated_jar = jar.add(auth_cookie);
+    // If no error set the cookies in the jar
+    let updated_jar = jar.add(auth_cookie).add(refresh_cookie);
     // return a 200 OK status 
     (updated_jar, StatusCode::OK.into_response())
 }
 
+// Sends a new code for a pending login attempt, for when the first email got lost
+pub async fn resend_2fa_code(State(state): State<AppState>,
+    Json(request): Json<ResendRequest>) -> Result<impl IntoResponse, AuthAPIError> {
+
+    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let login_attempt_id = LoginAttemptId::parse(request.login_attempt_id).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    // users with an authenticator app have no emailed code to resend. They get the same error as
+    // unknown users and made up login attempts, so the route doesn't tell which emails are registered
+    let user = state.user_store.read().await.get_user(email.as_ref()).await.map_err(|_| AuthAPIError::IncorrectCredentials)?;
+    if !user.requires_2fa || user.two_fa_method != TwoFAMethod::Email {
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+
+    // the old code stops working once the new one is stored
+    let code = TwoFACode::default();
+    let replaced = state.two_fa_code_store.write().await.replace_code(&email, &login_attempt_id, code.clone()).await;
+    match replaced {
+        Ok(()) => {},
+        Err(TwoFACodeStoreError::ResendCooldown(seconds)) => return Err(AuthAPIError::TooManyRequests(seconds)),
+        Err(TwoFACodeStoreError::TooManyResends) => return Err(AuthAPIError::TooManyResends),
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => return Err(AuthAPIError::IncorrectCredentials),
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    };
+
+    // The old code is gone by now. If the new one can't be queued either, drop the login attempt
+    // rather than leave one behind whose code the user never gets, so they log in again instead
+    let queued = match EmailMessage::two_fa_code(&code) {
+        Ok(message) => state.email_outbox.write().await.enqueue(email.clone(), message).await.is_ok(),
+        Err(_) => false,
+    };
+    if !queued {
+        state.two_fa_code_store.write().await.remove_code(&email).await.ok();
+        return Err(AuthAPIError::UnexpectedError);
+    }
+
+    Ok(StatusCode::OK)
+}
+
+enum SecondFactor {
+    Code(TwoFACode),
+    RecoveryCode(RecoveryCode),
+}
+
 #[derive(Deserialize)]
 pub struct VerifyRequest {
     pub email: String,
@@ -65,3 +155,10 @@ pub struct VerifyRequest {
     #[serde(rename = "2FACode")]
     pub two_fa_code: String,
 }
+
+#[derive(Deserialize)]
+pub struct ResendRequest {
+    pub email: String,
+    #[serde(rename = "LoginAttemptId")]
+    pub login_attempt_id: String,
+}
@@ -0,0 +1,74 @@
+use axum::{extract::{Query, State}, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{app_state::AppState, routes::send_verification_email, domain::{data_store::EmailVerificationToken, email::Email, error::AuthAPIError}};
+
+// This route is reached through the link emailed at signup, so it takes its input from the query string
+pub async fn verify_email(State(state): State<AppState>,
+    Query(request): Query<VerifyEmailRequest>) -> Result<impl IntoResponse, AuthAPIError> {
+
+    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let token = EmailVerificationToken::parse(request.token).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let mut token_store = state.email_verification_token_store.write().await;
+    let stored_token = token_store.get_token(&email).await.map_err(|_| AuthAPIError::InvalidToken)?;
+    if !token.matches(&stored_token) {
+        return Err(AuthAPIError::InvalidToken);
+    }
+
+    state
+        .user_store
+        .write()
+        .await
+        .verify_email(&email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    // the link only works once
+    token_store.remove_token(&email).await.map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let response = Json(VerifyEmailResponse {
+        message: "Email verified successfully!".to_string(),
+    });
+    Ok((StatusCode::OK, response))
+}
+
+pub async fn resend_verification_email(State(state): State<AppState>,
+    Json(request): Json<ResendVerificationEmailRequest>) -> Result<impl IntoResponse, AuthAPIError> {
+
+    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    // Like password reset requests, the response doesn't tell whether the account
+    // exists or is already verified
+    let response = Json(VerifyEmailResponse {
+        message: "If an unverified account exists for this email, a new verification link has been sent".to_string(),
+    });
+
+    let user = match state.user_store.read().await.get_user(email.as_ref()).await {
+        Ok(user) => user,
+        Err(_) => return Ok((StatusCode::OK, response)),
+    };
+    if user.email_verified {
+        return Ok((StatusCode::OK, response));
+    }
+
+    // The new link replaces the one sent before
+    send_verification_email(&state, email).await?;
+    Ok((StatusCode::OK, response))
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailRequest {
+    pub email: String,
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResendVerificationEmailRequest {
+    pub email: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, PartialOrd)]
+pub struct VerifyEmailResponse {
+    pub message: String,
+}
@@ -1,37 +1,101 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
 use serde::Deserialize;
 
-use crate::{app_state::AppState, domain::data_store::BannedTokenStore, services::{data_store::PostgresUserStore, mock_email_client::MockEmailClient, redis_banned_token_store::RedisBannedTokenStore, redis_two_fa_code_store::RedisTwoFACodeStore}, utils::auth::validate_token};
+use crate::{app_state::AppState, domain::{data_store::{RefreshTokenFamily, ServiceAccountStoreError, SessionStoreError, UserStoreError}, email::Email, error::AuthAPIError, oauth::ServiceAccount, session::Session}, utils::{auth::{validate_token, Claims, PrincipalType}, constants::JWT_COOKIE_NAME}};
 
-pub async fn verify_token(State(AppState {banned_token_store, .. }): State<AppState<PostgresUserStore, RedisBannedTokenStore, RedisTwoFACodeStore, MockEmailClient>>,Json(request): Json<TokenRequest>) -> impl IntoResponse {
-    let token = request.token;
+pub async fn verify_token(State(state): State<AppState>,Json(request): Json<TokenRequest>) -> impl IntoResponse {
+    let claims = match validate_token(&request.token).await {
+        Ok(claims) => claims,
+        Err(_) => return StatusCode::UNAUTHORIZED.into_response(),
+    };
 
-    let banned_store = banned_token_store.read().await;
+    // Services can ask for tokens minted for them specifically
+    if request.audience.is_some_and(|audience| !claims.aud.contains(&audience)) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let unrevoked = match claims.principal_type {
+        PrincipalType::User => unrevoked_session(&state, &claims).await.map(|_| ()),
+        PrincipalType::ServiceAccount => unrevoked_service_account(&state, &claims).await.map(|_| ()),
+    };
+    match unrevoked {
+        Ok(()) => StatusCode::OK.into_response(),
+        // the token was revoked, or a store failed
+        Err(_) => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
+// Email of the user the request's JWT cookie was issued to
+pub(crate) async fn authenticated_email(jar: &CookieJar,
+    state: &AppState) -> Result<Email, AuthAPIError> {
+    authenticated_session(jar, state).await.map(|session| session.email)
+}
+
+// Session the request's JWT cookie was issued in. Access tokens issued to OAuth clients are
+// rejected, so a client can't act as the user on our own pages just by sending its token as the cookie
+pub(crate) async fn authenticated_session(jar: &CookieJar,
+    state: &AppState) -> Result<Session, AuthAPIError> {
+    let token = jar.get(JWT_COOKIE_NAME).ok_or(AuthAPIError::MissingToken)?.value().to_owned();
+    let claims = validate_token(&token).await.map_err(|_| AuthAPIError::InvalidToken)?;
+    if claims.client_id.is_some() {
+        return Err(AuthAPIError::InvalidToken);
+    }
+
+    let session = unrevoked_session(state, &claims).await?;
+    if session.client_id.is_some() {
+        return Err(AuthAPIError::InvalidToken);
+    }
+    Ok(session)
+}
+
+// The session a valid token was issued in, unless the token was revoked at logout, the user
+// has bumped their token epoch since it was issued, or the session has been ended.
+// Tokens issued to service accounts have no session and are rejected
+pub(crate) async fn unrevoked_session(state: &AppState, claims: &Claims) -> Result<Session, AuthAPIError> {
+    if claims.principal_type != PrincipalType::User || is_revoked(state, claims).await? {
+        return Err(AuthAPIError::InvalidToken);
+    }
+
+    let email = Email::parse(claims.sub.clone()).map_err(|_| AuthAPIError::InvalidToken)?;
+    let epoch = match state.user_store.read().await.get_token_epoch(&email).await {
+        Ok(epoch) => epoch,
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    };
+    if claims.epoch != epoch {
+        return Err(AuthAPIError::InvalidToken);
+    }
 
-    match banned_store.is_token_banned(token.clone()).await {
-        Ok(true) => {
-            // the token is banned
-            StatusCode::UNAUTHORIZED.into_response()
-        },
-        Ok(false) => {
-            // Token is not banned, proceed with the validation
-            let validation_result = validate_token(&token).await;
+    let id = claims.sid.clone().and_then(|sid| RefreshTokenFamily::parse(sid).ok()).ok_or(AuthAPIError::InvalidToken)?;
+    match state.session_store.read().await.get_session(&id).await {
+        Ok(session) if session.email == email => Ok(session),
+        Ok(_) | Err(SessionStoreError::SessionNotFound) => Err(AuthAPIError::InvalidToken),
+        Err(_) => Err(AuthAPIError::UnexpectedError),
+    }
+}
 
-            if validation_result.is_err(){
-                StatusCode::UNAUTHORIZED.into_response()
-            } else {
-                StatusCode::OK.into_response() 
-            }
-        }
-        Err(_) => {
-            StatusCode::UNAUTHORIZED.into_response()
-        }
+// The service account a valid token was issued to, unless the token was revoked or the
+// account has been deleted since
+pub(crate) async fn unrevoked_service_account(state: &AppState, claims: &Claims) -> Result<ServiceAccount, AuthAPIError> {
+    if claims.principal_type != PrincipalType::ServiceAccount || is_revoked(state, claims).await? {
+        return Err(AuthAPIError::InvalidToken);
     }
 
-    
+    match state.service_account_store.read().await.get_service_account(&claims.sub).await {
+        Ok(account) => Ok(account),
+        Err(ServiceAccountStoreError::AccountNotFound) => Err(AuthAPIError::InvalidToken),
+        Err(_) => Err(AuthAPIError::UnexpectedError),
+    }
+}
+
+async fn is_revoked(state: &AppState, claims: &Claims) -> Result<bool, AuthAPIError> {
+    state.banned_token_store.read().await.is_token_revoked(&claims.jti).await.map_err(|_| AuthAPIError::UnexpectedError)
 }
 
 #[derive(Deserialize)]
 pub struct TokenRequest {
     pub token: String,
+    // the service checking the token, which must be in the token's `aud` claim
+    pub audience: Option<String>,
 }
@@ -1,9 +1,17 @@
+mod postgres_email_outbox;
+mod postgres_oauth_client_store;
+mod postgres_service_account_store;
+
+pub use postgres_email_outbox::PostgresEmailOutbox;
+pub use postgres_oauth_client_store::PostgresOAuthClientStore;
+pub use postgres_service_account_store::PostgresServiceAccountStore;
+
 use std::error::Error;
 
 use argon2::{password_hash::{rand_core::OsRng, SaltString}, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier};
 use sqlx::PgPool;
 
-use crate::domain::{data_store::{UserStore, UserStoreError}, email::Email, password::Password, user::User};
+use crate::{domain::{data_store::{RecoveryCode, UserStore, UserStoreError}, email::Email, password::Password, totp::TotpSecret, user::{TwoFAMethod, User}}, utils::{constants::TOTP_ENCRYPTION_KEY, encryption::{decrypt, encrypt}}};
 #[derive(Clone )]
 pub struct PostgresUserStore {
     pool: PgPool,
@@ -19,12 +27,15 @@ impl PostgresUserStore {
 impl UserStore for PostgresUserStore {
    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError > {
         println!("Adding user to the database...");
+        let id = user.id;
         let email = user.email.as_ref();
         let password_hash = compute_password_hash(user.password.as_ref()).await.map_err(|_| UserStoreError::UnexpectedError)?;
         let requires_2fa  = user.requires_2fa;
+        let email_verified = user.email_verified;
+        let two_fa_method = user.two_fa_method.as_ref();
         let result = sqlx::query!(
-            r#"INSERT INTO users (email, password_hash, requires_2fa ) VALUES ($1, $2, $3)"#,
-            email, password_hash, requires_2fa
+            r#"INSERT INTO users (id, email, password_hash, requires_2fa, email_verified, two_fa_method ) VALUES ($1, $2, $3, $4, $5, $6)"#,
+            id, email, password_hash, requires_2fa, email_verified, two_fa_method
         )
         .execute(&self.pool)
         .await;
@@ -50,7 +61,7 @@ impl UserStore for PostgresUserStore {
         println!("Searching for user with email: {}", email);
         let user_record = sqlx::query!(
             r#"
-                SELECT email, password_hash, requires_2fa
+                SELECT id, email, password_hash, requires_2fa, email_verified, two_fa_method
                 FROM users
                 WHERE email = $1
             "#,
@@ -63,11 +74,15 @@ impl UserStore for PostgresUserStore {
         if let Some(record) = user_record {
             let email = Email::parse(record.email).map_err(|_| UserStoreError::UnexpectedError)?;
             let password = Password::parse(record.password_hash).map_err(|_| UserStoreError::UnexpectedError)?;
+            let two_fa_method = TwoFAMethod::parse(&record.two_fa_method).map_err(|_| UserStoreError::UnexpectedError)?;
              Ok(
                  User{
+                     id: record.id,
                      email,
                      password,
-                     requires_2fa: record.requires_2fa
+                     requires_2fa: record.requires_2fa,
+                     email_verified: record.email_verified,
+                     two_fa_method,
                  })
         } else {
             Err(UserStoreError::UserNotFound)
@@ -85,6 +100,210 @@ impl UserStore for PostgresUserStore {
             Err(UserStoreError::UnexpectedError)
         }
     }
+
+    async fn update_password(&mut self, email: &Email, password: Password) -> Result<(), UserStoreError> {
+        let password_hash = compute_password_hash(password.as_ref()).await.map_err(|_| UserStoreError::UnexpectedError)?;
+        let result = sqlx::query!(
+            r#"UPDATE users SET password_hash = $1 WHERE email = $2"#,
+            password_hash, email.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        // no rows are touched when there is no user with that email
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
+
+    async fn verify_email(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"UPDATE users SET email_verified = TRUE WHERE email = $1"#,
+            email.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
+
+    async fn set_pending_totp_secret(&mut self, email: &Email, secret: TotpSecret) -> Result<(), UserStoreError> {
+        let encrypted_secret = encrypt(&TOTP_ENCRYPTION_KEY, secret.as_ref()).map_err(|_| UserStoreError::UnexpectedError)?;
+        let result = sqlx::query!(
+            r#"UPDATE users SET pending_totp_secret = $1 WHERE email = $2"#,
+            encrypted_secret, email.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
+
+    async fn get_pending_totp_secret(&self, email: &Email) -> Result<TotpSecret, UserStoreError> {
+        let encrypted_secret = sqlx::query_scalar!(
+            r#"SELECT pending_totp_secret FROM users WHERE email = $1"#,
+            email.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .ok_or(UserStoreError::UserNotFound)?
+        .ok_or(UserStoreError::TotpSecretNotFound)?;
+
+        decrypt_totp_secret(&encrypted_secret)
+    }
+
+    async fn enable_totp(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        // Only users that have a pending secret are updated
+        let result = sqlx::query!(
+            r#"
+                UPDATE users
+                SET totp_secret = pending_totp_secret, pending_totp_secret = NULL, two_fa_method = $1, requires_2fa = TRUE
+                WHERE email = $2 AND pending_totp_secret IS NOT NULL
+            "#,
+            TwoFAMethod::Totp.as_ref(), email.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::TotpSecretNotFound);
+        }
+        Ok(())
+    }
+
+    async fn get_totp_secret(&self, email: &Email) -> Result<TotpSecret, UserStoreError> {
+        let encrypted_secret = sqlx::query_scalar!(
+            r#"SELECT totp_secret FROM users WHERE email = $1"#,
+            email.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .ok_or(UserStoreError::UserNotFound)?
+        .ok_or(UserStoreError::TotpSecretNotFound)?;
+
+        decrypt_totp_secret(&encrypted_secret)
+    }
+
+    async fn use_totp_step(&mut self, email: &Email, step: u64) -> Result<(), UserStoreError> {
+        let step = i64::try_from(step).map_err(|_| UserStoreError::UnexpectedError)?;
+        // Check and record the step in one statement, so two requests can't both use the same code
+        let result = sqlx::query!(
+            r#"
+                UPDATE users
+                SET totp_last_step = $1
+                WHERE email = $2 AND (totp_last_step IS NULL OR totp_last_step < $1)
+            "#,
+            step, email.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return match self.get_user(email.as_ref()).await {
+                Ok(_) => Err(UserStoreError::TotpCodeAlreadyUsed),
+                Err(e) => Err(e),
+            };
+        }
+        Ok(())
+    }
+
+    async fn set_recovery_codes(&mut self, email: &Email, codes: &[RecoveryCode]) -> Result<(), UserStoreError> {
+        let mut code_hashes = Vec::with_capacity(codes.len());
+        for code in codes {
+            code_hashes.push(compute_password_hash(code.as_ref()).await.map_err(|_| UserStoreError::UnexpectedError)?);
+        }
+
+        // Swap the old codes for the new ones in one go
+        let mut transaction = self.pool.begin().await.map_err(|_| UserStoreError::UnexpectedError)?;
+        sqlx::query!(r#"DELETE FROM recovery_codes WHERE email = $1"#, email.as_ref())
+            .execute(&mut *transaction)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+        for code_hash in code_hashes {
+            sqlx::query!(
+                r#"INSERT INTO recovery_codes (email, code_hash) VALUES ($1, $2)"#,
+                email.as_ref(), code_hash
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| match e.as_database_error() {
+                Some(db_err) if db_err.is_foreign_key_violation() => UserStoreError::UserNotFound,
+                _ => UserStoreError::UnexpectedError,
+            })?;
+        }
+        transaction.commit().await.map_err(|_| UserStoreError::UnexpectedError)
+    }
+
+    async fn use_recovery_code(&mut self, email: &Email, code: &RecoveryCode) -> Result<(), UserStoreError> {
+        let records = sqlx::query!(
+            r#"SELECT id, code_hash FROM recovery_codes WHERE email = $1"#,
+            email.as_ref()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        for record in records {
+            if verify_password_hash(&record.code_hash, code.as_ref()).await.is_err() {
+                continue;
+            }
+            // The code is only burnt by whoever manages to delete it, so it can't be used twice concurrently
+            let result = sqlx::query!(r#"DELETE FROM recovery_codes WHERE id = $1"#, record.id)
+                .execute(&self.pool)
+                .await
+                .map_err(|_| UserStoreError::UnexpectedError)?;
+            if result.rows_affected() == 1 {
+                return Ok(());
+            }
+        }
+        Err(UserStoreError::InvalidRecoveryCode)
+    }
+
+    async fn get_token_epoch(&self, email: &Email) -> Result<u32, UserStoreError> {
+        let epoch = sqlx::query_scalar!(
+            r#"SELECT token_epoch FROM users WHERE email = $1"#,
+            email.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        u32::try_from(epoch).map_err(|_| UserStoreError::UnexpectedError)
+    }
+
+    async fn bump_token_epoch(&mut self, email: &Email) -> Result<u32, UserStoreError> {
+        // incremented in the database so concurrent bumps are never lost
+        let epoch = sqlx::query_scalar!(
+            r#"UPDATE users SET token_epoch = token_epoch + 1 WHERE email = $1 RETURNING token_epoch"#,
+            email.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        u32::try_from(epoch).map_err(|_| UserStoreError::UnexpectedError)
+    }
+}
+
+fn decrypt_totp_secret(encrypted_secret: &str) -> Result<TotpSecret, UserStoreError> {
+    let secret = decrypt(&TOTP_ENCRYPTION_KEY, encrypted_secret).map_err(|_| UserStoreError::UnexpectedError)?;
+    TotpSecret::parse(secret).map_err(|_| UserStoreError::UnexpectedError)
 }
 
 async fn verify_password_hash(expected_pass_hash:&str, password_candidate: &str) -> Result<(), Box<dyn Error>> {
@@ -0,0 +1,181 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{
+    data_store::{EmailOutbox, EmailOutboxError, EmailStatus, OutboxEmail},
+    email::Email,
+    email_message::EmailMessage,
+};
+
+#[derive(Clone)]
+pub struct PostgresEmailOutbox {
+    pool: PgPool,
+}
+
+impl PostgresEmailOutbox {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailOutbox for PostgresEmailOutbox {
+    async fn enqueue(&mut self, recipient: Email, message: EmailMessage) -> Result<Uuid, EmailOutboxError> {
+        let id = Uuid::new_v4();
+        sqlx::query!(
+            r#"
+                INSERT INTO email_outbox (id, recipient, subject, html_body, text_body)
+                VALUES ($1, $2, $3, $4, $5)
+            "#,
+            id,
+            recipient.as_ref(),
+            message.subject,
+            message.html_body,
+            message.text_body
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| EmailOutboxError::UnexpectedError)?;
+
+        Ok(id)
+    }
+
+    async fn claim_due(
+        &mut self,
+        now: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+        limit: u32,
+    ) -> Result<Vec<OutboxEmail>, EmailOutboxError> {
+        // SKIP LOCKED lets several instances of the service work through the outbox without
+        // handing out the same email twice
+        let rows = sqlx::query!(
+            r#"
+                UPDATE email_outbox
+                SET next_attempt_at = $2
+                WHERE id IN (
+                    SELECT id FROM email_outbox
+                    WHERE status = 'pending' AND next_attempt_at <= $1
+                    ORDER BY next_attempt_at
+                    LIMIT $3
+                    FOR UPDATE SKIP LOCKED
+                )
+                RETURNING id, recipient, subject, html_body, text_body, status, attempts, last_error, created_at
+            "#,
+            now,
+            lease_until,
+            i64::from(limit)
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| EmailOutboxError::UnexpectedError)?;
+
+        rows.into_iter()
+            .map(|row| {
+                to_outbox_email(
+                    row.id,
+                    row.recipient,
+                    EmailMessage { subject: row.subject, html_body: row.html_body, text_body: row.text_body },
+                    &row.status,
+                    row.attempts,
+                    row.last_error,
+                    lease_until,
+                    row.created_at,
+                )
+            })
+            .collect()
+    }
+
+    async fn mark_sent(&mut self, id: Uuid) -> Result<(), EmailOutboxError> {
+        // Emails often carry one-time codes, so the content isn't kept once delivered
+        let result = sqlx::query!(
+            r#"UPDATE email_outbox SET status = 'sent', html_body = '', text_body = '' WHERE id = $1"#,
+            id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| EmailOutboxError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(EmailOutboxError::EmailNotFound);
+        }
+        Ok(())
+    }
+
+    async fn mark_failed(&mut self, id: Uuid, error: &str, retry_at: Option<DateTime<Utc>>) -> Result<(), EmailOutboxError> {
+        let result = sqlx::query!(
+            r#"
+                UPDATE email_outbox
+                SET attempts = attempts + 1,
+                    last_error = $2,
+                    status = CASE WHEN $3::TIMESTAMPTZ IS NULL THEN 'dead' ELSE status END,
+                    next_attempt_at = COALESCE($3, next_attempt_at)
+                WHERE id = $1
+            "#,
+            id,
+            error,
+            retry_at
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| EmailOutboxError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(EmailOutboxError::EmailNotFound);
+        }
+        Ok(())
+    }
+
+    async fn get_emails(&self, recipient: &Email) -> Result<Vec<OutboxEmail>, EmailOutboxError> {
+        let rows = sqlx::query!(
+            r#"
+                SELECT id, recipient, subject, html_body, text_body, status, attempts, last_error, next_attempt_at, created_at
+                FROM email_outbox
+                WHERE recipient = $1
+                ORDER BY created_at DESC
+            "#,
+            recipient.as_ref()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| EmailOutboxError::UnexpectedError)?;
+
+        rows.into_iter()
+            .map(|row| {
+                to_outbox_email(
+                    row.id,
+                    row.recipient,
+                    EmailMessage { subject: row.subject, html_body: row.html_body, text_body: row.text_body },
+                    &row.status,
+                    row.attempts,
+                    row.last_error,
+                    row.next_attempt_at,
+                    row.created_at,
+                )
+            })
+            .collect()
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn to_outbox_email(
+    id: Uuid,
+    recipient: String,
+    message: EmailMessage,
+    status: &str,
+    attempts: i32,
+    last_error: Option<String>,
+    next_attempt_at: DateTime<Utc>,
+    created_at: DateTime<Utc>,
+) -> Result<OutboxEmail, EmailOutboxError> {
+    Ok(OutboxEmail {
+        id,
+        recipient: Email::parse(recipient).map_err(|_| EmailOutboxError::UnexpectedError)?,
+        message,
+        status: EmailStatus::parse(status).map_err(|_| EmailOutboxError::UnexpectedError)?,
+        attempts: attempts.try_into().map_err(|_| EmailOutboxError::UnexpectedError)?,
+        last_error,
+        next_attempt_at,
+        created_at,
+    })
+}
@@ -0,0 +1,89 @@
+use sqlx::PgPool;
+
+use super::{compute_password_hash, verify_password_hash};
+use crate::domain::{
+    data_store::{OAuthClientStore, OAuthClientStoreError},
+    oauth::OAuthClient,
+};
+
+#[derive(Clone)]
+pub struct PostgresOAuthClientStore {
+    pool: PgPool,
+}
+
+impl PostgresOAuthClientStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl OAuthClientStore for PostgresOAuthClientStore {
+    async fn add_client(&mut self, client: OAuthClient, secret: Option<String>) -> Result<(), OAuthClientStoreError> {
+        if client.confidential != secret.is_some() {
+            return Err(OAuthClientStoreError::UnexpectedError);
+        }
+        // client secrets are hashed like passwords
+        let secret_hash = match secret {
+            Some(secret) => Some(compute_password_hash(&secret).await.map_err(|_| OAuthClientStoreError::UnexpectedError)?),
+            None => None,
+        };
+
+        let result = sqlx::query!(
+            r#"
+                INSERT INTO oauth_clients (client_id, name, client_secret_hash, redirect_uris, scopes)
+                VALUES ($1, $2, $3, $4, $5)
+            "#,
+            client.client_id,
+            client.name,
+            secret_hash,
+            &client.redirect_uris,
+            &client.scopes
+        )
+        .execute(&self.pool)
+        .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) if e.as_database_error().is_some_and(|db_err| db_err.is_unique_violation()) => {
+                Err(OAuthClientStoreError::ClientAlreadyExists)
+            }
+            Err(_) => Err(OAuthClientStoreError::UnexpectedError),
+        }
+    }
+
+    async fn get_client(&self, client_id: &str) -> Result<OAuthClient, OAuthClientStoreError> {
+        let record = sqlx::query!(
+            r#"
+                SELECT client_id, name, client_secret_hash IS NOT NULL AS "confidential!", redirect_uris, scopes
+                FROM oauth_clients
+                WHERE client_id = $1
+            "#,
+            client_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| OAuthClientStoreError::UnexpectedError)?
+        .ok_or(OAuthClientStoreError::ClientNotFound)?;
+
+        OAuthClient::new(record.client_id, record.name, record.redirect_uris, record.scopes, record.confidential)
+            .map_err(|_| OAuthClientStoreError::UnexpectedError)
+    }
+
+    async fn validate_client_secret(&self, client_id: &str, secret: &str) -> Result<(), OAuthClientStoreError> {
+        let secret_hash = sqlx::query_scalar!(
+            r#"SELECT client_secret_hash FROM oauth_clients WHERE client_id = $1"#,
+            client_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| OAuthClientStoreError::UnexpectedError)?
+        .ok_or(OAuthClientStoreError::ClientNotFound)?
+        // public clients have no secret to check
+        .ok_or(OAuthClientStoreError::InvalidClientSecret)?;
+
+        verify_password_hash(&secret_hash, secret)
+            .await
+            .map_err(|_| OAuthClientStoreError::InvalidClientSecret)
+    }
+}
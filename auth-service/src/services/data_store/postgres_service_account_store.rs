@@ -0,0 +1,75 @@
+use sqlx::PgPool;
+
+use super::{compute_password_hash, verify_password_hash};
+use crate::domain::{
+    data_store::{ServiceAccountStore, ServiceAccountStoreError},
+    oauth::ServiceAccount,
+};
+
+#[derive(Clone)]
+pub struct PostgresServiceAccountStore {
+    pool: PgPool,
+}
+
+impl PostgresServiceAccountStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl ServiceAccountStore for PostgresServiceAccountStore {
+    async fn add_service_account(&mut self, account: ServiceAccount, secret: String) -> Result<(), ServiceAccountStoreError> {
+        // secrets are hashed like passwords
+        let secret_hash = compute_password_hash(&secret).await.map_err(|_| ServiceAccountStoreError::UnexpectedError)?;
+
+        let result = sqlx::query!(
+            r#"
+                INSERT INTO service_accounts (id, name, client_secret_hash, scopes)
+                VALUES ($1, $2, $3, $4)
+            "#,
+            account.id,
+            account.name,
+            secret_hash,
+            &account.scopes
+        )
+        .execute(&self.pool)
+        .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) if e.as_database_error().is_some_and(|db_err| db_err.is_unique_violation()) => {
+                Err(ServiceAccountStoreError::AccountAlreadyExists)
+            }
+            Err(_) => Err(ServiceAccountStoreError::UnexpectedError),
+        }
+    }
+
+    async fn get_service_account(&self, id: &str) -> Result<ServiceAccount, ServiceAccountStoreError> {
+        let record = sqlx::query!(
+            r#"SELECT id, name, scopes FROM service_accounts WHERE id = $1"#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| ServiceAccountStoreError::UnexpectedError)?
+        .ok_or(ServiceAccountStoreError::AccountNotFound)?;
+
+        ServiceAccount::new(record.id, record.name, record.scopes).map_err(|_| ServiceAccountStoreError::UnexpectedError)
+    }
+
+    async fn validate_secret(&self, id: &str, secret: &str) -> Result<(), ServiceAccountStoreError> {
+        let secret_hash = sqlx::query_scalar!(
+            r#"SELECT client_secret_hash FROM service_accounts WHERE id = $1"#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| ServiceAccountStoreError::UnexpectedError)?
+        .ok_or(ServiceAccountStoreError::AccountNotFound)?;
+
+        verify_password_hash(&secret_hash, secret)
+            .await
+            .map_err(|_| ServiceAccountStoreError::InvalidSecret)
+    }
+}
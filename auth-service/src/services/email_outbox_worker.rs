@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::{app_state::{EmailClientType, EmailOutboxType}, domain::data_store::EmailOutboxError};
+
+// How often the outbox is checked for emails that are due
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+// How many emails are claimed at a time
+const BATCH_SIZE: u32 = 10;
+// How long a claimed email is hidden from other workers. If the worker dies mid delivery
+// the email is picked up again after this
+const CLAIM_LEASE_SECONDS: i64 = 60;
+// Attempts before an email is dead-lettered. With the backoff below the last attempt
+// happens about 40 minutes after the first one
+const MAX_ATTEMPTS: u32 = 10;
+const RETRY_BASE_DELAY_SECONDS: i64 = 5;
+
+// Background task delivering the emails queued in the outbox through the email client
+pub struct EmailOutboxWorker {
+    outbox: EmailOutboxType,
+    email_client: EmailClientType,
+}
+
+impl EmailOutboxWorker {
+    pub fn new(outbox: EmailOutboxType, email_client: EmailClientType) -> Self {
+        Self { outbox, email_client }
+    }
+
+    pub async fn run(self) {
+        loop {
+            if let Err(e) = self.run_once().await {
+                eprintln!("Failed to process the email outbox: {:?}", e);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    // Try to deliver every email that is due, returning how many were attempted
+    pub async fn run_once(&self) -> Result<usize, EmailOutboxError> {
+        let now = Utc::now();
+        let emails = self
+            .outbox
+            .write()
+            .await
+            .claim_due(now, now + TimeDelta::seconds(CLAIM_LEASE_SECONDS), BATCH_SIZE)
+            .await?;
+
+        for email in &emails {
+            let result = self.email_client.read().await.send_email(&email.recipient, &email.message).await;
+            let mut outbox = self.outbox.write().await;
+            match result {
+                Ok(()) => outbox.mark_sent(email.id).await?,
+                Err(e) => {
+                    eprintln!("Failed to send email {} to {}: {}", email.id, email.recipient.as_ref(), e);
+                    outbox.mark_failed(email.id, &e, retry_at(email.attempts + 1, Utc::now())).await?
+                }
+            }
+        }
+        Ok(emails.len())
+    }
+}
+
+// Exponential backoff after the `attempts`-th failure, or None once the email should be dead-lettered
+fn retry_at(attempts: u32, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    if attempts >= MAX_ATTEMPTS {
+        return None;
+    }
+    let delay = RETRY_BASE_DELAY_SECONDS * 2_i64.pow(attempts.saturating_sub(1));
+    Some(now + TimeDelta::seconds(delay))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::RwLock;
+
+    use super::*;
+    use crate::{
+        domain::{data_store::{EmailOutbox, EmailStatus}, email::Email, email_client::EmailClient, email_message::EmailMessage},
+        services::{hashmap_email_outbox::HashmapEmailOutbox, mock_email_client::MockEmailClient},
+    };
+
+    struct FailingEmailClient;
+
+    #[async_trait::async_trait]
+    impl EmailClient for FailingEmailClient {
+        async fn send_email(&self, _recipient: &Email, _message: &EmailMessage) -> Result<(), String> {
+            Err("connection refused".to_owned())
+        }
+    }
+
+    fn message() -> EmailMessage {
+        EmailMessage {
+            subject: "Hello".to_owned(),
+            html_body: "<p>Hi</p>".to_owned(),
+            text_body: "Hi".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_retry_at_backs_off_exponentially() {
+        let now = Utc::now();
+        assert_eq!(retry_at(1, now), Some(now + TimeDelta::seconds(5)));
+        assert_eq!(retry_at(2, now), Some(now + TimeDelta::seconds(10)));
+        assert_eq!(retry_at(3, now), Some(now + TimeDelta::seconds(20)));
+        assert_eq!(retry_at(MAX_ATTEMPTS - 1, now), Some(now + TimeDelta::seconds(1280)));
+        assert_eq!(retry_at(MAX_ATTEMPTS, now), None);
+    }
+
+    #[tokio::test]
+    async fn test_run_once_delivers_pending_emails() {
+        let outbox = Arc::new(RwLock::new(HashmapEmailOutbox::default()));
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        outbox.write().await.enqueue(email.clone(), message()).await.unwrap();
+
+        let worker = EmailOutboxWorker::new(outbox.clone(), Arc::new(RwLock::new(MockEmailClient)));
+        assert_eq!(worker.run_once().await, Ok(1));
+        assert_eq!(outbox.read().await.get_emails(&email).await.unwrap()[0].status, EmailStatus::Sent);
+        assert_eq!(worker.run_once().await, Ok(0));
+    }
+
+    #[tokio::test]
+    async fn test_run_once_schedules_a_retry_on_failure() {
+        let outbox = Arc::new(RwLock::new(HashmapEmailOutbox::default()));
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        outbox.write().await.enqueue(email.clone(), message()).await.unwrap();
+
+        let worker = EmailOutboxWorker::new(outbox.clone(), Arc::new(RwLock::new(FailingEmailClient)));
+        assert_eq!(worker.run_once().await, Ok(1));
+
+        let queued = outbox.read().await.get_emails(&email).await.unwrap().remove(0);
+        assert_eq!(queued.status, EmailStatus::Pending);
+        assert_eq!(queued.attempts, 1);
+        assert_eq!(queued.last_error, Some("connection refused".to_owned()));
+        assert!(queued.next_attempt_at > Utc::now());
+        // not retried before the backoff is over
+        assert_eq!(worker.run_once().await, Ok(0));
+    }
+}
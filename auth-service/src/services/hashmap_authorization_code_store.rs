@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::{
+    domain::{
+        data_store::{AuthorizationCode, AuthorizationCodeStore, AuthorizationCodeStoreError},
+        oauth::AuthorizationGrant,
+    },
+    utils::auth::AUTHORIZATION_CODE_TTL_SECONDS,
+};
+
+#[derive(Default, Clone)]
+pub struct HashmapAuthorizationCodeStore {
+    // code -> the grant and when the code expires
+    pub codes: HashMap<AuthorizationCode, (AuthorizationGrant, DateTime<Utc>)>,
+}
+
+#[async_trait::async_trait]
+impl AuthorizationCodeStore for HashmapAuthorizationCodeStore {
+    async fn add_code(&mut self, code: AuthorizationCode, grant: AuthorizationGrant) -> Result<(), AuthorizationCodeStoreError> {
+        let now = Utc::now();
+        self.codes.retain(|_, (_, expires_at)| *expires_at > now);
+        self.codes.insert(code, (grant, now + TimeDelta::seconds(AUTHORIZATION_CODE_TTL_SECONDS)));
+        Ok(())
+    }
+
+    async fn take_code(&mut self, code: &AuthorizationCode) -> Result<AuthorizationGrant, AuthorizationCodeStoreError> {
+        match self.codes.remove(code) {
+            Some((grant, expires_at)) if expires_at > Utc::now() => Ok(grant),
+            _ => Err(AuthorizationCodeStoreError::CodeNotFound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{email::Email, oauth::CodeChallenge};
+
+    fn grant() -> AuthorizationGrant {
+        AuthorizationGrant {
+            client_id: "dashboard".to_owned(),
+            redirect_uri: "https://app.example.com/callback".to_owned(),
+            redirect_uri_supplied: true,
+            email: Email::parse("test@email.com".to_owned()).unwrap(),
+            scopes: vec!["read".to_owned()],
+            code_challenge: CodeChallenge::parse("E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM".to_owned(), Some("S256")).unwrap(),
+            device: "Firefox on Linux".to_owned(),
+            ip: "127.0.0.1".to_owned(),
+            nonce: Some("n-0S6_WzA2Mj".to_owned()),
+            amr: vec!["pwd".to_owned()],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_codes_can_only_be_taken_once() {
+        let mut store = HashmapAuthorizationCodeStore::default();
+        let code = AuthorizationCode::default();
+        store.add_code(code.clone(), grant()).await.unwrap();
+
+        assert_eq!(store.take_code(&code).await, Ok(grant()));
+        assert_eq!(store.take_code(&code).await, Err(AuthorizationCodeStoreError::CodeNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_expired_codes_are_rejected() {
+        let mut store = HashmapAuthorizationCodeStore::default();
+        let code = AuthorizationCode::default();
+        store.codes.insert(code.clone(), (grant(), Utc::now() - TimeDelta::seconds(1)));
+
+        assert_eq!(store.take_code(&code).await, Err(AuthorizationCodeStoreError::CodeNotFound));
+    }
+}
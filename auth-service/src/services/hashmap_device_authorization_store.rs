@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::{
+    domain::{
+        data_store::{DeviceAuthorizationStore, DeviceAuthorizationStoreError, DeviceCode, UserCode},
+        oauth::{DeviceAuthorization, DeviceAuthorizationStatus},
+    },
+    utils::auth::{DEVICE_CODE_TTL_SECONDS, DEVICE_POLL_INTERVAL_SECONDS},
+};
+
+#[derive(Clone)]
+pub struct DeviceAuthorizationEntry {
+    pub authorization: DeviceAuthorization,
+    pub status: DeviceAuthorizationStatus,
+    pub expires_at: DateTime<Utc>,
+    pub last_polled_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Default, Clone)]
+pub struct HashmapDeviceAuthorizationStore {
+    pub authorizations: HashMap<DeviceCode, DeviceAuthorizationEntry>,
+    // user code -> device code, until the user decides
+    pub user_codes: HashMap<UserCode, DeviceCode>,
+}
+
+impl HashmapDeviceAuthorizationStore {
+    fn live_entry(&self, user_code: &UserCode) -> Option<(&DeviceCode, &DeviceAuthorizationEntry)> {
+        let device_code = self.user_codes.get(user_code)?;
+        let entry = self.authorizations.get(device_code).filter(|entry| entry.expires_at > Utc::now())?;
+        Some((device_code, entry))
+    }
+}
+
+#[async_trait::async_trait]
+impl DeviceAuthorizationStore for HashmapDeviceAuthorizationStore {
+    async fn add_authorization(&mut self, device_code: DeviceCode, user_code: UserCode, authorization: DeviceAuthorization) -> Result<(), DeviceAuthorizationStoreError> {
+        let now = Utc::now();
+        self.authorizations.retain(|_, entry| entry.expires_at > now);
+        let authorizations = &self.authorizations;
+        self.user_codes.retain(|_, device_code| authorizations.contains_key(device_code));
+
+        // user codes are short enough to collide now and then, the device should just ask again
+        if self.user_codes.contains_key(&user_code) {
+            return Err(DeviceAuthorizationStoreError::UnexpectedError);
+        }
+        self.user_codes.insert(user_code, device_code.clone());
+        self.authorizations.insert(device_code, DeviceAuthorizationEntry {
+            authorization,
+            status: DeviceAuthorizationStatus::Pending,
+            expires_at: now + TimeDelta::seconds(DEVICE_CODE_TTL_SECONDS),
+            last_polled_at: None,
+        });
+        Ok(())
+    }
+
+    async fn get_authorization(&self, user_code: &UserCode) -> Result<DeviceAuthorization, DeviceAuthorizationStoreError> {
+        self.live_entry(user_code)
+            .map(|(_, entry)| entry.authorization.clone())
+            .ok_or(DeviceAuthorizationStoreError::AuthorizationNotFound)
+    }
+
+    async fn decide(&mut self, user_code: &UserCode, status: DeviceAuthorizationStatus) -> Result<(), DeviceAuthorizationStoreError> {
+        let device_code = self
+            .live_entry(user_code)
+            .map(|(device_code, _)| device_code.clone())
+            .ok_or(DeviceAuthorizationStoreError::AuthorizationNotFound)?;
+        self.user_codes.remove(user_code);
+        if let Some(entry) = self.authorizations.get_mut(&device_code) {
+            entry.status = status;
+        }
+        Ok(())
+    }
+
+    async fn poll(&mut self, device_code: &DeviceCode, client_id: &str) -> Result<(DeviceAuthorization, DeviceAuthorizationStatus), DeviceAuthorizationStoreError> {
+        let now = Utc::now();
+        let entry = match self.authorizations.get_mut(device_code) {
+            Some(entry) if entry.expires_at > now => entry,
+            _ => return Err(DeviceAuthorizationStoreError::AuthorizationNotFound),
+        };
+        if entry.authorization.client_id != client_id {
+            return Err(DeviceAuthorizationStoreError::WrongClient);
+        }
+        if entry.last_polled_at.is_some_and(|polled_at| now < polled_at + TimeDelta::seconds(DEVICE_POLL_INTERVAL_SECONDS)) {
+            return Err(DeviceAuthorizationStoreError::PolledTooSoon);
+        }
+        entry.last_polled_at = Some(now);
+
+        let polled = (entry.authorization.clone(), entry.status.clone());
+        if polled.1 != DeviceAuthorizationStatus::Pending {
+            self.authorizations.remove(device_code);
+        }
+        Ok(polled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::email::Email;
+
+    fn authorization() -> DeviceAuthorization {
+        DeviceAuthorization {
+            client_id: "cli".to_owned(),
+            scopes: vec!["read".to_owned()],
+            device: "curl/8.5.0".to_owned(),
+            ip: "127.0.0.1".to_owned(),
+        }
+    }
+
+    fn approved() -> DeviceAuthorizationStatus {
+        DeviceAuthorizationStatus::Approved { email: Email::parse("test@email.com".to_owned()).unwrap(), amr: vec!["pwd".to_owned()] }
+    }
+
+    #[tokio::test]
+    async fn test_approved_devices_get_their_authorization_once() {
+        let mut store = HashmapDeviceAuthorizationStore::default();
+        let (device_code, user_code) = (DeviceCode::default(), UserCode::default());
+        store.add_authorization(device_code.clone(), user_code.clone(), authorization()).await.unwrap();
+
+        assert_eq!(store.get_authorization(&user_code).await, Ok(authorization()));
+        assert_eq!(store.decide(&user_code, approved()).await, Ok(()));
+        // the user code is used up
+        assert_eq!(store.decide(&user_code, DeviceAuthorizationStatus::Denied).await, Err(DeviceAuthorizationStoreError::AuthorizationNotFound));
+        assert_eq!(store.get_authorization(&user_code).await, Err(DeviceAuthorizationStoreError::AuthorizationNotFound));
+
+        assert_eq!(store.poll(&device_code, "cli").await, Ok((authorization(), approved())));
+        assert_eq!(store.poll(&device_code, "cli").await, Err(DeviceAuthorizationStoreError::AuthorizationNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_other_clients_cannot_use_up_the_authorization() {
+        let mut store = HashmapDeviceAuthorizationStore::default();
+        let (device_code, user_code) = (DeviceCode::default(), UserCode::default());
+        store.add_authorization(device_code.clone(), user_code.clone(), authorization()).await.unwrap();
+        store.decide(&user_code, approved()).await.unwrap();
+
+        assert_eq!(store.poll(&device_code, "other").await, Err(DeviceAuthorizationStoreError::WrongClient));
+        // neither counted as a poll nor used up
+        assert_eq!(store.poll(&device_code, "cli").await, Ok((authorization(), approved())));
+    }
+
+    #[tokio::test]
+    async fn test_polling_too_often_is_rejected() {
+        let mut store = HashmapDeviceAuthorizationStore::default();
+        let device_code = DeviceCode::default();
+        store.add_authorization(device_code.clone(), UserCode::default(), authorization()).await.unwrap();
+
+        assert_eq!(store.poll(&device_code, "cli").await, Ok((authorization(), DeviceAuthorizationStatus::Pending)));
+        assert_eq!(store.poll(&device_code, "cli").await, Err(DeviceAuthorizationStoreError::PolledTooSoon));
+
+        store.authorizations.get_mut(&device_code).unwrap().last_polled_at = Some(Utc::now() - TimeDelta::seconds(DEVICE_POLL_INTERVAL_SECONDS));
+        assert_eq!(store.poll(&device_code, "cli").await, Ok((authorization(), DeviceAuthorizationStatus::Pending)));
+    }
+
+    #[tokio::test]
+    async fn test_expired_authorizations_are_rejected() {
+        let mut store = HashmapDeviceAuthorizationStore::default();
+        let (device_code, user_code) = (DeviceCode::default(), UserCode::default());
+        store.add_authorization(device_code.clone(), user_code.clone(), authorization()).await.unwrap();
+        store.authorizations.get_mut(&device_code).unwrap().expires_at = Utc::now() - TimeDelta::seconds(1);
+
+        assert_eq!(store.get_authorization(&user_code).await, Err(DeviceAuthorizationStoreError::AuthorizationNotFound));
+        assert_eq!(store.decide(&user_code, approved()).await, Err(DeviceAuthorizationStoreError::AuthorizationNotFound));
+        assert_eq!(store.poll(&device_code, "cli").await, Err(DeviceAuthorizationStoreError::AuthorizationNotFound));
+    }
+
+    #[test]
+    fn test_user_codes() {
+        let code = UserCode::parse("bcdf-ghjk").unwrap();
+        assert_eq!(code.as_ref(), "BCDFGHJK");
+        assert_eq!(code.display(), "BCDF-GHJK");
+        assert_eq!(UserCode::parse(" BCDF GHJK ").unwrap(), code);
+        assert!(UserCode::parse("BCDF-GHJA").is_err());
+        assert!(UserCode::parse("BCDF-GHJ").is_err());
+
+        let generated = UserCode::default();
+        assert_eq!(UserCode::parse(&generated.display()), Ok(generated));
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::domain::{
+    data_store::{EmailOutbox, EmailOutboxError, EmailStatus, OutboxEmail},
+    email::Email,
+    email_message::EmailMessage,
+};
+
+#[derive(Default, Clone)]
+pub struct HashmapEmailOutbox {
+    pub emails: HashMap<Uuid, OutboxEmail>,
+}
+
+#[async_trait::async_trait]
+impl EmailOutbox for HashmapEmailOutbox {
+    async fn enqueue(&mut self, recipient: Email, message: EmailMessage) -> Result<Uuid, EmailOutboxError> {
+        let id = Uuid::new_v4();
+        let now = Utc::now();
+        self.emails.insert(id, OutboxEmail {
+            id,
+            recipient,
+            message,
+            status: EmailStatus::Pending,
+            attempts: 0,
+            last_error: None,
+            next_attempt_at: now,
+            created_at: now,
+        });
+        Ok(id)
+    }
+
+    async fn claim_due(
+        &mut self,
+        now: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+        limit: u32,
+    ) -> Result<Vec<OutboxEmail>, EmailOutboxError> {
+        let mut due: Vec<&mut OutboxEmail> = self
+            .emails
+            .values_mut()
+            .filter(|email| email.status == EmailStatus::Pending && email.next_attempt_at <= now)
+            .collect();
+        due.sort_by_key(|email| email.next_attempt_at);
+
+        Ok(due
+            .into_iter()
+            .take(limit as usize)
+            .map(|email| {
+                email.next_attempt_at = lease_until;
+                email.clone()
+            })
+            .collect())
+    }
+
+    async fn mark_sent(&mut self, id: Uuid) -> Result<(), EmailOutboxError> {
+        let email = self.emails.get_mut(&id).ok_or(EmailOutboxError::EmailNotFound)?;
+        email.status = EmailStatus::Sent;
+        // Emails often carry one-time codes, so the content isn't kept once delivered
+        email.message.html_body.clear();
+        email.message.text_body.clear();
+        Ok(())
+    }
+
+    async fn mark_failed(&mut self, id: Uuid, error: &str, retry_at: Option<DateTime<Utc>>) -> Result<(), EmailOutboxError> {
+        let email = self.emails.get_mut(&id).ok_or(EmailOutboxError::EmailNotFound)?;
+        email.attempts += 1;
+        email.last_error = Some(error.to_owned());
+        match retry_at {
+            Some(retry_at) => email.next_attempt_at = retry_at,
+            None => email.status = EmailStatus::Dead,
+        }
+        Ok(())
+    }
+
+    async fn get_emails(&self, recipient: &Email) -> Result<Vec<OutboxEmail>, EmailOutboxError> {
+        let mut emails: Vec<OutboxEmail> = self
+            .emails
+            .values()
+            .filter(|email| &email.recipient == recipient)
+            .cloned()
+            .collect();
+        emails.sort_by_key(|email| std::cmp::Reverse(email.created_at));
+        Ok(emails)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn message() -> EmailMessage {
+        EmailMessage {
+            subject: "Hello".to_owned(),
+            html_body: "<p>Hi</p>".to_owned(),
+            text_body: "Hi".to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_claim_and_mark_sent() {
+        let mut outbox = HashmapEmailOutbox::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let id = outbox.enqueue(email.clone(), message()).await.unwrap();
+
+        let now = Utc::now();
+        let claimed = outbox.claim_due(now, now + Duration::seconds(60), 10).await.unwrap();
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(claimed[0].id, id);
+        assert_eq!(claimed[0].message, message());
+
+        // a claimed email is not handed out again while leased
+        assert!(outbox.claim_due(now, now + Duration::seconds(60), 10).await.unwrap().is_empty());
+
+        outbox.mark_sent(id).await.unwrap();
+        let emails = outbox.get_emails(&email).await.unwrap();
+        assert_eq!(emails[0].status, EmailStatus::Sent);
+        assert!(emails[0].message.text_body.is_empty());
+        assert!(outbox.claim_due(now + Duration::seconds(120), now, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_mark_failed_retries_then_dead_letters() {
+        let mut outbox = HashmapEmailOutbox::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let id = outbox.enqueue(email.clone(), message()).await.unwrap();
+
+        let now = Utc::now();
+        outbox.claim_due(now, now + Duration::seconds(60), 10).await.unwrap();
+        outbox.mark_failed(id, "connection refused", Some(now + Duration::seconds(5))).await.unwrap();
+
+        // not due until the retry time
+        assert!(outbox.claim_due(now, now, 10).await.unwrap().is_empty());
+        let claimed = outbox.claim_due(now + Duration::seconds(5), now + Duration::seconds(60), 10).await.unwrap();
+        assert_eq!(claimed[0].attempts, 1);
+        assert_eq!(claimed[0].last_error, Some("connection refused".to_owned()));
+
+        outbox.mark_failed(id, "connection refused", None).await.unwrap();
+        let emails = outbox.get_emails(&email).await.unwrap();
+        assert_eq!(emails[0].status, EmailStatus::Dead);
+        assert_eq!(emails[0].attempts, 2);
+        assert!(outbox.claim_due(now + Duration::days(1), now, 10).await.unwrap().is_empty());
+
+        assert_eq!(outbox.mark_sent(Uuid::new_v4()).await, Err(EmailOutboxError::EmailNotFound));
+    }
+}
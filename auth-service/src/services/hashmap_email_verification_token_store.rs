@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use crate::domain::{
+    data_store::{EmailVerificationToken, EmailVerificationTokenStore, EmailVerificationTokenStoreError},
+    email::Email,
+};
+
+#[derive(Default, Clone)]
+pub struct HashmapEmailVerificationTokenStore {
+    pub tokens: HashMap<Email, EmailVerificationToken>,
+}
+
+#[async_trait::async_trait]
+impl EmailVerificationTokenStore for HashmapEmailVerificationTokenStore {
+    async fn add_token(
+        &mut self,
+        email: Email,
+        token: EmailVerificationToken,
+    ) -> Result<(), EmailVerificationTokenStoreError> {
+        self.tokens.insert(email, token);
+        Ok(())
+    }
+
+    async fn remove_token(&mut self, email: &Email) -> Result<(), EmailVerificationTokenStoreError> {
+        match self.tokens.remove(email) {
+            Some(_token) => Ok(()),
+            None => Err(EmailVerificationTokenStoreError::TokenNotFound),
+        }
+    }
+
+    async fn get_token(&self, email: &Email) -> Result<EmailVerificationToken, EmailVerificationTokenStoreError> {
+        self.tokens
+            .get(email)
+            .cloned()
+            .ok_or(EmailVerificationTokenStoreError::TokenNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn tests_for_email_verification_token_store() {
+        let email = Email::parse("test@email.com".to_string()).unwrap();
+        let token = EmailVerificationToken::default();
+        let mut store = HashmapEmailVerificationTokenStore::default();
+
+        // check adding the token to store works
+        assert!(store.add_token(email.clone(), token.clone()).await.is_ok());
+        // check getting the token works
+        assert_eq!(store.get_token(&email).await, Ok(token));
+
+        // check removing the token works
+        assert!(store.remove_token(&email).await.is_ok());
+        assert_eq!(store.get_token(&email).await, Err(EmailVerificationTokenStoreError::TokenNotFound));
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::{
+    domain::{
+        data_store::{FailedLoginStore, FailedLoginStoreError},
+        email::Email,
+    },
+    utils::lockout::FAILED_LOGIN_WINDOW_SECONDS,
+};
+
+#[derive(Default, Clone)]
+pub struct HashmapFailedLoginStore {
+    // email -> (failures, when the count resets)
+    pub failures: HashMap<Email, (u32, DateTime<Utc>)>,
+    // email -> when the lock is lifted
+    pub locks: HashMap<Email, DateTime<Utc>>,
+}
+
+#[async_trait::async_trait]
+impl FailedLoginStore for HashmapFailedLoginStore {
+    async fn record_failure(&mut self, email: &Email) -> Result<u32, FailedLoginStoreError> {
+        let now = Utc::now();
+        let entry = self.failures.entry(email.clone()).or_insert((0, now));
+        if entry.1 <= now {
+            entry.0 = 0;
+        }
+        entry.0 += 1;
+        entry.1 = now + TimeDelta::seconds(FAILED_LOGIN_WINDOW_SECONDS);
+        Ok(entry.0)
+    }
+
+    async fn clear_failures(&mut self, email: &Email) -> Result<(), FailedLoginStoreError> {
+        self.failures.remove(email);
+        Ok(())
+    }
+
+    async fn lock(&mut self, email: &Email, seconds: u64) -> Result<(), FailedLoginStoreError> {
+        let seconds = i64::try_from(seconds).map_err(|_| FailedLoginStoreError::UnexpectedError)?;
+        self.locks.insert(email.clone(), Utc::now() + TimeDelta::seconds(seconds));
+        Ok(())
+    }
+
+    async fn get_lock(&self, email: &Email) -> Result<Option<u64>, FailedLoginStoreError> {
+        let remaining = self
+            .locks
+            .get(email)
+            .map(|locked_until| (*locked_until - Utc::now()).num_milliseconds());
+        // round up so clients told to retry after this many seconds aren't turned away again
+        Ok(remaining
+            .and_then(|millis| u64::try_from(millis).ok())
+            .map(|millis| millis.div_ceil(1000))
+            .filter(|seconds| *seconds > 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_record_and_clear_failures() {
+        let mut store = HashmapFailedLoginStore::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+
+        assert_eq!(store.record_failure(&email).await, Ok(1));
+        assert_eq!(store.record_failure(&email).await, Ok(2));
+        store.clear_failures(&email).await.unwrap();
+        assert_eq!(store.record_failure(&email).await, Ok(1));
+
+        // failures outside the window are forgotten
+        store.failures.get_mut(&email).unwrap().1 = Utc::now() - TimeDelta::seconds(1);
+        assert_eq!(store.record_failure(&email).await, Ok(1));
+    }
+
+    #[tokio::test]
+    async fn test_lock() {
+        let mut store = HashmapFailedLoginStore::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        assert_eq!(store.get_lock(&email).await, Ok(None));
+
+        store.lock(&email, 60).await.unwrap();
+        let remaining = store.get_lock(&email).await.unwrap().expect("the account should be locked");
+        assert!(remaining > 55 && remaining <= 60);
+
+        // expired locks no longer apply
+        store.locks.insert(email.clone(), Utc::now() - TimeDelta::seconds(1));
+        assert_eq!(store.get_lock(&email).await, Ok(None));
+    }
+}
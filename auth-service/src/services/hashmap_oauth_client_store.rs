@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use crate::domain::{
+    data_store::{OAuthClientStore, OAuthClientStoreError},
+    oauth::OAuthClient,
+};
+
+#[derive(Default, Clone)]
+pub struct HashmapOAuthClientStore {
+    // client id -> the client and its secret
+    pub clients: HashMap<String, (OAuthClient, Option<String>)>,
+}
+
+#[async_trait::async_trait]
+impl OAuthClientStore for HashmapOAuthClientStore {
+    async fn add_client(&mut self, client: OAuthClient, secret: Option<String>) -> Result<(), OAuthClientStoreError> {
+        if client.confidential != secret.is_some() {
+            return Err(OAuthClientStoreError::UnexpectedError);
+        }
+        if self.clients.contains_key(&client.client_id) {
+            return Err(OAuthClientStoreError::ClientAlreadyExists);
+        }
+        self.clients.insert(client.client_id.clone(), (client, secret));
+        Ok(())
+    }
+
+    async fn get_client(&self, client_id: &str) -> Result<OAuthClient, OAuthClientStoreError> {
+        self.clients
+            .get(client_id)
+            .map(|(client, _)| client.clone())
+            .ok_or(OAuthClientStoreError::ClientNotFound)
+    }
+
+    async fn validate_client_secret(&self, client_id: &str, secret: &str) -> Result<(), OAuthClientStoreError> {
+        match self.clients.get(client_id) {
+            Some((_, Some(expected))) if expected == secret => Ok(()),
+            Some(_) => Err(OAuthClientStoreError::InvalidClientSecret),
+            None => Err(OAuthClientStoreError::ClientNotFound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(client_id: &str, confidential: bool) -> OAuthClient {
+        OAuthClient::new(
+            client_id.to_owned(),
+            "App".to_owned(),
+            vec!["https://app.example.com/callback".to_owned()],
+            vec![],
+            confidential,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_client_secrets() {
+        let mut store = HashmapOAuthClientStore::default();
+        store.add_client(client("server", true), Some("secret".to_owned())).await.unwrap();
+        store.add_client(client("spa", false), None).await.unwrap();
+
+        assert_eq!(store.get_client("server").await, Ok(client("server", true)));
+        assert_eq!(store.validate_client_secret("server", "secret").await, Ok(()));
+        assert_eq!(store.validate_client_secret("server", "wrong").await, Err(OAuthClientStoreError::InvalidClientSecret));
+        // public clients have no secret to check
+        assert_eq!(store.validate_client_secret("spa", "").await, Err(OAuthClientStoreError::InvalidClientSecret));
+        assert_eq!(store.get_client("unknown").await, Err(OAuthClientStoreError::ClientNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_add_client() {
+        let mut store = HashmapOAuthClientStore::default();
+        assert_eq!(store.add_client(client("server", true), None).await, Err(OAuthClientStoreError::UnexpectedError));
+        assert_eq!(store.add_client(client("spa", false), None).await, Ok(()));
+        assert_eq!(store.add_client(client("spa", false), None).await, Err(OAuthClientStoreError::ClientAlreadyExists));
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use crate::domain::{
+    data_store::{PasswordResetToken, PasswordResetTokenStore, PasswordResetTokenStoreError},
+    email::Email,
+};
+
+#[derive(Default, Clone)]
+pub struct HashmapPasswordResetTokenStore {
+    pub tokens: HashMap<Email, PasswordResetToken>,
+}
+
+#[async_trait::async_trait]
+impl PasswordResetTokenStore for HashmapPasswordResetTokenStore {
+    async fn add_token(
+        &mut self,
+        email: Email,
+        token: PasswordResetToken,
+    ) -> Result<(), PasswordResetTokenStoreError> {
+        // a new request replaces any token that was issued before it
+        self.tokens.insert(email, token);
+        Ok(())
+    }
+
+    async fn remove_token(&mut self, email: &Email) -> Result<(), PasswordResetTokenStoreError> {
+        match self.tokens.remove(email) {
+            Some(_token) => Ok(()),
+            None => Err(PasswordResetTokenStoreError::TokenNotFound),
+        }
+    }
+
+    async fn get_token(&self, email: &Email) -> Result<PasswordResetToken, PasswordResetTokenStoreError> {
+        self.tokens
+            .get(email)
+            .cloned()
+            .ok_or(PasswordResetTokenStoreError::TokenNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn tests_for_password_reset_token_store() {
+        let email = Email::parse("test@email.com".to_string()).unwrap();
+        let token = PasswordResetToken::default();
+        let mut store = HashmapPasswordResetTokenStore::default();
+
+        // check adding the token to store works
+        assert!(store.add_token(email.clone(), token.clone()).await.is_ok());
+        // check getting the token works
+        assert_eq!(store.get_token(&email).await, Ok(token));
+
+        // a second request replaces the first token
+        let new_token = PasswordResetToken::default();
+        assert!(store.add_token(email.clone(), new_token.clone()).await.is_ok());
+        assert_eq!(store.get_token(&email).await, Ok(new_token));
+
+        // check removing the token works and that it cannot be used again
+        assert!(store.remove_token(&email).await.is_ok());
+        assert_eq!(store.get_token(&email).await, Err(PasswordResetTokenStoreError::TokenNotFound));
+    }
+}
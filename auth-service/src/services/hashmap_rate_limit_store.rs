@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::domain::{
+    data_store::{RateLimitStore, RateLimitStoreError},
+    rate_limit::{RateLimit, RateLimitDecision},
+};
+
+// Past this many buckets the ones that have refilled are dropped
+const MAX_BUCKETS: usize = 10_000;
+
+#[derive(Default, Clone)]
+pub struct HashmapRateLimitStore {
+    // key -> (tokens left, when they were counted, when the bucket is full again)
+    pub buckets: HashMap<String, (f64, DateTime<Utc>, DateTime<Utc>)>,
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for HashmapRateLimitStore {
+    async fn take_token(&mut self, key: &str, limit: &RateLimit) -> Result<RateLimitDecision, RateLimitStoreError> {
+        let now = Utc::now();
+        if self.buckets.len() >= MAX_BUCKETS {
+            self.buckets.retain(|_, (_, _, full_at)| *full_at > now);
+        }
+
+        let (tokens, updated_at) = self
+            .buckets
+            .get(key)
+            .map(|(tokens, updated_at, _)| (*tokens, *updated_at))
+            .unwrap_or((f64::from(limit.capacity), now));
+        let elapsed_millis = u64::try_from((now - updated_at).num_milliseconds()).unwrap_or(0);
+        let (tokens, decision) = limit.take(tokens, elapsed_millis);
+
+        let reset = TimeDelta::seconds(i64::try_from(decision.reset_seconds).map_err(|_| RateLimitStoreError::UnexpectedError)?);
+        self.buckets.insert(key.to_owned(), (tokens, now, now + reset));
+        Ok(decision)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_take_token() {
+        let mut store = HashmapRateLimitStore::default();
+        let limit = RateLimit { capacity: 2, period_seconds: 60 };
+
+        assert!(store.take_token("/login:127.0.0.1", &limit).await.unwrap().allowed);
+        assert!(store.take_token("/login:127.0.0.1", &limit).await.unwrap().allowed);
+        let decision = store.take_token("/login:127.0.0.1", &limit).await.unwrap();
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after_seconds, 30);
+
+        // buckets are independent
+        assert!(store.take_token("/login:10.0.0.1", &limit).await.unwrap().allowed);
+
+        // tokens come back over time
+        let bucket = store.buckets.get_mut("/login:127.0.0.1").unwrap();
+        bucket.1 -= TimeDelta::seconds(30);
+        assert!(store.take_token("/login:127.0.0.1", &limit).await.unwrap().allowed);
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::domain::{
+    data_store::{RefreshToken, RefreshTokenFamily, RefreshTokenStore, RefreshTokenStoreError},
+    email::Email,
+};
+
+#[derive(Default, Clone)]
+pub struct HashmapRefreshTokenStore {
+    pub tokens: HashMap<RefreshToken, (Email, RefreshTokenFamily)>,
+    // family -> the only token of the family that can still be used
+    pub families: HashMap<RefreshTokenFamily, RefreshToken>,
+    pub user_families: HashMap<Email, HashSet<RefreshTokenFamily>>,
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenStore for HashmapRefreshTokenStore {
+    async fn add_token(
+        &mut self,
+        email: Email,
+        family: RefreshTokenFamily,
+        token: RefreshToken,
+    ) -> Result<(), RefreshTokenStoreError> {
+        self.tokens.insert(token.clone(), (email.clone(), family.clone()));
+        self.families.insert(family.clone(), token);
+        self.user_families.entry(email).or_default().insert(family);
+        Ok(())
+    }
+
+    async fn get_token(&self, token: &RefreshToken) -> Result<(Email, RefreshTokenFamily), RefreshTokenStoreError> {
+        self.tokens
+            .get(token)
+            .cloned()
+            .ok_or(RefreshTokenStoreError::TokenNotFound)
+    }
+
+    async fn get_current_token(&self, family: &RefreshTokenFamily) -> Result<RefreshToken, RefreshTokenStoreError> {
+        self.families
+            .get(family)
+            .cloned()
+            .ok_or(RefreshTokenStoreError::FamilyNotFound)
+    }
+
+    async fn revoke_family(&mut self, family: &RefreshTokenFamily) -> Result<(), RefreshTokenStoreError> {
+        self.families.remove(family);
+        Ok(())
+    }
+
+    async fn revoke_user_families(&mut self, email: &Email) -> Result<(), RefreshTokenStoreError> {
+        for family in self.user_families.remove(email).unwrap_or_default() {
+            self.families.remove(&family);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rotating_tokens_in_a_family() {
+        let email = Email::parse("test@email.com".to_string()).unwrap();
+        let family = RefreshTokenFamily::default();
+        let first_token = RefreshToken::default();
+        let second_token = RefreshToken::default();
+        let mut store = HashmapRefreshTokenStore::default();
+
+        store.add_token(email.clone(), family.clone(), first_token.clone()).await.unwrap();
+        assert_eq!(store.get_current_token(&family).await, Ok(first_token.clone()));
+
+        // rotating makes the new token current, the old one is still known to the store
+        store.add_token(email.clone(), family.clone(), second_token.clone()).await.unwrap();
+        assert_eq!(store.get_current_token(&family).await, Ok(second_token));
+        assert_eq!(store.get_token(&first_token).await, Ok((email, family.clone())));
+
+        store.revoke_family(&family).await.unwrap();
+        assert_eq!(store.get_current_token(&family).await, Err(RefreshTokenStoreError::FamilyNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_user_families() {
+        let email = Email::parse("test@email.com".to_string()).unwrap();
+        let other_email = Email::parse("other@email.com".to_string()).unwrap();
+        let families = [RefreshTokenFamily::default(), RefreshTokenFamily::default()];
+        let other_family = RefreshTokenFamily::default();
+        let mut store = HashmapRefreshTokenStore::default();
+
+        for family in &families {
+            store.add_token(email.clone(), family.clone(), RefreshToken::default()).await.unwrap();
+        }
+        store.add_token(other_email, other_family.clone(), RefreshToken::default()).await.unwrap();
+
+        store.revoke_user_families(&email).await.unwrap();
+        for family in &families {
+            assert_eq!(store.get_current_token(family).await, Err(RefreshTokenStoreError::FamilyNotFound));
+        }
+        // other users keep their sessions
+        assert!(store.get_current_token(&other_family).await.is_ok());
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+
+use crate::domain::{
+    data_store::{ServiceAccountStore, ServiceAccountStoreError},
+    oauth::ServiceAccount,
+};
+
+#[derive(Default, Clone)]
+pub struct HashmapServiceAccountStore {
+    // account id -> the account and its secret
+    pub accounts: HashMap<String, (ServiceAccount, String)>,
+}
+
+#[async_trait::async_trait]
+impl ServiceAccountStore for HashmapServiceAccountStore {
+    async fn add_service_account(&mut self, account: ServiceAccount, secret: String) -> Result<(), ServiceAccountStoreError> {
+        if self.accounts.contains_key(&account.id) {
+            return Err(ServiceAccountStoreError::AccountAlreadyExists);
+        }
+        self.accounts.insert(account.id.clone(), (account, secret));
+        Ok(())
+    }
+
+    async fn get_service_account(&self, id: &str) -> Result<ServiceAccount, ServiceAccountStoreError> {
+        self.accounts
+            .get(id)
+            .map(|(account, _)| account.clone())
+            .ok_or(ServiceAccountStoreError::AccountNotFound)
+    }
+
+    async fn validate_secret(&self, id: &str, secret: &str) -> Result<(), ServiceAccountStoreError> {
+        match self.accounts.get(id) {
+            Some((_, expected)) if expected == secret => Ok(()),
+            Some(_) => Err(ServiceAccountStoreError::InvalidSecret),
+            None => Err(ServiceAccountStoreError::AccountNotFound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(id: &str) -> ServiceAccount {
+        ServiceAccount::new(id.to_owned(), "Nightly export".to_owned(), vec!["read".to_owned()]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_service_accounts() {
+        let mut store = HashmapServiceAccountStore::default();
+        assert_eq!(store.add_service_account(account("nightly-export"), "secret".to_owned()).await, Ok(()));
+        assert_eq!(
+            store.add_service_account(account("nightly-export"), "other".to_owned()).await,
+            Err(ServiceAccountStoreError::AccountAlreadyExists)
+        );
+
+        assert_eq!(store.get_service_account("nightly-export").await, Ok(account("nightly-export")));
+        assert_eq!(store.validate_secret("nightly-export", "secret").await, Ok(()));
+        assert_eq!(store.validate_secret("nightly-export", "wrong").await, Err(ServiceAccountStoreError::InvalidSecret));
+        assert_eq!(store.get_service_account("unknown").await, Err(ServiceAccountStoreError::AccountNotFound));
+        assert_eq!(store.validate_secret("unknown", "secret").await, Err(ServiceAccountStoreError::AccountNotFound));
+    }
+}
@@ -0,0 +1,111 @@
+use std::{cmp::Reverse, collections::{HashMap, HashSet}};
+
+use chrono::{DateTime, Utc};
+
+use crate::domain::{
+    data_store::{RefreshTokenFamily, SessionStore, SessionStoreError},
+    email::Email,
+    session::Session,
+};
+
+#[derive(Default, Clone)]
+pub struct HashmapSessionStore {
+    pub sessions: HashMap<RefreshTokenFamily, Session>,
+    pub user_sessions: HashMap<Email, HashSet<RefreshTokenFamily>>,
+}
+
+#[async_trait::async_trait]
+impl SessionStore for HashmapSessionStore {
+    async fn add_session(&mut self, session: Session) -> Result<(), SessionStoreError> {
+        self.user_sessions.entry(session.email.clone()).or_default().insert(session.id.clone());
+        self.sessions.insert(session.id.clone(), session);
+        Ok(())
+    }
+
+    async fn get_session(&self, id: &RefreshTokenFamily) -> Result<Session, SessionStoreError> {
+        self.sessions.get(id).cloned().ok_or(SessionStoreError::SessionNotFound)
+    }
+
+    async fn get_user_sessions(&self, email: &Email) -> Result<Vec<Session>, SessionStoreError> {
+        let mut sessions: Vec<Session> = self
+            .user_sessions
+            .get(email)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| self.sessions.get(id).cloned())
+            .collect();
+        sessions.sort_by_key(|session| Reverse(session.last_seen_at));
+        Ok(sessions)
+    }
+
+    async fn touch_session(&mut self, id: &RefreshTokenFamily, last_seen_at: DateTime<Utc>) -> Result<(), SessionStoreError> {
+        let session = self.sessions.get_mut(id).ok_or(SessionStoreError::SessionNotFound)?;
+        session.last_seen_at = last_seen_at;
+        Ok(())
+    }
+
+    async fn remove_session(&mut self, id: &RefreshTokenFamily) -> Result<(), SessionStoreError> {
+        let session = self.sessions.remove(id).ok_or(SessionStoreError::SessionNotFound)?;
+        if let Some(ids) = self.user_sessions.get_mut(&session.email) {
+            ids.remove(id);
+        }
+        Ok(())
+    }
+
+    async fn remove_user_sessions(&mut self, email: &Email) -> Result<(), SessionStoreError> {
+        for id in self.user_sessions.remove(email).unwrap_or_default() {
+            self.sessions.remove(&id);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    fn session(email: &Email, device: &str) -> Session {
+        Session::new(RefreshTokenFamily::default(), email.clone(), device.to_owned(), "127.0.0.1".to_owned())
+    }
+
+    #[tokio::test]
+    async fn test_sessions_are_listed_most_recently_seen_first() {
+        let email = Email::parse("test@email.com".to_string()).unwrap();
+        let laptop = session(&email, "Firefox on Linux");
+        let phone = session(&email, "Safari on iOS");
+        let mut store = HashmapSessionStore::default();
+        store.add_session(laptop.clone()).await.unwrap();
+        store.add_session(phone.clone()).await.unwrap();
+
+        let later = Utc::now() + TimeDelta::minutes(5);
+        store.touch_session(&laptop.id, later).await.unwrap();
+        let sessions = store.get_user_sessions(&email).await.unwrap();
+        assert_eq!(sessions.iter().map(|s| s.device.as_str()).collect::<Vec<_>>(), ["Firefox on Linux", "Safari on iOS"]);
+        assert_eq!(sessions[0].last_seen_at, later);
+
+        store.remove_session(&laptop.id).await.unwrap();
+        assert_eq!(store.get_session(&laptop.id).await, Err(SessionStoreError::SessionNotFound));
+        assert_eq!(store.get_user_sessions(&email).await.unwrap(), vec![phone]);
+        assert_eq!(store.touch_session(&laptop.id, later).await, Err(SessionStoreError::SessionNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_remove_user_sessions_leaves_other_users_alone() {
+        let email = Email::parse("test@email.com".to_string()).unwrap();
+        let other_email = Email::parse("other@email.com".to_string()).unwrap();
+        let mut store = HashmapSessionStore::default();
+        let first = session(&email, "Firefox on Linux");
+        let second = session(&email, "Chrome on Android");
+        let other = session(&other_email, "Edge on Windows");
+        for session in [first.clone(), second.clone(), other.clone()] {
+            store.add_session(session).await.unwrap();
+        }
+
+        store.remove_user_sessions(&email).await.unwrap();
+        assert!(store.get_user_sessions(&email).await.unwrap().is_empty());
+        assert_eq!(store.get_session(&first.id).await, Err(SessionStoreError::SessionNotFound));
+        assert_eq!(store.get_session(&other.id).await, Ok(other));
+    }
+}
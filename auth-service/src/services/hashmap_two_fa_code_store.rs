@@ -1,13 +1,30 @@
 use std::collections::HashMap;
 
-use crate::domain::{
-    data_store::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
-    email::Email,
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::{
+    domain::{
+        data_store::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
+        email::Email,
+    },
+    utils::lockout::{MAX_TWO_FA_ATTEMPTS, MAX_TWO_FA_RESENDS, TWO_FA_RESEND_COOLDOWN_SECONDS},
 };
 
 #[derive(Default, Clone)]
 pub struct HashmapTwoFACodeStore {
     pub codes: HashMap<Email, (LoginAttemptId, TwoFACode)>,
+    // wrong codes tried against the pending login attempt
+    pub failures: HashMap<Email, u32>,
+    // codes resent for the pending login attempt, and when the next resend is allowed
+    pub resends: HashMap<Email, (u32, DateTime<Utc>)>,
+}
+
+impl HashmapTwoFACodeStore {
+    fn forget(&mut self, email: &Email) -> Option<(LoginAttemptId, TwoFACode)> {
+        self.failures.remove(email);
+        self.resends.remove(email);
+        self.codes.remove(email)
+    }
 }
 
 #[async_trait::async_trait]
@@ -18,15 +35,16 @@ impl TwoFACodeStore for HashmapTwoFACodeStore {
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError> {
-        let insert_result = self.codes.insert(email, (login_attempt_id, code));
-        match insert_result {
-            Some(_val) => Err(TwoFACodeStoreError::UnexpectedError),
-            None => Ok(()),
-        }
+        // A new login attempt replaces any code still pending for the user
+        self.forget(&email);
+        let cooldown_until = Utc::now() + TimeDelta::seconds(TWO_FA_RESEND_COOLDOWN_SECONDS);
+        self.resends.insert(email.clone(), (0, cooldown_until));
+        self.codes.insert(email, (login_attempt_id, code));
+        Ok(())
     }
 
     async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        match self.codes.remove_entry(email) {
+        match self.forget(email) {
             Some(_entry) => Ok(()),
             None => Err(TwoFACodeStoreError::UnexpectedError),
         }
@@ -40,12 +58,74 @@ impl TwoFACodeStore for HashmapTwoFACodeStore {
 
         Ok(entry.to_owned())
     }
+
+    async fn verify_code(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        code: &TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let is_valid = self.codes.get(email).is_some_and(|(_, stored_code)| stored_code == code);
+        self.settle_attempt(email, login_attempt_id, is_valid).await
+    }
+
+    async fn settle_attempt(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        is_valid: bool,
+    ) -> Result<(), TwoFACodeStoreError> {
+        match self.codes.get(email) {
+            Some((stored_id, _)) if stored_id == login_attempt_id => {}
+            _ => return Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+        }
+        if is_valid {
+            self.forget(email);
+            return Ok(());
+        }
+
+        let failures = self.failures.entry(email.clone()).or_insert(0);
+        *failures += 1;
+        if *failures >= MAX_TWO_FA_ATTEMPTS {
+            self.forget(email);
+        }
+        Err(TwoFACodeStoreError::IncorrectCode)
+    }
+
+    async fn replace_code(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        code: TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let Some((stored_id, stored_code)) = self.codes.get_mut(email) else {
+            return Err(TwoFACodeStoreError::LoginAttemptIdNotFound);
+        };
+        if stored_id != login_attempt_id {
+            return Err(TwoFACodeStoreError::LoginAttemptIdNotFound);
+        }
+
+        let now = Utc::now();
+        let (resends, cooldown_until) = self.resends.entry(email.clone()).or_insert((0, now));
+        if *cooldown_until > now {
+            // round up so clients told to retry after this many seconds aren't turned away again
+            let millis = u64::try_from((*cooldown_until - now).num_milliseconds()).unwrap_or(0);
+            return Err(TwoFACodeStoreError::ResendCooldown(millis.div_ceil(1000)));
+        }
+        if *resends >= MAX_TWO_FA_RESENDS {
+            return Err(TwoFACodeStoreError::TooManyResends);
+        }
+        *resends += 1;
+        *cooldown_until = now + TimeDelta::seconds(TWO_FA_RESEND_COOLDOWN_SECONDS);
+        *stored_code = code;
+        Ok(())
+    }
 }
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use chrono::Utc;
 
-    use crate::{domain::{data_store::{LoginAttemptId, TwoFACode, TwoFACodeStore}, email::Email}, services::hashmap_two_fa_code_store::HashmapTwoFACodeStore};
+    use crate::{domain::{data_store::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError}, email::Email}, services::hashmap_two_fa_code_store::HashmapTwoFACodeStore, utils::lockout::{MAX_TWO_FA_ATTEMPTS, MAX_TWO_FA_RESENDS, TWO_FA_RESEND_COOLDOWN_SECONDS}};
 
     #[tokio::test]
     async fn tests_for_two_fa_store() {
@@ -53,17 +133,81 @@ mod tests {
         let email = Email::parse("test@email.com".to_string()).unwrap();
         let id = LoginAttemptId::default();
         let code = TwoFACode::default();
-        let mut store = HashmapTwoFACodeStore{
-          codes: HashMap::new(),  
-        };
+        let mut store = HashmapTwoFACodeStore::default();
 
         // check adding the code to store works
         assert!(store.add_code(email.clone(), id.clone(), code.clone()).await.is_ok());
         // check getting the code works
-        assert_eq!(store.get_code(&email).await, Ok((id, code)) );
+        assert_eq!(store.get_code(&email).await, Ok((id.clone(), code.clone())) );
         // check removing the code works
         assert!(store.remove_code(&email).await.is_ok());
 
-        
+        // a later login attempt replaces the pending code
+        let new_id = LoginAttemptId::default();
+        let new_code = TwoFACode::default();
+        assert!(store.add_code(email.clone(), id, code).await.is_ok());
+        assert!(store.add_code(email.clone(), new_id.clone(), new_code.clone()).await.is_ok());
+        assert_eq!(store.get_code(&email).await, Ok((new_id, new_code)));
+    }
+
+    #[tokio::test]
+    async fn test_verify_code_consumes_the_attempt() {
+        let email = Email::parse("test@email.com".to_string()).unwrap();
+        let id = LoginAttemptId::default();
+        let code = TwoFACode::parse("123456".to_owned()).unwrap();
+        let mut store = HashmapTwoFACodeStore::default();
+        store.add_code(email.clone(), id.clone(), code.clone()).await.unwrap();
+
+        // the code only works with its own login attempt
+        assert_eq!(store.verify_code(&email, &LoginAttemptId::default(), &code).await, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+        assert_eq!(store.verify_code(&email, &id, &code).await, Ok(()));
+        // and only once
+        assert_eq!(store.verify_code(&email, &id, &code).await, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_attempt_is_dropped_after_too_many_wrong_codes() {
+        let email = Email::parse("test@email.com".to_string()).unwrap();
+        let id = LoginAttemptId::default();
+        let code = TwoFACode::parse("123456".to_owned()).unwrap();
+        let wrong_code = TwoFACode::parse("654321".to_owned()).unwrap();
+        let mut store = HashmapTwoFACodeStore::default();
+        store.add_code(email.clone(), id.clone(), code.clone()).await.unwrap();
+
+        for _ in 0..MAX_TWO_FA_ATTEMPTS - 1 {
+            assert_eq!(store.verify_code(&email, &id, &wrong_code).await, Err(TwoFACodeStoreError::IncorrectCode));
+        }
+        assert_eq!(store.settle_attempt(&email, &id, false).await, Err(TwoFACodeStoreError::IncorrectCode));
+        assert_eq!(store.verify_code(&email, &id, &code).await, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+
+        // a new login attempt starts over
+        store.add_code(email.clone(), id.clone(), code.clone()).await.unwrap();
+        assert_eq!(store.verify_code(&email, &id, &wrong_code).await, Err(TwoFACodeStoreError::IncorrectCode));
+        assert_eq!(store.settle_attempt(&email, &id, true).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_replace_code_with_cooldown_and_limit() {
+        let email = Email::parse("test@email.com".to_string()).unwrap();
+        let id = LoginAttemptId::default();
+        let mut store = HashmapTwoFACodeStore::default();
+        store.add_code(email.clone(), id.clone(), TwoFACode::default()).await.unwrap();
+
+        // the first code was only just sent
+        let cooldown = TwoFACodeStoreError::ResendCooldown(TWO_FA_RESEND_COOLDOWN_SECONDS.unsigned_abs());
+        assert_eq!(store.replace_code(&email, &id, TwoFACode::default()).await, Err(cooldown));
+        assert_eq!(
+            store.replace_code(&email, &LoginAttemptId::default(), TwoFACode::default()).await,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        );
+
+        for _ in 0..MAX_TWO_FA_RESENDS {
+            store.resends.get_mut(&email).unwrap().1 = Utc::now();
+            let code = TwoFACode::default();
+            assert_eq!(store.replace_code(&email, &id, code.clone()).await, Ok(()));
+            assert_eq!(store.get_code(&email).await, Ok((id.clone(), code)));
+        }
+        store.resends.get_mut(&email).unwrap().1 = Utc::now();
+        assert_eq!(store.replace_code(&email, &id, TwoFACode::default()).await, Err(TwoFACodeStoreError::TooManyResends));
     }
 }
@@ -1,11 +1,17 @@
 use std::collections::HashMap;
 
-use crate::domain::{data_store::{UserStore, UserStoreError}, email::Email, user::User};
+use crate::domain::{data_store::{RecoveryCode, UserStore, UserStoreError}, email::Email, password::Password, totp::TotpSecret, user::{TwoFAMethod, User}};
 
 
 #[derive(Default, Clone)]
 pub struct HashmapUserStore {
     pub users: HashMap<Email, User>,
+    pub totp_secrets: HashMap<Email, TotpSecret>,
+    pub pending_totp_secrets: HashMap<Email, TotpSecret>,
+    pub totp_last_steps: HashMap<Email, u64>,
+    pub recovery_codes: HashMap<Email, Vec<RecoveryCode>>,
+    // users whose epoch was never bumped are at 0
+    pub token_epochs: HashMap<Email, u32>,
 }
 
 #[async_trait::async_trait]
@@ -38,12 +44,97 @@ impl UserStore for HashmapUserStore {
             Err(UserStoreError::UserNotFound)
         }
     }
+
+    async fn update_password(&mut self, email: &Email, password: Password) -> Result<(), UserStoreError> {
+        match self.users.get_mut(email) {
+            Some(user) => {
+                user.password = password;
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn verify_email(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        match self.users.get_mut(email) {
+            Some(user) => {
+                user.email_verified = true;
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn set_pending_totp_secret(&mut self, email: &Email, secret: TotpSecret) -> Result<(), UserStoreError> {
+        if !self.users.contains_key(email) {
+            return Err(UserStoreError::UserNotFound);
+        }
+        self.pending_totp_secrets.insert(email.clone(), secret);
+        Ok(())
+    }
+
+    async fn get_pending_totp_secret(&self, email: &Email) -> Result<TotpSecret, UserStoreError> {
+        self.pending_totp_secrets.get(email).cloned().ok_or(UserStoreError::TotpSecretNotFound)
+    }
+
+    async fn enable_totp(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        let user = self.users.get_mut(email).ok_or(UserStoreError::UserNotFound)?;
+        let secret = self.pending_totp_secrets.remove(email).ok_or(UserStoreError::TotpSecretNotFound)?;
+        user.requires_2fa = true;
+        user.two_fa_method = TwoFAMethod::Totp;
+        self.totp_secrets.insert(email.clone(), secret);
+        Ok(())
+    }
+
+    async fn get_totp_secret(&self, email: &Email) -> Result<TotpSecret, UserStoreError> {
+        self.totp_secrets.get(email).cloned().ok_or(UserStoreError::TotpSecretNotFound)
+    }
+
+    async fn use_totp_step(&mut self, email: &Email, step: u64) -> Result<(), UserStoreError> {
+        if !self.users.contains_key(email) {
+            return Err(UserStoreError::UserNotFound);
+        }
+        if self.totp_last_steps.get(email).is_some_and(|last_step| step <= *last_step) {
+            return Err(UserStoreError::TotpCodeAlreadyUsed);
+        }
+        self.totp_last_steps.insert(email.clone(), step);
+        Ok(())
+    }
+
+    async fn set_recovery_codes(&mut self, email: &Email, codes: &[RecoveryCode]) -> Result<(), UserStoreError> {
+        if !self.users.contains_key(email) {
+            return Err(UserStoreError::UserNotFound);
+        }
+        self.recovery_codes.insert(email.clone(), codes.to_vec());
+        Ok(())
+    }
+
+    async fn use_recovery_code(&mut self, email: &Email, code: &RecoveryCode) -> Result<(), UserStoreError> {
+        let codes = self.recovery_codes.get_mut(email).ok_or(UserStoreError::InvalidRecoveryCode)?;
+        let position = codes.iter().position(|c| c == code).ok_or(UserStoreError::InvalidRecoveryCode)?;
+        codes.remove(position);
+        Ok(())
+    }
+
+    async fn get_token_epoch(&self, email: &Email) -> Result<u32, UserStoreError> {
+        if !self.users.contains_key(email) {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(self.token_epochs.get(email).copied().unwrap_or(0))
+    }
+
+    async fn bump_token_epoch(&mut self, email: &Email) -> Result<u32, UserStoreError> {
+        if !self.users.contains_key(email) {
+            return Err(UserStoreError::UserNotFound);
+        }
+        let epoch = self.token_epochs.entry(email.clone()).or_insert(0);
+        *epoch += 1;
+        Ok(*epoch)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::domain::password::Password;
-
     use super::*;
 
     #[tokio::test]
@@ -51,7 +142,7 @@ mod tests {
         let email = Email::parse("email@example.com".into()).unwrap();
         let password = Password::parse("password123".into()).unwrap();
         // Create an empty store
-        let mut store = HashmapUserStore{users: HashMap::new()};
+        let mut store = HashmapUserStore::default();
         // Create user
         let user = User::new(email.clone(), password, true);
 
@@ -65,16 +156,17 @@ mod tests {
         let password = Password::parse("password123".into()).unwrap();
         // Create a user
         let user = User::new(email.clone(), password.clone(), true);
+        let id = user.id;
 
         // Put user in a the users store
         let users = HashMap::from([
             (email.clone(), user)
         ]);
         
-        let store = HashmapUserStore{users};
+        let store = HashmapUserStore{users, ..Default::default()};
 
         // check for user
-        assert_eq!(store.get_user("email@example.com").await, Ok(User{email, password, requires_2fa: true }));
+        assert_eq!(store.get_user("email@example.com").await, Ok(User{id, email, password, requires_2fa: true, email_verified: false, two_fa_method: TwoFAMethod::Email }));
     }
 
     #[tokio::test]
@@ -89,8 +181,122 @@ mod tests {
             (email.clone(), user)
         ]);
         // inser users into the hashmap store
-        let store = HashmapUserStore{users};
+        let store = HashmapUserStore{users, ..Default::default()};
 
         assert_eq!(store.validate_user(email.as_ref(), password.as_ref()).await, Ok(()));
     }
+
+    #[tokio::test]
+    async fn test_update_password() {
+        let email = Email::parse("email@example.com".into()).unwrap();
+        let password = Password::parse("password123".into()).unwrap();
+        let new_password = Password::parse("newpassword123".into()).unwrap();
+        let user = User::new(email.clone(), password.clone(), false);
+        let mut store = HashmapUserStore{users: HashMap::from([(email.clone(), user)]), ..Default::default()};
+
+        assert_eq!(store.update_password(&email, new_password.clone()).await, Ok(()));
+        // the old password should no longer be accepted
+        assert_eq!(store.validate_user(email.as_ref(), password.as_ref()).await, Err(UserStoreError::InvalidCredentials));
+        assert_eq!(store.validate_user(email.as_ref(), new_password.as_ref()).await, Ok(()));
+
+        // updating an unknown user should fail
+        let unknown = Email::parse("unknown@example.com".into()).unwrap();
+        assert_eq!(store.update_password(&unknown, new_password).await, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_verify_email() {
+        let email = Email::parse("email@example.com".into()).unwrap();
+        let password = Password::parse("password123".into()).unwrap();
+        let user = User::new(email.clone(), password, false);
+        let mut store = HashmapUserStore{users: HashMap::from([(email.clone(), user)]), ..Default::default()};
+
+        assert!(!store.get_user(email.as_ref()).await.unwrap().email_verified);
+        assert_eq!(store.verify_email(&email).await, Ok(()));
+        assert!(store.get_user(email.as_ref()).await.unwrap().email_verified);
+
+        let unknown = Email::parse("unknown@example.com".into()).unwrap();
+        assert_eq!(store.verify_email(&unknown).await, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_enable_totp() {
+        let email = Email::parse("email@example.com".into()).unwrap();
+        let password = Password::parse("password123".into()).unwrap();
+        let user = User::new(email.clone(), password, false);
+        let mut store = HashmapUserStore{users: HashMap::from([(email.clone(), user)]), ..Default::default()};
+
+        // nothing to enable before a secret was set
+        assert_eq!(store.enable_totp(&email).await, Err(UserStoreError::TotpSecretNotFound));
+
+        let secret = TotpSecret::default();
+        assert_eq!(store.set_pending_totp_secret(&email, secret.clone()).await, Ok(()));
+        assert_eq!(store.get_pending_totp_secret(&email).await, Ok(secret.clone()));
+        // the pending secret is not used until it is enabled
+        assert_eq!(store.get_totp_secret(&email).await, Err(UserStoreError::TotpSecretNotFound));
+
+        assert_eq!(store.enable_totp(&email).await, Ok(()));
+        assert_eq!(store.get_totp_secret(&email).await, Ok(secret));
+        assert_eq!(store.get_pending_totp_secret(&email).await, Err(UserStoreError::TotpSecretNotFound));
+        let user = store.get_user(email.as_ref()).await.unwrap();
+        assert!(user.requires_2fa);
+        assert_eq!(user.two_fa_method, TwoFAMethod::Totp);
+
+        let unknown = Email::parse("unknown@example.com".into()).unwrap();
+        assert_eq!(store.set_pending_totp_secret(&unknown, TotpSecret::default()).await, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_use_totp_step() {
+        let email = Email::parse("email@example.com".into()).unwrap();
+        let password = Password::parse("password123".into()).unwrap();
+        let user = User::new(email.clone(), password, true);
+        let mut store = HashmapUserStore{users: HashMap::from([(email.clone(), user)]), ..Default::default()};
+
+        assert_eq!(store.use_totp_step(&email, 100).await, Ok(()));
+        // the same step, or an earlier one, can't be used again
+        assert_eq!(store.use_totp_step(&email, 100).await, Err(UserStoreError::TotpCodeAlreadyUsed));
+        assert_eq!(store.use_totp_step(&email, 99).await, Err(UserStoreError::TotpCodeAlreadyUsed));
+        assert_eq!(store.use_totp_step(&email, 101).await, Ok(()));
+
+        let unknown = Email::parse("unknown@example.com".into()).unwrap();
+        assert_eq!(store.use_totp_step(&unknown, 100).await, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_use_recovery_code() {
+        let email = Email::parse("email@example.com".into()).unwrap();
+        let password = Password::parse("password123".into()).unwrap();
+        let user = User::new(email.clone(), password, true);
+        let mut store = HashmapUserStore{users: HashMap::from([(email.clone(), user)]), ..Default::default()};
+
+        let codes = vec![RecoveryCode::default(), RecoveryCode::default()];
+        assert_eq!(store.set_recovery_codes(&email, &codes).await, Ok(()));
+
+        assert_eq!(store.use_recovery_code(&email, &codes[0]).await, Ok(()));
+        // every code can only be used once
+        assert_eq!(store.use_recovery_code(&email, &codes[0]).await, Err(UserStoreError::InvalidRecoveryCode));
+        assert_eq!(store.use_recovery_code(&email, &RecoveryCode::default()).await, Err(UserStoreError::InvalidRecoveryCode));
+
+        // regenerating replaces the codes that were left
+        assert_eq!(store.set_recovery_codes(&email, &[RecoveryCode::default()]).await, Ok(()));
+        assert_eq!(store.use_recovery_code(&email, &codes[1]).await, Err(UserStoreError::InvalidRecoveryCode));
+    }
+
+    #[tokio::test]
+    async fn test_bump_token_epoch() {
+        let email = Email::parse("email@example.com".into()).unwrap();
+        let password = Password::parse("password123".into()).unwrap();
+        let user = User::new(email.clone(), password, true);
+        let mut store = HashmapUserStore{users: HashMap::from([(email.clone(), user)]), ..Default::default()};
+
+        assert_eq!(store.get_token_epoch(&email).await, Ok(0));
+        assert_eq!(store.bump_token_epoch(&email).await, Ok(1));
+        assert_eq!(store.bump_token_epoch(&email).await, Ok(2));
+        assert_eq!(store.get_token_epoch(&email).await, Ok(2));
+
+        let unknown = Email::parse("unknown@example.com".into()).unwrap();
+        assert_eq!(store.get_token_epoch(&unknown).await, Err(UserStoreError::UserNotFound));
+        assert_eq!(store.bump_token_epoch(&unknown).await, Err(UserStoreError::UserNotFound));
+    }
 }
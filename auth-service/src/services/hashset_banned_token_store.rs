@@ -1,74 +1,75 @@
-use std::collections::HashSet;
+use std::collections::HashMap;
+
+use chrono::Utc;
 
 use crate::domain::data_store::{BannedTokenStore, BannedTokenStoreError};
-#[derive(Debug, Clone)]
-pub struct HashsetBannedTokenStore(pub HashSet<String>);
+#[derive(Debug, Clone, Default)]
+pub struct HashsetBannedTokenStore {
+    // jti -> when the token expires
+    pub tokens: HashMap<String, usize>,
+}
+
+fn now() -> usize {
+    Utc::now().timestamp().try_into().unwrap_or(0)
+}
 
 #[async_trait::async_trait]
 impl BannedTokenStore for HashsetBannedTokenStore {
-    async fn store_token(&mut self, token: String)-> Result<(), BannedTokenStoreError> {
-       let insert_result = self.0.insert(token);
-       if insert_result {
-           Ok(())
-       } else {
-           Err(BannedTokenStoreError::TokenAlreadyInStore)
-       }
+    async fn revoke_token(&mut self, jti: &str, expires_at: usize) -> Result<(), BannedTokenStoreError> {
+        // forget the tokens that expired since
+        let now = now();
+        self.tokens.retain(|_, expires_at| *expires_at > now);
+        if self.tokens.contains_key(jti) {
+            return Err(BannedTokenStoreError::TokenAlreadyInStore);
+        }
+        if expires_at > now {
+            self.tokens.insert(jti.to_owned(), expires_at);
+        }
+        Ok(())
     }
 
-    async fn is_token_banned(&self, token:String) -> Result<bool, BannedTokenStoreError>{
-         if self.0.contains(&token) {
-             Ok(true)
-         } else {
-            Err(BannedTokenStoreError::UnexpectedError)  
-         }
+    async fn is_token_revoked(&self, jti: &str) -> Result<bool, BannedTokenStoreError> {
+        Ok(self.tokens.get(jti).is_some_and(|expires_at| *expires_at > now()))
     }
 }
 
 
 #[cfg(test)]
 mod tests {
-    use crate::{domain::email::Email, utils::auth::generate_auth_cookie};
-
     use super::*;
 
     #[tokio::test]
-    async fn test_store_token() {
-        // create a banned token store
-        let mut store = HashsetBannedTokenStore(HashSet::new());
-
-        // create a token from email
-        let email = Email::parse("example@email.com".to_string()).expect("Should parse the email");
+    async fn test_revoke_token() {
+        let mut store = HashsetBannedTokenStore::default();
+        let expires_at = now() + 600;
 
-        let cookie = generate_auth_cookie(email).expect("shoudl get a cookie");
-
-        let token = cookie.value();
-        // store the token in the store
-        let result = store.store_token(token.into()).await;
+        let result = store.revoke_token("jti", expires_at).await;
         assert_eq!(result, Ok(()));
+        assert_eq!(store.revoke_token("jti", expires_at).await, Err(BannedTokenStoreError::TokenAlreadyInStore));
     }
 
     #[tokio::test]
-    async fn test_is_token_banned() {
-        // create a banned token store
-        let mut store = HashsetBannedTokenStore(HashSet::new());
+    async fn test_is_token_revoked() {
+        let mut store = HashsetBannedTokenStore::default();
+
+        store.revoke_token("jti", now() + 600).await.expect("Should revoke the token successfully");
 
-        // create a token from email
-        let email = Email::parse("example@email.com".to_string()).expect("Should parse the email");
-        let email1 = Email::parse("example2@email.com".to_string()).expect("Should parse the email succesfully");
-        
-        let cookie = generate_auth_cookie(email).expect("shoudl get a cookie");
-        let cookie2 = generate_auth_cookie(email1).expect("should create cookie");
+        assert_eq!(store.is_token_revoked("jti").await, Ok(true));
+        assert_eq!(store.is_token_revoked("other jti").await, Ok(false), "A token that was never revoked is not revoked");
+    }
+
+    #[tokio::test]
+    async fn test_revoked_tokens_are_forgotten_once_expired() {
+        let mut store = HashsetBannedTokenStore::default();
 
-        let token = cookie.value();
-        let token2 = cookie2.value();
-        // store the token in the store
-         store.store_token(token.into()).await.expect("Should store the token successfully");
+        // already expired tokens don't need an entry
+        store.revoke_token("expired", now() - 1).await.unwrap();
+        assert!(store.tokens.is_empty());
 
-        // check if token is banned
-        let result = store.is_token_banned(token.into()).await;
-        let result2 = store.is_token_banned(token2.into()).await;
-        assert!(result.is_ok());
-        assert!(result2.is_err(), "This should be false ");
-        
+        store.revoke_token("jti", now() + 600).await.unwrap();
+        store.tokens.insert("jti".to_owned(), now() - 1);
+        assert_eq!(store.is_token_revoked("jti").await, Ok(false));
+        store.revoke_token("other jti", now() + 600).await.unwrap();
+        assert!(!store.tokens.contains_key("jti"));
     }
 }
@@ -0,0 +1,71 @@
+use std::{
+    path::PathBuf,
+    sync::{PoisonError, RwLock},
+    time::Duration,
+};
+
+use crate::utils::jwt_keyring::JwtKeyring;
+
+// How often the keyring file is read again
+const RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+// Background task re-reading the keyring file, so keys can be added, promoted and retired
+// without restarting the service. Scheduled promotions and retirements need no reload, the
+// keyring checks the time whenever it is used
+pub struct JwtKeyringReloader {
+    path: PathBuf,
+    keyring: &'static RwLock<JwtKeyring>,
+}
+
+impl JwtKeyringReloader {
+    pub fn new(path: PathBuf, keyring: &'static RwLock<JwtKeyring>) -> Self {
+        Self { path, keyring }
+    }
+
+    pub async fn run(self) {
+        loop {
+            tokio::time::sleep(RELOAD_INTERVAL).await;
+            if let Err(e) = self.reload() {
+                eprintln!("Failed to reload the JWT keyring, keeping the current keys: {}", e);
+            }
+        }
+    }
+
+    // Replace the keyring with the one in the file, unless the file is invalid
+    pub fn reload(&self) -> Result<(), String> {
+        let keyring = JwtKeyring::load(&self.path)?;
+        *self.keyring.write().unwrap_or_else(PoisonError::into_inner) = keyring;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::utils::jwt_key::JwtKey;
+
+    #[test]
+    fn test_reload_keeps_current_keys_when_file_is_invalid() {
+        let keyring: &'static RwLock<JwtKeyring> =
+            Box::leak(Box::new(RwLock::new(JwtKeyring::single(JwtKey::from_secret(b"old")))));
+        let path = std::env::temp_dir().join(format!("jwt_keyring_{}.json", Uuid::new_v4()));
+        let reloader = JwtKeyringReloader::new(path.clone(), keyring);
+        let signing_kid = || keyring.read().unwrap().signing_key(Utc::now()).unwrap().kid().to_owned();
+        let old_kid = signing_kid();
+
+        fs::write(&path, "not json").unwrap();
+        assert!(reloader.reload().is_err());
+        assert_eq!(signing_kid(), old_kid);
+
+        fs::write(&path, r#"[{"algorithm": "HS256", "secret": "new"}]"#).unwrap();
+        reloader.reload().unwrap();
+        assert_eq!(signing_kid(), JwtKey::from_secret(b"new").kid());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
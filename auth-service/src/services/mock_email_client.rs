@@ -1,12 +1,12 @@
-use crate::domain::{email::Email, email_client::EmailClient};
+use crate::domain::{email::Email, email_client::EmailClient, email_message::EmailMessage};
 
 #[derive(Clone)]
 pub struct MockEmailClient;
 
 #[async_trait::async_trait]
 impl EmailClient for MockEmailClient {
-    async fn send_email(&self, recepient: &Email, subject: &str, content: &str) -> Result<(), String> {
-        println!( "sending email to {} with subject: {} and content: {}", recepient.as_ref(), subject, content);
+    async fn send_email(&self, recepient: &Email, message: &EmailMessage) -> Result<(), String> {
+        println!( "sending email to {} with subject: {} and content: {}", recepient.as_ref(), message.subject, message.text_body);
 
         Ok(())
      }
@@ -1,7 +1,29 @@
 pub mod data_store;
+pub mod email_outbox_worker;
+pub mod hashmap_authorization_code_store;
+pub mod hashmap_device_authorization_store;
+pub mod hashmap_email_outbox;
+pub mod hashmap_email_verification_token_store;
+pub mod hashmap_failed_login_store;
+pub mod hashmap_oauth_client_store;
+pub mod hashmap_password_reset_token_store;
+pub mod hashmap_rate_limit_store;
+pub mod hashmap_service_account_store;
+pub mod hashmap_refresh_token_store;
+pub mod hashmap_session_store;
 pub mod hashmap_two_fa_code_store;
 pub mod hashmap_user_store;
 pub mod hashset_banned_token_store;
+pub mod jwt_keyring_reloader;
 pub mod mock_email_client;
+pub mod redis_authorization_code_store;
 pub mod redis_banned_token_store;
+pub mod redis_device_authorization_store;
+pub mod redis_email_verification_token_store;
+pub mod redis_failed_login_store;
+pub mod redis_password_reset_token_store;
+pub mod redis_rate_limit_store;
+pub mod redis_refresh_token_store;
+pub mod redis_session_store;
 pub mod redis_two_fa_code_store;
+pub mod smtp_email_client;
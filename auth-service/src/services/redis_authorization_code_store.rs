@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use redis::{Commands, Connection};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::{domain::{data_store::{AuthorizationCode, AuthorizationCodeStore, AuthorizationCodeStoreError}, email::Email, oauth::{AuthorizationGrant, CodeChallenge}}, utils::auth::AUTHORIZATION_CODE_TTL_SECONDS};
+
+#[derive(Clone)]
+pub struct RedisAuthorizationCodeStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisAuthorizationCodeStore {
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl AuthorizationCodeStore for RedisAuthorizationCodeStore {
+    async fn add_code(&mut self, code: AuthorizationCode, grant: AuthorizationGrant) -> Result<(), AuthorizationCodeStoreError> {
+        let ttl: u64 = AUTHORIZATION_CODE_TTL_SECONDS.try_into().map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?;
+        let record = serde_json::to_string(&AuthorizationGrantRecord::from(grant))
+            .map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?;
+
+        let mut connection = self.conn.write().await;
+        connection
+            .set_ex::<_, _, ()>(get_key(&code), record, ttl)
+            .map_err(|_| AuthorizationCodeStoreError::UnexpectedError)
+    }
+
+    async fn take_code(&mut self, code: &AuthorizationCode) -> Result<AuthorizationGrant, AuthorizationCodeStoreError> {
+        // GETDEL so two concurrent exchanges of the same code can't both succeed
+        let mut connection = self.conn.write().await;
+        let record: Option<String> = connection
+            .get_del(get_key(code))
+            .map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?;
+        let record: AuthorizationGrantRecord = serde_json::from_str(&record.ok_or(AuthorizationCodeStoreError::CodeNotFound)?)
+            .map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?;
+
+        record.try_into().map_err(|_: String| AuthorizationCodeStoreError::UnexpectedError)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct AuthorizationGrantRecord {
+    client_id: String,
+    redirect_uri: String,
+    // missing from codes issued before it was recorded
+    #[serde(default)]
+    redirect_uri_supplied: bool,
+    email: String,
+    scopes: Vec<String>,
+    code_challenge: String,
+    device: String,
+    ip: String,
+    nonce: Option<String>,
+    amr: Vec<String>,
+}
+
+impl From<AuthorizationGrant> for AuthorizationGrantRecord {
+    fn from(grant: AuthorizationGrant) -> Self {
+        Self {
+            client_id: grant.client_id,
+            redirect_uri: grant.redirect_uri,
+            redirect_uri_supplied: grant.redirect_uri_supplied,
+            email: grant.email.as_ref().to_owned(),
+            scopes: grant.scopes,
+            code_challenge: grant.code_challenge.as_ref().to_owned(),
+            device: grant.device,
+            ip: grant.ip,
+            nonce: grant.nonce,
+            amr: grant.amr,
+        }
+    }
+}
+
+impl TryFrom<AuthorizationGrantRecord> for AuthorizationGrant {
+    type Error = String;
+
+    fn try_from(record: AuthorizationGrantRecord) -> Result<Self, Self::Error> {
+        Ok(Self {
+            client_id: record.client_id,
+            redirect_uri: record.redirect_uri,
+            redirect_uri_supplied: record.redirect_uri_supplied,
+            email: Email::parse(record.email).map_err(|_| "Invalid email".to_owned())?,
+            scopes: record.scopes,
+            code_challenge: CodeChallenge::parse(record.code_challenge, Some("S256"))?,
+            device: record.device,
+            ip: record.ip,
+            nonce: record.nonce,
+            amr: record.amr,
+        })
+    }
+}
+
+const AUTHORIZATION_CODE_PREFIX: &str = "authorization_code:";
+
+fn get_key(code: &AuthorizationCode) -> String {
+    format!("{}{}", AUTHORIZATION_CODE_PREFIX, code.as_ref())
+}
@@ -1,9 +1,10 @@
 use std::sync::Arc;
 
+use chrono::Utc;
 use redis::{Commands, Connection};
 use tokio::sync::RwLock;
 
-use crate::{domain::data_store::{BannedTokenStore, BannedTokenStoreError}, utils::auth::TOKEN_TTL_SECDONDS};
+use crate::domain::data_store::{BannedTokenStore, BannedTokenStoreError};
 
 #[derive(Clone)]
 pub struct RedisBannedTokenStore {
@@ -18,38 +19,35 @@ impl RedisBannedTokenStore {
 
 #[async_trait::async_trait]
 impl BannedTokenStore for RedisBannedTokenStore {
-    async fn store_token(&mut self, token: String) -> Result<(), BannedTokenStoreError> {
-        // create a new key using helper fn
-        let key =  get_key(&token);
-        // call the set_ex command on the Redis connection to se ta new key/value pair
-        // with an expiration time (TTL)
-        let ttl: u64= TOKEN_TTL_SECDONDS.try_into().map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+    async fn revoke_token(&mut self, jti: &str, expires_at: usize) -> Result<(), BannedTokenStoreError> {
+        let expires_at = i64::try_from(expires_at).map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+        // the entry only has to last as long as the token
+        let ttl = expires_at - Utc::now().timestamp();
+        if ttl <= 0 {
+            return Ok(());
+        }
         let mut connection = self
             .conn
             .write()
             .await;
         connection
-            .set_ex(key, true, ttl)
+            .set_ex::<_, _, ()>(get_key(jti), true, ttl as u64)
             .map_err(|_| BannedTokenStoreError::UnexpectedError)?;
         Ok(())
-        
     }
-    async fn is_token_banned(&self, token:String) -> Result<bool, BannedTokenStoreError> {
+
+    async fn is_token_revoked(&self, jti: &str) -> Result<bool, BannedTokenStoreError> {
         let mut connection = self
             .conn
             .write()
             .await;
 
-        // add the prefix to the token to before searching
-        let token = get_key(&token);
-        let check_result:Result<bool, BannedTokenStoreError> = connection.exists(token)
-            .map_err(|_| BannedTokenStoreError::UnexpectedError);
-
-        check_result
+        connection.exists(get_key(jti))
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)
     }
 }
 // we are suing a key prefix to prevent collisons and organize data
-const BANNED_TOKEN_KEY_PREFIX: &str = "banned_token:";
-fn get_key(token: &str) -> String {
-    format!("{}{}", BANNED_TOKEN_KEY_PREFIX, token)
+const REVOKED_TOKEN_KEY_PREFIX: &str = "revoked_jti:";
+fn get_key(jti: &str) -> String {
+    format!("{}{}", REVOKED_TOKEN_KEY_PREFIX, jti)
 }
@@ -0,0 +1,211 @@
+use std::sync::Arc;
+
+use redis::{Commands, Connection, ExistenceCheck, SetExpiry, SetOptions};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::{
+    domain::{
+        data_store::{DeviceAuthorizationStore, DeviceAuthorizationStoreError, DeviceCode, UserCode},
+        email::Email,
+        oauth::{DeviceAuthorization, DeviceAuthorizationStatus},
+    },
+    utils::auth::{DEVICE_CODE_TTL_SECONDS, DEVICE_POLL_INTERVAL_SECONDS},
+};
+
+// An authorization is kept under its device code, with the user code pointing to it until the
+// user decides. The decision is kept under a key of its own, so that taking it with GETDEL hands
+// the tokens out once, and a key that lives for the poll interval tells polls that come too soon
+#[derive(Clone)]
+pub struct RedisDeviceAuthorizationStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisDeviceAuthorizationStore {
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl DeviceAuthorizationStore for RedisDeviceAuthorizationStore {
+    async fn add_authorization(&mut self, device_code: DeviceCode, user_code: UserCode, authorization: DeviceAuthorization) -> Result<(), DeviceAuthorizationStoreError> {
+        let ttl: u64 = DEVICE_CODE_TTL_SECONDS.try_into().map_err(|_| DeviceAuthorizationStoreError::UnexpectedError)?;
+        let record = serde_json::to_string(&DeviceAuthorizationRecord::from(authorization))
+            .map_err(|_| DeviceAuthorizationStoreError::UnexpectedError)?;
+
+        let mut connection = self.conn.write().await;
+        // user codes are short enough to collide now and then, the device should just ask again
+        let options = SetOptions::default()
+            .conditional_set(ExistenceCheck::NX)
+            .with_expiration(SetExpiry::EX(ttl as usize));
+        let added: Option<String> = connection
+            .set_options(get_user_code_key(&user_code), device_code.as_ref(), options)
+            .map_err(|_| DeviceAuthorizationStoreError::UnexpectedError)?;
+        if added.is_none() {
+            return Err(DeviceAuthorizationStoreError::UnexpectedError);
+        }
+        connection
+            .set_ex::<_, _, ()>(get_authorization_key(&device_code), record, ttl)
+            .map_err(|_| DeviceAuthorizationStoreError::UnexpectedError)
+    }
+
+    async fn get_authorization(&self, user_code: &UserCode) -> Result<DeviceAuthorization, DeviceAuthorizationStoreError> {
+        let mut connection = self.conn.write().await;
+        let device_code: Option<String> = connection
+            .get(get_user_code_key(user_code))
+            .map_err(|_| DeviceAuthorizationStoreError::UnexpectedError)?;
+        let device_code = parse_device_code(device_code)?;
+        get_record(&mut connection, &device_code)
+    }
+
+    async fn decide(&mut self, user_code: &UserCode, status: DeviceAuthorizationStatus) -> Result<(), DeviceAuthorizationStoreError> {
+        let record = serde_json::to_string(&DeviceAuthorizationStatusRecord::from(status))
+            .map_err(|_| DeviceAuthorizationStoreError::UnexpectedError)?;
+
+        let mut connection = self.conn.write().await;
+        let device_code: Option<String> = connection
+            .get_del(get_user_code_key(user_code))
+            .map_err(|_| DeviceAuthorizationStoreError::UnexpectedError)?;
+        let device_code = parse_device_code(device_code)?;
+
+        // the decision goes when the authorization does
+        let ttl: i64 = connection
+            .ttl(get_authorization_key(&device_code))
+            .map_err(|_| DeviceAuthorizationStoreError::UnexpectedError)?;
+        if ttl <= 0 {
+            return Err(DeviceAuthorizationStoreError::AuthorizationNotFound);
+        }
+        connection
+            .set_ex::<_, _, ()>(get_status_key(&device_code), record, ttl as u64)
+            .map_err(|_| DeviceAuthorizationStoreError::UnexpectedError)
+    }
+
+    async fn poll(&mut self, device_code: &DeviceCode, client_id: &str) -> Result<(DeviceAuthorization, DeviceAuthorizationStatus), DeviceAuthorizationStoreError> {
+        let mut connection = self.conn.write().await;
+        let authorization = get_record(&mut connection, device_code)?;
+        if authorization.client_id != client_id {
+            return Err(DeviceAuthorizationStoreError::WrongClient);
+        }
+
+        let options = SetOptions::default()
+            .conditional_set(ExistenceCheck::NX)
+            .with_expiration(SetExpiry::EX(DEVICE_POLL_INTERVAL_SECONDS as usize));
+        let polled: Option<String> = connection
+            .set_options(get_poll_key(device_code), 1, options)
+            .map_err(|_| DeviceAuthorizationStoreError::UnexpectedError)?;
+        if polled.is_none() {
+            return Err(DeviceAuthorizationStoreError::PolledTooSoon);
+        }
+
+        let status: Option<String> = connection
+            .get_del(get_status_key(device_code))
+            .map_err(|_| DeviceAuthorizationStoreError::UnexpectedError)?;
+        let Some(status) = status else {
+            return Ok((authorization, DeviceAuthorizationStatus::Pending));
+        };
+        let status: DeviceAuthorizationStatusRecord = serde_json::from_str(&status).map_err(|_| DeviceAuthorizationStoreError::UnexpectedError)?;
+        connection
+            .del::<_, ()>(&[get_authorization_key(device_code), get_poll_key(device_code)])
+            .map_err(|_| DeviceAuthorizationStoreError::UnexpectedError)?;
+        Ok((authorization, status.try_into().map_err(|_: String| DeviceAuthorizationStoreError::UnexpectedError)?))
+    }
+}
+
+fn parse_device_code(device_code: Option<String>) -> Result<DeviceCode, DeviceAuthorizationStoreError> {
+    DeviceCode::parse(device_code.ok_or(DeviceAuthorizationStoreError::AuthorizationNotFound)?)
+        .map_err(|_| DeviceAuthorizationStoreError::UnexpectedError)
+}
+
+fn get_record(connection: &mut Connection, device_code: &DeviceCode) -> Result<DeviceAuthorization, DeviceAuthorizationStoreError> {
+    let record: Option<String> = connection
+        .get(get_authorization_key(device_code))
+        .map_err(|_| DeviceAuthorizationStoreError::UnexpectedError)?;
+    let record: DeviceAuthorizationRecord = serde_json::from_str(&record.ok_or(DeviceAuthorizationStoreError::AuthorizationNotFound)?)
+        .map_err(|_| DeviceAuthorizationStoreError::UnexpectedError)?;
+    Ok(record.into())
+}
+
+#[derive(Serialize, Deserialize)]
+struct DeviceAuthorizationRecord {
+    client_id: String,
+    scopes: Vec<String>,
+    device: String,
+    ip: String,
+}
+
+impl From<DeviceAuthorization> for DeviceAuthorizationRecord {
+    fn from(authorization: DeviceAuthorization) -> Self {
+        Self {
+            client_id: authorization.client_id,
+            scopes: authorization.scopes,
+            device: authorization.device,
+            ip: authorization.ip,
+        }
+    }
+}
+
+impl From<DeviceAuthorizationRecord> for DeviceAuthorization {
+    fn from(record: DeviceAuthorizationRecord) -> Self {
+        Self {
+            client_id: record.client_id,
+            scopes: record.scopes,
+            device: record.device,
+            ip: record.ip,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum DeviceAuthorizationStatusRecord {
+    Pending,
+    Approved { email: String, amr: Vec<String> },
+    Denied,
+}
+
+impl From<DeviceAuthorizationStatus> for DeviceAuthorizationStatusRecord {
+    fn from(status: DeviceAuthorizationStatus) -> Self {
+        match status {
+            DeviceAuthorizationStatus::Pending => Self::Pending,
+            DeviceAuthorizationStatus::Approved { email, amr } => Self::Approved { email: email.as_ref().to_owned(), amr },
+            DeviceAuthorizationStatus::Denied => Self::Denied,
+        }
+    }
+}
+
+impl TryFrom<DeviceAuthorizationStatusRecord> for DeviceAuthorizationStatus {
+    type Error = String;
+
+    fn try_from(record: DeviceAuthorizationStatusRecord) -> Result<Self, Self::Error> {
+        Ok(match record {
+            DeviceAuthorizationStatusRecord::Pending => Self::Pending,
+            DeviceAuthorizationStatusRecord::Approved { email, amr } => Self::Approved {
+                email: Email::parse(email).map_err(|_| "Invalid email".to_owned())?,
+                amr,
+            },
+            DeviceAuthorizationStatusRecord::Denied => Self::Denied,
+        })
+    }
+}
+
+const DEVICE_AUTHORIZATION_PREFIX: &str = "device_authorization:";
+const DEVICE_AUTHORIZATION_STATUS_PREFIX: &str = "device_authorization_status:";
+const DEVICE_AUTHORIZATION_POLL_PREFIX: &str = "device_authorization_poll:";
+const USER_CODE_PREFIX: &str = "user_code:";
+
+fn get_authorization_key(device_code: &DeviceCode) -> String {
+    format!("{}{}", DEVICE_AUTHORIZATION_PREFIX, device_code.as_ref())
+}
+
+fn get_status_key(device_code: &DeviceCode) -> String {
+    format!("{}{}", DEVICE_AUTHORIZATION_STATUS_PREFIX, device_code.as_ref())
+}
+
+fn get_poll_key(device_code: &DeviceCode) -> String {
+    format!("{}{}", DEVICE_AUTHORIZATION_POLL_PREFIX, device_code.as_ref())
+}
+
+fn get_user_code_key(user_code: &UserCode) -> String {
+    format!("{}{}", USER_CODE_PREFIX, user_code.as_ref())
+}
@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use redis::{Commands, Connection};
+use tokio::sync::RwLock;
+
+use crate::{
+    domain::{
+        data_store::{FailedLoginStore, FailedLoginStoreError},
+        email::Email,
+    },
+    utils::lockout::FAILED_LOGIN_WINDOW_SECONDS,
+};
+
+#[derive(Clone)]
+pub struct RedisFailedLoginStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisFailedLoginStore {
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl FailedLoginStore for RedisFailedLoginStore {
+    async fn record_failure(&mut self, email: &Email) -> Result<u32, FailedLoginStoreError> {
+        let key = get_failures_key(email);
+        let mut connection = self.conn.write().await;
+        // every failure pushes the window back, so the count only resets after a quiet period
+        let (failures, ()): (u32, ()) = redis::pipe()
+            .atomic()
+            .incr(&key, 1)
+            .expire(&key, FAILED_LOGIN_WINDOW_SECONDS)
+            .query(&mut *connection)
+            .map_err(|_| FailedLoginStoreError::UnexpectedError)?;
+        Ok(failures)
+    }
+
+    async fn clear_failures(&mut self, email: &Email) -> Result<(), FailedLoginStoreError> {
+        let mut connection = self.conn.write().await;
+        connection
+            .del::<_, ()>(get_failures_key(email))
+            .map_err(|_| FailedLoginStoreError::UnexpectedError)
+    }
+
+    async fn lock(&mut self, email: &Email, seconds: u64) -> Result<(), FailedLoginStoreError> {
+        let mut connection = self.conn.write().await;
+        connection
+            .set_ex::<_, _, ()>(get_lock_key(email), true, seconds)
+            .map_err(|_| FailedLoginStoreError::UnexpectedError)
+    }
+
+    async fn get_lock(&self, email: &Email) -> Result<Option<u64>, FailedLoginStoreError> {
+        let mut connection = self.conn.write().await;
+        // TTL is -2 when the key doesn't exist
+        let ttl: i64 = connection
+            .ttl(get_lock_key(email))
+            .map_err(|_| FailedLoginStoreError::UnexpectedError)?;
+        Ok(u64::try_from(ttl).ok().filter(|ttl| *ttl > 0))
+    }
+}
+
+const FAILED_LOGINS_KEY_PREFIX: &str = "failed_logins:";
+const LOGIN_LOCK_KEY_PREFIX: &str = "login_lock:";
+
+fn get_failures_key(email: &Email) -> String {
+    format!("{}{}", FAILED_LOGINS_KEY_PREFIX, email.as_ref())
+}
+
+fn get_lock_key(email: &Email) -> String {
+    format!("{}{}", LOGIN_LOCK_KEY_PREFIX, email.as_ref())
+}
@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use redis::{Commands, Connection};
+use tokio::sync::RwLock;
+
+use crate::domain::{data_store::{PasswordResetToken, PasswordResetTokenStore, PasswordResetTokenStoreError}, email::Email};
+
+#[derive(Clone)]
+pub struct RedisPasswordResetTokenStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisPasswordResetTokenStore {
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl PasswordResetTokenStore for RedisPasswordResetTokenStore {
+    async fn add_token(
+        &mut self,
+        email: Email,
+        token: PasswordResetToken,
+    ) -> Result<(), PasswordResetTokenStoreError> {
+        let key = get_key(&email);
+        // a new request replaces any token that was issued before it
+        let mut connection = self.conn.write().await;
+        connection
+            .set_ex::<_, _, ()>(key, token.as_ref(), FIFTEEN_MINUTES_IN_SECONDS)
+            .map_err(|_| PasswordResetTokenStoreError::UnexpectedError)?;
+        Ok(())
+    }
+
+    async fn remove_token(&mut self, email: &Email) -> Result<(), PasswordResetTokenStoreError> {
+        let key = get_key(email);
+        let mut connection = self.conn.write().await;
+        connection
+            .del::<_, ()>(key)
+            .map_err(|_| PasswordResetTokenStoreError::UnexpectedError)?;
+        Ok(())
+    }
+
+    async fn get_token(&self, email: &Email) -> Result<PasswordResetToken, PasswordResetTokenStoreError> {
+        let key = get_key(email);
+        let mut connection = self.conn.write().await;
+        let token: Option<String> = connection
+            .get(key)
+            .map_err(|_| PasswordResetTokenStoreError::UnexpectedError)?;
+
+        match token {
+            Some(token) => PasswordResetToken::parse(token).map_err(|_| PasswordResetTokenStoreError::UnexpectedError),
+            None => Err(PasswordResetTokenStoreError::TokenNotFound),
+        }
+    }
+}
+
+const FIFTEEN_MINUTES_IN_SECONDS: u64 = 900;
+const PASSWORD_RESET_TOKEN_PREFIX: &str = "password_reset_token:";
+
+fn get_key(email: &Email) -> String {
+    format!("{}{}", PASSWORD_RESET_TOKEN_PREFIX, email.as_ref())
+}
@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use redis::{Connection, Script};
+use tokio::sync::RwLock;
+
+use crate::domain::{
+    data_store::{RateLimitStore, RateLimitStoreError},
+    rate_limit::{RateLimit, RateLimitDecision},
+};
+
+// Refills the bucket and takes a token in one step, so concurrent requests (possibly
+// on other instances) can't both spend the last token. The Redis clock is used so
+// that instances with skewed clocks share the same view of the bucket.
+// Returns whether a token was taken and the tokens left, as a string to keep the fraction
+const TAKE_TOKEN_SCRIPT: &str = r#"
+local capacity = tonumber(ARGV[1])
+local period_ms = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now_ms = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'updated_at')
+local tokens = tonumber(bucket[1]) or capacity
+local updated_at = tonumber(bucket[2]) or now_ms
+tokens = math.min(capacity, tokens + math.max(0, now_ms - updated_at) * capacity / period_ms)
+
+local allowed = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+    allowed = 1
+end
+
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated_at', now_ms)
+-- an untouched bucket is full again after a period, so it can go
+redis.call('PEXPIRE', KEYS[1], period_ms)
+return {allowed, tostring(tokens)}
+"#;
+
+#[derive(Clone)]
+pub struct RedisRateLimitStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisRateLimitStore {
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for RedisRateLimitStore {
+    async fn take_token(&mut self, key: &str, limit: &RateLimit) -> Result<RateLimitDecision, RateLimitStoreError> {
+        let mut connection = self.conn.write().await;
+        let (allowed, tokens): (bool, f64) = Script::new(TAKE_TOKEN_SCRIPT)
+            .key(get_key(key))
+            .arg(limit.capacity)
+            .arg(limit.period_millis())
+            .invoke(&mut *connection)
+            .map_err(|_| RateLimitStoreError::UnexpectedError)?;
+        Ok(limit.decision(allowed, tokens))
+    }
+}
+
+const RATE_LIMIT_KEY_PREFIX: &str = "rate_limit:";
+
+fn get_key(key: &str) -> String {
+    format!("{}{}", RATE_LIMIT_KEY_PREFIX, key)
+}
@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use redis::{Commands, Connection};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::{domain::{data_store::{RefreshToken, RefreshTokenFamily, RefreshTokenStore, RefreshTokenStoreError}, email::Email}, utils::auth::REFRESH_TOKEN_TTL_SECONDS};
+
+#[derive(Clone)]
+pub struct RedisRefreshTokenStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisRefreshTokenStore {
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenStore for RedisRefreshTokenStore {
+    async fn add_token(
+        &mut self,
+        email: Email,
+        family: RefreshTokenFamily,
+        token: RefreshToken,
+    ) -> Result<(), RefreshTokenStoreError> {
+        let ttl: u64 = REFRESH_TOKEN_TTL_SECONDS.try_into().map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+        let record = serde_json::to_string(&RefreshTokenRecord(email.as_ref().into(), family.as_ref().into()))
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+        let user_families_key = get_user_families_key(&email);
+
+        // Rotated tokens are kept around until they expire so that reusing them can be detected
+        let mut connection = self.conn.write().await;
+        redis::pipe()
+            .atomic()
+            .set_ex(get_token_key(&token), record, ttl)
+            .ignore()
+            .set_ex(get_family_key(&family), token.as_ref(), ttl)
+            .ignore()
+            .sadd(&user_families_key, family.as_ref())
+            .ignore()
+            .expire(&user_families_key, ttl as i64)
+            .ignore()
+            .query::<()>(&mut *connection)
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+        Ok(())
+    }
+
+    async fn get_token(&self, token: &RefreshToken) -> Result<(Email, RefreshTokenFamily), RefreshTokenStoreError> {
+        let mut connection = self.conn.write().await;
+        let record: Option<String> = connection
+            .get(get_token_key(token))
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+        let record = record.ok_or(RefreshTokenStoreError::TokenNotFound)?;
+
+        let RefreshTokenRecord(email, family) = serde_json::from_str(&record)
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+        let email = Email::parse(email).map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+        let family = RefreshTokenFamily::parse(family).map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+        Ok((email, family))
+    }
+
+    async fn get_current_token(&self, family: &RefreshTokenFamily) -> Result<RefreshToken, RefreshTokenStoreError> {
+        let mut connection = self.conn.write().await;
+        let token: Option<String> = connection
+            .get(get_family_key(family))
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        match token {
+            Some(token) => RefreshToken::parse(token).map_err(|_| RefreshTokenStoreError::UnexpectedError),
+            None => Err(RefreshTokenStoreError::FamilyNotFound),
+        }
+    }
+
+    async fn revoke_family(&mut self, family: &RefreshTokenFamily) -> Result<(), RefreshTokenStoreError> {
+        // Without a current token no token of the family can be used anymore
+        let mut connection = self.conn.write().await;
+        connection
+            .del::<_, ()>(get_family_key(family))
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+        Ok(())
+    }
+
+    async fn revoke_user_families(&mut self, email: &Email) -> Result<(), RefreshTokenStoreError> {
+        let user_families_key = get_user_families_key(email);
+        let mut connection = self.conn.write().await;
+        let families: Vec<String> = connection
+            .smembers(&user_families_key)
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        let mut keys: Vec<String> = families.iter().map(|family| format!("{}{}", REFRESH_TOKEN_FAMILY_PREFIX, family)).collect();
+        keys.push(user_families_key);
+        connection
+            .del::<_, ()>(keys)
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+        Ok(())
+    }
+}
+
+// (email, family) the refresh token was issued for
+#[derive(Serialize, Deserialize)]
+struct RefreshTokenRecord(pub String, pub String);
+
+const REFRESH_TOKEN_PREFIX: &str = "refresh_token:";
+const REFRESH_TOKEN_FAMILY_PREFIX: &str = "refresh_token_family:";
+const USER_REFRESH_TOKEN_FAMILIES_PREFIX: &str = "refresh_token_user_families:";
+
+fn get_token_key(token: &RefreshToken) -> String {
+    format!("{}{}", REFRESH_TOKEN_PREFIX, token.as_ref())
+}
+
+fn get_family_key(family: &RefreshTokenFamily) -> String {
+    format!("{}{}", REFRESH_TOKEN_FAMILY_PREFIX, family.as_ref())
+}
+
+fn get_user_families_key(email: &Email) -> String {
+    format!("{}{}", USER_REFRESH_TOKEN_FAMILIES_PREFIX, email.as_ref())
+}
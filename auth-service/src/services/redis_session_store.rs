@@ -0,0 +1,193 @@
+use std::{cmp::Reverse, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use redis::{Commands, Connection, ExistenceCheck, SetExpiry, SetOptions};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::{domain::{data_store::{RefreshTokenFamily, SessionStore, SessionStoreError}, email::Email, session::Session}, utils::auth::REFRESH_TOKEN_TTL_SECONDS};
+
+#[derive(Clone)]
+pub struct RedisSessionStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisSessionStore {
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for RedisSessionStore {
+    async fn add_session(&mut self, session: Session) -> Result<(), SessionStoreError> {
+        let ttl = session_ttl()?;
+        let record = serde_json::to_string(&SessionRecord::from(&session)).map_err(|_| SessionStoreError::UnexpectedError)?;
+        let user_sessions_key = get_user_sessions_key(&session.email);
+
+        let mut connection = self.conn.write().await;
+        redis::pipe()
+            .atomic()
+            .set_ex(get_session_key(&session.id), record, ttl)
+            .ignore()
+            .sadd(&user_sessions_key, session.id.as_ref())
+            .ignore()
+            .expire(&user_sessions_key, ttl as i64)
+            .ignore()
+            .query::<()>(&mut *connection)
+            .map_err(|_| SessionStoreError::UnexpectedError)?;
+        Ok(())
+    }
+
+    async fn get_session(&self, id: &RefreshTokenFamily) -> Result<Session, SessionStoreError> {
+        let mut connection = self.conn.write().await;
+        let record: Option<String> = connection
+            .get(get_session_key(id))
+            .map_err(|_| SessionStoreError::UnexpectedError)?;
+
+        parse_record(id.clone(), &record.ok_or(SessionStoreError::SessionNotFound)?)
+    }
+
+    async fn get_user_sessions(&self, email: &Email) -> Result<Vec<Session>, SessionStoreError> {
+        let user_sessions_key = get_user_sessions_key(email);
+        let mut connection = self.conn.write().await;
+        let ids: Vec<String> = connection
+            .smembers(&user_sessions_key)
+            .map_err(|_| SessionStoreError::UnexpectedError)?;
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let keys: Vec<String> = ids.iter().map(|id| format!("{}{}", SESSION_PREFIX, id)).collect();
+        let records: Vec<Option<String>> = connection
+            .mget(keys)
+            .map_err(|_| SessionStoreError::UnexpectedError)?;
+
+        let mut sessions = Vec::with_capacity(ids.len());
+        for (id, record) in ids.into_iter().zip(records) {
+            match record {
+                Some(record) => {
+                    let id = RefreshTokenFamily::parse(id).map_err(|_| SessionStoreError::UnexpectedError)?;
+                    sessions.push(parse_record(id, &record)?);
+                }
+                // the session expired, forget about it
+                None => connection
+                    .srem::<_, _, ()>(&user_sessions_key, id)
+                    .map_err(|_| SessionStoreError::UnexpectedError)?,
+            }
+        }
+        sessions.sort_by_key(|session| Reverse(session.last_seen_at));
+        Ok(sessions)
+    }
+
+    async fn touch_session(&mut self, id: &RefreshTokenFamily, last_seen_at: DateTime<Utc>) -> Result<(), SessionStoreError> {
+        let mut session = self.get_session(id).await?;
+        session.last_seen_at = last_seen_at;
+        let record = serde_json::to_string(&SessionRecord::from(&session)).map_err(|_| SessionStoreError::UnexpectedError)?;
+
+        // Only overwrite a session that still exists, so one revoked in the meantime stays revoked.
+        // Using the session extends it along with its refresh token family
+        let options = SetOptions::default()
+            .conditional_set(ExistenceCheck::XX)
+            .with_expiration(SetExpiry::EX(session_ttl()? as usize));
+        let mut connection = self.conn.write().await;
+        let updated: Option<String> = connection
+            .set_options(get_session_key(id), record, options)
+            .map_err(|_| SessionStoreError::UnexpectedError)?;
+        if updated.is_none() {
+            return Err(SessionStoreError::SessionNotFound);
+        }
+        connection
+            .expire::<_, ()>(get_user_sessions_key(&session.email), session_ttl()? as i64)
+            .map_err(|_| SessionStoreError::UnexpectedError)
+    }
+
+    async fn remove_session(&mut self, id: &RefreshTokenFamily) -> Result<(), SessionStoreError> {
+        let session = self.get_session(id).await?;
+        let mut connection = self.conn.write().await;
+        redis::pipe()
+            .atomic()
+            .del(get_session_key(id))
+            .ignore()
+            .srem(get_user_sessions_key(&session.email), id.as_ref())
+            .ignore()
+            .query::<()>(&mut *connection)
+            .map_err(|_| SessionStoreError::UnexpectedError)
+    }
+
+    async fn remove_user_sessions(&mut self, email: &Email) -> Result<(), SessionStoreError> {
+        let user_sessions_key = get_user_sessions_key(email);
+        let mut connection = self.conn.write().await;
+        let ids: Vec<String> = connection
+            .smembers(&user_sessions_key)
+            .map_err(|_| SessionStoreError::UnexpectedError)?;
+
+        let mut keys: Vec<String> = ids.iter().map(|id| format!("{}{}", SESSION_PREFIX, id)).collect();
+        keys.push(user_sessions_key);
+        connection
+            .del::<_, ()>(keys)
+            .map_err(|_| SessionStoreError::UnexpectedError)
+    }
+}
+
+// Timestamps are stored as milliseconds since the epoch
+#[derive(Serialize, Deserialize)]
+struct SessionRecord {
+    email: String,
+    device: String,
+    ip: String,
+    created_at: i64,
+    last_seen_at: i64,
+    #[serde(default)]
+    client_id: Option<String>,
+    #[serde(default)]
+    scopes: Vec<String>,
+    #[serde(default)]
+    amr: Vec<String>,
+}
+
+impl From<&Session> for SessionRecord {
+    fn from(session: &Session) -> Self {
+        Self {
+            email: session.email.as_ref().to_owned(),
+            device: session.device.clone(),
+            ip: session.ip.clone(),
+            created_at: session.created_at.timestamp_millis(),
+            last_seen_at: session.last_seen_at.timestamp_millis(),
+            client_id: session.client_id.clone(),
+            scopes: session.scopes.clone(),
+            amr: session.amr.clone(),
+        }
+    }
+}
+
+fn parse_record(id: RefreshTokenFamily, record: &str) -> Result<Session, SessionStoreError> {
+    let record: SessionRecord = serde_json::from_str(record).map_err(|_| SessionStoreError::UnexpectedError)?;
+    let timestamp = |millis| DateTime::from_timestamp_millis(millis).ok_or(SessionStoreError::UnexpectedError);
+    Ok(Session {
+        id,
+        email: Email::parse(record.email).map_err(|_| SessionStoreError::UnexpectedError)?,
+        device: record.device,
+        ip: record.ip,
+        created_at: timestamp(record.created_at)?,
+        last_seen_at: timestamp(record.last_seen_at)?,
+        client_id: record.client_id,
+        scopes: record.scopes,
+        amr: record.amr,
+    })
+}
+
+fn session_ttl() -> Result<u64, SessionStoreError> {
+    REFRESH_TOKEN_TTL_SECONDS.try_into().map_err(|_| SessionStoreError::UnexpectedError)
+}
+
+const SESSION_PREFIX: &str = "session:";
+const USER_SESSIONS_PREFIX: &str = "user_sessions:";
+
+fn get_session_key(id: &RefreshTokenFamily) -> String {
+    format!("{}{}", SESSION_PREFIX, id.as_ref())
+}
+
+fn get_user_sessions_key(email: &Email) -> String {
+    format!("{}{}", USER_SESSIONS_PREFIX, email.as_ref())
+}
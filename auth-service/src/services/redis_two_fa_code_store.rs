@@ -1,10 +1,71 @@
 use std::sync::Arc;
 
-use redis::{Commands, Connection};
+use redis::{Commands, Connection, Script};
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
-use crate::domain::{data_store::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError}, email::Email};
+use crate::{
+    domain::{data_store::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError}, email::Email},
+    utils::lockout::{MAX_TWO_FA_ATTEMPTS, MAX_TWO_FA_RESENDS, TWO_FA_RESEND_COOLDOWN_SECONDS},
+};
+
+// Checks a code against the pending login attempt and consumes the attempt if it is valid, or counts
+// the failure, in one step so concurrent requests can't use the same code twice or go past the limit.
+// KEYS: the login attempt, its failure count
+// ARGV: login attempt id, failures allowed, TTL in seconds,
+//       the code to compare with the stored one ("" if it was checked elsewhere), "1" if a code checked elsewhere was valid
+const SETTLE_ATTEMPT_SCRIPT: &str = r#"
+local stored = redis.call('GET', KEYS[1])
+if not stored then
+    return 'not_found'
+end
+local attempt = cjson.decode(stored)
+if attempt[1] ~= ARGV[1] then
+    return 'not_found'
+end
+
+if ARGV[5] == '1' or (ARGV[4] ~= '' and attempt[2] == ARGV[4]) then
+    redis.call('DEL', KEYS[1], KEYS[2])
+    return 'valid'
+end
+
+local failures = redis.call('INCR', KEYS[2])
+redis.call('EXPIRE', KEYS[2], ARGV[3])
+if failures >= tonumber(ARGV[2]) then
+    redis.call('DEL', KEYS[1], KEYS[2])
+end
+return 'incorrect'
+"#;
+
+// Stores a new code for the pending login attempt unless it was issued too recently or resent too often.
+// The attempt gets a fresh TTL so the new code lasts as long as the first one did.
+// KEYS: the login attempt, its resend count, its resend cooldown
+// ARGV: login attempt id, new code, resends allowed, cooldown in seconds, TTL in seconds
+// Returns the outcome and, during the cooldown, the seconds left
+const REPLACE_CODE_SCRIPT: &str = r#"
+local stored = redis.call('GET', KEYS[1])
+if not stored then
+    return {'not_found', 0}
+end
+local attempt = cjson.decode(stored)
+if attempt[1] ~= ARGV[1] then
+    return {'not_found', 0}
+end
+
+local cooldown = redis.call('TTL', KEYS[3])
+if cooldown > 0 then
+    return {'cooldown', cooldown}
+end
+if tonumber(redis.call('GET', KEYS[2]) or '0') >= tonumber(ARGV[3]) then
+    return {'too_many', 0}
+end
+
+redis.call('SET', KEYS[1], cjson.encode({ARGV[1], ARGV[2]}), 'EX', ARGV[5])
+redis.call('INCR', KEYS[2])
+redis.call('EXPIRE', KEYS[2], ARGV[5])
+redis.call('SET', KEYS[3], 1, 'EX', ARGV[4])
+return {'replaced', 0}
+"#;
 
 #[derive(Clone)]
 pub struct RedisTwoFACodeStore {
@@ -37,8 +98,18 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
         // The value should be the serialized 2FA tuple.
         // The expiration time should be set to TEN_MINUTES_IN_SECONDS.
         // Return TwoFACodeStoreError::UnexpectedError if casting fails or the call to set_ex fails.
+        // A new login attempt starts with a clean slate of failures and resends,
+        // and its code can't be resent right away
         let mut connection = self.conn.write().await;
-        connection.set_ex(key, serialized_2fa, TEN_MINUTES_IN_SECONDS)
+        redis::pipe()
+            .atomic()
+            .set_ex(key, serialized_2fa, TEN_MINUTES_IN_SECONDS)
+            .ignore()
+            .del(&[get_failures_key(&email), get_resends_key(&email)])
+            .ignore()
+            .set_ex(get_resend_cooldown_key(&email), true, TWO_FA_RESEND_COOLDOWN_SECONDS.unsigned_abs())
+            .ignore()
+            .query::<()>(&mut *connection)
             .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
         Ok(()) 
     }
@@ -49,7 +120,9 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
         // 2. Call the del command on the Redis connection to delete the 2FA code entry. 
         // Return TwoFACodeStoreError::UnexpectedError if the operation fails.
         let mut connection = self.conn.write().await;
-        connection.del(key).map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+        connection
+            .del::<_, ()>(&[key, get_failures_key(email), get_resends_key(email), get_resend_cooldown_key(email)])
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
         Ok(())
     }
 
@@ -79,6 +152,79 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
         } 
 
     }
+
+    async fn verify_code(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        code: &TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        self.settle(email, login_attempt_id, code.as_ref(), false).await
+    }
+
+    async fn settle_attempt(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        is_valid: bool,
+    ) -> Result<(), TwoFACodeStoreError> {
+        self.settle(email, login_attempt_id, "", is_valid).await
+    }
+
+    async fn replace_code(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        code: TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let mut connection = self.conn.write().await;
+        let (outcome, cooldown): (String, u64) = Script::new(REPLACE_CODE_SCRIPT)
+            .key(get_key(email))
+            .key(get_resends_key(email))
+            .key(get_resend_cooldown_key(email))
+            .arg(login_attempt_id.as_ref())
+            .arg(code.as_ref())
+            .arg(MAX_TWO_FA_RESENDS)
+            .arg(TWO_FA_RESEND_COOLDOWN_SECONDS)
+            .arg(TEN_MINUTES_IN_SECONDS)
+            .invoke(&mut *connection)
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+        match outcome.as_str() {
+            "replaced" => Ok(()),
+            "cooldown" => Err(TwoFACodeStoreError::ResendCooldown(cooldown)),
+            "too_many" => Err(TwoFACodeStoreError::TooManyResends),
+            "not_found" => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+            _ => Err(TwoFACodeStoreError::UnexpectedError),
+        }
+    }
+}
+
+impl RedisTwoFACodeStore {
+    async fn settle(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        code: &str,
+        is_valid: bool,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let mut connection = self.conn.write().await;
+        let outcome: String = Script::new(SETTLE_ATTEMPT_SCRIPT)
+            .key(get_key(email))
+            .key(get_failures_key(email))
+            .arg(login_attempt_id.as_ref())
+            .arg(MAX_TWO_FA_ATTEMPTS)
+            .arg(TEN_MINUTES_IN_SECONDS)
+            .arg(code)
+            .arg(if is_valid { "1" } else { "0" })
+            .invoke(&mut *connection)
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+        match outcome.as_str() {
+            "valid" => Ok(()),
+            "incorrect" => Err(TwoFACodeStoreError::IncorrectCode),
+            "not_found" => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+            _ => Err(TwoFACodeStoreError::UnexpectedError),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -86,7 +232,22 @@ struct TwoFATuple(pub String, pub String);
 
 const TEN_MINUTES_IN_SECONDS: u64 = 600;
 const TWO_FA_CODE_PREFIX: &str = "two_fa_code:";
+const TWO_FA_FAILURES_PREFIX: &str = "two_fa_failures:";
+const TWO_FA_RESENDS_PREFIX: &str = "two_fa_resends:";
+const TWO_FA_RESEND_COOLDOWN_PREFIX: &str = "two_fa_resend_cooldown:";
 
 fn get_key(email: &Email) -> String {
     format!("{}{}", TWO_FA_CODE_PREFIX, email.as_ref())
 }
+
+fn get_failures_key(email: &Email) -> String {
+    format!("{}{}", TWO_FA_FAILURES_PREFIX, email.as_ref())
+}
+
+fn get_resends_key(email: &Email) -> String {
+    format!("{}{}", TWO_FA_RESENDS_PREFIX, email.as_ref())
+}
+
+fn get_resend_cooldown_key(email: &Email) -> String {
+    format!("{}{}", TWO_FA_RESEND_COOLDOWN_PREFIX, email.as_ref())
+}
@@ -0,0 +1,65 @@
+use lettre::{message::{Mailbox, MultiPart}, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use crate::domain::{email::Email, email_client::EmailClient, email_message::EmailMessage};
+
+// Sends emails through an SMTP relay. Locally this can point at a sink like MailHog
+#[derive(Clone)]
+pub struct SmtpEmailClient {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+    sender: Mailbox,
+}
+
+impl SmtpEmailClient {
+    pub fn new(mailer: AsyncSmtpTransport<Tokio1Executor>, sender: Mailbox) -> Self {
+        Self { mailer, sender }
+    }
+
+    fn build_message(&self, recipient: &Email, message: &EmailMessage) -> Result<Message, String> {
+        let recipient: Mailbox = recipient.as_ref().parse().map_err(|e: lettre::address::AddressError| e.to_string())?;
+        Message::builder()
+            .from(self.sender.clone())
+            .to(recipient)
+            .subject(&message.subject)
+            .multipart(MultiPart::alternative_plain_html(
+                message.text_body.clone(),
+                message.html_body.clone(),
+            ))
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailClient for SmtpEmailClient {
+    async fn send_email(&self, recipient: &Email, message: &EmailMessage) -> Result<(), String> {
+        let email = self.build_message(recipient, message)?;
+        self.mailer.send(email).await.map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_build_message() {
+        let client = SmtpEmailClient::new(
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous("localhost").build(),
+            "Auth Service <no-reply@example.com>".parse().unwrap(),
+        );
+        let recipient = Email::parse("user@example.com".to_owned()).unwrap();
+        let message = EmailMessage {
+            subject: "Hello".to_owned(),
+            html_body: "<p>Hi there</p>".to_owned(),
+            text_body: "Hi there".to_owned(),
+        };
+
+        let formatted = String::from_utf8(client.build_message(&recipient, &message).unwrap().formatted()).unwrap();
+        assert!(formatted.contains("From: \"Auth Service\" <no-reply@example.com>"));
+        assert!(formatted.contains("To: user@example.com"));
+        assert!(formatted.contains("Subject: Hello"));
+        assert!(formatted.contains("multipart/alternative"));
+        assert!(formatted.contains("Content-Type: text/plain"));
+        assert!(formatted.contains("Content-Type: text/html"));
+    }
+}
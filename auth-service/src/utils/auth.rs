@@ -1,15 +1,18 @@
+use std::sync::PoisonError;
+
 use axum_extra::extract::cookie::{Cookie, SameSite};
 use chrono::Utc;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Validation};
+use jsonwebtoken::{decode, decode_header, encode, errors::ErrorKind, Validation};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use crate::{domain::email::Email, utils::constants::{JWT_COOKIE_NAME, JWT_SECRET}};
+use crate::{domain::{data_store::RefreshToken, oauth::{ServiceAccount, UserInfo}, session::Session}, utils::{constants::{JWT_AUDIENCES, JWT_COOKIE_NAME, JWT_ISSUER, JWT_KEYRING, JWT_LEEWAY_SECONDS, REFRESH_TOKEN_COOKIE_NAME}, jwt_key::JwtKey}};
 
 
 
 // Create cookie with a new JWT auth token 
-pub fn generate_auth_cookie(email: Email) -> Result<Cookie<'static>, GenerateTokenError> {
-    let token = generate_auth_token(&email)?;
+pub fn generate_auth_cookie(session: &Session, epoch: u32) -> Result<Cookie<'static>, GenerateTokenError> {
+    let token = generate_auth_token(session, epoch)?;
     Ok(create_auth_cookie(token))
 }
 
@@ -24,6 +27,16 @@ pub fn create_auth_cookie(token: String) -> Cookie<'static> {
     cookie
 }
 
+// Create cookie holding an opaque refresh token
+pub fn create_refresh_cookie(token: &RefreshToken) -> Cookie<'static> {
+    Cookie::build((REFRESH_TOKEN_COOKIE_NAME, token.as_ref().to_owned()))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(REFRESH_TOKEN_TTL_SECONDS))
+        .build()
+}
+
 #[derive(Debug)]
 pub enum GenerateTokenError {
     TokenError(jsonwebtoken::errors::Error),
@@ -33,12 +46,49 @@ pub enum GenerateTokenError {
 // Ths value determines how long the JWT auth token is valid for
 pub const TOKEN_TTL_SECDONDS: i64 = 600;
 
-// Create JWT auth token
-fn generate_auth_token(email: &Email) -> Result<String, GenerateTokenError> {
+// Refresh tokens let the user get new JWTs without logging in again for 14 days
+pub const REFRESH_TOKEN_TTL_SECONDS: i64 = 14 * 24 * 60 * 60;
+
+// OAuth clients have a minute to exchange an authorization code for tokens
+pub const AUTHORIZATION_CODE_TTL_SECONDS: i64 = 60;
+
+// Users have 10 minutes to approve a device, which may poll for tokens every 5 seconds
+pub const DEVICE_CODE_TTL_SECONDS: i64 = 600;
+pub const DEVICE_POLL_INTERVAL_SECONDS: i64 = 5;
+
+// Create JWT auth token for a session, in the user's current token epoch
+pub fn generate_auth_token(session: &Session, epoch: u32) -> Result<String, GenerateTokenError> {
+    let claims = Claims {
+        epoch,
+        sid: Some(session.id.as_ref().to_owned()),
+        scope: (!session.scopes.is_empty()).then(|| session.scopes.join(" ")),
+        client_id: session.client_id.clone(),
+        ..new_claims(session.email.as_ref().to_owned(), PrincipalType::User)?
+    };
+
+    create_token(&claims)
+}
+
+// Create JWT access token for a service account, restricted to the scopes it was granted.
+// Service accounts have no sessions or token epochs, their tokens simply run out
+pub fn generate_service_token(account: &ServiceAccount, scopes: &[String]) -> Result<String, GenerateTokenError> {
+    let claims = Claims {
+        scope: (!scopes.is_empty()).then(|| scopes.join(" ")),
+        client_id: Some(account.id.clone()),
+        ..new_claims(account.id.clone(), PrincipalType::ServiceAccount)?
+    };
+
+    create_token(&claims)
+}
+
+// Claims valid from now for `TOKEN_TTL_SECDONDS`, in no session and unrestricted by scopes
+fn new_claims(sub: String, principal_type: PrincipalType) -> Result<Claims, GenerateTokenError> {
     let delta = chrono::Duration::try_seconds(TOKEN_TTL_SECDONDS).ok_or(GenerateTokenError::UnexpectedError)?;
 
+    let now = Utc::now();
+
     // Create JWT expiraton time
-    let exp = Utc::now()
+    let exp = now
         .checked_add_signed(delta)
         .ok_or(GenerateTokenError::UnexpectedError)?
         .timestamp();
@@ -46,50 +96,167 @@ fn generate_auth_token(email: &Email) -> Result<String, GenerateTokenError> {
     // Cast exp to a usize, which is what Claims expects
     let exp: usize = exp.try_into().map_err(|_| GenerateTokenError::UnexpectedError)?;
 
-    // Convert Email struct to String
-    let sub = email.as_ref().to_owned();
+    // Record when the token was issued so it can be revoked later
+    let iat: usize = now.timestamp().try_into().map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    Ok(Claims {
+        sub,
+        principal_type,
+        exp,
+        iat,
+        // the token is valid as soon as it is issued
+        nbf: iat,
+        iss: JWT_ISSUER.clone(),
+        aud: JWT_AUDIENCES.clone(),
+        jti: Uuid::new_v4().to_string(),
+        epoch: 0,
+        sid: None,
+        scope: None,
+        client_id: None,
+    })
+}
+
 
-    let claims = Claims {sub, exp};
+// Create an OpenID Connect ID token telling the OAuth client the session was started for who the user is.
+// It is only meant for the client, so unlike access tokens its audience is the client alone. Fails when
+// the keyring has no asymmetric key to sign it with
+pub fn generate_id_token(session: &Session, user_info: &UserInfo, nonce: Option<String>) -> Result<String, GenerateTokenError> {
+    let claims = id_token_claims(session, user_info, nonce)?;
+    let keyring = JWT_KEYRING.read().unwrap_or_else(PoisonError::into_inner);
+    let key = keyring.id_token_signing_key(Utc::now()).ok_or(GenerateTokenError::UnexpectedError)?;
 
-    create_token(&claims).map_err(GenerateTokenError::TokenError)
+    encode(&key.header(), &claims, key.encoding_key()).map_err(GenerateTokenError::TokenError)
 }
 
+fn id_token_claims(session: &Session, user_info: &UserInfo, nonce: Option<String>) -> Result<IdTokenClaims, GenerateTokenError> {
+    let delta = chrono::Duration::try_seconds(TOKEN_TTL_SECDONDS).ok_or(GenerateTokenError::UnexpectedError)?;
+    let now = Utc::now();
+    let exp = (now + delta).timestamp().try_into().map_err(|_| GenerateTokenError::UnexpectedError)?;
+    let iat = now.timestamp().try_into().map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    Ok(IdTokenClaims {
+        iss: JWT_ISSUER.clone(),
+        sub: user_info.sub.clone(),
+        aud: session.client_id.clone().ok_or(GenerateTokenError::UnexpectedError)?,
+        exp,
+        iat,
+        sid: session.id.as_ref().to_owned(),
+        nonce,
+        amr: session.amr.clone(),
+        email: user_info.email.clone(),
+        email_verified: user_info.email_verified,
+    })
+}
 
-// Check if JWT auth token is valid by decoding it using the JWT secret
+// Check if JWT auth token is valid by decoding it using the key named by its kid
 pub async fn validate_token(token: &str) -> Result<Claims, jsonwebtoken::errors::Error>{
+    let kid = decode_header(token)?.kid;
+    // the keyring is only ever replaced whole, so a poisoned lock still holds a usable one
+    let keyring = JWT_KEYRING.read().unwrap_or_else(PoisonError::into_inner);
+    let key = keyring.verification_key(kid.as_deref(), Utc::now()).ok_or(ErrorKind::InvalidSignature)?;
+
     decode::<Claims>(
         token,
-        &DecodingKey::from_secret(JWT_SECRET.as_bytes()),
-        &Validation::default()
+        key.decoding_key(),
+        &validation(key)
     )
     .map(|data| data.claims)
 }
 
-// Create JWT auth token by encoding claims using the JWT secret
+// Only accept tokens we issued for one of our audiences, that are already valid and not expired yet
+fn validation(key: &JwtKey) -> Validation {
+    let mut validation = key.validation();
+    validation.set_issuer(&[JWT_ISSUER.as_str()]);
+    validation.set_audience(&JWT_AUDIENCES);
+    validation.set_required_spec_claims(&["sub", "exp", "nbf", "iss", "aud"]);
+    validation.validate_nbf = true;
+    validation.leeway = *JWT_LEEWAY_SECONDS;
+    validation
+}
+
+// Create JWT auth token by encoding claims using the keyring's active key, whose kid goes in the header
+
+fn create_token<T: Serialize>(claims: &T) -> Result<String, GenerateTokenError> {
+    let keyring = JWT_KEYRING.read().unwrap_or_else(PoisonError::into_inner);
+    let key = keyring.signing_key(Utc::now()).ok_or(GenerateTokenError::UnexpectedError)?;
 
-fn create_token(claims: &Claims) -> Result<String, jsonwebtoken::errors::Error> {
     encode(
-        &jsonwebtoken::Header::default(),
-        &claims,
-        &EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+        &key.header(),
+        claims,
+        key.encoding_key(),
     )
+    .map_err(GenerateTokenError::TokenError)
+}
+
+// Who a token was issued to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrincipalType {
+    // `sub` is the user's email. Tokens from before the claim existed were all issued to users
+    #[default]
+    User,
+    // `sub` is the id of a service account, see `ServiceAccount`
+    ServiceAccount,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
-    pub exp: usize
+    #[serde(default)]
+    pub principal_type: PrincipalType,
+    pub exp: usize,
+    pub iat: usize,
+    pub nbf: usize,
+    pub iss: String,
+    // the services the token was minted for
+    pub aud: Vec<String>,
+    // unique per token
+    pub jti: String,
+    // the user's token epoch when the token was issued, 0 for service accounts
+    pub epoch: u32,
+    // the session the token was issued in, see `Session`. Service accounts have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    // the scopes granted to the OAuth client the token was issued to, space separated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
 }
 
+// Claims of an ID token (OpenID Connect Core section 2). The email claims are only there
+// when the client was granted the `email` scope
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    // the client the token was issued to
+    pub aud: String,
+    pub exp: usize,
+    pub iat: usize,
+    pub sid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    pub amr: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email_verified: Option<bool>,
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::{data_store::RefreshTokenFamily, email::Email};
+
+    fn session() -> Session {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        Session::new(RefreshTokenFamily::default(), email, "Firefox on Linux".to_owned(), "127.0.0.1".to_owned())
+    }
 
     #[tokio::test]
     async fn test_generate_auth_cookie() {
-        let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let cookie = generate_auth_cookie(email).unwrap();
+        let cookie = generate_auth_cookie(&session(), 0).unwrap();
         assert_eq!(cookie.name(), JWT_COOKIE_NAME);
         assert_eq!(cookie.value().split('.').count(), 3);
         assert_eq!(cookie.path(), Some("/"));
@@ -108,17 +275,25 @@ mod tests {
         assert_eq!(cookie.same_site(), Some(SameSite::Lax));
     }
 
+    #[tokio::test]
+    async fn test_create_refresh_cookie() {
+        let token = RefreshToken::default();
+        let cookie = create_refresh_cookie(&token);
+        assert_eq!(cookie.name(), REFRESH_TOKEN_COOKIE_NAME);
+        assert_eq!(cookie.value(), token.as_ref());
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.max_age(), Some(time::Duration::seconds(REFRESH_TOKEN_TTL_SECONDS)));
+    }
+
     #[tokio::test]
     async fn test_generate_auth_token() {
-        let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let result = generate_auth_token(&email).unwrap();
+        let result = generate_auth_token(&session(), 0).unwrap();
         assert_eq!(result.split('.').count(), 3);
     }
 
     #[tokio::test]
     async fn test_validate_token_with_valid_token() {
-        let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let token = generate_auth_token(&email).unwrap();
+        let token = generate_auth_token(&session(), 0).unwrap();
         let result = validate_token(&token).await.unwrap();
         assert_eq!(result.sub, "test@example.com");
 
@@ -130,6 +305,109 @@ mod tests {
         assert!(result.exp > exp as usize);
     }
 
+    #[tokio::test]
+    async fn test_generate_auth_token_sets_standard_claims() {
+        let session = session();
+        let first = validate_token(&generate_auth_token(&session, 0).unwrap()).await.unwrap();
+        let second = validate_token(&generate_auth_token(&session, 0).unwrap()).await.unwrap();
+        assert_eq!(first.iss, *JWT_ISSUER);
+        assert_eq!(first.aud, *JWT_AUDIENCES);
+        assert_eq!(first.nbf, first.iat);
+        assert_eq!(first.sid.as_deref(), Some(session.id.as_ref()));
+        assert_eq!(first.principal_type, PrincipalType::User);
+        assert_ne!(first.jti, second.jti);
+        // first-party sessions aren't restricted to scopes
+        assert_eq!(first.scope, None);
+        assert_eq!(first.client_id, None);
+    }
+
+    #[tokio::test]
+    async fn test_generate_id_token() {
+        let session = Session {
+            client_id: Some("dashboard".to_owned()),
+            amr: vec!["pwd".to_owned()],
+            ..session()
+        };
+        let user_info = UserInfo { sub: "8c1f0e3e-8d4b-4a0e-9f4a-3a3e1b0d2c7f".to_owned(), email: None, email_verified: None };
+        let claims = id_token_claims(&session, &user_info, Some("n-0S6_WzA2Mj".to_owned())).unwrap();
+        assert_eq!(claims.sub, "8c1f0e3e-8d4b-4a0e-9f4a-3a3e1b0d2c7f");
+        assert_eq!(claims.aud, "dashboard");
+        assert_eq!(claims.iss, *JWT_ISSUER);
+        assert_eq!(claims.nonce.as_deref(), Some("n-0S6_WzA2Mj"));
+        assert_eq!(claims.amr, ["pwd"]);
+        assert_eq!(claims.email, None);
+
+        // only sessions started for a client get one
+        assert!(id_token_claims(&Session { client_id: None, ..session.clone() }, &user_info, None).is_err());
+        // and the tests sign with HS256, which ID tokens are never signed with
+        assert!(generate_id_token(&session, &user_info, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_generate_auth_token_for_oauth_clients() {
+        let session = Session {
+            client_id: Some("dashboard".to_owned()),
+            scopes: vec!["read".to_owned(), "write".to_owned()],
+            ..session()
+        };
+        let claims = validate_token(&generate_auth_token(&session, 0).unwrap()).await.unwrap();
+        assert_eq!(claims.scope.as_deref(), Some("read write"));
+        assert_eq!(claims.client_id.as_deref(), Some("dashboard"));
+    }
+
+    #[tokio::test]
+    async fn test_generate_service_token() {
+        let account = ServiceAccount::new("nightly-export".to_owned(), "Nightly export".to_owned(), vec!["read".to_owned()]).unwrap();
+        let claims = validate_token(&generate_service_token(&account, &account.scopes).unwrap()).await.unwrap();
+        assert_eq!(claims.sub, "nightly-export");
+        assert_eq!(claims.principal_type, PrincipalType::ServiceAccount);
+        assert_eq!(claims.scope.as_deref(), Some("read"));
+        assert_eq!(claims.client_id.as_deref(), Some("nightly-export"));
+        assert_eq!(claims.sid, None);
+    }
+
+    fn claims(email: &str) -> Claims {
+        let now = Utc::now().timestamp() as usize;
+        Claims {
+            sub: email.to_owned(),
+            principal_type: PrincipalType::User,
+            exp: now + 600,
+            iat: now,
+            nbf: now,
+            iss: JWT_ISSUER.clone(),
+            aud: JWT_AUDIENCES.clone(),
+            jti: Uuid::new_v4().to_string(),
+            epoch: 0,
+            sid: Some(RefreshTokenFamily::default().as_ref().to_owned()),
+            scope: None,
+            client_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_rejects_other_audiences_and_issuers() {
+        let token = create_token(&Claims { aud: vec!["billing-service".to_owned()], ..claims("test@example.com") }).unwrap();
+        assert!(validate_token(&token).await.is_err());
+
+        let token = create_token(&Claims { iss: "https://evil.example.com".to_owned(), ..claims("test@example.com") }).unwrap();
+        assert!(validate_token(&token).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_allows_clock_skew_within_leeway() {
+        let leeway = *JWT_LEEWAY_SECONDS as usize;
+        let now = Utc::now().timestamp() as usize;
+
+        let token = create_token(&Claims { nbf: now + leeway / 2, ..claims("test@example.com") }).unwrap();
+        assert!(validate_token(&token).await.is_ok());
+
+        let token = create_token(&Claims { nbf: now + leeway + 60, ..claims("test@example.com") }).unwrap();
+        assert!(validate_token(&token).await.is_err());
+
+        let token = create_token(&Claims { exp: now - leeway - 60, ..claims("test@example.com") }).unwrap();
+        assert!(validate_token(&token).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_validate_token_with_invalid_token() {
         let token = "invalid_token".to_owned();
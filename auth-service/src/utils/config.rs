@@ -0,0 +1,108 @@
+// Backends the binary can be wired up with at startup, so the same build can run
+// fully in memory (demos, CI) or against Postgres and Redis (production)
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UserStoreBackend {
+    Memory,
+    Postgres,
+}
+
+impl UserStoreBackend {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "memory" => Ok(Self::Memory),
+            "postgres" => Ok(Self::Postgres),
+            other => Err(format!("Unknown user store backend '{}', expected 'memory' or 'postgres'", other)),
+        }
+    }
+}
+
+// Used for the banned token, 2FA code and other short lived token stores
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenStoreBackend {
+    Memory,
+    Redis,
+}
+
+impl TokenStoreBackend {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "memory" => Ok(Self::Memory),
+            "redis" => Ok(Self::Redis),
+            other => Err(format!("Unknown token store backend '{}', expected 'memory' or 'redis'", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmailClientBackend {
+    // Logs emails instead of sending them
+    Mock,
+    Smtp,
+}
+
+impl EmailClientBackend {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "mock" => Ok(Self::Mock),
+            "smtp" => Ok(Self::Smtp),
+            other => Err(format!("Unknown email client '{}', expected 'mock' or 'smtp'", other)),
+        }
+    }
+}
+
+// How to secure the connection to the SMTP server
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmtpTls {
+    // Plain text, only meant for local sinks like MailHog
+    None,
+    // Upgrade a plain connection with STARTTLS (usually port 587)
+    StartTls,
+    // TLS from the start (usually port 465)
+    Tls,
+}
+
+impl SmtpTls {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "starttls" => Ok(Self::StartTls),
+            "tls" => Ok(Self::Tls),
+            other => Err(format!("Unknown SMTP TLS mode '{}', expected 'none', 'starttls' or 'tls'", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_user_store_backend() {
+        assert_eq!(UserStoreBackend::parse("memory"), Ok(UserStoreBackend::Memory));
+        assert_eq!(UserStoreBackend::parse(" Postgres "), Ok(UserStoreBackend::Postgres));
+        assert!(UserStoreBackend::parse("redis").is_err());
+    }
+
+    #[test]
+    fn test_parse_token_store_backend() {
+        assert_eq!(TokenStoreBackend::parse("MEMORY"), Ok(TokenStoreBackend::Memory));
+        assert_eq!(TokenStoreBackend::parse("redis"), Ok(TokenStoreBackend::Redis));
+        assert!(TokenStoreBackend::parse("postgres").is_err());
+    }
+
+    #[test]
+    fn test_parse_email_client_backend() {
+        assert_eq!(EmailClientBackend::parse("mock"), Ok(EmailClientBackend::Mock));
+        assert_eq!(EmailClientBackend::parse("SMTP"), Ok(EmailClientBackend::Smtp));
+        assert!(EmailClientBackend::parse("").is_err());
+    }
+
+    #[test]
+    fn test_parse_smtp_tls() {
+        assert_eq!(SmtpTls::parse("none"), Ok(SmtpTls::None));
+        assert_eq!(SmtpTls::parse("STARTTLS"), Ok(SmtpTls::StartTls));
+        assert_eq!(SmtpTls::parse("tls"), Ok(SmtpTls::Tls));
+        assert!(SmtpTls::parse("ssl").is_err());
+    }
+}
@@ -1,12 +1,45 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
 use dotenvy::dotenv;
 use lazy_static::lazy_static;
-use std::env as std_env;
+use jsonwebtoken::Algorithm;
+use std::{env as std_env, fs, path::Path, str::FromStr, sync::RwLock};
+
+use crate::{
+    domain::rate_limit::RateLimits,
+    utils::{
+        config::{EmailClientBackend, SmtpTls, TokenStoreBackend, UserStoreBackend},
+        jwt_key::JwtKey,
+        jwt_keyring::JwtKeyring,
+    },
+};
 
 // Define a lazily evaluated static. lazy_static is needed because std_env::var is not a const function.
 lazy_static! {
     pub static ref JWT_SECRET: String = set_token();
+    pub static ref JWT_ISSUER: String = set_jwt_issuer();
+    pub static ref JWT_AUDIENCES: Vec<String> = set_jwt_audiences();
+    pub static ref JWT_LEEWAY_SECONDS: u64 = set_jwt_leeway_seconds();
+    pub static ref JWT_KEYRING_FILE: Option<String> = optional_env(env::JWT_KEYRING_FILE_ENV_VAR);
+    // Swapped out when the keyring file is reloaded
+    pub static ref JWT_KEYRING: RwLock<JwtKeyring> = RwLock::new(set_jwt_keyring());
     pub static ref DATABASE_URL: String = get_database_url();
     pub static ref REDIS_HOST_NAME: String = set_redis_host();
+    pub static ref AUTH_SERVICE_URL: String = set_auth_service_url();
+    pub static ref ALLOW_UNVERIFIED_LOGIN: bool = set_allow_unverified_login();
+    pub static ref TOTP_ENCRYPTION_KEY: [u8; 32] = set_totp_encryption_key();
+    pub static ref USER_STORE_BACKEND: UserStoreBackend = set_user_store_backend();
+    pub static ref BANNED_TOKEN_STORE_BACKEND: TokenStoreBackend = set_token_store_backend(env::BANNED_TOKEN_STORE_ENV_VAR);
+    pub static ref TWO_FA_CODE_STORE_BACKEND: TokenStoreBackend = set_token_store_backend(env::TWO_FA_CODE_STORE_ENV_VAR);
+    pub static ref TOKEN_STORE_BACKEND: TokenStoreBackend = set_token_store_backend(env::TOKEN_STORE_ENV_VAR);
+    pub static ref RATE_LIMIT_STORE_BACKEND: TokenStoreBackend = set_token_store_backend(env::RATE_LIMIT_STORE_ENV_VAR);
+    pub static ref RATE_LIMITS: RateLimits = set_rate_limits();
+    pub static ref EMAIL_CLIENT_BACKEND: EmailClientBackend = set_email_client_backend();
+    pub static ref SMTP_HOST: String = set_smtp_host();
+    pub static ref SMTP_PORT: Option<u16> = set_smtp_port();
+    pub static ref SMTP_TLS: SmtpTls = set_smtp_tls();
+    pub static ref SMTP_USERNAME: Option<String> = optional_env(env::SMTP_USERNAME_ENV_VAR);
+    pub static ref SMTP_PASSWORD: Option<String> = optional_env(env::SMTP_PASSWORD_ENV_VAR);
+    pub static ref EMAIL_SENDER: String = set_email_sender();
 }
 
 fn set_token() -> String {
@@ -19,6 +52,62 @@ fn set_token() -> String {
     secret
 }
 
+// Keys listed in JWT_KEYRING_FILE if set, otherwise the single key below
+fn set_jwt_keyring() -> JwtKeyring {
+    match JWT_KEYRING_FILE.as_ref() {
+        Some(path) => JwtKeyring::load(Path::new(path)).unwrap_or_else(|e| panic!("JWT_KEYRING_FILE must be valid: {}", e)),
+        None => JwtKeyring::single(set_jwt_key()),
+    }
+}
+
+// HS256 with JWT_SECRET by default. RS256, ES256 and EdDSA sign with the PEM encoded
+// private key at JWT_PRIVATE_KEY_FILE and publish the public key in the JWKS
+fn set_jwt_key() -> JwtKey {
+    dotenv().ok();
+    let algorithm = std_env::var(env::JWT_ALGORITHM_ENV_VAR)
+        .map(|value| Algorithm::from_str(value.trim()).expect("JWT_ALGORITHM must be HS256, RS256, ES256 or EdDSA"))
+        .unwrap_or(Algorithm::HS256);
+    if algorithm == Algorithm::HS256 {
+        return JwtKey::from_secret(JWT_SECRET.as_bytes());
+    }
+
+    let path = std_env::var(env::JWT_PRIVATE_KEY_FILE_ENV_VAR)
+        .unwrap_or_else(|_| panic!("JWT_PRIVATE_KEY_FILE env var must be set for {:?}", algorithm));
+    let pem = fs::read_to_string(&path).unwrap_or_else(|e| panic!("Could not read JWT private key {}: {}", path, e));
+    JwtKey::from_pem(algorithm, &pem).unwrap_or_else(|e| panic!("JWT_PRIVATE_KEY_FILE must be a valid key: {}", e))
+}
+
+// The `iss` claim, the public URL of the auth service unless set
+fn set_jwt_issuer() -> String {
+    dotenv().ok();
+    std_env::var(env::JWT_ISSUER_ENV_VAR).unwrap_or_else(|_| AUTH_SERVICE_URL.clone())
+}
+
+// Services tokens are minted for, as a comma separated list. Tokens name all of them in their
+// `aud` claim and only tokens naming at least one of them are accepted
+fn set_jwt_audiences() -> Vec<String> {
+    dotenv().ok();
+    let audiences: Vec<String> = std_env::var(env::JWT_AUDIENCES_ENV_VAR)
+        .unwrap_or(DEFAULT_JWT_AUDIENCE.to_owned())
+        .split(',')
+        .map(str::trim)
+        .filter(|audience| !audience.is_empty())
+        .map(str::to_owned)
+        .collect();
+    if audiences.is_empty() {
+        panic!("JWT_AUDIENCES must name at least one audience");
+    }
+    audiences
+}
+
+// How far apart the clocks of the auth service and the services checking its tokens may be
+fn set_jwt_leeway_seconds() -> u64 {
+    dotenv().ok();
+    std_env::var(env::JWT_LEEWAY_SECONDS_ENV_VAR)
+        .map(|value| value.parse().expect("JWT_LEEWAY_SECONDS must be a number of seconds"))
+        .unwrap_or(DEFAULT_JWT_LEEWAY_SECONDS)
+}
+
 fn get_database_url() -> String {
     dotenv().ok();
     let url = std_env::var(env::DATABASE_URL_ENV_VAR).expect("DATABASE_URL env var should be set");
@@ -33,14 +122,129 @@ fn set_redis_host() -> String {
     dotenv().ok();
     std_env::var(env::REDIS_HOST_NAME_ENV_VAR).unwrap_or(DEFAULT_REDIS_HOSTNAME.to_owned())
 }
+
+// The public URL of the auth service, used to build links that are sent by email
+fn set_auth_service_url() -> String {
+    dotenv().ok();
+    std_env::var(env::AUTH_SERVICE_URL_ENV_VAR).unwrap_or(DEFAULT_AUTH_SERVICE_URL.to_owned())
+}
+
+// Users must verify their email before logging in unless this is set to "true"
+fn set_allow_unverified_login() -> bool {
+    dotenv().ok();
+    std_env::var(env::ALLOW_UNVERIFIED_LOGIN_ENV_VAR)
+        .map(|value| value.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+// Key used to encrypt TOTP secrets at rest, given as 32 base64 encoded bytes
+// (generate one with `openssl rand -base64 32`)
+fn set_totp_encryption_key() -> [u8; 32] {
+    dotenv().ok();
+    let key = std_env::var(env::TOTP_ENCRYPTION_KEY_ENV_VAR).expect("TOTP_ENCRYPTION_KEY env var must be set");
+    let key = STANDARD.decode(key).expect("TOTP_ENCRYPTION_KEY must be base64 encoded");
+
+    key.try_into().expect("TOTP_ENCRYPTION_KEY must be 32 bytes long")
+}
+
+// The backends default to what production runs on; set them to "memory" to run without Postgres or Redis
+fn set_user_store_backend() -> UserStoreBackend {
+    dotenv().ok();
+    std_env::var(env::USER_STORE_ENV_VAR)
+        .map(|value| UserStoreBackend::parse(&value).expect("USER_STORE must be a valid backend"))
+        .unwrap_or(UserStoreBackend::Postgres)
+}
+
+fn set_token_store_backend(env_var: &str) -> TokenStoreBackend {
+    dotenv().ok();
+    std_env::var(env_var)
+        .map(|value| TokenStoreBackend::parse(&value).unwrap_or_else(|e| panic!("{} must be a valid backend: {}", env_var, e)))
+        .unwrap_or(TokenStoreBackend::Redis)
+}
+
+// Overrides for the default limits, e.g. "/login=10/60,default=60/60"
+fn set_rate_limits() -> RateLimits {
+    dotenv().ok();
+    std_env::var(env::RATE_LIMITS_ENV_VAR)
+        .map(|value| RateLimits::parse(&value).unwrap_or_else(|e| panic!("RATE_LIMITS must be valid: {}", e)))
+        .unwrap_or_default()
+}
+
+fn set_email_client_backend() -> EmailClientBackend {
+    dotenv().ok();
+    std_env::var(env::EMAIL_CLIENT_ENV_VAR)
+        .map(|value| EmailClientBackend::parse(&value).expect("EMAIL_CLIENT must be a valid backend"))
+        .unwrap_or(EmailClientBackend::Mock)
+}
+
+fn set_smtp_host() -> String {
+    dotenv().ok();
+    std_env::var(env::SMTP_HOST_ENV_VAR).unwrap_or(DEFAULT_SMTP_HOST.to_owned())
+}
+
+// Falls back to the standard port for the TLS mode when not set
+fn set_smtp_port() -> Option<u16> {
+    dotenv().ok();
+    std_env::var(env::SMTP_PORT_ENV_VAR)
+        .ok()
+        .map(|port| port.parse().expect("SMTP_PORT must be a valid port number"))
+}
+
+fn set_smtp_tls() -> SmtpTls {
+    dotenv().ok();
+    std_env::var(env::SMTP_TLS_ENV_VAR)
+        .map(|value| SmtpTls::parse(&value).expect("SMTP_TLS must be a valid TLS mode"))
+        .unwrap_or(SmtpTls::StartTls)
+}
+
+fn set_email_sender() -> String {
+    dotenv().ok();
+    std_env::var(env::EMAIL_SENDER_ENV_VAR).unwrap_or(DEFAULT_EMAIL_SENDER.to_owned())
+}
+
+fn optional_env(env_var: &str) -> Option<String> {
+    dotenv().ok();
+    std_env::var(env_var).ok().filter(|value| !value.is_empty())
+}
+
 pub mod env {
     pub const JWT_SECRET_ENV_VAR: &str = "JWT_SECRET";
+    pub const JWT_ALGORITHM_ENV_VAR: &str = "JWT_ALGORITHM";
+    pub const JWT_PRIVATE_KEY_FILE_ENV_VAR: &str = "JWT_PRIVATE_KEY_FILE";
+    pub const JWT_KEYRING_FILE_ENV_VAR: &str = "JWT_KEYRING_FILE";
+    pub const JWT_ISSUER_ENV_VAR: &str = "JWT_ISSUER";
+    pub const JWT_AUDIENCES_ENV_VAR: &str = "JWT_AUDIENCES";
+    pub const JWT_LEEWAY_SECONDS_ENV_VAR: &str = "JWT_LEEWAY_SECONDS";
     pub const DATABASE_URL_ENV_VAR: &str = "DATABASE_URL";
     pub const REDIS_HOST_NAME_ENV_VAR: &str = "REDIS_HOST_NAME";
+    pub const AUTH_SERVICE_URL_ENV_VAR: &str = "AUTH_SERVICE_URL";
+    pub const ALLOW_UNVERIFIED_LOGIN_ENV_VAR: &str = "ALLOW_UNVERIFIED_LOGIN";
+    pub const TOTP_ENCRYPTION_KEY_ENV_VAR: &str = "TOTP_ENCRYPTION_KEY";
+    pub const USER_STORE_ENV_VAR: &str = "USER_STORE";
+    pub const BANNED_TOKEN_STORE_ENV_VAR: &str = "BANNED_TOKEN_STORE";
+    pub const TWO_FA_CODE_STORE_ENV_VAR: &str = "TWO_FA_CODE_STORE";
+    // password reset, email verification and refresh tokens, and failed login counters
+    pub const TOKEN_STORE_ENV_VAR: &str = "TOKEN_STORE";
+    pub const RATE_LIMIT_STORE_ENV_VAR: &str = "RATE_LIMIT_STORE";
+    pub const RATE_LIMITS_ENV_VAR: &str = "RATE_LIMITS";
+    pub const EMAIL_CLIENT_ENV_VAR: &str = "EMAIL_CLIENT";
+    pub const SMTP_HOST_ENV_VAR: &str = "SMTP_HOST";
+    pub const SMTP_PORT_ENV_VAR: &str = "SMTP_PORT";
+    pub const SMTP_TLS_ENV_VAR: &str = "SMTP_TLS";
+    pub const SMTP_USERNAME_ENV_VAR: &str = "SMTP_USERNAME";
+    pub const SMTP_PASSWORD_ENV_VAR: &str = "SMTP_PASSWORD";
+    pub const EMAIL_SENDER_ENV_VAR: &str = "EMAIL_SENDER";
 }
 
 pub const JWT_COOKIE_NAME: &str = "jwt";
+pub const REFRESH_TOKEN_COOKIE_NAME: &str = "refresh_token";
 pub const DEFAULT_REDIS_HOSTNAME: &str = "127.0.0.1";
+pub const DEFAULT_AUTH_SERVICE_URL: &str = "http://localhost:3000";
+pub const DEFAULT_JWT_AUDIENCE: &str = "app-service";
+pub const DEFAULT_JWT_LEEWAY_SECONDS: u64 = 60;
+pub const DEFAULT_SMTP_HOST: &str = "localhost";
+pub const DEFAULT_EMAIL_SENDER: &str = "Auth Service <no-reply@localhost>";
+// Name authenticator apps show next to the codes for this service
+pub const TOTP_ISSUER: &str = "Auth Service";
 
 pub mod prod {
     pub const APP_ADDRESS: &str = "0.0.0.0:3000";
@@ -0,0 +1,64 @@
+use aes_gcm::{aead::{Aead, AeadCore, KeyInit, OsRng}, Aes256Gcm, Key, Nonce};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+// Length of the random nonce AES-GCM needs for every encryption
+const NONCE_LENGTH: usize = 12;
+
+#[derive(Debug, PartialEq)]
+pub enum EncryptionError {
+    InvalidCiphertext,
+    UnexpectedError,
+}
+
+// Encrypt `plaintext` with AES-256-GCM, returning the nonce followed by the ciphertext, base64 encoded
+pub fn encrypt(key: &[u8; 32], plaintext: &str) -> Result<String, EncryptionError> {
+    let cipher = Aes256Gcm::new(&Key::<Aes256Gcm>::from(*key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|_| EncryptionError::UnexpectedError)?;
+
+    let mut output = nonce.to_vec();
+    output.extend_from_slice(&ciphertext);
+    Ok(STANDARD.encode(output))
+}
+
+// Reverse `encrypt`. Fails if the value was not encrypted with the same key or was tampered with
+pub fn decrypt(key: &[u8; 32], encrypted: &str) -> Result<String, EncryptionError> {
+    let bytes = STANDARD.decode(encrypted).map_err(|_| EncryptionError::InvalidCiphertext)?;
+    if bytes.len() <= NONCE_LENGTH {
+        return Err(EncryptionError::InvalidCiphertext);
+    }
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LENGTH);
+    let nonce: [u8; NONCE_LENGTH] = nonce.try_into().map_err(|_| EncryptionError::InvalidCiphertext)?;
+
+    let cipher = Aes256Gcm::new(&Key::<Aes256Gcm>::from(*key));
+    let plaintext = cipher
+        .decrypt(&Nonce::from(nonce), ciphertext)
+        .map_err(|_| EncryptionError::InvalidCiphertext)?;
+    String::from_utf8(plaintext).map_err(|_| EncryptionError::InvalidCiphertext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_and_decrypt() {
+        let key = [7u8; 32];
+        let encrypted = encrypt(&key, "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP").unwrap();
+        assert_ne!(encrypted, "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP");
+        assert_eq!(decrypt(&key, &encrypted), Ok("JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP".to_owned()));
+
+        // a fresh nonce is used every time
+        assert_ne!(encrypt(&key, "secret").unwrap(), encrypt(&key, "secret").unwrap());
+    }
+
+    #[test]
+    fn test_decrypt_with_wrong_key_or_garbage() {
+        let encrypted = encrypt(&[7u8; 32], "secret").unwrap();
+        assert_eq!(decrypt(&[8u8; 32], &encrypted), Err(EncryptionError::InvalidCiphertext));
+        assert_eq!(decrypt(&[7u8; 32], "not base64!"), Err(EncryptionError::InvalidCiphertext));
+        assert_eq!(decrypt(&[7u8; 32], "c2hvcnQ="), Err(EncryptionError::InvalidCiphertext));
+    }
+}
@@ -0,0 +1,251 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters, EllipticCurveKeyType, Jwk,
+        KeyAlgorithm, OctetKeyPairParameters, OctetKeyPairType, OctetKeyParameters, OctetKeyType, PublicKeyUse,
+        RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use ring::{
+    digest::{digest, SHA256},
+    rand::SystemRandom,
+    rsa::PublicKeyComponents,
+    signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, RsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+};
+
+// A key JWTs can be signed and verified with: either an HS256 shared secret, or a private key
+// whose public half is published as a JWK so other services can verify tokens without a secret
+pub struct JwtKey {
+    algorithm: Algorithm,
+    kid: String,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    // None for HS256, the secret is never published
+    jwk: Option<Jwk>,
+}
+
+impl JwtKey {
+    pub fn from_secret(secret: &[u8]) -> Self {
+        let kid = thumbprint(&AlgorithmParameters::OctetKey(OctetKeyParameters {
+            key_type: OctetKeyType::Octet,
+            value: URL_SAFE_NO_PAD.encode(secret),
+        }));
+        Self {
+            algorithm: Algorithm::HS256,
+            kid,
+            encoding_key: EncodingKey::from_secret(secret),
+            decoding_key: DecodingKey::from_secret(secret),
+            jwk: None,
+        }
+    }
+
+    // Loads a PEM encoded private key for RS256, ES256 (P-256) or EdDSA (Ed25519). Keys should be
+    // PKCS#8 ("BEGIN PRIVATE KEY"), RSA keys may also be PKCS#1 ("BEGIN RSA PRIVATE KEY")
+    pub fn from_pem(algorithm: Algorithm, pem: &str) -> Result<Self, String> {
+        let parsed = pem::parse(pem).map_err(|e| format!("Invalid PEM: {}", e))?;
+        let der = parsed.contents();
+        let invalid_key = |e: ring::error::KeyRejected| format!("Invalid {:?} private key: {}", algorithm, e);
+
+        let (encoding_key, params) = match (algorithm, parsed.tag()) {
+            (Algorithm::RS256, "PRIVATE KEY" | "RSA PRIVATE KEY") => {
+                let key_pair = if parsed.tag() == "PRIVATE KEY" {
+                    RsaKeyPair::from_pkcs8(der)
+                } else {
+                    RsaKeyPair::from_der(der)
+                }
+                .map_err(invalid_key)?;
+                let public = PublicKeyComponents::<Vec<u8>>::from(key_pair.public());
+                let params = AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n: URL_SAFE_NO_PAD.encode(public.n),
+                    e: URL_SAFE_NO_PAD.encode(public.e),
+                });
+                (EncodingKey::from_rsa_pem(pem.as_bytes()), params)
+            }
+            (Algorithm::ES256, "PRIVATE KEY") => {
+                let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, der, &SystemRandom::new())
+                    .map_err(invalid_key)?;
+                // uncompressed point: 0x04 || x || y
+                let (x, y) = key_pair.public_key().as_ref()[1..].split_at(32);
+                let params = AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+                    key_type: EllipticCurveKeyType::EC,
+                    curve: EllipticCurve::P256,
+                    x: URL_SAFE_NO_PAD.encode(x),
+                    y: URL_SAFE_NO_PAD.encode(y),
+                });
+                (EncodingKey::from_ec_pem(pem.as_bytes()), params)
+            }
+            (Algorithm::EdDSA, "PRIVATE KEY") => {
+                let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(der).map_err(invalid_key)?;
+                let params = AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x: URL_SAFE_NO_PAD.encode(key_pair.public_key()),
+                });
+                (EncodingKey::from_ed_pem(pem.as_bytes()), params)
+            }
+            (Algorithm::RS256 | Algorithm::ES256 | Algorithm::EdDSA, tag) => {
+                return Err(format!("Expected a PKCS#8 private key for {:?}, got '{}'", algorithm, tag))
+            }
+            (algorithm, _) => return Err(format!("Unsupported signing algorithm {:?}", algorithm)),
+        };
+        let encoding_key = encoding_key.map_err(|e| format!("Invalid {:?} private key: {}", algorithm, e))?;
+
+        let kid = thumbprint(&params);
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(key_algorithm(algorithm)),
+                key_id: Some(kid.clone()),
+                ..Default::default()
+            },
+            algorithm: params,
+        };
+        let decoding_key = DecodingKey::from_jwk(&jwk).map_err(|e| e.to_string())?;
+
+        Ok(Self { algorithm, kid, encoding_key, decoding_key, jwk: Some(jwk) })
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    // Picks the key a token was signed with out of the keyring. Derived from the key itself
+    // (the secret is hashed, never published) so it changes along with it
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    pub fn header(&self) -> Header {
+        Header {
+            kid: Some(self.kid.clone()),
+            ..Header::new(self.algorithm)
+        }
+    }
+
+    pub fn encoding_key(&self) -> &EncodingKey {
+        &self.encoding_key
+    }
+
+    pub fn decoding_key(&self) -> &DecodingKey {
+        &self.decoding_key
+    }
+
+    // Only accept tokens signed with this key's algorithm, so an RS256 deployment can't be
+    // fed HS256 tokens signed with the public key
+    pub fn validation(&self) -> Validation {
+        Validation::new(self.algorithm)
+    }
+
+    // Public key to publish at /.well-known/jwks.json, None when signing with a shared secret
+    pub fn jwk(&self) -> Option<&Jwk> {
+        self.jwk.as_ref()
+    }
+}
+
+fn key_algorithm(algorithm: Algorithm) -> KeyAlgorithm {
+    match algorithm {
+        Algorithm::RS256 => KeyAlgorithm::RS256,
+        Algorithm::ES256 => KeyAlgorithm::ES256,
+        _ => KeyAlgorithm::EdDSA,
+    }
+}
+
+// JWK thumbprint (RFC 7638): SHA-256 of the required members in lexicographic order
+fn thumbprint(params: &AlgorithmParameters) -> String {
+    let members = match params {
+        AlgorithmParameters::RSA(key) => format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, key.e, key.n),
+        AlgorithmParameters::EllipticCurve(key) => {
+            format!(r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#, key.x, key.y)
+        }
+        AlgorithmParameters::OctetKeyPair(key) => format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, key.x),
+        AlgorithmParameters::OctetKey(key) => format!(r#"{{"k":"{}","kty":"oct"}}"#, key.value),
+    };
+    URL_SAFE_NO_PAD.encode(digest(&SHA256, members.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{decode, decode_header, encode};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TestClaims {
+        sub: String,
+        exp: usize,
+    }
+
+    fn pkcs8_pem(der: &[u8]) -> String {
+        pem::encode(&pem::Pem::new("PRIVATE KEY", der.to_vec()))
+    }
+
+    fn round_trip(key: &JwtKey) {
+        let claims = TestClaims { sub: "test@example.com".to_owned(), exp: usize::MAX / 2 };
+        let token = encode(&key.header(), &claims, key.encoding_key()).unwrap();
+
+        let header = decode_header(&token).unwrap();
+        assert_eq!(header.alg, key.algorithm());
+        assert_eq!(header.kid.as_deref(), Some(key.kid()));
+
+        // verifying only needs the published public key
+        let decoding_key = match key.jwk() {
+            Some(jwk) => DecodingKey::from_jwk(jwk).unwrap(),
+            None => key.decoding_key().clone(),
+        };
+        let decoded = decode::<TestClaims>(&token, &decoding_key, &key.validation()).unwrap();
+        assert_eq!(decoded.claims, claims);
+    }
+
+    #[test]
+    fn test_hs256_key_is_not_published() {
+        let key = JwtKey::from_secret(b"secret");
+        assert!(key.jwk().is_none());
+        assert_ne!(key.kid(), JwtKey::from_secret(b"other secret").kid());
+        round_trip(&key);
+    }
+
+    #[test]
+    fn test_rs256_key() {
+        let key = JwtKey::from_pem(Algorithm::RS256, include_str!("../../tests/fixtures/rsa_private_key.pem")).unwrap();
+        assert_eq!(key.jwk().unwrap().common.key_id.as_deref(), Some(key.kid()));
+        round_trip(&key);
+    }
+
+    #[test]
+    fn test_es256_key() {
+        let der = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new()).unwrap();
+        let key = JwtKey::from_pem(Algorithm::ES256, &pkcs8_pem(der.as_ref())).unwrap();
+        round_trip(&key);
+    }
+
+    #[test]
+    fn test_eddsa_key() {
+        let der = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key = JwtKey::from_pem(Algorithm::EdDSA, &pkcs8_pem(der.as_ref())).unwrap();
+        round_trip(&key);
+    }
+
+    #[test]
+    fn test_kid_is_stable_per_key() {
+        let der = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let pem = pkcs8_pem(der.as_ref());
+        let first = JwtKey::from_pem(Algorithm::EdDSA, &pem).unwrap();
+        let second = JwtKey::from_pem(Algorithm::EdDSA, &pem).unwrap();
+        assert_eq!(first.kid(), second.kid());
+
+        let other = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let other = JwtKey::from_pem(Algorithm::EdDSA, &pkcs8_pem(other.as_ref())).unwrap();
+        assert_ne!(first.kid(), other.kid());
+    }
+
+    #[test]
+    fn test_rejects_mismatched_keys() {
+        let der = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let pem = pkcs8_pem(der.as_ref());
+        assert!(JwtKey::from_pem(Algorithm::ES256, &pem).is_err());
+        assert!(JwtKey::from_pem(Algorithm::HS256, &pem).is_err());
+        assert!(JwtKey::from_pem(Algorithm::RS256, "not a pem").is_err());
+    }
+}
@@ -0,0 +1,296 @@
+use std::{collections::HashSet, fs, path::Path, str::FromStr};
+
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{jwk::JwkSet, Algorithm};
+use serde::Deserialize;
+
+use crate::utils::jwt_key::JwtKey;
+
+// A key in the keyring, with when it starts signing tokens and when tokens signed with it stop being accepted
+pub struct KeyringEntry {
+    pub key: JwtKey,
+    // None if the key can sign right away
+    pub activate_at: Option<DateTime<Utc>>,
+    // None if the key is never retired
+    pub retire_at: Option<DateTime<Utc>>,
+}
+
+impl KeyringEntry {
+    fn is_retired(&self, now: DateTime<Utc>) -> bool {
+        self.retire_at.is_some_and(|retire_at| retire_at <= now)
+    }
+
+    fn is_active(&self, now: DateTime<Utc>) -> bool {
+        !self.is_retired(now) && self.activate_at.is_none_or(|activate_at| activate_at <= now)
+    }
+}
+
+// Keys JWTs are signed and verified with. The most recently activated key signs new tokens, the
+// others only verify them, so tokens signed before a rotation keep working until their key is retired
+pub struct JwtKeyring {
+    entries: Vec<KeyringEntry>,
+}
+
+impl JwtKeyring {
+    pub fn new(entries: Vec<KeyringEntry>) -> Result<Self, String> {
+        if entries.is_empty() {
+            return Err("The JWT keyring must hold at least one key".to_owned());
+        }
+        let mut kids = HashSet::new();
+        for entry in &entries {
+            if !kids.insert(entry.key.kid()) {
+                return Err(format!("Key {} is in the JWT keyring more than once", entry.key.kid()));
+            }
+            if let (Some(activate_at), Some(retire_at)) = (entry.activate_at, entry.retire_at) {
+                if retire_at <= activate_at {
+                    return Err(format!("Key {} is retired before it is activated", entry.key.kid()));
+                }
+            }
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn single(key: JwtKey) -> Self {
+        Self { entries: vec![KeyringEntry { key, activate_at: None, retire_at: None }] }
+    }
+
+    // Reads a keyring file, refusing keyrings that have no key to sign with right now
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let keyring = Self::parse(&json, path.parent().unwrap_or(Path::new(".")))?;
+        if keyring.signing_key(Utc::now()).is_none() {
+            return Err("No key in the JWT keyring is active".to_owned());
+        }
+        Ok(keyring)
+    }
+
+    // Parses a JSON array of keys, e.g.
+    // [{"algorithm": "HS256", "secret": "...", "retireAt": "2026-01-01T00:10:00Z"},
+    //  {"algorithm": "RS256", "privateKeyFile": "new.pem", "activateAt": "2026-01-01T00:00:00Z"}]
+    // Private key files are relative to `base_dir`
+    pub fn parse(json: &str, base_dir: &Path) -> Result<Self, String> {
+        let file_entries: Vec<KeyringFileEntry> =
+            serde_json::from_str(json).map_err(|e| format!("Invalid JWT keyring: {}", e))?;
+
+        let mut entries = Vec::with_capacity(file_entries.len());
+        for entry in file_entries {
+            let algorithm = Algorithm::from_str(&entry.algorithm)
+                .map_err(|_| format!("Unknown JWT algorithm '{}' in the keyring", entry.algorithm))?;
+            let key = match (algorithm, entry.secret, entry.private_key_file) {
+                (Algorithm::HS256, Some(secret), None) if !secret.is_empty() => JwtKey::from_secret(secret.as_bytes()),
+                (Algorithm::HS256, _, _) => return Err("HS256 keys need a non-empty secret and no privateKeyFile".to_owned()),
+                (algorithm, None, Some(path)) => {
+                    let path = base_dir.join(path);
+                    let pem = fs::read_to_string(&path)
+                        .map_err(|e| format!("Could not read JWT private key {}: {}", path.display(), e))?;
+                    JwtKey::from_pem(algorithm, &pem)?
+                }
+                (algorithm, _, _) => return Err(format!("{:?} keys need a privateKeyFile and no secret", algorithm)),
+            };
+            entries.push(KeyringEntry {
+                key,
+                activate_at: entry.activate_at.as_deref().map(parse_timestamp).transpose()?,
+                retire_at: entry.retire_at.as_deref().map(parse_timestamp).transpose()?,
+            });
+        }
+        Self::new(entries)
+    }
+
+    pub fn signing_key(&self, now: DateTime<Utc>) -> Option<&JwtKey> {
+        // keys that were always active sort first, and the later entry wins ties
+        self.entries
+            .iter()
+            .filter(|entry| entry.is_active(now))
+            .max_by_key(|entry| entry.activate_at)
+            .map(|entry| &entry.key)
+    }
+
+    // The signing key, if clients can check what it signs against the JWKS. ID tokens are only signed
+    // with such a key, since every client holding a shared secret could forge them for the others
+    pub fn id_token_signing_key(&self, now: DateTime<Utc>) -> Option<&JwtKey> {
+        self.signing_key(now).filter(|key| key.jwk().is_some())
+    }
+
+    // The key a token with this `kid` was signed with, unless it was retired. Tokens issued
+    // before keys had ids are checked against the signing key
+    pub fn verification_key(&self, kid: Option<&str>, now: DateTime<Utc>) -> Option<&JwtKey> {
+        match kid {
+            Some(kid) => self
+                .entries
+                .iter()
+                .find(|entry| entry.key.kid() == kid && !entry.is_retired(now))
+                .map(|entry| &entry.key),
+            None => self.signing_key(now),
+        }
+    }
+
+    // Public keys that aren't retired, including the ones that aren't active yet so consumers
+    // already have them cached when they start signing
+    pub fn jwks(&self, now: DateTime<Utc>) -> JwkSet {
+        JwkSet {
+            keys: self
+                .entries
+                .iter()
+                .filter(|entry| !entry.is_retired(now))
+                .filter_map(|entry| entry.key.jwk().cloned())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct KeyringFileEntry {
+    algorithm: String,
+    secret: Option<String>,
+    private_key_file: Option<String>,
+    activate_at: Option<String>,
+    retire_at: Option<String>,
+}
+
+fn parse_timestamp(timestamp: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+        .map_err(|_| format!("Invalid timestamp '{}' in the JWT keyring, expected RFC 3339", timestamp))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use jsonwebtoken::{decode, decode_header, encode};
+    use serde::Serialize;
+
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct TestClaims {
+        sub: String,
+        exp: usize,
+    }
+
+    fn sign(keyring: &JwtKeyring, now: DateTime<Utc>) -> String {
+        let key = keyring.signing_key(now).unwrap();
+        let claims = TestClaims { sub: "test@example.com".to_owned(), exp: usize::MAX / 2 };
+        encode(&key.header(), &claims, key.encoding_key()).unwrap()
+    }
+
+    fn verify(keyring: &JwtKeyring, token: &str, now: DateTime<Utc>) -> bool {
+        let kid = decode_header(token).unwrap().kid;
+        keyring
+            .verification_key(kid.as_deref(), now)
+            .is_some_and(|key| decode::<TestClaims>(token, key.decoding_key(), &key.validation()).is_ok())
+    }
+
+    fn entry(secret: &str, activate_at: Option<DateTime<Utc>>, retire_at: Option<DateTime<Utc>>) -> KeyringEntry {
+        KeyringEntry { key: JwtKey::from_secret(secret.as_bytes()), activate_at, retire_at }
+    }
+
+    #[test]
+    fn test_previous_key_verifies_until_retired() {
+        let now = Utc::now();
+        let promoted_at = now + TimeDelta::minutes(5);
+        let retired_at = now + TimeDelta::minutes(15);
+        let keyring = JwtKeyring::new(vec![
+            entry("old", None, Some(retired_at)),
+            entry("new", Some(promoted_at), None),
+        ])
+        .unwrap();
+
+        let old_token = sign(&keyring, now);
+        assert!(verify(&keyring, &old_token, now));
+
+        // once promoted the new key signs and both keys verify
+        let new_token = sign(&keyring, promoted_at);
+        assert_ne!(decode_header(&old_token).unwrap().kid, decode_header(&new_token).unwrap().kid);
+        assert!(verify(&keyring, &old_token, promoted_at));
+        assert!(verify(&keyring, &new_token, promoted_at));
+
+        // after retirement only the new key does
+        assert!(!verify(&keyring, &old_token, retired_at));
+        assert!(verify(&keyring, &new_token, retired_at));
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        let now = Utc::now();
+        let keyring = JwtKeyring::single(JwtKey::from_secret(b"secret"));
+        let other = JwtKeyring::single(JwtKey::from_secret(b"other"));
+        assert!(!verify(&keyring, &sign(&other, now), now));
+    }
+
+    #[test]
+    fn test_tokens_without_kid_use_the_signing_key() {
+        let now = Utc::now();
+        let keyring = JwtKeyring::single(JwtKey::from_secret(b"secret"));
+        let claims = TestClaims { sub: "test@example.com".to_owned(), exp: usize::MAX / 2 };
+        let token = encode(
+            &jsonwebtoken::Header::default(),
+            &claims,
+            &jsonwebtoken::EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+        assert!(verify(&keyring, &token, now));
+    }
+
+    #[test]
+    fn test_jwks_publishes_upcoming_but_not_retired_keys() {
+        let now = Utc::now();
+        let pem = include_str!("../../tests/fixtures/rsa_private_key.pem");
+        let keyring = JwtKeyring::new(vec![
+            entry("secret", None, None),
+            KeyringEntry {
+                key: JwtKey::from_pem(Algorithm::RS256, pem).unwrap(),
+                activate_at: Some(now + TimeDelta::minutes(5)),
+                retire_at: Some(now + TimeDelta::minutes(15)),
+            },
+        ])
+        .unwrap();
+
+        assert_eq!(keyring.jwks(now).keys.len(), 1);
+        assert_eq!(keyring.signing_key(now).unwrap().algorithm(), Algorithm::HS256);
+        assert!(keyring.jwks(now + TimeDelta::minutes(15)).keys.is_empty());
+    }
+
+    #[test]
+    fn test_id_tokens_need_an_asymmetric_key() {
+        let now = Utc::now();
+        let promoted_at = now + TimeDelta::minutes(5);
+        let pem = include_str!("../../tests/fixtures/rsa_private_key.pem");
+        let keyring = JwtKeyring::new(vec![
+            entry("secret", None, None),
+            KeyringEntry { key: JwtKey::from_pem(Algorithm::RS256, pem).unwrap(), activate_at: Some(promoted_at), retire_at: None },
+        ])
+        .unwrap();
+
+        assert!(keyring.id_token_signing_key(now).is_none());
+        assert_eq!(keyring.id_token_signing_key(promoted_at).unwrap().algorithm(), Algorithm::RS256);
+    }
+
+    #[test]
+    fn test_parse_keyring() {
+        let base_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let keyring = JwtKeyring::parse(
+            r#"[
+                {"algorithm": "HS256", "secret": "old", "retireAt": "2026-01-01T00:10:00Z"},
+                {"algorithm": "RS256", "privateKeyFile": "rsa_private_key.pem", "activateAt": "2026-01-01T00:00:00+00:00"}
+            ]"#,
+            &base_dir,
+        )
+        .unwrap();
+        let before = parse_timestamp("2025-12-31T23:59:59Z").unwrap();
+        let after = parse_timestamp("2026-01-01T00:00:00Z").unwrap();
+        assert_eq!(keyring.signing_key(before).unwrap().algorithm(), Algorithm::HS256);
+        assert_eq!(keyring.signing_key(after).unwrap().algorithm(), Algorithm::RS256);
+
+        assert!(JwtKeyring::parse("[]", &base_dir).is_err());
+        assert!(JwtKeyring::parse(r#"[{"algorithm": "HS256", "secret": ""}]"#, &base_dir).is_err());
+        assert!(JwtKeyring::parse(r#"[{"algorithm": "RS256", "secret": "secret"}]"#, &base_dir).is_err());
+        assert!(JwtKeyring::parse(r#"[{"algorithm": "HS256", "secret": "a", "activateAt": "tomorrow"}]"#, &base_dir).is_err());
+        // the same key twice
+        assert!(JwtKeyring::parse(
+            r#"[{"algorithm": "HS256", "secret": "a"}, {"algorithm": "HS256", "secret": "a"}]"#,
+            &base_dir
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,42 @@
+// Failed logins are counted per account for this long after the last failure
+pub const FAILED_LOGIN_WINDOW_SECONDS: i64 = 15 * 60;
+// Failures allowed before logins start being slowed down
+const FREE_FAILED_LOGINS: u32 = 3;
+// Failures after which the account is locked, and the user told about it
+pub const MAX_FAILED_LOGINS: u32 = 10;
+pub const LOCKOUT_SECONDS: u64 = 15 * 60;
+// Wrong 2FA codes allowed per login attempt before it is thrown away and the user has to log in again
+pub const MAX_TWO_FA_ATTEMPTS: u32 = 5;
+// How often a 2FA code can be sent again for the same login attempt
+pub const TWO_FA_RESEND_COOLDOWN_SECONDS: i64 = 30;
+pub const MAX_TWO_FA_RESENDS: u32 = 3;
+
+// How long to refuse logins after the `failures`-th failed login in a row.
+// Past the free attempts every failure doubles the wait (1s, 2s, 4s, ...) until
+// the account gets locked for `LOCKOUT_SECONDS`
+pub fn lock_seconds(failures: u32) -> Option<u64> {
+    if failures >= MAX_FAILED_LOGINS {
+        Some(LOCKOUT_SECONDS)
+    } else if failures > FREE_FAILED_LOGINS {
+        Some(1 << (failures - FREE_FAILED_LOGINS - 1))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_seconds() {
+        assert_eq!(lock_seconds(1), None);
+        assert_eq!(lock_seconds(FREE_FAILED_LOGINS), None);
+        assert_eq!(lock_seconds(FREE_FAILED_LOGINS + 1), Some(1));
+        assert_eq!(lock_seconds(FREE_FAILED_LOGINS + 2), Some(2));
+        assert_eq!(lock_seconds(FREE_FAILED_LOGINS + 3), Some(4));
+        assert_eq!(lock_seconds(MAX_FAILED_LOGINS - 1), Some(32));
+        assert_eq!(lock_seconds(MAX_FAILED_LOGINS), Some(LOCKOUT_SECONDS));
+        assert_eq!(lock_seconds(MAX_FAILED_LOGINS + 5), Some(LOCKOUT_SECONDS));
+    }
+}
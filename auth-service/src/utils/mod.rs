@@ -1,2 +1,7 @@
 pub mod auth;
+pub mod config;
 pub mod constants;
+pub mod encryption;
+pub mod jwt_key;
+pub mod jwt_keyring;
+pub mod lockout;
@@ -0,0 +1,120 @@
+use auth_service::{domain::oauth::ServiceAccount, utils::auth::{validate_token, PrincipalType}};
+use serde_json::{json, Value};
+
+use crate::{helpers::TestApp, oauth::add_client};
+
+async fn add_service_account(app: &TestApp) {
+    let account = ServiceAccount::new(
+        "nightly-export".to_owned(),
+        "Nightly export".to_owned(),
+        vec!["read".to_owned(), "write".to_owned()],
+    )
+    .unwrap();
+    app.service_account_store.write().await.add_service_account(account, "s3cret".to_owned()).await.unwrap();
+}
+
+#[tokio::test]
+async fn should_issue_scoped_tokens_to_service_accounts() {
+    let mut app = TestApp::new().await;
+    add_service_account(&app).await;
+
+    let response = app.oauth_token_with_basic_auth("nightly-export", "s3cret", &[("grant_type", "client_credentials"), ("scope", "read")]).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers().get("cache-control").unwrap(), "no-store");
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["token_type"], "Bearer");
+    assert_eq!(body["scope"], "read");
+    // the account asks for a new token instead
+    assert!(body.get("refresh_token").is_none());
+
+    let access_token = body["access_token"].as_str().unwrap();
+    let claims = validate_token(access_token).await.unwrap();
+    assert_eq!(claims.sub, "nightly-export");
+    assert_eq!(claims.principal_type, PrincipalType::ServiceAccount);
+    assert_eq!(claims.scope.as_deref(), Some("read"));
+    assert_eq!(app.verify_token(&json!({ "token": access_token })).await.status().as_u16(), 200);
+
+    // resource servers can tell it apart from a user's token
+    add_client(&app, "api", Some("api-s3cret")).await;
+    let body: Value = app.oauth_introspect("api", "api-s3cret", access_token).await.json().await.unwrap();
+    assert_eq!(body["active"], true);
+    assert_eq!(body["sub"], "nightly-export");
+    assert_eq!(body["principal_type"], "service_account");
+    assert!(body.get("sid").is_none());
+
+    // credentials can be posted too, and asking for no scope grants all of the account's
+    let response = app.oauth_token(&[("grant_type", "client_credentials"), ("client_id", "nightly-export"), ("client_secret", "s3cret")]).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.json::<Value>().await.unwrap()["scope"], "read write");
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_bad_client_credentials() {
+    let mut app = TestApp::new_in_memory().await;
+    add_service_account(&app).await;
+    add_client(&app, "dashboard", None).await;
+    add_client(&app, "backend", Some("s3cret")).await;
+
+    for (id, secret) in [("nightly-export", "wrong"), ("unknown", "s3cret"), ("backend", "s3cret")] {
+        let response = app.oauth_token_with_basic_auth(id, secret, &[("grant_type", "client_credentials")]).await;
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(response.json::<Value>().await.unwrap()["error"], "invalid_client");
+    }
+    // a secret is required
+    let response = app.oauth_token(&[("grant_type", "client_credentials"), ("client_id", "nightly-export")]).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.oauth_token_with_basic_auth("nightly-export", "s3cret", &[("grant_type", "client_credentials"), ("scope", "read admin")]).await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(response.json::<Value>().await.unwrap()["error"], "invalid_scope");
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_accept_service_tokens_where_a_user_is_needed() {
+    let mut app = TestApp::new_in_memory().await;
+    let account = ServiceAccount::new("reporting".to_owned(), "Reporting".to_owned(), vec!["openid".to_owned()]).unwrap();
+    app.service_account_store.write().await.add_service_account(account, "s3cret".to_owned()).await.unwrap();
+
+    let response = app.oauth_token_with_basic_auth("reporting", "s3cret", &[("grant_type", "client_credentials")]).await;
+    let body: Value = response.json().await.unwrap();
+    // there is no user to describe, even with the openid scope
+    assert!(body.get("id_token").is_none());
+    assert_eq!(app.userinfo(body["access_token"].as_str().unwrap()).await.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_let_service_accounts_revoke_their_own_tokens() {
+    let mut app = TestApp::new().await;
+    add_service_account(&app).await;
+    add_client(&app, "api", Some("api-s3cret")).await;
+    let other = ServiceAccount::new("reporting".to_owned(), "Reporting".to_owned(), vec!["read".to_owned()]).unwrap();
+    app.service_account_store.write().await.add_service_account(other, "other-s3cret".to_owned()).await.unwrap();
+
+    let response = app.oauth_token_with_basic_auth("nightly-export", "s3cret", &[("grant_type", "client_credentials")]).await;
+    let access_token = response.json::<Value>().await.unwrap()["access_token"].as_str().unwrap().to_owned();
+
+    // other accounts and clients can't revoke it
+    for (id, secret) in [("reporting", "other-s3cret"), ("api", "api-s3cret")] {
+        let response = app.oauth_revoke(&[("token", &access_token), ("client_id", id), ("client_secret", secret)]).await;
+        assert_eq!(response.status().as_u16(), 200);
+        let body: Value = app.oauth_introspect("api", "api-s3cret", &access_token).await.json().await.unwrap();
+        assert_eq!(body["active"], true);
+    }
+    // nor can anyone without the account's secret
+    let response = app.oauth_revoke(&[("token", &access_token), ("client_id", "nightly-export"), ("client_secret", "wrong")]).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.oauth_revoke(&[("token", &access_token), ("client_id", "nightly-export"), ("client_secret", "s3cret")]).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: Value = app.oauth_introspect("api", "api-s3cret", &access_token).await.json().await.unwrap();
+    assert_eq!(body["active"], false);
+    assert_eq!(app.verify_token(&json!({ "token": access_token })).await.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
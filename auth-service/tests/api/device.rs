@@ -0,0 +1,133 @@
+use auth_service::utils::constants::AUTH_SERVICE_URL;
+use serde_json::{json, Value};
+
+use crate::{helpers::TestApp, oauth::{add_client, login}};
+
+const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+// Starts a device authorization for the client, returning the response the device gets
+async fn start(app: &TestApp, client_id: &str) -> Value {
+    let response = app.oauth_device_authorization(&[("client_id", client_id), ("scope", "read")]).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers().get("cache-control").unwrap(), "no-store");
+    response.json().await.unwrap()
+}
+
+async fn poll(app: &TestApp, client_id: &str, device_code: &str) -> reqwest::Response {
+    app.oauth_token(&[("grant_type", DEVICE_CODE_GRANT_TYPE), ("device_code", device_code), ("client_id", client_id)]).await
+}
+
+async fn poll_error(app: &TestApp, client_id: &str, device_code: &str) -> String {
+    let response = poll(app, client_id, device_code).await;
+    assert_eq!(response.status().as_u16(), 400);
+    response.json::<Value>().await.unwrap()["error"].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn should_issue_tokens_once_the_user_approves_the_device() {
+    let mut app = TestApp::new().await;
+    add_client(&app, "cli", None).await;
+
+    let device = start(&app, "cli").await;
+    let user_code = device["user_code"].as_str().unwrap();
+    assert_eq!(user_code.len(), 9);
+    assert_eq!(device["verification_uri"], format!("{}/device/", AUTH_SERVICE_URL.trim_end_matches('/')));
+    assert!(device["verification_uri_complete"].as_str().unwrap().ends_with(&format!("?user_code={}", user_code)));
+    assert_eq!(device["interval"], 5);
+
+    // the user logs in on another screen and approves the device, typing the code in however they like
+    login(&app).await;
+    let response = app.get_device_authorization(&user_code.to_lowercase()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let details: Value = response.json().await.unwrap();
+    assert_eq!(details["clientName"], "Dashboard");
+    assert_eq!(details["scopes"], json!(["read"]));
+    let response = app.decide_device_authorization(&json!({ "userCode": user_code, "approve": true })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    // the code is used up
+    let response = app.decide_device_authorization(&json!({ "userCode": user_code, "approve": false })).await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    let device_code = device["device_code"].as_str().unwrap();
+    let response = poll(&app, "cli", device_code).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let tokens: Value = response.json().await.unwrap();
+    assert_eq!(tokens["scope"], "read");
+    assert!(tokens["refresh_token"].is_string());
+    let access_token = tokens["access_token"].as_str().unwrap();
+    assert_eq!(app.verify_token(&json!({ "token": access_token })).await.status().as_u16(), 200);
+
+    // the device shows up among the user's sessions
+    let sessions: Value = app.get_sessions().await.json().await.unwrap();
+    assert!(sessions["sessions"].as_array().unwrap().iter().any(|session| session["device"].as_str().unwrap().ends_with("(Dashboard)")));
+
+    // and gets its tokens only once
+    assert_eq!(poll_error(&app, "cli", device_code).await, "expired_token");
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_tell_the_device_to_keep_polling_until_the_user_decides() {
+    let mut app = TestApp::new_in_memory().await;
+    add_client(&app, "cli", None).await;
+
+    let device = start(&app, "cli").await;
+    let device_code = device["device_code"].as_str().unwrap();
+    assert_eq!(poll_error(&app, "cli", device_code).await, "authorization_pending");
+    assert_eq!(poll_error(&app, "cli", device_code).await, "slow_down");
+
+    let device = start(&app, "cli").await;
+    login(&app).await;
+    let response = app.decide_device_authorization(&json!({ "userCode": device["user_code"], "approve": false })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(poll_error(&app, "cli", device["device_code"].as_str().unwrap()).await, "access_denied");
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_only_let_logged_in_users_decide_for_their_devices() {
+    let mut app = TestApp::new_in_memory().await;
+    add_client(&app, "cli", None).await;
+    add_client(&app, "other", None).await;
+
+    // the verification page sends users to the login page when this fails
+    let response = app.http_client.get(format!("{}/device/", app.address)).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let device = start(&app, "cli").await;
+    let user_code = device["user_code"].as_str().unwrap();
+    assert_eq!(app.get_device_authorization(user_code).await.status().as_u16(), 400);
+    let response = app.decide_device_authorization(&json!({ "userCode": user_code, "approve": true })).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    login(&app).await;
+    assert_eq!(app.get_device_authorization("BCDF-GHJK").await.status().as_u16(), 404);
+    assert_eq!(app.get_device_authorization("not a code").await.status().as_u16(), 404);
+
+    // device codes only work for the client they were issued to
+    assert_eq!(poll_error(&app, "other", device["device_code"].as_str().unwrap()).await, "invalid_grant");
+    assert_eq!(poll_error(&app, "cli", "unknown").await, "invalid_grant");
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_let_another_client_use_up_an_approved_device_code() {
+    let mut app = TestApp::new().await;
+    add_client(&app, "cli", None).await;
+    add_client(&app, "other", None).await;
+
+    let device = start(&app, "cli").await;
+    login(&app).await;
+    let response = app.decide_device_authorization(&json!({ "userCode": device["user_code"], "approve": true })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let device_code = device["device_code"].as_str().unwrap();
+    assert_eq!(poll_error(&app, "other", device_code).await, "invalid_grant");
+    // the device still gets its tokens, without being told to slow down
+    let response = poll(&app, "cli", device_code).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
@@ -1,51 +1,163 @@
 
+use auth_service::app_state::{BannedTokenStoreType, EmailClientType, EmailOutboxType, EmailVerificationTokenStoreType, FailedLoginStoreType, OAuthClientStoreType, PasswordResetTokenStoreType, RefreshTokenStoreType, ServiceAccountStoreType, SessionStoreType, TwoFACodeStoreType};
+use auth_service::services::hashmap_authorization_code_store::HashmapAuthorizationCodeStore;
+use auth_service::services::hashmap_device_authorization_store::HashmapDeviceAuthorizationStore;
+use auth_service::services::hashmap_email_verification_token_store::HashmapEmailVerificationTokenStore;
+use auth_service::services::hashmap_oauth_client_store::HashmapOAuthClientStore;
+use auth_service::services::hashmap_password_reset_token_store::HashmapPasswordResetTokenStore;
+use auth_service::services::hashmap_failed_login_store::HashmapFailedLoginStore;
+use auth_service::services::hashmap_rate_limit_store::HashmapRateLimitStore;
+use auth_service::domain::rate_limit::{RateLimit, RateLimits};
+use auth_service::services::hashmap_refresh_token_store::HashmapRefreshTokenStore;
+use auth_service::services::hashmap_service_account_store::HashmapServiceAccountStore;
+use auth_service::services::hashmap_session_store::HashmapSessionStore;
+use auth_service::services::hashmap_two_fa_code_store::HashmapTwoFACodeStore;
+use auth_service::services::hashmap_user_store::HashmapUserStore;
+use auth_service::services::hashset_banned_token_store::HashsetBannedTokenStore;
+use auth_service::services::redis_authorization_code_store::RedisAuthorizationCodeStore;
+use auth_service::services::redis_device_authorization_store::RedisDeviceAuthorizationStore;
+use auth_service::services::redis_email_verification_token_store::RedisEmailVerificationTokenStore;
+use auth_service::services::redis_password_reset_token_store::RedisPasswordResetTokenStore;
+use auth_service::services::redis_failed_login_store::RedisFailedLoginStore;
+use auth_service::services::redis_refresh_token_store::RedisRefreshTokenStore;
+use auth_service::services::redis_session_store::RedisSessionStore;
 use auth_service::services::redis_two_fa_code_store::RedisTwoFACodeStore;
 use auth_service::utils::constants::DEFAULT_REDIS_HOSTNAME;
+use std::collections::HashMap;
 use std::str::FromStr;
 use auth_service::get_redis_client;
 use auth_service::services::redis_banned_token_store::RedisBannedTokenStore;
 use sqlx::postgres::PgConnectOptions;
 use sqlx::Connection;
 use sqlx::PgConnection;
-use std::sync::Arc;
+use std::sync::{Arc, Once};
 
-use auth_service::{app_state::AppState, get_postgres_pool, services::{data_store::PostgresUserStore, mock_email_client::MockEmailClient}, utils::constants::{test, DATABASE_URL}, Application};
+use auth_service::domain::{data_store::{EmailOutbox, EmailOutboxError, OutboxEmail}, email::Email, email_message::EmailMessage};
+use chrono::{DateTime, Utc};
+
+use auth_service::{app_state::AppState, get_postgres_pool, services::{data_store::{PostgresEmailOutbox, PostgresOAuthClientStore, PostgresServiceAccountStore, PostgresUserStore}, hashmap_email_outbox::HashmapEmailOutbox, mock_email_client::MockEmailClient}, utils::constants::{test, DATABASE_URL}, Application};
 use reqwest::cookie::Jar;
 use sqlx::{postgres::PgPoolOptions, Executor, PgPool};
 use tokio::sync::RwLock;
 use uuid::Uuid;
+use auth_service::utils::{constants::JWT_KEYRING, jwt_key::JwtKey, jwt_keyring::JwtKeyring};
+use jsonwebtoken::Algorithm;
+
+// ID tokens are never signed with the HS256 secret from .env, so the tests sign everything with
+// the RS256 fixture key instead
+fn use_rs256_keyring() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let key = JwtKey::from_pem(Algorithm::RS256, include_str!("../fixtures/rsa_private_key.pem")).unwrap();
+        *JWT_KEYRING.write().unwrap() = JwtKeyring::single(key);
+    });
+}
 
 pub struct TestApp {
     pub address: String,
     pub cookie_jar: Arc<Jar>,
     pub http_client: reqwest::Client,
-    pub banned_token_store: Arc<RwLock<RedisBannedTokenStore>>,
-    pub two_fa_code_store: Arc<RwLock<RedisTwoFACodeStore>>,
-    pub db_name: String,
+    pub banned_token_store: BannedTokenStoreType,
+    pub two_fa_code_store: TwoFACodeStoreType,
+    pub password_reset_token_store: PasswordResetTokenStoreType,
+    pub email_verification_token_store: EmailVerificationTokenStoreType,
+    pub refresh_token_store: RefreshTokenStoreType,
+    pub session_store: SessionStoreType,
+    pub oauth_client_store: OAuthClientStoreType,
+    pub service_account_store: ServiceAccountStoreType,
+    pub email_outbox: EmailOutboxType,
+    pub failed_login_store: FailedLoginStoreType,
+    // None when the app runs on the in-memory stores
+    pub db_name: Option<String>,
     pub clean_up_called: bool,
 }
 
 impl TestApp {
+    // Most tests don't care about email verification, so users can log in straight after signing up
     pub async fn new() -> Self {
+        Self::build(true).await
+    }
+
+    pub async fn new_requiring_email_verification() -> Self {
+        Self::build(false).await
+    }
+
+    // Runs the app on the in-memory stores, without Postgres or Redis
+    pub async fn new_in_memory() -> Self {
+        Self::new_in_memory_with_email_client(Arc::new(RwLock::new(MockEmailClient))).await
+    }
+
+    pub async fn new_in_memory_with_email_client(email_client: EmailClientType) -> Self {
+        let mut app_state = Self::in_memory_app_state();
+        app_state.email_client = email_client;
+        Self::new_in_memory_with_state(app_state).await
+    }
+
+    pub async fn new_in_memory_with_rate_limits(rate_limits: RateLimits) -> Self {
+        let mut app_state = Self::in_memory_app_state();
+        app_state.rate_limits = Arc::new(rate_limits);
+        Self::new_in_memory_with_state(app_state).await
+    }
+
+    // For tests that need to swap in their own stores, e.g. to reach into them
+    pub async fn new_in_memory_with_state(app_state: AppState) -> Self {
+        Self::start(app_state, None).await
+    }
+
+    pub fn in_memory_app_state() -> AppState {
+        AppState::new(
+            Arc::new(RwLock::new(HashmapUserStore::default())),
+            Arc::new(RwLock::new(HashsetBannedTokenStore::default())),
+            Arc::new(RwLock::new(HashmapTwoFACodeStore::default())),
+            Arc::new(RwLock::new(MockEmailClient)),
+            Arc::new(RwLock::new(HashmapEmailOutbox::default())),
+            Arc::new(RwLock::new(HashmapPasswordResetTokenStore::default())),
+            Arc::new(RwLock::new(HashmapEmailVerificationTokenStore::default())),
+            Arc::new(RwLock::new(HashmapRefreshTokenStore::default())),
+            Arc::new(RwLock::new(HashmapSessionStore::default())),
+            Arc::new(RwLock::new(HashmapOAuthClientStore::default())),
+            Arc::new(RwLock::new(HashmapServiceAccountStore::default())),
+            Arc::new(RwLock::new(HashmapAuthorizationCodeStore::default())),
+            Arc::new(RwLock::new(HashmapDeviceAuthorizationStore::default())),
+            Arc::new(RwLock::new(HashmapFailedLoginStore::default())),
+            Arc::new(RwLock::new(HashmapRateLimitStore::default())),
+            relaxed_rate_limits(),
+            true)
+    }
+
+    async fn build(allow_unverified_login: bool) -> Self {
 
         // create a unique database name
         let db_name = Uuid::new_v4().to_string();
         let pg_pool= configure_postgresql(&db_name).await;
-        // let user_store: HashMap<Email, User> = HashMap::new();
-        let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
-        // Get a redis store connection and create a banned token store
+        // Get a redis store connection shared by all the redis stores
         let conn = configure_redis();
         let conn = Arc::new(RwLock::new(conn));
-        let banned_token_store: Arc<RwLock<RedisBannedTokenStore>> = Arc::new(RwLock::new(RedisBannedTokenStore::new(conn.clone())));
-
-        let two_fa_code_store: Arc<RwLock<RedisTwoFACodeStore>> = Arc::new(RwLock::new(RedisTwoFACodeStore::new(conn)));
-        let email_client = Arc::new(RwLock::new(MockEmailClient));
         let app_state  = AppState::new(
-            user_store,
-            banned_token_store.clone(),
-            two_fa_code_store.clone(),
-            email_client.clone());
-        let app = Application::build(app_state, test::APP_ADDRESS )
+            Arc::new(RwLock::new(PostgresUserStore::new(pg_pool.clone()))),
+            Arc::new(RwLock::new(RedisBannedTokenStore::new(conn.clone()))),
+            Arc::new(RwLock::new(RedisTwoFACodeStore::new(conn.clone()))),
+            Arc::new(RwLock::new(MockEmailClient)),
+            Arc::new(RwLock::new(PostgresEmailOutbox::new(pg_pool.clone()))),
+            Arc::new(RwLock::new(RedisPasswordResetTokenStore::new(conn.clone()))),
+            Arc::new(RwLock::new(RedisEmailVerificationTokenStore::new(conn.clone()))),
+            Arc::new(RwLock::new(RedisRefreshTokenStore::new(conn.clone()))),
+            Arc::new(RwLock::new(RedisSessionStore::new(conn.clone()))),
+            Arc::new(RwLock::new(PostgresOAuthClientStore::new(pg_pool.clone()))),
+            Arc::new(RwLock::new(PostgresServiceAccountStore::new(pg_pool))),
+            Arc::new(RwLock::new(RedisAuthorizationCodeStore::new(conn.clone()))),
+            Arc::new(RwLock::new(RedisDeviceAuthorizationStore::new(conn.clone()))),
+            Arc::new(RwLock::new(RedisFailedLoginStore::new(conn))),
+            // every app gets its own buckets so tests running in parallel don't limit each other
+            Arc::new(RwLock::new(HashmapRateLimitStore::default())),
+            relaxed_rate_limits(),
+            allow_unverified_login);
+        Self::start(app_state, Some(db_name)).await
+    }
+
+    async fn start(app_state: AppState, db_name: Option<String>) -> Self {
+        use_rs256_keyring();
+        let app = Application::build(app_state.clone(), test::APP_ADDRESS )
             .await
             .expect("Failed to build app");
         let address = format!("http://{}", app.address.clone());
@@ -59,6 +171,8 @@ impl TestApp {
         // Create a reqwest http client instance
         let http_client = reqwest::Client::builder()
             .cookie_provider(cookie_jar.clone())
+            // so tests can look at where /oauth/authorize sends the browser
+            .redirect(reqwest::redirect::Policy::none())
             .build()
             .unwrap();
 
@@ -68,8 +182,16 @@ impl TestApp {
             address,
             cookie_jar,
             http_client,
-            banned_token_store,
-            two_fa_code_store,
+            banned_token_store: app_state.banned_token_store,
+            two_fa_code_store: app_state.two_fa_code_store,
+            password_reset_token_store: app_state.password_reset_token_store,
+            email_verification_token_store: app_state.email_verification_token_store,
+            refresh_token_store: app_state.refresh_token_store,
+            session_store: app_state.session_store,
+            oauth_client_store: app_state.oauth_client_store,
+            service_account_store: app_state.service_account_store,
+            email_outbox: app_state.email_outbox,
+            failed_login_store: app_state.failed_login_store,
             db_name,
             clean_up_called: false,
         } 
@@ -83,6 +205,14 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn get_jwks(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/.well-known/jwks.json", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn signup<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize
@@ -106,6 +236,17 @@ impl TestApp {
         
     }
 
+    // Logs in from a browser identified by its User-Agent header
+    pub async fn login_from_device<B: serde::Serialize>(&self, body: &B, user_agent: &str) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/login", &self.address))
+            .header(reqwest::header::USER_AGENT, user_agent)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to post to login route")
+    }
+
     pub async fn logout(&self) -> reqwest::Response {
 
         self.http_client
@@ -116,6 +257,14 @@ impl TestApp {
         
     }
 
+    pub async fn logout_all(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/logout/all", &self.address))
+            .send()
+            .await
+            .expect("Failed to post to logout/all route")
+    }
+
     pub async fn verify2fa<B: serde::Serialize>(&self, body: &B) -> reqwest::Response {
         self.http_client
             .post(format!("{}/verify-2fa", &self.address))
@@ -125,6 +274,16 @@ impl TestApp {
             .expect("Failed to post to verify2FA route")
         
     }
+
+    pub async fn resend_2fa_code<B: serde::Serialize>(&self, body: &B) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/verify-2fa/resend", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn verify_token<B: serde::Serialize> (&self, body: &B) -> reqwest::Response {
 
         self.http_client
@@ -136,9 +295,187 @@ impl TestApp {
         
     }
 
+    pub async fn request_password_reset<B: serde::Serialize>(&self, body: &B) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/password-reset/request", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to post to password-reset/request route")
+    }
+
+    pub async fn confirm_password_reset<B: serde::Serialize>(&self, body: &B) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/password-reset/confirm", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to post to password-reset/confirm route")
+    }
+
+    pub async fn verify_email(&self, email: &str, token: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/verify-email", &self.address))
+            .query(&[("email", email), ("token", token)])
+            .send()
+            .await
+            .expect("Failed to get verify-email route")
+    }
+
+    pub async fn resend_verification_email<B: serde::Serialize>(&self, body: &B) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/verify-email/resend", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to post to verify-email/resend route")
+    }
+
+    pub async fn refresh_token(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/token/refresh", &self.address))
+            .send()
+            .await
+            .expect("Failed to post to token/refresh route")
+    }
+
+    pub async fn enroll_totp(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/totp/enroll", &self.address))
+            .send()
+            .await
+            .expect("Failed to post to totp/enroll route")
+    }
+
+    pub async fn confirm_totp<B: serde::Serialize>(&self, body: &B) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/totp/confirm", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to post to totp/confirm route")
+    }
+
+    pub async fn regenerate_recovery_codes(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/recovery-codes", &self.address))
+            .send()
+            .await
+            .expect("Failed to post to recovery-codes route")
+    }
+
+    pub async fn get_sessions(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to get sessions route")
+    }
+
+    pub async fn delete_session(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/sessions/{}", &self.address, id))
+            .send()
+            .await
+            .expect("Failed to delete session")
+    }
+
+    pub async fn oauth_authorize(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/oauth/authorize", &self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn oauth_token(&self, form: &[(&str, &str)]) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/oauth/token", &self.address))
+            .form(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn oauth_token_with_basic_auth(&self, client_id: &str, client_secret: &str, form: &[(&str, &str)]) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/oauth/token", &self.address))
+            .basic_auth(client_id, Some(client_secret))
+            .form(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn oauth_device_authorization(&self, form: &[(&str, &str)]) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/oauth/device_authorization", &self.address))
+            .form(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_device_authorization(&self, user_code: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/oauth/device", &self.address))
+            .query(&[("user_code", user_code)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn decide_device_authorization<B: serde::Serialize>(&self, body: &B) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/oauth/device", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn oauth_introspect(&self, client_id: &str, client_secret: &str, token: &str) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/oauth/introspect", &self.address))
+            .basic_auth(client_id, Some(client_secret))
+            .form(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn oauth_revoke(&self, form: &[(&str, &str)]) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/oauth/revoke", &self.address))
+            .form(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_openid_configuration(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/.well-known/openid-configuration", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn userinfo(&self, access_token: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/userinfo", &self.address))
+            .bearer_auth(access_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn clean_up(&mut self) {
         
-        delete_database(&self.db_name).await;
+        if let Some(db_name) = &self.db_name {
+            delete_database(db_name).await;
+        }
 
         // change the clean up bool 
         self.clean_up_called = true;
@@ -157,6 +494,14 @@ pub fn get_random_email()-> String {
     format!("{}@example.com", Uuid::new_v4())
 }
 
+// High enough that only the rate limiting tests ever hit them
+fn relaxed_rate_limits() -> RateLimits {
+    RateLimits {
+        default: RateLimit { capacity: 1000, period_seconds: 60 },
+        routes: HashMap::new(),
+    }
+}
+
 async fn configure_postgresql(db_name: &String) -> PgPool {
     let postgresql_conn_url = DATABASE_URL.to_owned();
 
@@ -241,3 +586,51 @@ fn configure_redis() -> redis::Connection {
         .get_connection()
         .expect("Failed to get Redis Connection")
 }
+
+// Polls `condition` until it holds, giving up after a few seconds
+pub async fn wait_for<F, Fut>(condition: F) -> bool
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = bool>,
+{
+    for _ in 0..50 {
+        if condition().await {
+            return true;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    false
+}
+
+// In-memory outbox that refuses new emails while `failing` is set, like a database that is down
+#[derive(Default)]
+pub struct FlakyEmailOutbox {
+    pub inner: HashmapEmailOutbox,
+    pub failing: bool,
+}
+
+#[async_trait::async_trait]
+impl EmailOutbox for FlakyEmailOutbox {
+    async fn enqueue(&mut self, recipient: Email, message: EmailMessage) -> Result<Uuid, EmailOutboxError> {
+        if self.failing {
+            return Err(EmailOutboxError::UnexpectedError);
+        }
+        self.inner.enqueue(recipient, message).await
+    }
+
+    async fn claim_due(&mut self, now: DateTime<Utc>, lease_until: DateTime<Utc>, limit: u32) -> Result<Vec<OutboxEmail>, EmailOutboxError> {
+        self.inner.claim_due(now, lease_until, limit).await
+    }
+
+    async fn mark_sent(&mut self, id: Uuid) -> Result<(), EmailOutboxError> {
+        self.inner.mark_sent(id).await
+    }
+
+    async fn mark_failed(&mut self, id: Uuid, error: &str, retry_at: Option<DateTime<Utc>>) -> Result<(), EmailOutboxError> {
+        self.inner.mark_failed(id, error, retry_at).await
+    }
+
+    async fn get_emails(&self, recipient: &Email) -> Result<Vec<OutboxEmail>, EmailOutboxError> {
+        self.inner.get_emails(recipient).await
+    }
+}
@@ -0,0 +1,92 @@
+use auth_service::{domain::email::Email, routes::TwoFactorAuthResponse, utils::constants::JWT_COOKIE_NAME};
+use serde_json::json;
+
+use crate::helpers::{get_random_email, TestApp};
+
+#[tokio::test]
+async fn should_signup_login_and_logout_without_postgres_or_redis() {
+    let mut app = TestApp::new_in_memory().await;
+    let random_email = get_random_email();
+
+    let signup_body = json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false,
+    });
+    let response = app.signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = json!({
+        "email": random_email,
+        "password": "Password123",
+    });
+    let response = app.login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    let response = app.verify_token(&json!({ "token": token })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // the token was banned in the in-memory banned token store
+    let response = app.verify_token(&json!({ "token": token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_complete_2fa_login_without_postgres_or_redis() {
+    let mut app = TestApp::new_in_memory().await;
+    let random_email = get_random_email();
+
+    let signup_body = json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": true,
+    });
+    let response = app.signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = json!({
+        "email": random_email,
+        "password": "Password123",
+    });
+    let response = app.login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let code = {
+        let email = Email::parse(random_email.clone()).expect("email should be parsed ok");
+        let (_, code) = app.two_fa_code_store
+            .read()
+            .await
+            .get_code(&email)
+            .await
+            .expect("should find the code");
+        code
+    };
+
+    let body = json!({
+        "email": random_email,
+        "LoginAttemptId": login_attempt_id,
+        "2FACode": code.as_ref(),
+    });
+    let response = app.verify2fa(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    // call clean up
+    app.clean_up().await;
+
+}
@@ -0,0 +1,114 @@
+use auth_service::utils::constants::JWT_COOKIE_NAME;
+use serde_json::{json, Value};
+
+use crate::{helpers::{get_random_email, TestApp}, oauth::{add_client, login, tokens_for}};
+
+// The resource server asking about tokens
+async fn add_resource_server(app: &TestApp) {
+    add_client(app, "api", Some("s3cret")).await;
+}
+
+async fn introspect(app: &TestApp, token: &str) -> Value {
+    let response = app.oauth_introspect("api", "s3cret", token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn should_describe_active_access_tokens() {
+    let mut app = TestApp::new().await;
+    add_resource_server(&app).await;
+    add_client(&app, "dashboard", None).await;
+    login(&app).await;
+    let tokens = tokens_for(&app, "dashboard").await;
+
+    let body = introspect(&app, tokens["access_token"].as_str().unwrap()).await;
+    assert_eq!(body["active"], true);
+    assert_eq!(body["client_id"], "dashboard");
+    assert_eq!(body["scope"], "read");
+    assert_eq!(body["token_type"], "access_token");
+    assert!(body["sub"].as_str().unwrap().contains('@'));
+    assert!(body["exp"].as_u64().is_some());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_describe_refresh_tokens_until_they_are_rotated() {
+    let mut app = TestApp::new().await;
+    add_resource_server(&app).await;
+    add_client(&app, "dashboard", None).await;
+    login(&app).await;
+    let tokens = tokens_for(&app, "dashboard").await;
+    let refresh_token = tokens["refresh_token"].as_str().unwrap();
+
+    let body = introspect(&app, refresh_token).await;
+    assert_eq!(body["active"], true);
+    assert_eq!(body["token_type"], "refresh_token");
+    assert_eq!(body["client_id"], "dashboard");
+
+    let response = app.oauth_token(&[("grant_type", "refresh_token"), ("refresh_token", refresh_token), ("client_id", "dashboard")]).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(introspect(&app, refresh_token).await, json!({ "active": false }));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_describe_first_party_tokens_and_see_logouts() {
+    let mut app = TestApp::new_in_memory().await;
+    add_resource_server(&app).await;
+    let email = get_random_email();
+    let body = json!({ "email": email, "password": "Password123", "requires2FA": false });
+    assert_eq!(app.signup(&body).await.status().as_u16(), 201);
+    let response = app.login(&json!({ "email": email, "password": "Password123" })).await;
+    let jwt = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("the jwt cookie should be set at login")
+        .value()
+        .to_owned();
+
+    let body = introspect(&app, &jwt).await;
+    assert_eq!(body["active"], true);
+    assert!(body.get("client_id").is_none());
+
+    // tokens revoked in the BannedTokenStore are inactive
+    assert_eq!(app.logout().await.status().as_u16(), 200);
+    assert_eq!(introspect(&app, &jwt).await, json!({ "active": false }));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_report_invalid_tokens_as_inactive() {
+    let mut app = TestApp::new_in_memory().await;
+    add_resource_server(&app).await;
+
+    assert_eq!(introspect(&app, "not-a-token").await, json!({ "active": false }));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_only_answer_confidential_clients() {
+    let mut app = TestApp::new_in_memory().await;
+    add_resource_server(&app).await;
+    add_client(&app, "dashboard", None).await;
+
+    let response = app.oauth_introspect("api", "wrong", "not-a-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response.json::<Value>().await.unwrap()["error"], "invalid_client");
+
+    // public clients can't prove who they are
+    let response = app
+        .http_client
+        .post(format!("{}/oauth/introspect", app.address))
+        .form(&[("token", "not-a-token"), ("client_id", "dashboard")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,21 @@
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn should_publish_only_the_public_key() {
+    let mut app = TestApp::new_in_memory().await;
+
+    let response = app.get_jwks().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers().get("content-type").unwrap(), "application/json");
+    assert!(response.headers().get("cache-control").is_some());
+    // the tests sign with an RS256 key, whose private exponent must never be published
+    let body: serde_json::Value = response.json().await.unwrap();
+    let keys = body["keys"].as_array().unwrap();
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0]["kty"], "RSA");
+    assert!(keys[0]["n"].is_string());
+    assert!(keys[0].get("d").is_none());
+
+    app.clean_up().await;
+}
@@ -1,7 +1,10 @@
-use auth_service::{domain::{data_store::TwoFACodeStore, email::Email}, routes::TwoFactorAuthResponse, utils::constants::JWT_COOKIE_NAME};
+use std::sync::Arc;
+
+use auth_service::{domain::{data_store::EmailStatus, email::Email, email_client::EmailClient, email_message::EmailMessage}, routes::TwoFactorAuthResponse, utils::{constants::JWT_COOKIE_NAME, lockout::MAX_FAILED_LOGINS}, ErrorResponse};
 use serde_json::json;
+use tokio::sync::RwLock;
 
-use crate::helpers::{get_random_email, TestApp};
+use crate::helpers::{get_random_email, wait_for, FlakyEmailOutbox, TestApp};
 
 
 #[tokio::test]
@@ -57,7 +60,7 @@ async fn should_return_401_if_incorrect_credentials() {
         "requires2FA": true
     });
     let body2  = json!({
-        "email": "nonexistenuser@email.com",
+        "email": get_random_email(),
         "password": "password123"
     });
 
@@ -130,12 +133,15 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
     let response = app.login(&login_body).await;
 
     assert_eq!(response.status().as_u16(), 206);
+    // the session only starts once the second factor is verified
+    assert!(response.cookies().all(|cookie| cookie.name() != JWT_COOKIE_NAME));
 
     let response_json = response
                 .json::<TwoFactorAuthResponse>()
                 .await
                 .expect("Could not deserialize response body to TwoFactorAuthResponse");
     assert_eq!(response_json.message, "2FA required".to_owned());
+    assert_eq!(response_json.two_fa_method, "email".to_owned());
 
     // get id from the response
     let login_attempt_id = response_json.login_attempt_id;
@@ -157,3 +163,224 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
     app.clean_up().await;
 
 }
+
+// Keeps every email sent so tests can check what the configured client was asked to send
+#[derive(Clone, Default)]
+struct RecordingEmailClient {
+    sent: Arc<RwLock<Vec<(Email, EmailMessage)>>>,
+}
+
+#[async_trait::async_trait]
+impl EmailClient for RecordingEmailClient {
+    async fn send_email(&self, recipient: &Email, message: &EmailMessage) -> Result<(), String> {
+        self.sent.write().await.push((recipient.clone(), message.clone()));
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn should_send_2fa_code_through_the_configured_email_client() {
+    let email_client = RecordingEmailClient::default();
+    let mut app = TestApp::new_in_memory_with_email_client(Arc::new(RwLock::new(email_client.clone()))).await;
+
+    let random_email = get_random_email();
+    let signup_body = json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": true
+    });
+    let response = app.signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = json!({
+        "email": random_email,
+        "password": "Password123",
+    });
+    let response = app.login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let email = Email::parse(random_email).expect("email should be parsed ok");
+    let (_, code) = app
+        .two_fa_code_store
+        .read()
+        .await
+        .get_code(&email)
+        .await
+        .expect("should find the code");
+
+    // the outbox worker delivers the verification email and then the code in the background
+    let sent = wait_for(|| async { email_client.sent.read().await.len() == 2 }).await;
+    assert!(sent, "the 2FA code should have been emailed");
+    let sent = email_client.sent.read().await;
+    let (recipient, message) = sent.last().expect("the 2FA code should have been emailed");
+    assert_eq!(recipient, &email);
+    assert_eq!(message.subject, "Your login code");
+    assert!(message.text_body.contains(code.as_ref()));
+    assert!(message.html_body.contains(code.as_ref()));
+    drop(sent);
+    // call clean up
+    app.clean_up().await;
+}
+
+struct FailingEmailClient;
+
+#[async_trait::async_trait]
+impl EmailClient for FailingEmailClient {
+    async fn send_email(&self, _recipient: &Email, _message: &EmailMessage) -> Result<(), String> {
+        Err("mail server unavailable".to_owned())
+    }
+}
+
+#[tokio::test]
+async fn should_return_206_and_retry_later_if_2fa_email_cannot_be_sent() {
+    let mut app = TestApp::new_in_memory_with_email_client(Arc::new(RwLock::new(FailingEmailClient))).await;
+
+    let random_email = get_random_email();
+    let signup_body = json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": true
+    });
+    let response = app.signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = json!({
+        "email": random_email,
+        "password": "Password123",
+    });
+    let response = app.login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    // the code email stays in the outbox, waiting to be retried
+    let email = Email::parse(random_email).expect("email should be parsed ok");
+    let failed = wait_for(|| async {
+        let emails = app.email_outbox.read().await.get_emails(&email).await.expect("should get the emails");
+        emails.len() == 2 && emails.iter().all(|email| email.attempts == 1)
+    })
+    .await;
+    assert!(failed, "both emails should have been attempted once");
+
+    let emails = app.email_outbox.read().await.get_emails(&email).await.expect("should get the emails");
+    let code_email = emails.iter().find(|email| email.message.subject == "Your login code").expect("the code email should be queued");
+    assert_eq!(code_email.status, EmailStatus::Pending);
+    assert_eq!(code_email.last_error, Some("mail server unavailable".to_owned()));
+    // call clean up
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_429_with_retry_after_once_failed_logins_pile_up() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let signup_body = json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false
+    });
+    app.signup(&signup_body).await;
+
+    let wrong_password = json!({
+        "email": random_email,
+        "password": "WrongPassword123",
+    });
+    // the first few failures are not slowed down, the next one locks the account briefly
+    for _ in 0..4 {
+        let response = app.login(&wrong_password).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // even the right password is refused while locked
+    let correct_password = json!({
+        "email": random_email,
+        "password": "Password123",
+    });
+    let response = app.login(&correct_password).await;
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response
+        .headers()
+        .get("retry-after")
+        .expect("a Retry-After header should be set")
+        .to_str()
+        .unwrap()
+        .parse()
+        .expect("Retry-After should be a number of seconds");
+    assert!(retry_after >= 1);
+    assert_eq!(
+        response.json::<ErrorResponse>().await.expect("Could not deserialize response body to ErrorResponse").error,
+        "Account temporarily locked".to_owned()
+    );
+
+    // once the lock is over a successful login resets the count
+    tokio::time::sleep(std::time::Duration::from_secs(retry_after + 1)).await;
+    let response = app.login(&correct_password).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let email = Email::parse(random_email).expect("email should be parsed ok");
+    let failures = app.failed_login_store.write().await.record_failure(&email).await.expect("should record the failure");
+    assert_eq!(failures, 1);
+    // call clean up
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_lock_account_and_notify_user_after_too_many_failed_logins() {
+    let mut app = TestApp::new_in_memory().await;
+
+    let random_email = get_random_email();
+    let signup_body = json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false
+    });
+    app.signup(&signup_body).await;
+
+    // pretend the earlier failures already happened, without waiting out the delays between them
+    let email = Email::parse(random_email.clone()).expect("email should be parsed ok");
+    for _ in 0..MAX_FAILED_LOGINS - 1 {
+        app.failed_login_store.write().await.record_failure(&email).await.expect("should record the failure");
+    }
+
+    let response = app.login(&json!({ "email": random_email, "password": "WrongPassword123" })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.login(&json!({ "email": random_email, "password": "Password123" })).await;
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response.headers()["retry-after"].to_str().unwrap().parse().unwrap();
+    assert!(retry_after > 14 * 60, "the account should be locked for 15 minutes, got {}s", retry_after);
+
+    let emails = app.email_outbox.read().await.get_emails(&email).await.expect("should get the emails");
+    assert!(emails.iter().any(|email| email.message.subject == "Your account has been locked"));
+    // call clean up
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_500_and_keep_no_code_if_2fa_email_cannot_be_queued() {
+    let outbox = Arc::new(RwLock::new(FlakyEmailOutbox::default()));
+    let mut app_state = TestApp::in_memory_app_state();
+    app_state.email_outbox = outbox.clone();
+    let mut app = TestApp::new_in_memory_with_state(app_state).await;
+
+    let random_email = get_random_email();
+    let signup_body = json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": true
+    });
+    let response = app.signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    outbox.write().await.failing = true;
+    let login_body = json!({
+        "email": random_email,
+        "password": "Password123",
+    });
+    let response = app.login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 500);
+
+    // no login attempt is left waiting for a code that was never sent
+    let email = Email::parse(random_email).expect("email should be parsed ok");
+    assert!(app.two_fa_code_store.read().await.get_code(&email).await.is_err());
+
+    app.clean_up().await;
+}
@@ -1,8 +1,8 @@
-use auth_service::{domain::data_store::BannedTokenStore, utils::constants::JWT_COOKIE_NAME};
+use auth_service::utils::{auth::validate_token, constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME}};
 use reqwest::{Url};
 use serde_json::json;
 
-use crate::helpers::TestApp;
+use crate::helpers::{get_random_email, TestApp};
 
 // #[tokio::test]
 // async fn logout_returns_auth_ui() {
@@ -99,10 +99,11 @@ async fn pass_if_jwt_is_added_to_banned_token_store() {
         //try using the token
         let banned_token_store = app.banned_token_store.read().await;
 
-        // check if the token is in the store
-        let res = banned_token_store.is_token_banned(token).await;
+        // check if the token's jti is in the store
+        let claims = validate_token(&token).await.expect("the token should still be well formed");
+        let res = banned_token_store.is_token_revoked(&claims.jti).await;
 
-        assert!(res.is_ok(), "should be true");    
+        assert_eq!(res, Ok(true));
     }    // call clean up
     app.clean_up().await;
 
@@ -137,3 +138,64 @@ async fn should_return_400_if_logout_called_twice_in_a_row() {
 
     
 }
+
+// Logs in and returns the JWT and refresh token of the new session
+async fn login(app: &TestApp, email: &str) -> (String, String) {
+    let response = app.login(&json!({
+        "email": email,
+        "password": "Password123",
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let cookie = |name| response.cookies().find(|c| c.name() == name).expect("cookie should be set").value().to_owned();
+    (cookie(JWT_COOKIE_NAME), cookie(REFRESH_TOKEN_COOKIE_NAME))
+}
+
+#[tokio::test]
+async fn should_return_200_and_revoke_every_session_on_logout_all() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let _response = app.signup(&json!({
+        "email": email,
+        "password": "Password123",
+        "requires2FA": false,
+    })).await;
+
+    let (first_jwt, first_refresh_token) = login(&app, &email).await;
+    let (second_jwt, _) = login(&app, &email).await;
+
+    let response = app.logout_all().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let cookies: Vec<_> = response.cookies().collect();
+    assert!(cookies.iter().any(|c| c.name() == JWT_COOKIE_NAME && c.value().is_empty()));
+
+    // the JWTs of both sessions are rejected, not just the one that logged out
+    for jwt in [&first_jwt, &second_jwt] {
+        let response = app.verify_token(&json!({ "token": jwt })).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+    // and so are the refresh tokens
+    app.cookie_jar.add_cookie_str(
+        &format!("{}={}; HttpOnly; SameSite=Lax; Path=/", REFRESH_TOKEN_COOKIE_NAME, first_refresh_token),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+    let response = app.refresh_token().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // logging in again starts a valid session
+    let (jwt, _) = login(&app, &email).await;
+    let response = app.verify_token(&json!({ "token": jwt })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing_on_logout_all() {
+    let mut app = TestApp::new_in_memory().await;
+
+    let response = app.logout_all().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
@@ -1,7 +1,22 @@
+mod client_credentials;
+mod device;
 mod helpers;
+mod in_memory;
+mod introspect;
+mod jwks;
 mod login;
 mod logout;
+mod oauth;
+mod oidc;
+mod password_reset;
+mod rate_limit;
+mod recovery_codes;
+mod refresh_token;
+mod revoke;
 mod root;
+mod sessions;
 mod signup;
+mod totp;
 mod verify_2fa;
+mod verify_email;
 mod verify_token;
@@ -0,0 +1,290 @@
+use auth_service::{domain::oauth::OAuthClient, utils::{auth::validate_token, constants::JWT_COOKIE_NAME}};
+use reqwest::Url;
+use serde_json::{json, Value};
+
+use crate::helpers::{get_random_email, TestApp};
+
+pub const REDIRECT_URI: &str = "https://app.example.com/callback";
+// the example from RFC 7636 appendix B
+pub const CODE_VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+const CODE_CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+pub async fn add_client(app: &TestApp, client_id: &str, secret: Option<&str>) {
+    let client = OAuthClient::new(
+        client_id.to_owned(),
+        "Dashboard".to_owned(),
+        vec![REDIRECT_URI.to_owned()],
+        vec!["read".to_owned(), "write".to_owned(), "openid".to_owned(), "email".to_owned()],
+        secret.is_some(),
+    )
+    .unwrap();
+    app.oauth_client_store.write().await.add_client(client, secret.map(str::to_owned)).await.unwrap();
+}
+
+// Signs up a user without 2FA and logs them in, so the app's cookie jar holds their JWT
+pub async fn login(app: &TestApp) {
+    let email = get_random_email();
+    let body = json!({ "email": email, "password": "Password123", "requires2FA": false });
+    assert_eq!(app.signup(&body).await.status().as_u16(), 201);
+    assert_eq!(app.login(&json!({ "email": email, "password": "Password123" })).await.status().as_u16(), 200);
+}
+
+pub fn authorize_query(client_id: &str) -> Vec<(&str, &str)> {
+    vec![
+        ("response_type", "code"),
+        ("client_id", client_id),
+        ("redirect_uri", REDIRECT_URI),
+        ("scope", "read"),
+        ("state", "xyz"),
+        ("code_challenge", CODE_CHALLENGE),
+        ("code_challenge_method", "S256"),
+    ]
+}
+
+pub fn location(response: &reqwest::Response) -> String {
+    response.headers().get("location").expect("the response should redirect").to_str().unwrap().to_owned()
+}
+
+pub fn query_param(url: &str, name: &str) -> Option<String> {
+    Url::parse(url).unwrap().query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned())
+}
+
+// Authorizes the client for the logged in user, returning the code it was sent back with
+async fn authorize(app: &TestApp, client_id: &str) -> String {
+    let response = app.oauth_authorize(&authorize_query(client_id)).await;
+    assert_eq!(response.status().as_u16(), 303);
+    let redirect = location(&response);
+    assert!(redirect.starts_with(REDIRECT_URI));
+    assert_eq!(query_param(&redirect, "state").as_deref(), Some("xyz"));
+    query_param(&redirect, "code").expect("the client should get a code")
+}
+
+// Authorizes a public client for the logged in user and exchanges the code, returning the token response
+pub async fn tokens_for(app: &TestApp, client_id: &str) -> Value {
+    let code = authorize(app, client_id).await;
+    let response = app.oauth_token(&[
+        ("grant_type", "authorization_code"),
+        ("code", &code),
+        ("redirect_uri", REDIRECT_URI),
+        ("client_id", client_id),
+        ("code_verifier", CODE_VERIFIER),
+    ]).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn should_issue_tokens_for_a_code_exchanged_with_pkce() {
+    let mut app = TestApp::new().await;
+    add_client(&app, "dashboard", None).await;
+    login(&app).await;
+
+    let code = authorize(&app, "dashboard").await;
+    let response = app.oauth_token(&[
+        ("grant_type", "authorization_code"),
+        ("code", &code),
+        ("redirect_uri", REDIRECT_URI),
+        ("client_id", "dashboard"),
+        ("code_verifier", CODE_VERIFIER),
+    ]).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers().get("cache-control").unwrap(), "no-store");
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["token_type"], "Bearer");
+    assert_eq!(body["scope"], "read");
+
+    // the access token is a JWT like the ones in our own cookies, restricted to the client's scopes
+    let access_token = body["access_token"].as_str().unwrap();
+    assert_eq!(app.verify_token(&json!({ "token": access_token })).await.status().as_u16(), 200);
+    let claims = validate_token(access_token).await.unwrap();
+    assert_eq!(claims.client_id.as_deref(), Some("dashboard"));
+    assert_eq!(claims.scope.as_deref(), Some("read"));
+
+    // the client keeps the session going with its refresh token
+    let response = app.oauth_token(&[
+        ("grant_type", "refresh_token"),
+        ("refresh_token", body["refresh_token"].as_str().unwrap()),
+        ("client_id", "dashboard"),
+    ]).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let refreshed: Value = response.json().await.unwrap();
+    assert_ne!(refreshed["refresh_token"], body["refresh_token"]);
+
+    // and the user sees it among their devices
+    let sessions: Value = app.get_sessions().await.json().await.unwrap();
+    assert!(sessions["sessions"].as_array().unwrap().iter().any(|session| session["device"].as_str().unwrap().ends_with("(Dashboard)")));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_accept_client_access_tokens_as_the_jwt_cookie() {
+    let mut app = TestApp::new().await;
+    add_client(&app, "dashboard", None).await;
+    login(&app).await;
+
+    let tokens = tokens_for(&app, "dashboard").await;
+    app.cookie_jar.add_cookie_str(
+        &format!("{}={}; HttpOnly; SameSite=Lax; Secure; Path=/", JWT_COOKIE_NAME, tokens["access_token"].as_str().unwrap()),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+
+    // the token only works at the services it was issued for, not on the user's own account pages
+    assert_eq!(app.regenerate_recovery_codes().await.status().as_u16(), 401);
+    assert_eq!(app.get_sessions().await.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_a_wrong_code_verifier_and_reused_codes() {
+    let mut app = TestApp::new().await;
+    add_client(&app, "dashboard", None).await;
+    login(&app).await;
+
+    let code = authorize(&app, "dashboard").await;
+    let exchange = |verifier: &'static str| {
+        let code = code.clone();
+        let app = &app;
+        async move {
+            app.oauth_token(&[
+                ("grant_type", "authorization_code"),
+                ("code", &code),
+                ("redirect_uri", REDIRECT_URI),
+                ("client_id", "dashboard"),
+                ("code_verifier", verifier),
+            ]).await
+        }
+    };
+
+    let response = exchange("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXj").await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(response.json::<Value>().await.unwrap()["error"], "invalid_grant");
+
+    // the failed attempt used the code up
+    let response = exchange(CODE_VERIFIER).await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(response.json::<Value>().await.unwrap()["error"], "invalid_grant");
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_require_the_redirect_uri_if_it_was_authorized_with_one() {
+    let mut app = TestApp::new().await;
+    add_client(&app, "dashboard", None).await;
+    login(&app).await;
+
+    let code = authorize(&app, "dashboard").await;
+    let response = app.oauth_token(&[
+        ("grant_type", "authorization_code"),
+        ("code", &code),
+        ("client_id", "dashboard"),
+        ("code_verifier", CODE_VERIFIER),
+    ]).await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(response.json::<Value>().await.unwrap()["error"], "invalid_grant");
+
+    // clients relying on their only registered redirect URI don't have to send it
+    let query: Vec<_> = authorize_query("dashboard").into_iter().filter(|(name, _)| *name != "redirect_uri").collect();
+    let response = app.oauth_authorize(&query).await;
+    assert_eq!(response.status().as_u16(), 303);
+    let code = query_param(&location(&response), "code").expect("the client should get a code");
+    let response = app.oauth_token(&[
+        ("grant_type", "authorization_code"),
+        ("code", &code),
+        ("client_id", "dashboard"),
+        ("code_verifier", CODE_VERIFIER),
+    ]).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_redirect_to_unregistered_redirect_uris() {
+    let mut app = TestApp::new_in_memory().await;
+    add_client(&app, "dashboard", None).await;
+    login(&app).await;
+
+    let mut query = authorize_query("dashboard");
+    query[2] = ("redirect_uri", "https://evil.example.com/callback");
+    let response = app.oauth_authorize(&query).await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response.headers().get("location").is_none());
+    assert_eq!(response.json::<Value>().await.unwrap()["error"], "invalid_request");
+
+    let response = app.oauth_authorize(&authorize_query("unknown")).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_send_request_errors_back_to_the_client() {
+    let mut app = TestApp::new_in_memory().await;
+    add_client(&app, "dashboard", None).await;
+    login(&app).await;
+
+    // PKCE is required
+    let query: Vec<_> = authorize_query("dashboard").into_iter().filter(|(name, _)| !name.starts_with("code_challenge")).collect();
+    let response = app.oauth_authorize(&query).await;
+    assert_eq!(response.status().as_u16(), 303);
+    let redirect = location(&response);
+    assert!(redirect.starts_with(REDIRECT_URI));
+    assert_eq!(query_param(&redirect, "error").as_deref(), Some("invalid_request"));
+    assert_eq!(query_param(&redirect, "state").as_deref(), Some("xyz"));
+
+    let mut query = authorize_query("dashboard");
+    query[3] = ("scope", "read admin");
+    let response = app.oauth_authorize(&query).await;
+    assert_eq!(query_param(&location(&response), "error").as_deref(), Some("invalid_scope"));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_send_users_that_are_not_logged_in_to_the_login_page() {
+    let mut app = TestApp::new_in_memory().await;
+    add_client(&app, "dashboard", None).await;
+
+    let response = app.oauth_authorize(&authorize_query("dashboard")).await;
+    assert_eq!(response.status().as_u16(), 303);
+    let login_page = format!("http://127.0.0.1{}", location(&response));
+    let return_to = query_param(&login_page, "return_to").expect("the login page should know where to go back to");
+    assert!(return_to.starts_with("/oauth/authorize?"));
+    assert!(return_to.contains("client_id=dashboard"));
+
+    // once logged in the same request goes through
+    login(&app).await;
+    let response = app.http_client.get(format!("{}{}", app.address, return_to)).send().await.unwrap();
+    assert!(query_param(&location(&response), "code").is_some());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_authenticate_confidential_clients() {
+    let mut app = TestApp::new().await;
+    add_client(&app, "backend", Some("s3cret")).await;
+    login(&app).await;
+
+    let code = authorize(&app, "backend").await;
+    let form = [
+        ("grant_type", "authorization_code"),
+        ("code", code.as_str()),
+        ("redirect_uri", REDIRECT_URI),
+        ("code_verifier", CODE_VERIFIER),
+    ];
+    let response = app.oauth_token(&[&form[..], &[("client_id", "backend")]].concat()).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response.json::<Value>().await.unwrap()["error"], "invalid_client");
+
+    // a rejected client doesn't use the code up
+    let response = app.oauth_token_with_basic_auth("backend", "wrong", &form).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.oauth_token_with_basic_auth("backend", "s3cret", &form).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
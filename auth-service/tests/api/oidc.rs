@@ -0,0 +1,120 @@
+use auth_service::utils::constants::JWT_ISSUER;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{decode_header, Algorithm};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{helpers::TestApp, oauth::{add_client, authorize_query, location, login, query_param, CODE_VERIFIER, REDIRECT_URI}};
+
+// Logs a user in and authorizes the client for `scope`, returning the token response
+async fn tokens_for_scope(app: &TestApp, scope: &str) -> Value {
+    add_client(app, "dashboard", None).await;
+    login(app).await;
+
+    let mut query = authorize_query("dashboard");
+    query[3] = ("scope", scope);
+    query.push(("nonce", "n-0S6_WzA2Mj"));
+    let response = app.oauth_authorize(&query).await;
+    let code = query_param(&location(&response), "code").expect("the client should get a code");
+
+    let response = app.oauth_token(&[
+        ("grant_type", "authorization_code"),
+        ("code", &code),
+        ("redirect_uri", REDIRECT_URI),
+        ("client_id", "dashboard"),
+        ("code_verifier", CODE_VERIFIER),
+    ]).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+// The claims of a JWT, without checking its signature
+fn payload(token: &str) -> Value {
+    let payload = token.split('.').nth(1).expect("a JWT has a payload");
+    serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn should_serve_the_discovery_document() {
+    let mut app = TestApp::new_in_memory().await;
+
+    let response = app.get_openid_configuration().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let configuration: Value = response.json().await.unwrap();
+    let issuer = JWT_ISSUER.trim_end_matches('/');
+    assert_eq!(configuration["issuer"], *JWT_ISSUER);
+    assert_eq!(configuration["authorization_endpoint"], format!("{}/oauth/authorize", issuer));
+    assert_eq!(configuration["token_endpoint"], format!("{}/oauth/token", issuer));
+    assert_eq!(configuration["userinfo_endpoint"], format!("{}/userinfo", issuer));
+    assert_eq!(configuration["jwks_uri"], format!("{}/.well-known/jwks.json", issuer));
+    assert_eq!(configuration["code_challenge_methods_supported"], serde_json::json!(["S256"]));
+    // ID tokens are signed with the RS256 key the tests use
+    assert_eq!(configuration["id_token_signing_alg_values_supported"], serde_json::json!(["RS256"]));
+    assert_eq!(configuration["scopes_supported"], serde_json::json!(["openid", "email"]));
+    let grant_types = configuration["grant_types_supported"].as_array().unwrap();
+    assert!(grant_types.contains(&Value::from("client_credentials")));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_issue_id_tokens_for_the_openid_scope() {
+    let mut app = TestApp::new().await;
+
+    let tokens = tokens_for_scope(&app, "openid email").await;
+    let claims = payload(tokens["id_token"].as_str().expect("an ID token should be issued"));
+    assert_eq!(claims["aud"], "dashboard");
+    assert_eq!(claims["iss"], *JWT_ISSUER);
+    assert_eq!(claims["nonce"], "n-0S6_WzA2Mj");
+    assert_eq!(claims["amr"], serde_json::json!(["pwd"]));
+    assert!(claims["email"].as_str().unwrap().contains('@'));
+    assert_eq!(claims["email_verified"], false);
+    // the subject is the user's id rather than their email, so it doesn't change with the email
+    assert!(Uuid::parse_str(claims["sub"].as_str().unwrap()).is_ok());
+    assert_eq!(decode_header(tokens["id_token"].as_str().unwrap()).unwrap().alg, Algorithm::RS256);
+
+    let response = app.userinfo(tokens["access_token"].as_str().unwrap()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let user_info: Value = response.json().await.unwrap();
+    assert_eq!(user_info["sub"], claims["sub"]);
+    assert_eq!(user_info["email"], claims["email"]);
+
+    // the ID token is only for the client and isn't an access token
+    let response = app.userinfo(tokens["id_token"].as_str().unwrap()).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_only_share_the_claims_the_scopes_allow() {
+    let mut app = TestApp::new_in_memory().await;
+
+    let tokens = tokens_for_scope(&app, "openid").await;
+    let claims = payload(tokens["id_token"].as_str().unwrap());
+    assert!(claims.get("email").is_none());
+
+    let user_info: Value = app.userinfo(tokens["access_token"].as_str().unwrap()).await.json().await.unwrap();
+    assert!(user_info["sub"].is_string());
+    assert!(user_info.get("email").is_none());
+    assert!(user_info.get("email_verified").is_none());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_require_the_openid_scope() {
+    let mut app = TestApp::new_in_memory().await;
+
+    // plain OAuth clients get no ID token and can't ask who the user is
+    let tokens = tokens_for_scope(&app, "read").await;
+    assert!(tokens.get("id_token").is_none());
+    let response = app.userinfo(tokens["access_token"].as_str().unwrap()).await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    let response = app.userinfo("not-a-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response.headers().get("www-authenticate").unwrap(), "Bearer error=\"invalid_token\"");
+
+    app.clean_up().await;
+}
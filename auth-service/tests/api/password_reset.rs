@@ -0,0 +1,239 @@
+use auth_service::{domain::email::Email, routes::PasswordResetResponse, utils::constants::JWT_COOKIE_NAME};
+use serde_json::json;
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Signs up a user without 2FA and requests a password reset for them,
+// returning the token that was emailed
+async fn signup_and_request_reset(app: &TestApp, email: &str) -> String {
+    let signup_body = json!({
+        "email": email,
+        "password": "Password123",
+        "requires2FA": false,
+    });
+    let response = app.signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.request_password_reset(&json!({ "email": email })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email = Email::parse(email.to_owned()).expect("email should be parsed ok");
+    app.password_reset_token_store
+        .read()
+        .await
+        .get_token(&email)
+        .await
+        .expect("a reset token should be stored")
+        .as_ref()
+        .to_owned()
+}
+
+#[tokio::test]
+async fn should_return_422_if_malformed_input() {
+    let mut app = TestApp::new().await;
+
+    let response = app.request_password_reset(&json!({})).await;
+    assert_eq!(response.status().as_u16(), 422);
+
+    let malformed_bodies = [
+        json!({
+            "email": "example@email.com",
+            "token": "sometoken",
+        }),
+        json!({
+            "email": "example@email.com",
+            "newPassword": "NewPassword123",
+        }),
+        json!({
+            "token": "sometoken",
+            "newPassword": "NewPassword123",
+        }),
+    ];
+    for body in malformed_bodies {
+        let response = app.confirm_password_reset(&body).await;
+        assert_eq!(response.status().as_u16(), 422, "Failed for input: {:?}", body);
+    }
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_return_400_if_invalid_input() {
+    let mut app = TestApp::new().await;
+
+    let response = app.request_password_reset(&json!({ "email": "noatsymbol.com" })).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let valid_token = "a".repeat(32);
+    let invalid_bodies = [
+        json!({
+            "email": "noatsymbol.com",
+            "token": valid_token,
+            "newPassword": "NewPassword123",
+        }),
+        json!({
+            "email": "example@email.com",
+            "token": "tooshort",
+            "newPassword": "NewPassword123",
+        }),
+        json!({
+            "email": "example@email.com",
+            "token": valid_token,
+            "newPassword": "short",
+        }),
+    ];
+    for body in invalid_bodies {
+        let response = app.confirm_password_reset(&body).await;
+        assert_eq!(response.status().as_u16(), 400, "Failed for input: {:?}", body);
+    }
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_return_200_without_token_if_user_does_not_exist() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    // The response must not reveal whether the account exists
+    let response = app.request_password_reset(&json!({ "email": random_email })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response
+        .json::<PasswordResetResponse>()
+        .await
+        .expect("Could not deserialize response body to PasswordResetResponse");
+
+    {
+        let email = Email::parse(random_email).expect("email should be parsed ok");
+        let token_store = app.password_reset_token_store.read().await;
+        assert!(token_store.get_token(&email).await.is_err());
+    }
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_return_401_if_incorrect_token() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let _token = signup_and_request_reset(&app, &random_email).await;
+
+    let body = json!({
+        "email": random_email,
+        "token": "a".repeat(32),
+        "newPassword": "NewPassword123",
+    });
+    let response = app.confirm_password_reset(&body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // No reset was requested for this email at all
+    let body = json!({
+        "email": get_random_email(),
+        "token": "a".repeat(32),
+        "newPassword": "NewPassword123",
+    });
+    let response = app.confirm_password_reset(&body).await;
+    assert_eq!(response.status().as_u16(), 401);
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_return_200_and_change_password_if_correct_token() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let token = signup_and_request_reset(&app, &random_email).await;
+
+    let body = json!({
+        "email": random_email,
+        "token": token,
+        "newPassword": "NewPassword123",
+    });
+    let response = app.confirm_password_reset(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The old password no longer works
+    let response = app.login(&json!({ "email": random_email, "password": "Password123" })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.login(&json!({ "email": random_email, "password": "NewPassword123" })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_return_401_if_same_token_twice() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let token = signup_and_request_reset(&app, &random_email).await;
+
+    let body = json!({
+        "email": random_email,
+        "token": token,
+        "newPassword": "NewPassword123",
+    });
+    let _response = app.confirm_password_reset(&body).await;
+    // the token is single-use
+    let response = app.confirm_password_reset(&body).await;
+    assert_eq!(response.status().as_u16(), 401);
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_revoke_existing_jwts_after_reset() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let signup_body = json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false,
+    });
+    let _response = app.signup(&signup_body).await;
+    let response = app.login(&json!({ "email": random_email, "password": "Password123" })).await;
+    let token = response
+        .cookies()
+        .find(|c| c.name() == JWT_COOKIE_NAME)
+        .expect("cookie should exist")
+        .value()
+        .to_owned();
+
+    let response = app.verify_token(&json!({ "token": token })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let _response = app.request_password_reset(&json!({ "email": random_email })).await;
+    let reset_token = {
+        let email = Email::parse(random_email.clone()).expect("email should be parsed ok");
+        app.password_reset_token_store
+            .read()
+            .await
+            .get_token(&email)
+            .await
+            .expect("a reset token should be stored")
+    };
+    let body = json!({
+        "email": random_email,
+        "token": reset_token.as_ref(),
+        "newPassword": "NewPassword123",
+    });
+    let response = app.confirm_password_reset(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // the JWT issued before the reset is no longer accepted
+    let response = app.verify_token(&json!({ "token": token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+    // call clean up
+    app.clean_up().await;
+
+}
@@ -0,0 +1,121 @@
+use std::{collections::HashMap, sync::Arc};
+
+use auth_service::{domain::{data_store::{RateLimitStore, RateLimitStoreError}, rate_limit::{RateLimit, RateLimitDecision, RateLimits}}, ErrorResponse};
+use serde_json::json;
+use tokio::sync::RwLock;
+
+use crate::helpers::{get_random_email, TestApp};
+
+fn login_limited_to(capacity: u32) -> RateLimits {
+    RateLimits {
+        default: RateLimit { capacity: 100, period_seconds: 60 },
+        routes: HashMap::from([("/login".to_owned(), RateLimit { capacity, period_seconds: 60 })]),
+    }
+}
+
+#[tokio::test]
+async fn should_return_429_once_the_route_limit_is_used_up() {
+    let mut app = TestApp::new_in_memory_with_rate_limits(login_limited_to(2)).await;
+    let login_body = json!({
+        "email": get_random_email(),
+        "password": "Password123",
+    });
+
+    for remaining in ["1", "0"] {
+        let response = app.login(&login_body).await;
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(response.headers()["ratelimit-limit"], "2");
+        assert_eq!(response.headers()["ratelimit-remaining"], remaining);
+    }
+
+    let response = app.login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 429);
+    assert_eq!(response.headers()["ratelimit-remaining"], "0");
+    assert_eq!(response.headers()["ratelimit-reset"], "60");
+    assert_eq!(response.headers()["retry-after"], "30");
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Too many requests".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_limit_each_route_separately() {
+    let mut app = TestApp::new_in_memory_with_rate_limits(login_limited_to(1)).await;
+    let login_body = json!({
+        "email": get_random_email(),
+        "password": "Password123",
+    });
+
+    assert_eq!(app.login(&login_body).await.status().as_u16(), 401);
+    assert_eq!(app.login(&login_body).await.status().as_u16(), 429);
+
+    let signup_body = json!({
+        "email": get_random_email(),
+        "password": "Password123",
+        "requires2FA": false,
+    });
+    let response = app.signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+    assert_eq!(response.headers()["ratelimit-limit"], "100");
+    assert_eq!(response.headers()["ratelimit-remaining"], "99");
+
+    app.clean_up().await;
+}
+
+struct UnavailableRateLimitStore;
+
+#[async_trait::async_trait]
+impl RateLimitStore for UnavailableRateLimitStore {
+    async fn take_token(&mut self, _key: &str, _limit: &RateLimit) -> Result<RateLimitDecision, RateLimitStoreError> {
+        Err(RateLimitStoreError::UnexpectedError)
+    }
+}
+
+#[tokio::test]
+async fn should_only_refuse_credential_routes_when_the_store_is_down() {
+    let mut app_state = TestApp::in_memory_app_state();
+    app_state.rate_limit_store = Arc::new(RwLock::new(UnavailableRateLimitStore));
+    let mut app = TestApp::new_in_memory_with_state(app_state).await;
+    let email = get_random_email();
+
+    let signup_body = json!({
+        "email": email,
+        "password": "Password123",
+        "requires2FA": false,
+    });
+    let response = app.signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+    assert!(response.headers().get("ratelimit-limit").is_none());
+
+    // logins would be open to brute forcing without their limit
+    let login_body = json!({
+        "email": email,
+        "password": "Password123",
+    });
+    let response = app.login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 503);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Service temporarily unavailable".to_owned()
+    );
+
+    // and so would every OAuth endpoint checking client secrets
+    let form = [("client_id", "backend"), ("client_secret", "s3cret"), ("token", "not-a-token")];
+    assert_eq!(app.oauth_token(&[("grant_type", "client_credentials"), ("client_id", "backend"), ("client_secret", "s3cret")]).await.status().as_u16(), 503);
+    assert_eq!(app.oauth_introspect("backend", "s3cret", "not-a-token").await.status().as_u16(), 503);
+    assert_eq!(app.oauth_revoke(&form).await.status().as_u16(), 503);
+    assert_eq!(app.oauth_device_authorization(&form[..2]).await.status().as_u16(), 503);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,120 @@
+use auth_service::routes::{RecoveryCodesResponse, SignupResponse, TwoFactorAuthResponse};
+use serde_json::json;
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Signs up a user with 2FA, returning the recovery codes they were given
+async fn signup_with_2fa(app: &TestApp, email: &str) -> Vec<String> {
+    let signup_body = json!({
+        "email": email,
+        "password": "Password123",
+        "requires2FA": true,
+    });
+    let response = app.signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    response
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to SignupResponse")
+        .recovery_codes
+        .expect("recovery codes should be returned")
+}
+
+// Logs in a user with 2FA, returning the login attempt id to pass to verify-2fa
+async fn start_login(app: &TestApp, email: &str) -> String {
+    let login_body = json!({
+        "email": email,
+        "password": "Password123",
+    });
+    let response = app.login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id
+}
+
+async fn verify2fa_with(app: &TestApp, email: &str, login_attempt_id: &str, code: &str) -> u16 {
+    let body = json!({
+        "email": email,
+        "LoginAttemptId": login_attempt_id,
+        "2FACode": code,
+    });
+    app.verify2fa(&body).await.status().as_u16()
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app.regenerate_recovery_codes().await;
+    assert_eq!(response.status().as_u16(), 400);
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_accept_recovery_code_once_in_place_of_2fa_code() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+    let recovery_codes = signup_with_2fa(&app, &random_email).await;
+
+    let login_attempt_id = start_login(&app, &random_email).await;
+    assert_eq!(verify2fa_with(&app, &random_email, &login_attempt_id, &recovery_codes[0]).await, 200);
+
+    // the code was burnt by the first use
+    let login_attempt_id = start_login(&app, &random_email).await;
+    assert_eq!(verify2fa_with(&app, &random_email, &login_attempt_id, &recovery_codes[0]).await, 401);
+
+    // codes typed without the dash or in upper case are accepted too
+    let code = recovery_codes[1].replace('-', "").to_uppercase();
+    assert_eq!(verify2fa_with(&app, &random_email, &login_attempt_id, &code).await, 200);
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_return_401_if_incorrect_recovery_code() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+    let _recovery_codes = signup_with_2fa(&app, &random_email).await;
+
+    let login_attempt_id = start_login(&app, &random_email).await;
+    assert_eq!(verify2fa_with(&app, &random_email, &login_attempt_id, "aaaaa-aaaaa").await, 401);
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_replace_recovery_codes_when_regenerating() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+    let old_recovery_codes = signup_with_2fa(&app, &random_email).await;
+
+    // log in with a recovery code to get a JWT
+    let login_attempt_id = start_login(&app, &random_email).await;
+    assert_eq!(verify2fa_with(&app, &random_email, &login_attempt_id, &old_recovery_codes[0]).await, 200);
+
+    let response = app.regenerate_recovery_codes().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let new_recovery_codes = response
+        .json::<RecoveryCodesResponse>()
+        .await
+        .expect("Could not deserialize response body to RecoveryCodesResponse")
+        .recovery_codes;
+    assert_eq!(new_recovery_codes.len(), 10);
+
+    // the old codes no longer work, the new ones do
+    let login_attempt_id = start_login(&app, &random_email).await;
+    assert_eq!(verify2fa_with(&app, &random_email, &login_attempt_id, &old_recovery_codes[1]).await, 401);
+    assert_eq!(verify2fa_with(&app, &random_email, &login_attempt_id, &new_recovery_codes[0]).await, 200);
+    // call clean up
+    app.clean_up().await;
+
+}
@@ -0,0 +1,153 @@
+use auth_service::{domain::data_store::RefreshToken, utils::constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME}};
+use reqwest::Url;
+use serde_json::json;
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Signs up and logs in a user without 2FA, returning the refresh token set at login
+async fn login_and_get_refresh_token(app: &TestApp) -> String {
+    let random_email = get_random_email();
+    let signup_body = json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false,
+    });
+    let _response = app.signup(&signup_body).await;
+
+    let login_body = json!({
+        "email": random_email,
+        "password": "Password123",
+    });
+    let response = app.login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let refresh_token = response
+        .cookies()
+        .find(|c| c.name() == REFRESH_TOKEN_COOKIE_NAME)
+        .expect("a refresh token cookie should be set at login")
+        .value()
+        .to_owned();
+    refresh_token
+}
+
+fn set_refresh_cookie(app: &TestApp, token: &str) {
+    app.cookie_jar.add_cookie_str(
+        &format!("{}={}; HttpOnly; SameSite=Lax; Path=/", REFRESH_TOKEN_COOKIE_NAME, token),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+}
+
+#[tokio::test]
+async fn should_return_400_if_refresh_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app.refresh_token().await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_refresh_token() {
+    let mut app = TestApp::new().await;
+
+    for token in ["invalid", &"a".repeat(64)] {
+        set_refresh_cookie(&app, token);
+        let response = app.refresh_token().await;
+        assert_eq!(response.status().as_u16(), 401, "Failed for token: {}", token);
+    }
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_return_200_and_rotate_if_valid_refresh_token() {
+    let mut app = TestApp::new().await;
+
+    let old_refresh_token = login_and_get_refresh_token(&app).await;
+
+    let response = app.refresh_token().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|c| c.name() == JWT_COOKIE_NAME)
+        .expect("a new jwt cookie should be set");
+    assert!(!auth_cookie.value().is_empty());
+
+    let new_refresh_token = response
+        .cookies()
+        .find(|c| c.name() == REFRESH_TOKEN_COOKIE_NAME)
+        .expect("a new refresh token cookie should be set")
+        .value()
+        .to_owned();
+    assert_ne!(new_refresh_token, old_refresh_token);
+
+    // the new JWT is accepted
+    let response = app.verify_token(&json!({ "token": auth_cookie.value() })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // and the rotated refresh token can be used in turn
+    let response = app.refresh_token().await;
+    assert_eq!(response.status().as_u16(), 200);
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_revoke_family_if_rotated_token_is_reused() {
+    let mut app = TestApp::new().await;
+
+    let old_refresh_token = login_and_get_refresh_token(&app).await;
+
+    let response = app.refresh_token().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let new_refresh_token = response
+        .cookies()
+        .find(|c| c.name() == REFRESH_TOKEN_COOKIE_NAME)
+        .expect("a new refresh token cookie should be set")
+        .value()
+        .to_owned();
+
+    // replaying the rotated token is rejected...
+    set_refresh_cookie(&app, &old_refresh_token);
+    let response = app.refresh_token().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // ...and revokes the token that replaced it as well
+    set_refresh_cookie(&app, &new_refresh_token);
+    let response = app.refresh_token().await;
+    assert_eq!(response.status().as_u16(), 401);
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_return_401_after_logout() {
+    let mut app = TestApp::new().await;
+
+    let refresh_token = login_and_get_refresh_token(&app).await;
+
+    let response = app.logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // the family no longer has a current token
+    {
+        let token = RefreshToken::parse(refresh_token.clone()).expect("refresh token should be parsed ok");
+        let refresh_token_store = app.refresh_token_store.read().await;
+        let (_, family) = refresh_token_store.get_token(&token).await.expect("token record should still exist");
+        assert!(refresh_token_store.get_current_token(&family).await.is_err());
+    }
+
+    set_refresh_cookie(&app, &refresh_token);
+    let response = app.refresh_token().await;
+    assert_eq!(response.status().as_u16(), 401);
+    // call clean up
+    app.clean_up().await;
+
+}
@@ -0,0 +1,72 @@
+use serde_json::{json, Value};
+
+use crate::{helpers::TestApp, oauth::{add_client, login, tokens_for}};
+
+async fn introspect(app: &TestApp, token: &str) -> Value {
+    app.oauth_introspect("api", "s3cret", token).await.json().await.unwrap()
+}
+
+async fn setup(app: &TestApp) -> Value {
+    add_client(app, "api", Some("s3cret")).await;
+    add_client(app, "dashboard", None).await;
+    login(app).await;
+    tokens_for(app, "dashboard").await
+}
+
+#[tokio::test]
+async fn should_revoke_access_tokens() {
+    let mut app = TestApp::new().await;
+    let tokens = setup(&app).await;
+    let access_token = tokens["access_token"].as_str().unwrap();
+
+    let response = app.oauth_revoke(&[("token", access_token), ("token_type_hint", "access_token"), ("client_id", "dashboard")]).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(introspect(&app, access_token).await["active"], false);
+    assert_eq!(app.verify_token(&json!({ "token": access_token })).await.status().as_u16(), 401);
+
+    // the session goes on
+    assert_eq!(introspect(&app, tokens["refresh_token"].as_str().unwrap()).await["active"], true);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_end_the_session_when_revoking_refresh_tokens() {
+    let mut app = TestApp::new().await;
+    let tokens = setup(&app).await;
+    let refresh_token = tokens["refresh_token"].as_str().unwrap();
+
+    let response = app.oauth_revoke(&[("token", refresh_token), ("client_id", "dashboard")]).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.oauth_token(&[("grant_type", "refresh_token"), ("refresh_token", refresh_token), ("client_id", "dashboard")]).await;
+    assert_eq!(response.status().as_u16(), 400);
+    // along with the access tokens issued in it
+    assert_eq!(introspect(&app, tokens["access_token"].as_str().unwrap()).await["active"], false);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_ignore_tokens_of_other_clients() {
+    let mut app = TestApp::new_in_memory().await;
+    let tokens = setup(&app).await;
+    add_client(&app, "other", None).await;
+
+    for token in ["access_token", "refresh_token"] {
+        let token = tokens[token].as_str().unwrap();
+        let response = app.oauth_revoke(&[("token", token), ("client_id", "other")]).await;
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(introspect(&app, token).await["active"], true);
+    }
+
+    // invalid tokens are fine too
+    let response = app.oauth_revoke(&[("token", "not-a-token"), ("client_id", "dashboard")]).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // but the client has to be known
+    let response = app.oauth_revoke(&[("token", "not-a-token"), ("client_id", "unknown")]).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,154 @@
+use auth_service::{domain::data_store::RefreshTokenFamily, utils::constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME}};
+use reqwest::Url;
+use serde_json::{json, Value};
+
+use crate::helpers::{get_random_email, TestApp};
+
+const FIREFOX_ON_LINUX: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0";
+const CHROME_ON_ANDROID: &str =
+    "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Mobile Safari/537.36";
+
+// Signs up a user without 2FA, returning the login body
+async fn signup(app: &TestApp) -> Value {
+    let email = get_random_email();
+    let response = app.signup(&json!({
+        "email": email,
+        "password": "Password123",
+        "requires2FA": false,
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+    json!({ "email": email, "password": "Password123" })
+}
+
+// Logs in from a device, returning its JWT and refresh token
+async fn login_from_device(app: &TestApp, login_body: &Value, user_agent: &str) -> (String, String) {
+    let response = app.login_from_device(login_body, user_agent).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let cookie = |name| response.cookies().find(|c| c.name() == name).expect("cookie should be set at login").value().to_owned();
+    (cookie(JWT_COOKIE_NAME), cookie(REFRESH_TOKEN_COOKIE_NAME))
+}
+
+async fn get_sessions(app: &TestApp) -> Vec<Value> {
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: Value = response.json().await.expect("Could not deserialize response body");
+    body["sessions"].as_array().expect("sessions should be a list").clone()
+}
+
+#[tokio::test]
+async fn should_list_every_device_the_user_is_logged_in_on() {
+    let mut app = TestApp::new().await;
+    let login_body = signup(&app).await;
+    login_from_device(&app, &login_body, FIREFOX_ON_LINUX).await;
+    // the jar now holds the phone's cookies
+    login_from_device(&app, &login_body, CHROME_ON_ANDROID).await;
+
+    let sessions = get_sessions(&app).await;
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions[0]["device"], "Chrome on Android");
+    assert_eq!(sessions[0]["current"], true);
+    assert_eq!(sessions[1]["device"], "Firefox on Linux");
+    assert_eq!(sessions[1]["current"], false);
+    for session in &sessions {
+        assert_eq!(session["ip"], "127.0.0.1");
+        assert!(session["createdAt"].is_string());
+        assert!(session["lastSeenAt"].is_string());
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_revoke_another_device() {
+    let mut app = TestApp::new().await;
+    let login_body = signup(&app).await;
+    let (laptop_token, laptop_refresh_token) = login_from_device(&app, &login_body, FIREFOX_ON_LINUX).await;
+    let (phone_token, _) = login_from_device(&app, &login_body, CHROME_ON_ANDROID).await;
+
+    let sessions = get_sessions(&app).await;
+    let laptop_id = sessions[1]["id"].as_str().unwrap();
+    let response = app.delete_session(laptop_id).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    // the laptop's JWT is rejected right away, the phone's still works
+    let response = app.verify_token(&json!({ "token": laptop_token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.verify_token(&json!({ "token": phone_token })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(get_sessions(&app).await.len(), 1);
+
+    // and the laptop can't get a new JWT with its refresh token either
+    app.cookie_jar.add_cookie_str(
+        &format!("{}={}; HttpOnly; SameSite=Lax; Path=/", REFRESH_TOKEN_COOKIE_NAME, laptop_refresh_token),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+    let response = app.refresh_token().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_clear_the_cookies_when_revoking_the_current_device() {
+    let mut app = TestApp::new_in_memory().await;
+    let login_body = signup(&app).await;
+    login_from_device(&app, &login_body, FIREFOX_ON_LINUX).await;
+
+    let sessions = get_sessions(&app).await;
+    let response = app.delete_session(sessions[0]["id"].as_str().unwrap()).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    // the cookies are gone, so the user is logged out
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_404_for_sessions_of_other_users() {
+    let mut app = TestApp::new_in_memory().await;
+    let other_login_body = signup(&app).await;
+    login_from_device(&app, &other_login_body, FIREFOX_ON_LINUX).await;
+    let other_id = get_sessions(&app).await[0]["id"].as_str().unwrap().to_owned();
+
+    let login_body = signup(&app).await;
+    login_from_device(&app, &login_body, CHROME_ON_ANDROID).await;
+
+    for id in [other_id.as_str(), "not-a-session-id"] {
+        let response = app.delete_session(id).await;
+        assert_eq!(response.status().as_u16(), 404);
+    }
+    // the other user is still logged in
+    let other_id = RefreshTokenFamily::parse(other_id).unwrap();
+    let other_session = app.session_store.read().await.get_session(&other_id).await;
+    assert!(other_session.is_ok());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new_in_memory().await;
+
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = app.delete_session("1c8f3f3e-2f5e-4d8e-9d7e-2f0f3a7b6c5d").await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_token() {
+    let mut app = TestApp::new_in_memory().await;
+    app.cookie_jar.add_cookie_str(
+        &format!("{}=invalid; HttpOnly; SameSite=Lax; Path=/", JWT_COOKIE_NAME),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
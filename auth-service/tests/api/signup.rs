@@ -1,6 +1,6 @@
-use auth_service::routes::SignupResponse;
+use auth_service::{domain::{data_store::EmailStatus, email::Email}, routes::SignupResponse};
 
-use crate::helpers::{get_random_email, TestApp};
+use crate::helpers::{get_random_email, wait_for, TestApp};
 
 #[tokio::test]
 async fn signup_returns_201_if_valid_input() {
@@ -17,17 +17,33 @@ async fn signup_returns_201_if_valid_input() {
 
     assert_eq!(response.status().as_u16(), 201);
 
-    let expected_response = SignupResponse {
-        message: "User created successfully!".to_owned(),
-    };
-
     // Assert that we are getting the correct response body!
+    let response_body = response
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to UserBody");
+    assert_eq!(response_body.message, "User created successfully!".to_owned());
+
+    // users with 2FA get a set of recovery codes
+    let recovery_codes = response_body.recovery_codes.expect("recovery codes should be returned");
+    assert_eq!(recovery_codes.len(), 10);
+
+    let body = serde_json::json!({
+        "email": get_random_email(),
+        "password" : "randompassword13k",
+        "requires2FA": false,
+    });
+    let response = app.signup(&body).await;
+    assert_eq!(response.status().as_u16(), 201);
     assert_eq!(
         response
             .json::<SignupResponse>()
             .await
             .expect("Could not deserialize response body to UserBody"),
-        expected_response
+        SignupResponse {
+            message: "User created successfully!".to_owned(),
+            recovery_codes: None,
+        }
     );
 
     
@@ -120,3 +136,33 @@ async fn should_return_409_if_email_already_exists() {
     app.clean_up().await;
 
 }
+
+#[tokio::test]
+async fn should_queue_and_deliver_verification_email() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false,
+    });
+    let response = app.signup(&body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let email = Email::parse(random_email).expect("email should be parsed ok");
+    let delivered = wait_for(|| async {
+        let emails = app.email_outbox.read().await.get_emails(&email).await.expect("should get the emails");
+        emails.len() == 1 && emails[0].status == EmailStatus::Sent
+    })
+    .await;
+    assert!(delivered, "the verification email should be delivered by the outbox worker");
+
+    let emails = app.email_outbox.read().await.get_emails(&email).await.expect("should get the emails");
+    assert_eq!(emails[0].message.subject, "Verify your email");
+    assert_eq!(emails[0].attempts, 0);
+    // the content, including the verification link, is not kept once delivered
+    assert!(emails[0].message.text_body.is_empty());
+    // call clean up
+    app.clean_up().await;
+}
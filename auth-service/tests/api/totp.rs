@@ -0,0 +1,238 @@
+use auth_service::{routes::{ConfirmTotpResponse, TotpEnrollmentResponse, TwoFactorAuthResponse}, ErrorResponse};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::json;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Signs up and logs in a user without 2FA, so they can enroll an authenticator app
+async fn signup_and_login(app: &TestApp, email: &str) {
+    let signup_body = json!({
+        "email": email,
+        "password": "Password123",
+        "requires2FA": false,
+    });
+    let _response = app.signup(&signup_body).await;
+
+    let login_body = json!({
+        "email": email,
+        "password": "Password123",
+    });
+    let response = app.login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+// The code the user's authenticator app would show right now
+fn current_code(secret: &str) -> String {
+    totp(secret).generate_current().expect("code should be generated")
+}
+
+// The code for the next 30 second step, which is still accepted to allow for clock drift.
+// Codes can only be used once, so this is the one to log in with after confirming
+fn next_code(secret: &str) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("time should be after the epoch").as_secs();
+    totp(secret).generate(now + 30)
+}
+
+fn totp(secret: &str) -> TOTP {
+    let secret = Secret::Encoded(secret.to_owned()).to_bytes().expect("secret should be base32");
+    TOTP::new_unchecked(Algorithm::SHA1, 6, 1, 30, secret, None, String::new())
+}
+
+async fn enroll(app: &TestApp) -> TotpEnrollmentResponse {
+    let response = app.enroll_totp().await;
+    assert_eq!(response.status().as_u16(), 200);
+    response
+        .json::<TotpEnrollmentResponse>()
+        .await
+        .expect("Could not deserialize response body to TotpEnrollmentResponse")
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app.enroll_totp().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.confirm_totp(&json!({ "code": "123456" })).await;
+    assert_eq!(response.status().as_u16(), 400);
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_return_200_with_secret_and_uri_when_enrolling() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+
+    let enrollment = enroll(&app).await;
+
+    assert!(enrollment.otpauth_uri.starts_with("otpauth://totp/"));
+    assert!(enrollment.otpauth_uri.contains(&format!("secret={}", enrollment.secret)));
+
+    // enrolling again starts over with a new secret
+    let second_enrollment = enroll(&app).await;
+    assert_ne!(second_enrollment.secret, enrollment.secret);
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_return_400_if_invalid_code() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+    let _enrollment = enroll(&app).await;
+
+    for code in ["12345", "abcdef"] {
+        let response = app.confirm_totp(&json!({ "code": code })).await;
+        assert_eq!(response.status().as_u16(), 400, "Failed for code: {}", code);
+    }
+
+    let response = app.confirm_totp(&json!({})).await;
+    assert_eq!(response.status().as_u16(), 422);
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_return_401_if_incorrect_code() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+
+    // nothing to confirm before enrolling
+    let response = app.confirm_totp(&json!({ "code": "123456" })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let enrollment = enroll(&app).await;
+    let correct_code = next_code(&enrollment.secret);
+    let incorrect_code = if correct_code == "000000" { "111111" } else { "000000" };
+
+    let response = app.confirm_totp(&json!({ "code": incorrect_code })).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Incorrect 2FA code".to_owned()
+    );
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_require_totp_code_at_login_after_confirming() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+
+    let enrollment = enroll(&app).await;
+    let response = app.confirm_totp(&json!({ "code": current_code(&enrollment.secret) })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response_body = response
+        .json::<ConfirmTotpResponse>()
+        .await
+        .expect("Could not deserialize response body to ConfirmTotpResponse");
+    assert_eq!(response_body.message, "TOTP enabled successfully!".to_owned());
+    assert_eq!(response_body.recovery_codes.len(), 10);
+
+    // logging in now asks for a code from the authenticator app
+    let login_body = json!({
+        "email": random_email,
+        "password": "Password123",
+    });
+    let response = app.login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let response_json = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+    assert_eq!(response_json.two_fa_method, "totp".to_owned());
+
+    let correct_code = next_code(&enrollment.secret);
+    let incorrect_code = if correct_code == "000000" { "111111" } else { "000000" };
+    let response = app.verify2fa(&json!({
+        "email": random_email,
+        "LoginAttemptId": response_json.login_attempt_id,
+        "2FACode": incorrect_code,
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.verify2fa(&json!({
+        "email": random_email,
+        "LoginAttemptId": response_json.login_attempt_id,
+        "2FACode": correct_code,
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_return_401_if_totp_code_replayed() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+
+    let enrollment = enroll(&app).await;
+    let confirm_code = current_code(&enrollment.secret);
+    let response = app.confirm_totp(&json!({ "code": confirm_code })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let login_body = json!({
+        "email": random_email,
+        "password": "Password123",
+    });
+    let response = app.login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    // the code used to confirm enrollment was already spent
+    let response = app.verify2fa(&json!({
+        "email": random_email,
+        "LoginAttemptId": login_attempt_id,
+        "2FACode": confirm_code,
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let login_code = next_code(&enrollment.secret);
+    let response = app.verify2fa(&json!({
+        "email": random_email,
+        "LoginAttemptId": login_attempt_id,
+        "2FACode": login_code,
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // replaying the same code on another login attempt is refused while it is still valid
+    let response = app.login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+    let response = app.verify2fa(&json!({
+        "email": random_email,
+        "LoginAttemptId": login_attempt_id,
+        "2FACode": login_code,
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+    // call clean up
+    app.clean_up().await;
+
+}
@@ -1,8 +1,12 @@
-use auth_service::{domain::{data_store::TwoFACodeStore, email::Email}, utils::constants::JWT_COOKIE_NAME};
+use std::sync::Arc;
+
+use auth_service::{domain::email::Email, services::hashmap_two_fa_code_store::HashmapTwoFACodeStore, utils::{constants::JWT_COOKIE_NAME, lockout::MAX_TWO_FA_ATTEMPTS}, ErrorResponse};
+use chrono::Utc;
 use serde_json::json;
+use tokio::sync::RwLock;
 use uuid::Uuid;
 
-use crate::helpers::TestApp;
+use crate::helpers::{get_random_email, FlakyEmailOutbox, TestApp};
 
 // #[tokio::test]
 // async fn verify2fa_returns_auth_ui() {
@@ -277,3 +281,218 @@ async fn should_return_401_if_same_code_twice() {
     app.clean_up().await;
 
 }
+
+#[tokio::test]
+async fn should_return_401_with_the_right_code_after_too_many_wrong_ones() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let signup_body = json!({
+       "email": random_email,
+       "password": "Password123",
+       "requires2FA": true,
+    });
+    app.signup(&signup_body).await;
+
+    let login_body = json!({
+       "email": random_email,
+       "password": "Password123",
+    });
+    app.login(&login_body).await;
+    let email = Email::parse(random_email.clone()).expect("an email should be parsed");
+    let (login_attempt_id, two_fa_code) = app
+        .two_fa_code_store
+        .read()
+        .await
+        .get_code(&email)
+        .await
+        .expect("login id and 2FA code should be set");
+    let wrong_code = if two_fa_code.as_ref() == "000000" { "111111" } else { "000000" };
+
+    for _ in 0..MAX_TWO_FA_ATTEMPTS {
+        let verify_body = json!({
+            "email": random_email,
+            "LoginAttemptId": login_attempt_id.as_ref(),
+            "2FACode": wrong_code,
+        });
+        assert_eq!(app.verify2fa(&verify_body).await.status().as_u16(), 401);
+    }
+
+    // the login attempt is gone, so the user has to log in again
+    let verify_body = json!({
+        "email": random_email,
+        "LoginAttemptId": login_attempt_id.as_ref(),
+        "2FACode": two_fa_code.as_ref(),
+    });
+    assert_eq!(app.verify2fa(&verify_body).await.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_429_if_code_is_resent_too_soon() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup(&json!({
+       "email": random_email,
+       "password": "Password123",
+       "requires2FA": true,
+    })).await;
+    app.login(&json!({
+       "email": random_email,
+       "password": "Password123",
+    })).await;
+    let email = Email::parse(random_email.clone()).expect("an email should be parsed");
+    let (login_attempt_id, _) = app
+        .two_fa_code_store
+        .read()
+        .await
+        .get_code(&email)
+        .await
+        .expect("login id and 2FA code should be set");
+
+    let response = app.resend_2fa_code(&json!({
+        "email": random_email,
+        "LoginAttemptId": login_attempt_id.as_ref(),
+    })).await;
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response.headers()["retry-after"]
+        .to_str()
+        .expect("Retry-After should be text")
+        .parse()
+        .expect("Retry-After should be a number of seconds");
+    assert!(retry_after > 0 && retry_after <= 30);
+
+    // a made up login attempt can't be resent
+    let response = app.resend_2fa_code(&json!({
+        "email": random_email,
+        "LoginAttemptId": Uuid::new_v4().to_string(),
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_resend_a_new_code_that_replaces_the_old_one() {
+    let two_fa_code_store = Arc::new(RwLock::new(HashmapTwoFACodeStore::default()));
+    let mut app_state = TestApp::in_memory_app_state();
+    app_state.two_fa_code_store = two_fa_code_store.clone();
+    let mut app = TestApp::new_in_memory_with_state(app_state).await;
+    let random_email = get_random_email();
+
+    app.signup(&json!({
+       "email": random_email,
+       "password": "Password123",
+       "requires2FA": true,
+    })).await;
+    app.login(&json!({
+       "email": random_email,
+       "password": "Password123",
+    })).await;
+    let email = Email::parse(random_email.clone()).expect("an email should be parsed");
+    let (login_attempt_id, old_code) = app
+        .two_fa_code_store
+        .read()
+        .await
+        .get_code(&email)
+        .await
+        .expect("login id and 2FA code should be set");
+
+    // skip the cooldown
+    two_fa_code_store.write().await.resends.get_mut(&email).expect("the first code should be tracked").1 = Utc::now();
+    let response = app.resend_2fa_code(&json!({
+        "email": random_email,
+        "LoginAttemptId": login_attempt_id.as_ref(),
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let (_, new_code) = app.two_fa_code_store.read().await.get_code(&email).await.expect("the new code should be set");
+    let emails = app.email_outbox.read().await.get_emails(&email).await.expect("should get the emails");
+    assert!(emails[0].message.text_body.contains(new_code.as_ref()));
+
+    if new_code != old_code {
+        let response = app.verify2fa(&json!({
+            "email": random_email,
+            "LoginAttemptId": login_attempt_id.as_ref(),
+            "2FACode": old_code.as_ref(),
+        })).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+    let response = app.verify2fa(&json!({
+        "email": random_email,
+        "LoginAttemptId": login_attempt_id.as_ref(),
+        "2FACode": new_code.as_ref(),
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_drop_the_login_attempt_if_the_resent_code_cannot_be_queued() {
+    let two_fa_code_store = Arc::new(RwLock::new(HashmapTwoFACodeStore::default()));
+    let outbox = Arc::new(RwLock::new(FlakyEmailOutbox::default()));
+    let mut app_state = TestApp::in_memory_app_state();
+    app_state.two_fa_code_store = two_fa_code_store.clone();
+    app_state.email_outbox = outbox.clone();
+    let mut app = TestApp::new_in_memory_with_state(app_state).await;
+    let random_email = get_random_email();
+
+    app.signup(&json!({
+       "email": random_email,
+       "password": "Password123",
+       "requires2FA": true,
+    })).await;
+    app.login(&json!({
+       "email": random_email,
+       "password": "Password123",
+    })).await;
+    let email = Email::parse(random_email.clone()).expect("an email should be parsed");
+    let (login_attempt_id, _) = app
+        .two_fa_code_store
+        .read()
+        .await
+        .get_code(&email)
+        .await
+        .expect("login id and 2FA code should be set");
+
+    // skip the cooldown
+    two_fa_code_store.write().await.resends.get_mut(&email).expect("the first code should be tracked").1 = Utc::now();
+    outbox.write().await.failing = true;
+    let response = app.resend_2fa_code(&json!({
+        "email": random_email,
+        "LoginAttemptId": login_attempt_id.as_ref(),
+    })).await;
+    assert_eq!(response.status().as_u16(), 500);
+    assert!(app.two_fa_code_store.read().await.get_code(&email).await.is_err());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_user_has_no_emailed_code() {
+    let mut app = TestApp::new_in_memory().await;
+    let random_email = get_random_email();
+    app.signup(&json!({
+       "email": random_email,
+       "password": "Password123",
+       "requires2FA": false,
+    })).await;
+
+    // answered like an unknown user, so the route doesn't reveal which emails are registered
+    for email in [random_email, get_random_email()] {
+        let response = app.resend_2fa_code(&json!({
+            "email": email,
+            "LoginAttemptId": Uuid::new_v4().to_string(),
+        })).await;
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(
+            response.json::<ErrorResponse>().await.expect("Could not deserialize response body to ErrorResponse").error,
+            "User does not exist".to_owned()
+        );
+    }
+
+    app.clean_up().await;
+}
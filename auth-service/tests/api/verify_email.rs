@@ -0,0 +1,192 @@
+use auth_service::{domain::email::Email, routes::VerifyEmailResponse, ErrorResponse};
+use serde_json::json;
+
+use crate::helpers::{get_random_email, TestApp};
+
+async fn get_verification_token(app: &TestApp, email: &str) -> String {
+    let email = Email::parse(email.to_owned()).expect("email should be parsed ok");
+    app.email_verification_token_store
+        .read()
+        .await
+        .get_token(&email)
+        .await
+        .expect("a verification token should be stored at signup")
+        .as_ref()
+        .to_owned()
+}
+
+#[tokio::test]
+async fn should_return_400_if_invalid_input() {
+    let mut app = TestApp::new_requiring_email_verification().await;
+
+    let valid_token = "a".repeat(32);
+    let invalid_inputs = [
+        ("noatsymbol.com", valid_token.as_str()),
+        ("example@email.com", "tooshort"),
+        ("example@email.com", "not-alphanumeric-but-32-chars-!!"),
+    ];
+
+    for (email, token) in invalid_inputs {
+        let response = app.verify_email(email, token).await;
+        assert_eq!(response.status().as_u16(), 400, "Failed for input: {} {}", email, token);
+    }
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_return_401_if_incorrect_token() {
+    let mut app = TestApp::new_requiring_email_verification().await;
+    let random_email = get_random_email();
+
+    let signup_body = json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false,
+    });
+    let _response = app.signup(&signup_body).await;
+
+    let response = app.verify_email(&random_email, &"a".repeat(32)).await;
+    assert_eq!(response.status().as_u16(), 401);
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_return_403_if_login_before_verifying_email() {
+    let mut app = TestApp::new_requiring_email_verification().await;
+    let random_email = get_random_email();
+
+    let signup_body = json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false,
+    });
+    let response = app.signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = json!({
+        "email": random_email,
+        "password": "Password123",
+    });
+    let response = app.login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Email not verified".to_owned()
+    );
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_return_200_and_allow_login_if_correct_token() {
+    let mut app = TestApp::new_requiring_email_verification().await;
+    let random_email = get_random_email();
+
+    let signup_body = json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false,
+    });
+    let _response = app.signup(&signup_body).await;
+
+    let token = get_verification_token(&app, &random_email).await;
+    let response = app.verify_email(&random_email, &token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response
+            .json::<VerifyEmailResponse>()
+            .await
+            .expect("Could not deserialize response body to VerifyEmailResponse"),
+        VerifyEmailResponse {
+            message: "Email verified successfully!".to_owned(),
+        }
+    );
+
+    let login_body = json!({
+        "email": random_email,
+        "password": "Password123",
+    });
+    let response = app.login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_return_401_if_same_token_twice() {
+    let mut app = TestApp::new_requiring_email_verification().await;
+    let random_email = get_random_email();
+
+    let signup_body = json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false,
+    });
+    let _response = app.signup(&signup_body).await;
+
+    let token = get_verification_token(&app, &random_email).await;
+    let _response = app.verify_email(&random_email, &token).await;
+    let response = app.verify_email(&random_email, &token).await;
+    assert_eq!(response.status().as_u16(), 401);
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_replace_token_when_verification_email_resent() {
+    let mut app = TestApp::new_requiring_email_verification().await;
+    let random_email = get_random_email();
+
+    let signup_body = json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false,
+    });
+    let _response = app.signup(&signup_body).await;
+    let first_token = get_verification_token(&app, &random_email).await;
+
+    let response = app.resend_verification_email(&json!({ "email": random_email })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let second_token = get_verification_token(&app, &random_email).await;
+    assert_ne!(first_token, second_token);
+
+    // only the latest link works
+    let response = app.verify_email(&random_email, &first_token).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.verify_email(&random_email, &second_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // verified users don't get another link
+    let response = app.resend_verification_email(&json!({ "email": random_email })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let email = Email::parse(random_email).expect("email should be parsed ok");
+    assert!(app.email_verification_token_store.read().await.get_token(&email).await.is_err());
+    // call clean up
+    app.clean_up().await;
+
+}
+
+#[tokio::test]
+async fn should_return_200_when_resending_for_unknown_email() {
+    let mut app = TestApp::new_requiring_email_verification().await;
+
+    let response = app.resend_verification_email(&json!({ "email": get_random_email() })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.resend_verification_email(&json!({ "email": "noatsymbol.com" })).await;
+    assert_eq!(response.status().as_u16(), 400);
+    // call clean up
+    app.clean_up().await;
+
+}
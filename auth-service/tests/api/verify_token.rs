@@ -1,9 +1,18 @@
 
-use auth_service::{domain::email::Email, utils::{auth::generate_auth_cookie, constants::JWT_COOKIE_NAME}};
+use auth_service::{domain::{data_store::RefreshTokenFamily, email::Email, session::Session}, utils::{auth::generate_auth_cookie, constants::{JWT_AUDIENCES, JWT_COOKIE_NAME}}};
 use serde_json::json;
 
 use crate::helpers::TestApp;
 
+// Tokens are only valid in a session, so start one for the user and mint a token in it
+async fn token_for_new_session(app: &TestApp, email: &str) -> String {
+    let email = Email::parse(email.to_owned()).unwrap();
+    let session = Session::new(RefreshTokenFamily::default(), email, "Test device".to_owned(), "127.0.0.1".to_owned());
+    app.session_store.write().await.add_session(session.clone()).await.unwrap();
+
+    generate_auth_cookie(&session, 0).unwrap().value().to_owned()
+}
+
 #[tokio::test]
 async fn should_return_200_if_valid_token() {
     let mut app = TestApp::new().await;
@@ -11,7 +20,7 @@ async fn should_return_200_if_valid_token() {
     let body = json!({
         "email": "example@email.com",
         "password": "password123",
-        "verify2FA": true,
+        "requires2FA": true,
     });
 
     let _response = app.signup(&body).await;
@@ -21,9 +30,7 @@ async fn should_return_200_if_valid_token() {
     });
     let _response = app.login(&body).await;
 
-    let cookie= generate_auth_cookie(Email::parse("example@email.com".to_string()).unwrap()).unwrap();
-
-    let token = cookie.value();
+    let token = token_for_new_session(&app, "example@email.com").await;
 
     let body = json!({
         "token": token,
@@ -130,3 +137,24 @@ async fn should_return_401_if_banned_token() {
     app.clean_up().await;
 
 }
+
+#[tokio::test]
+async fn should_check_the_audience_when_asked_to() {
+    let mut app = TestApp::new_in_memory().await;
+    let _response = app.signup(&json!({
+        "email": "example@email.com",
+        "password": "password123",
+        "requires2FA": false,
+    })).await;
+
+    let token = token_for_new_session(&app, "example@email.com").await;
+
+    let response = app.verify_token(&json!({ "token": token, "audience": JWT_AUDIENCES[0] })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // the token wasn't minted for this service
+    let response = app.verify_token(&json!({ "token": token, "audience": "billing-service" })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}